@@ -12,6 +12,8 @@ pub struct Model {
     pub smtp_server: String,
     pub smtp_username: String,
     pub smtp_password: String,
+    pub smtp_pool_max_size: i32,
+    pub smtp_pool_idle_timeout_seconds: i32,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -24,6 +24,18 @@ pub struct Model {
     pub donation_text: i32,
     #[sea_orm(column_type = "Text")]
     pub sign_up_url: String,
+    pub evening_surcharge_start_hour: i32,
+    pub evening_surcharge_percent: i32,
+    pub weekend_surcharge_percent: i32,
+    pub stale_calendar_hours: i32,
+    pub donation_reminder_months: i32,
+    pub heartbeat_on_tries_exceeded: bool,
+    pub error_mail_window_minutes: i32,
+    pub kuma_pause_on_stop: bool,
+    pub kuma_monitor_name_template: String,
+    pub kuma_notification_provider: String,
+    pub kuma_notification_settings: String,
+    pub kuma_status_page_url: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -44,6 +56,10 @@ pub enum Relation {
         on_delete = "Cascade"
     )]
     EmailProperties,
+    #[sea_orm(has_many = "super::feature_flag::Entity")]
+    FeatureFlag,
+    #[sea_orm(has_many = "super::invite_code::Entity")]
+    InviteCode,
     #[sea_orm(
         belongs_to = "super::kuma_properties::Entity",
         from = "Column::KumaProperties",
@@ -52,6 +68,10 @@ pub enum Relation {
         on_delete = "Cascade"
     )]
     KumaProperties,
+    #[sea_orm(has_many = "super::onboarding_variant::Entity")]
+    OnboardingVariant,
+    #[sea_orm(has_many = "super::user_account::Entity")]
+    UserAccount,
     #[sea_orm(has_many = "super::user_data::Entity")]
     UserData,
 }
@@ -68,12 +88,36 @@ impl Related<super::email_properties::Entity> for Entity {
     }
 }
 
+impl Related<super::feature_flag::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::FeatureFlag.def()
+    }
+}
+
+impl Related<super::invite_code::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::InviteCode.def()
+    }
+}
+
 impl Related<super::kuma_properties::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::KumaProperties.def()
     }
 }
 
+impl Related<super::onboarding_variant::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::OnboardingVariant.def()
+    }
+}
+
+impl Related<super::user_account::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserAccount.def()
+    }
+}
+
 impl Related<super::user_data::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::UserData.def()
@@ -2,10 +2,23 @@
 
 pub mod prelude;
 
+pub mod calendar_fetch;
+pub mod calendar_share;
 pub mod donation_text;
 pub mod email_properties;
+pub mod exit_code_transition;
+pub mod feature_flag;
 pub mod general_properties_db;
+pub mod invite_code;
+pub mod kuma_pending_action;
 pub mod kuma_properties;
+pub mod logbook_entry;
+pub mod onboarding_variant;
+pub mod sent_notification;
+pub mod shift_history;
+pub mod shift_note;
+pub mod short_link;
 pub mod user_account;
 pub mod user_data;
 pub mod user_properties;
+pub mod user_session;
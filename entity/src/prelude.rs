@@ -1,9 +1,22 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 2.0.0-rc.9
 
+pub use super::calendar_fetch::Entity as CalendarFetch;
+pub use super::calendar_share::Entity as CalendarShare;
 pub use super::donation_text::Entity as DonationText;
 pub use super::email_properties::Entity as EmailProperties;
+pub use super::exit_code_transition::Entity as ExitCodeTransition;
+pub use super::feature_flag::Entity as FeatureFlag;
 pub use super::general_properties_db::Entity as GeneralPropertiesDb;
+pub use super::invite_code::Entity as InviteCode;
+pub use super::kuma_pending_action::Entity as KumaPendingAction;
 pub use super::kuma_properties::Entity as KumaProperties;
+pub use super::logbook_entry::Entity as LogbookEntry;
+pub use super::onboarding_variant::Entity as OnboardingVariant;
+pub use super::sent_notification::Entity as SentNotification;
+pub use super::shift_history::Entity as ShiftHistory;
+pub use super::shift_note::Entity as ShiftNote;
+pub use super::short_link::Entity as ShortLink;
 pub use super::user_account::Entity as UserAccount;
 pub use super::user_data::Entity as UserData;
 pub use super::user_properties::Entity as UserProperties;
+pub use super::user_session::Entity as UserSession;
@@ -13,10 +13,19 @@ pub struct Model {
     pub password_hash: String,
     pub role: String,
     pub backend_user: Option<String>,
+    pub properties_group: Option<i32>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::general_properties_db::Entity",
+        from = "Column::PropertiesGroup",
+        to = "super::general_properties_db::Column::GeneralPropertiesId",
+        on_update = "Cascade",
+        on_delete = "SetNull"
+    )]
+    GeneralPropertiesDb,
     #[sea_orm(
         belongs_to = "super::user_data::Entity",
         from = "Column::BackendUser",
@@ -25,6 +34,14 @@ pub enum Relation {
         on_delete = "Cascade"
     )]
     UserData,
+    #[sea_orm(has_many = "super::user_session::Entity")]
+    UserSession,
+}
+
+impl Related<super::general_properties_db::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::GeneralPropertiesDb.def()
+    }
 }
 
 impl Related<super::user_data::Entity> for Entity {
@@ -33,4 +50,10 @@ impl Related<super::user_data::Entity> for Entity {
     }
 }
 
+impl Related<super::user_session::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserSession.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -22,10 +22,24 @@ pub struct Model {
     pub last_execution_date: Option<DateTime>,
     pub creation_date: DateTime,
     pub last_system_execution_date: Option<DateTime>,
+    pub last_calendar_write_date: Option<DateTime>,
+    pub last_donation_reminder_date: Option<DateTime>,
+    pub onboarding_variant: Option<String>,
+    pub first_calendar_fetch_date: Option<DateTime>,
+    #[sea_orm(unique)]
+    pub calendar_token: String,
+    pub mail_paused_date: Option<DateTime>,
+    pub mail_paused_reason: Option<String>,
+    pub last_weekly_summary_date: Option<DateTime>,
+    pub activated: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::calendar_fetch::Entity")]
+    CalendarFetch,
+    #[sea_orm(has_many = "super::exit_code_transition::Entity")]
+    ExitCodeTransition,
     #[sea_orm(
         belongs_to = "super::general_properties_db::Entity",
         from = "Column::CustomGeneralProperties",
@@ -34,6 +48,16 @@ pub enum Relation {
         on_delete = "Cascade"
     )]
     GeneralPropertiesDb,
+    #[sea_orm(has_many = "super::logbook_entry::Entity")]
+    LogbookEntry,
+    #[sea_orm(has_many = "super::sent_notification::Entity")]
+    SentNotification,
+    #[sea_orm(has_many = "super::shift_history::Entity")]
+    ShiftHistory,
+    #[sea_orm(has_many = "super::shift_note::Entity")]
+    ShiftNote,
+    #[sea_orm(has_many = "super::short_link::Entity")]
+    ShortLink,
     #[sea_orm(has_many = "super::user_account::Entity")]
     UserAccount,
     #[sea_orm(
@@ -46,12 +70,54 @@ pub enum Relation {
     UserProperties,
 }
 
+impl Related<super::calendar_fetch::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CalendarFetch.def()
+    }
+}
+
+impl Related<super::exit_code_transition::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ExitCodeTransition.def()
+    }
+}
+
 impl Related<super::general_properties_db::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::GeneralPropertiesDb.def()
     }
 }
 
+impl Related<super::logbook_entry::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::LogbookEntry.def()
+    }
+}
+
+impl Related<super::sent_notification::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::SentNotification.def()
+    }
+}
+
+impl Related<super::shift_history::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ShiftHistory.def()
+    }
+}
+
+impl Related<super::shift_note::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ShiftNote.def()
+    }
+}
+
+impl Related<super::short_link::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ShortLink.def()
+    }
+}
+
 impl Related<super::user_account::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::UserAccount.def()
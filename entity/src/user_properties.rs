@@ -19,6 +19,20 @@ pub struct Model {
     pub split_night_shift: bool,
     pub stop_midnight_shift: bool,
     pub auto_delete_account: bool,
+    pub max_weekly_hours: i32,
+    pub send_overtime_mail: bool,
+    pub min_rest_hours: i32,
+    pub hourly_wage_cents: i32,
+    pub suggest_swaps: bool,
+    pub show_broken_pause: bool,
+    pub send_donation_reminder: bool,
+    pub execution_retry_count: Option<i32>,
+    pub expected_execution_time_seconds: Option<i32>,
+    pub send_weekly_summary: bool,
+    pub log_level: Option<String>,
+    pub kuma_public_monitor: bool,
+    pub language: String,
+    pub kuma_notification_settings: String,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
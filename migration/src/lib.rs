@@ -10,6 +10,52 @@ mod m20251110_155639_user_account;
 mod m20251115_110830_name;
 mod m20251121_111842_account_deletion;
 mod m20260123_131720_system_restore;
+mod m20261015_090512_kuma_pending_action;
+mod m20261015_101204_logbook_entry;
+mod m20261015_104522_exit_code_transition;
+mod m20261015_113045_feature_flag;
+mod m20261015_121530_overtime;
+mod m20261015_123010_min_rest_hours;
+mod m20261015_130215_pay_rules;
+mod m20261015_133402_shift_history;
+mod m20261015_140127_shift_note;
+mod m20261015_142810_actual_shift_times;
+mod m20261015_145230_calendar_share;
+mod m20261015_151045_suggest_swaps;
+mod m20261015_153320_show_broken_pause;
+mod m20261015_155540_shift_uid;
+mod m20261015_161205_stale_calendar;
+mod m20261015_163540_donation_reminder;
+mod m20261015_165010_heartbeat_tries_exceeded;
+mod m20261015_171520_user_execution_overrides;
+mod m20261015_173045_onboarding_variants;
+mod m20261015_174510_calendar_fetch;
+mod m20261015_181205_short_link;
+mod m20261015_183020_mail_bounce;
+mod m20261015_185540_smtp_pool;
+mod m20261015_191210_sent_notification;
+mod m20261015_193045_weekly_summary;
+mod m20261015_194520_error_mail_window;
+mod m20261015_200130_logbook_ended_by;
+mod m20261015_201815_user_log_level;
+mod m20261015_203010_change_notify;
+mod m20261015_204400_kuma_pause_on_stop;
+mod m20261015_205230_kuma_monitor_name;
+mod m20261015_211845_kuma_notification_provider;
+mod m20261015_213020_kuma_status_page;
+mod m20261015_215540_invite_code;
+mod m20261015_221410_admin_scope;
+mod m20261015_223005_user_session;
+mod m20261015_231500_user_language;
+mod m20261015_234000_user_activated;
+mod m20261016_000500_sent_notification_once;
+mod m20261016_001000_kuma_notification_settings;
+mod m20261016_001500_calendar_token_default;
+mod m20261016_002000_sign_up_url_default;
+mod m20261016_002500_shift_uid_unique;
+mod m20261016_003000_logbook_ended_by_code;
+mod m20261016_003500_properties_change_notify;
+mod m20261016_004000_seed_donation_reminder;
 
 pub struct Migrator;
 
@@ -27,6 +73,52 @@ impl MigratorTrait for Migrator {
             Box::new(m20251115_110830_name::Migration),
             Box::new(m20251121_111842_account_deletion::Migration),
             Box::new(m20260123_131720_system_restore::Migration),
+            Box::new(m20261015_090512_kuma_pending_action::Migration),
+            Box::new(m20261015_101204_logbook_entry::Migration),
+            Box::new(m20261015_104522_exit_code_transition::Migration),
+            Box::new(m20261015_113045_feature_flag::Migration),
+            Box::new(m20261015_121530_overtime::Migration),
+            Box::new(m20261015_123010_min_rest_hours::Migration),
+            Box::new(m20261015_130215_pay_rules::Migration),
+            Box::new(m20261015_133402_shift_history::Migration),
+            Box::new(m20261015_140127_shift_note::Migration),
+            Box::new(m20261015_142810_actual_shift_times::Migration),
+            Box::new(m20261015_145230_calendar_share::Migration),
+            Box::new(m20261015_151045_suggest_swaps::Migration),
+            Box::new(m20261015_153320_show_broken_pause::Migration),
+            Box::new(m20261015_155540_shift_uid::Migration),
+            Box::new(m20261015_161205_stale_calendar::Migration),
+            Box::new(m20261015_163540_donation_reminder::Migration),
+            Box::new(m20261015_165010_heartbeat_tries_exceeded::Migration),
+            Box::new(m20261015_171520_user_execution_overrides::Migration),
+            Box::new(m20261015_173045_onboarding_variants::Migration),
+            Box::new(m20261015_174510_calendar_fetch::Migration),
+            Box::new(m20261015_181205_short_link::Migration),
+            Box::new(m20261015_183020_mail_bounce::Migration),
+            Box::new(m20261015_185540_smtp_pool::Migration),
+            Box::new(m20261015_191210_sent_notification::Migration),
+            Box::new(m20261015_193045_weekly_summary::Migration),
+            Box::new(m20261015_194520_error_mail_window::Migration),
+            Box::new(m20261015_200130_logbook_ended_by::Migration),
+            Box::new(m20261015_201815_user_log_level::Migration),
+            Box::new(m20261015_203010_change_notify::Migration),
+            Box::new(m20261015_204400_kuma_pause_on_stop::Migration),
+            Box::new(m20261015_205230_kuma_monitor_name::Migration),
+            Box::new(m20261015_211845_kuma_notification_provider::Migration),
+            Box::new(m20261015_213020_kuma_status_page::Migration),
+            Box::new(m20261015_215540_invite_code::Migration),
+            Box::new(m20261015_221410_admin_scope::Migration),
+            Box::new(m20261015_223005_user_session::Migration),
+            Box::new(m20261015_231500_user_language::Migration),
+            Box::new(m20261015_234000_user_activated::Migration),
+            Box::new(m20261016_000500_sent_notification_once::Migration),
+            Box::new(m20261016_001000_kuma_notification_settings::Migration),
+            Box::new(m20261016_001500_calendar_token_default::Migration),
+            Box::new(m20261016_002000_sign_up_url_default::Migration),
+            Box::new(m20261016_002500_shift_uid_unique::Migration),
+            Box::new(m20261016_003000_logbook_ended_by_code::Migration),
+            Box::new(m20261016_003500_properties_change_notify::Migration),
+            Box::new(m20261016_004000_seed_donation_reminder::Migration),
         ]
     }
 }
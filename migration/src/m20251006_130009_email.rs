@@ -36,4 +36,6 @@ pub enum EmailProperties {
     SmtpUsername,
     SmtpPassword,
     MailFrom,
+    SmtpPoolMaxSize,
+    SmtpPoolIdleTimeoutSeconds,
 }
@@ -95,4 +95,17 @@ pub enum GeneralPropertiesDB {
     DonationText,
 
     SignUpUrl,
+
+    EveningSurchargeStartHour,
+    EveningSurchargePercent,
+    WeekendSurchargePercent,
+    StaleCalendarHours,
+    DonationReminderMonths,
+    HeartbeatOnTriesExceeded,
+    ErrorMailWindowMinutes,
+    KumaPauseOnStop,
+    KumaMonitorNameTemplate,
+    KumaNotificationProvider,
+    KumaNotificationSettings,
+    KumaStatusPageUrl,
 }
@@ -50,4 +50,19 @@ pub enum UserProperties {
     StopMidnightShift,
 
     AutoDeleteAccount,
+
+    MaxWeeklyHours,
+    SendOvertimeMail,
+    MinRestHours,
+    HourlyWageCents,
+    SuggestSwaps,
+    ShowBrokenPause,
+    SendDonationReminder,
+    ExecutionRetryCount,
+    ExpectedExecutionTimeSeconds,
+    SendWeeklySummary,
+    LogLevel,
+    KumaPublicMonitor,
+    Language,
+    KumaNotificationSettings,
 }
@@ -73,4 +73,14 @@ pub enum UserData {
     LastSystemExecutionDate,
     LastSuccesfullSignInDate,
     CreationDate,
+
+    LastCalendarWriteDate,
+    LastDonationReminderDate,
+    OnboardingVariant,
+    FirstCalendarFetchDate,
+    CalendarToken,
+    MailPausedDate,
+    MailPausedReason,
+    LastWeeklySummaryDate,
+    Activated,
 }
@@ -43,11 +43,12 @@ impl MigrationTrait for Migration {
 }
 
 #[derive(DeriveIden)]
-enum UserAccount {
+pub enum UserAccount {
     Table,
     AccountId,
     Username,
     PasswordHash,
     Role,
     BackendUser,
+    PropertiesGroup,
 }
@@ -0,0 +1,41 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(KumaPendingAction::Table)
+                    .if_not_exists()
+                    .col(pk_auto(KumaPendingAction::PendingActionId))
+                    .col(string(KumaPendingAction::UserName))
+                    .col(string(KumaPendingAction::Action))
+                    .col(integer(KumaPendingAction::Attempts).default(0))
+                    .col(timestamp(KumaPendingAction::NextAttempt))
+                    .col(timestamp(KumaPendingAction::CreationDate))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(KumaPendingAction::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum KumaPendingAction {
+    Table,
+    PendingActionId,
+    UserName,
+    Action,
+    Attempts,
+    NextAttempt,
+    CreationDate,
+}
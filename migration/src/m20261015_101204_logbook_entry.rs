@@ -0,0 +1,75 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::m20251008_194417_user_data::UserData;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(LogbookEntry::Table)
+                    .if_not_exists()
+                    .col(pk_auto(LogbookEntry::LogbookEntryId))
+                    .col(integer(LogbookEntry::UserDataId).not_null())
+                    .col(timestamp(LogbookEntry::ExecutionTimestamp))
+                    .col(text(LogbookEntry::State))
+                    .col(big_integer(LogbookEntry::RepeatCount))
+                    .col(big_integer(LogbookEntry::ExecutionTimeMs))
+                    .col(big_integer(LogbookEntry::Shifts))
+                    .col(big_integer(LogbookEntry::BrokenShifts))
+                    .col(big_integer(LogbookEntry::NonRelevantShifts))
+                    .col(big_integer(LogbookEntry::FailedShifts))
+                    .col(big_integer(LogbookEntry::FailedBrokenShifts))
+                    .col(string(LogbookEntry::CalendarVersion))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("logbook_user_data_fk")
+                            .from(LogbookEntry::Table, LogbookEntry::UserDataId)
+                            .to(UserData::Table, UserData::UserDataId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("logbook_user_timestamp_idx")
+                    .table(LogbookEntry::Table)
+                    .col(LogbookEntry::UserDataId)
+                    .col(LogbookEntry::ExecutionTimestamp)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(LogbookEntry::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum LogbookEntry {
+    Table,
+    LogbookEntryId,
+    UserDataId,
+    ExecutionTimestamp,
+    State,
+    RepeatCount,
+    ExecutionTimeMs,
+    Shifts,
+    BrokenShifts,
+    NonRelevantShifts,
+    FailedShifts,
+    FailedBrokenShifts,
+    CalendarVersion,
+    ChangedShifts,
+    EndedBy,
+}
@@ -0,0 +1,49 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::m20251008_194417_user_data::UserData;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ExitCodeTransition::Table)
+                    .if_not_exists()
+                    .col(pk_auto(ExitCodeTransition::ExitCodeTransitionId))
+                    .col(integer(ExitCodeTransition::UserDataId).not_null())
+                    .col(text(ExitCodeTransition::PreviousState))
+                    .col(text(ExitCodeTransition::NewState))
+                    .col(timestamp(ExitCodeTransition::TransitionDate))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("exit_code_user_data_fk")
+                            .from(ExitCodeTransition::Table, ExitCodeTransition::UserDataId)
+                            .to(UserData::Table, UserData::UserDataId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ExitCodeTransition::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum ExitCodeTransition {
+    Table,
+    ExitCodeTransitionId,
+    UserDataId,
+    PreviousState,
+    NewState,
+    TransitionDate,
+}
@@ -0,0 +1,57 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::m20251006_143409_general_settings::GeneralPropertiesDB;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(FeatureFlag::Table)
+                    .if_not_exists()
+                    .col(pk_auto(FeatureFlag::FeatureFlagId))
+                    .col(integer(FeatureFlag::GeneralPropertiesId).not_null())
+                    .col(string(FeatureFlag::FlagName))
+                    .col(boolean(FeatureFlag::Enabled).default(false))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("feature_flag_properties_fk")
+                            .from(FeatureFlag::Table, FeatureFlag::GeneralPropertiesId)
+                            .to(
+                                GeneralPropertiesDB::Table,
+                                GeneralPropertiesDB::GeneralPropertiesId,
+                            )
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .index(
+                        Index::create()
+                            .name("feature_flag_unique_idx")
+                            .col(FeatureFlag::GeneralPropertiesId)
+                            .col(FeatureFlag::FlagName)
+                            .unique(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(FeatureFlag::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum FeatureFlag {
+    Table,
+    FeatureFlagId,
+    GeneralPropertiesId,
+    FlagName,
+    Enabled,
+}
@@ -0,0 +1,47 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20251008_194017_user_settings::UserProperties;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserProperties::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            UserProperties::MaxWeeklyHours,
+                            ColumnType::Integer,
+                        )
+                        .not_null()
+                        .default(48),
+                    )
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            UserProperties::SendOvertimeMail,
+                            ColumnType::Boolean,
+                        )
+                        .not_null()
+                        .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserProperties::Table)
+                    .drop_column(UserProperties::MaxWeeklyHours)
+                    .drop_column(UserProperties::SendOvertimeMail)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,35 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20251008_194017_user_settings::UserProperties;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserProperties::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(UserProperties::MinRestHours, ColumnType::Integer)
+                            .not_null()
+                            .default(11),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserProperties::Table)
+                    .drop_column(UserProperties::MinRestHours)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,84 @@
+use sea_orm_migration::prelude::*;
+
+use crate::{
+    m20251006_143409_general_settings::GeneralPropertiesDB,
+    m20251008_194017_user_settings::UserProperties,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserProperties::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            UserProperties::HourlyWageCents,
+                            ColumnType::Integer,
+                        )
+                        .not_null()
+                        .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(GeneralPropertiesDB::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            GeneralPropertiesDB::EveningSurchargeStartHour,
+                            ColumnType::Integer,
+                        )
+                        .not_null()
+                        .default(19),
+                    )
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            GeneralPropertiesDB::EveningSurchargePercent,
+                            ColumnType::Integer,
+                        )
+                        .not_null()
+                        .default(0),
+                    )
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            GeneralPropertiesDB::WeekendSurchargePercent,
+                            ColumnType::Integer,
+                        )
+                        .not_null()
+                        .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(GeneralPropertiesDB::Table)
+                    .drop_column(GeneralPropertiesDB::EveningSurchargeStartHour)
+                    .drop_column(GeneralPropertiesDB::EveningSurchargePercent)
+                    .drop_column(GeneralPropertiesDB::WeekendSurchargePercent)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserProperties::Table)
+                    .drop_column(UserProperties::HourlyWageCents)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,86 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::m20251008_194417_user_data::UserData;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ShiftHistory::Table)
+                    .if_not_exists()
+                    .col(pk_auto(ShiftHistory::ShiftHistoryId))
+                    .col(integer(ShiftHistory::UserDataId).not_null())
+                    .col(big_integer(ShiftHistory::MagicNumber))
+                    .col(string(ShiftHistory::Number))
+                    .col(string(ShiftHistory::Kind))
+                    .col(date(ShiftHistory::ShiftDate))
+                    .col(time(ShiftHistory::StartTime))
+                    .col(date(ShiftHistory::EndDate))
+                    .col(time(ShiftHistory::EndTime))
+                    .col(big_integer(ShiftHistory::DurationMinutes))
+                    .col(big_integer(ShiftHistory::WorkingMinutes))
+                    .col(string(ShiftHistory::State))
+                    .col(timestamp(ShiftHistory::LastSeen))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("shift_history_user_data_fk")
+                            .from(ShiftHistory::Table, ShiftHistory::UserDataId)
+                            .to(UserData::Table, UserData::UserDataId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .index(
+                        Index::create()
+                            .name("shift_history_unique_idx")
+                            .col(ShiftHistory::UserDataId)
+                            .col(ShiftHistory::MagicNumber)
+                            .unique(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("shift_history_user_date_idx")
+                    .table(ShiftHistory::Table)
+                    .col(ShiftHistory::UserDataId)
+                    .col(ShiftHistory::ShiftDate)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ShiftHistory::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum ShiftHistory {
+    Table,
+    ShiftHistoryId,
+    UserDataId,
+    MagicNumber,
+    Number,
+    Kind,
+    ShiftDate,
+    StartTime,
+    EndDate,
+    EndTime,
+    DurationMinutes,
+    WorkingMinutes,
+    State,
+    LastSeen,
+
+    ActualStartTime,
+    ActualEndTime,
+    ShiftUid,
+}
@@ -0,0 +1,58 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::m20251008_194417_user_data::UserData;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ShiftNote::Table)
+                    .if_not_exists()
+                    .col(pk_auto(ShiftNote::ShiftNoteId))
+                    .col(integer(ShiftNote::UserDataId).not_null())
+                    .col(big_integer(ShiftNote::MagicNumber))
+                    .col(text(ShiftNote::Note))
+                    .col(text(ShiftNote::Tags))
+                    .col(timestamp(ShiftNote::UpdatedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("shift_note_user_data_fk")
+                            .from(ShiftNote::Table, ShiftNote::UserDataId)
+                            .to(UserData::Table, UserData::UserDataId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .index(
+                        Index::create()
+                            .name("shift_note_unique_idx")
+                            .col(ShiftNote::UserDataId)
+                            .col(ShiftNote::MagicNumber)
+                            .unique(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ShiftNote::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum ShiftNote {
+    Table,
+    ShiftNoteId,
+    UserDataId,
+    MagicNumber,
+    Note,
+    Tags,
+    UpdatedAt,
+}
@@ -0,0 +1,62 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::m20251008_194417_user_data::UserData;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(CalendarShare::Table)
+                    .if_not_exists()
+                    .col(pk_auto(CalendarShare::CalendarShareId))
+                    .col(integer(CalendarShare::OwnerUserDataId).not_null())
+                    .col(integer(CalendarShare::ViewerUserDataId).not_null())
+                    .col(timestamp(CalendarShare::CreatedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("calendar_share_owner_fk")
+                            .from(CalendarShare::Table, CalendarShare::OwnerUserDataId)
+                            .to(UserData::Table, UserData::UserDataId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("calendar_share_viewer_fk")
+                            .from(CalendarShare::Table, CalendarShare::ViewerUserDataId)
+                            .to(UserData::Table, UserData::UserDataId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .index(
+                        Index::create()
+                            .name("calendar_share_unique_idx")
+                            .col(CalendarShare::OwnerUserDataId)
+                            .col(CalendarShare::ViewerUserDataId)
+                            .unique(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CalendarShare::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum CalendarShare {
+    Table,
+    CalendarShareId,
+    OwnerUserDataId,
+    ViewerUserDataId,
+    CreatedAt,
+}
@@ -0,0 +1,38 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20261015_133402_shift_history::ShiftHistory;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ShiftHistory::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            ShiftHistory::ShiftUid,
+                            ColumnType::String(StringLen::None),
+                        )
+                        .not_null()
+                        .default(""),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ShiftHistory::Table)
+                    .drop_column(ShiftHistory::ShiftUid)
+                    .to_owned(),
+            )
+            .await
+    }
+}
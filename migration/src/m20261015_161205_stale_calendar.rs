@@ -0,0 +1,64 @@
+use sea_orm_migration::prelude::*;
+
+use crate::{
+    m20251006_143409_general_settings::GeneralPropertiesDB, m20251008_194417_user_data::UserData,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserData::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            UserData::LastCalendarWriteDate,
+                            ColumnType::DateTime,
+                        )
+                        .null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(GeneralPropertiesDB::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            GeneralPropertiesDB::StaleCalendarHours,
+                            ColumnType::Integer,
+                        )
+                        .not_null()
+                        .default(48),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserData::Table)
+                    .drop_column(UserData::LastCalendarWriteDate)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(GeneralPropertiesDB::Table)
+                    .drop_column(GeneralPropertiesDB::StaleCalendarHours)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,91 @@
+use sea_orm_migration::prelude::*;
+
+use crate::{
+    m20251006_143409_general_settings::GeneralPropertiesDB,
+    m20251008_194017_user_settings::UserProperties, m20251008_194417_user_data::UserData,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserProperties::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            UserProperties::SendDonationReminder,
+                            ColumnType::Boolean,
+                        )
+                        .not_null()
+                        .default(true),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserData::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            UserData::LastDonationReminderDate,
+                            ColumnType::DateTime,
+                        )
+                        .null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // 0 disables the reminder
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(GeneralPropertiesDB::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            GeneralPropertiesDB::DonationReminderMonths,
+                            ColumnType::Integer,
+                        )
+                        .not_null()
+                        .default(6),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserProperties::Table)
+                    .drop_column(UserProperties::SendDonationReminder)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserData::Table)
+                    .drop_column(UserData::LastDonationReminderDate)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(GeneralPropertiesDB::Table)
+                    .drop_column(GeneralPropertiesDB::DonationReminderMonths)
+                    .to_owned(),
+            )
+            .await
+    }
+}
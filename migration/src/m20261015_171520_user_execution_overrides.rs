@@ -0,0 +1,46 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20251008_194017_user_settings::UserProperties;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Null means the value of the general properties is used
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserProperties::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            UserProperties::ExecutionRetryCount,
+                            ColumnType::Integer,
+                        )
+                        .null(),
+                    )
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            UserProperties::ExpectedExecutionTimeSeconds,
+                            ColumnType::Integer,
+                        )
+                        .null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserProperties::Table)
+                    .drop_column(UserProperties::ExecutionRetryCount)
+                    .drop_column(UserProperties::ExpectedExecutionTimeSeconds)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,86 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::{
+    m20251006_143409_general_settings::GeneralPropertiesDB, m20251008_194417_user_data::UserData,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(OnboardingVariant::Table)
+                    .if_not_exists()
+                    .col(pk_auto(OnboardingVariant::OnboardingVariantId))
+                    .col(integer(OnboardingVariant::GeneralPropertiesId).not_null())
+                    .col(string(OnboardingVariant::Template))
+                    .col(integer(OnboardingVariant::Weight).default(1))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("onboarding_variant_properties_fk")
+                            .from(
+                                OnboardingVariant::Table,
+                                OnboardingVariant::GeneralPropertiesId,
+                            )
+                            .to(
+                                GeneralPropertiesDB::Table,
+                                GeneralPropertiesDB::GeneralPropertiesId,
+                            )
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .index(
+                        Index::create()
+                            .name("onboarding_variant_unique_idx")
+                            .col(OnboardingVariant::GeneralPropertiesId)
+                            .col(OnboardingVariant::Template)
+                            .unique(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserData::Table)
+                    .add_column(ColumnDef::new(UserData::OnboardingVariant).string().null())
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            UserData::FirstCalendarFetchDate,
+                            ColumnType::DateTime,
+                        )
+                        .null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserData::Table)
+                    .drop_column(UserData::OnboardingVariant)
+                    .drop_column(UserData::FirstCalendarFetchDate)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(OnboardingVariant::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum OnboardingVariant {
+    Table,
+    OnboardingVariantId,
+    GeneralPropertiesId,
+    Template,
+    Weight,
+}
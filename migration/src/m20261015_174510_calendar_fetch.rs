@@ -0,0 +1,76 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::m20251008_194417_user_data::UserData;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The default is evaluated for every row, so existing users get their own token as well
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserData::Table)
+                    .add_column(
+                        ColumnDef::new(UserData::CalendarToken)
+                            .string()
+                            .not_null()
+                            .unique_key()
+                            .default(Expr::cust("md5(random()::text || clock_timestamp()::text)")),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(CalendarFetch::Table)
+                    .if_not_exists()
+                    .col(pk_auto(CalendarFetch::CalendarFetchId))
+                    .col(integer(CalendarFetch::UserDataId).not_null())
+                    .col(string_null(CalendarFetch::UserAgent))
+                    .col(timestamp(CalendarFetch::FetchedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("calendar_fetch_user_fk")
+                            .from(CalendarFetch::Table, CalendarFetch::UserDataId)
+                            .to(UserData::Table, UserData::UserDataId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .index(
+                        Index::create()
+                            .name("calendar_fetch_user_idx")
+                            .col(CalendarFetch::UserDataId)
+                            .col(CalendarFetch::FetchedAt),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CalendarFetch::Table).to_owned())
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserData::Table)
+                    .drop_column(UserData::CalendarToken)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum CalendarFetch {
+    Table,
+    CalendarFetchId,
+    UserDataId,
+    UserAgent,
+    FetchedAt,
+}
@@ -0,0 +1,59 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::m20251008_194417_user_data::UserData;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ShortLink::Table)
+                    .if_not_exists()
+                    .col(pk_auto(ShortLink::ShortLinkId))
+                    .col(string(ShortLink::Token).unique_key())
+                    .col(text(ShortLink::Target))
+                    .col(integer_null(ShortLink::UserDataId))
+                    .col(integer(ShortLink::Clicks).default(0))
+                    .col(timestamp(ShortLink::CreatedAt))
+                    .col(timestamp_null(ShortLink::LastClickedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("short_link_user_fk")
+                            .from(ShortLink::Table, ShortLink::UserDataId)
+                            .to(UserData::Table, UserData::UserDataId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .index(
+                        Index::create()
+                            .name("short_link_target_idx")
+                            .col(ShortLink::UserDataId)
+                            .col(ShortLink::Target),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ShortLink::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum ShortLink {
+    Table,
+    ShortLinkId,
+    Token,
+    Target,
+    UserDataId,
+    Clicks,
+    CreatedAt,
+    LastClickedAt,
+}
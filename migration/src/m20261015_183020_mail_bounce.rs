@@ -0,0 +1,43 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20251008_194417_user_data::UserData;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Set once a mail to the user bounced or was marked as spam, no mails are sent to the user until it is cleared
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserData::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(UserData::MailPausedDate, ColumnType::DateTime)
+                            .null(),
+                    )
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            UserData::MailPausedReason,
+                            ColumnType::String(StringLen::None),
+                        )
+                        .null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserData::Table)
+                    .drop_column(UserData::MailPausedDate)
+                    .drop_column(UserData::MailPausedReason)
+                    .to_owned(),
+            )
+            .await
+    }
+}
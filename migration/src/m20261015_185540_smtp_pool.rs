@@ -0,0 +1,47 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20251006_130009_email::EmailProperties;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(EmailProperties::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            EmailProperties::SmtpPoolMaxSize,
+                            ColumnType::Integer,
+                        )
+                        .not_null()
+                        .default(4),
+                    )
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            EmailProperties::SmtpPoolIdleTimeoutSeconds,
+                            ColumnType::Integer,
+                        )
+                        .not_null()
+                        .default(60),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(EmailProperties::Table)
+                    .drop_column(EmailProperties::SmtpPoolMaxSize)
+                    .drop_column(EmailProperties::SmtpPoolIdleTimeoutSeconds)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,61 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::m20251008_194417_user_data::UserData;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(SentNotification::Table)
+                    .if_not_exists()
+                    .col(pk_auto(SentNotification::SentNotificationId))
+                    .col(integer(SentNotification::UserDataId).not_null())
+                    .col(string(SentNotification::ShiftUid))
+                    .col(big_integer(SentNotification::MagicNumber))
+                    .col(string(SentNotification::ChangeType))
+                    .col(date(SentNotification::SentOn))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("sent_notification_user_fk")
+                            .from(SentNotification::Table, SentNotification::UserDataId)
+                            .to(UserData::Table, UserData::UserDataId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .index(
+                        Index::create()
+                            .name("sent_notification_unique_idx")
+                            .unique()
+                            .col(SentNotification::UserDataId)
+                            .col(SentNotification::ShiftUid)
+                            .col(SentNotification::MagicNumber)
+                            .col(SentNotification::ChangeType)
+                            .col(SentNotification::SentOn),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(SentNotification::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum SentNotification {
+    Table,
+    SentNotificationId,
+    UserDataId,
+    ShiftUid,
+    MagicNumber,
+    ChangeType,
+    SentOn,
+}
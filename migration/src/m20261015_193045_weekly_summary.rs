@@ -0,0 +1,92 @@
+use sea_orm_migration::prelude::*;
+
+use crate::{
+    m20251008_194017_user_settings::UserProperties, m20251008_194417_user_data::UserData,
+    m20261015_101204_logbook_entry::LogbookEntry,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Opt-in, most users only want mails when something changes
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserProperties::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            UserProperties::SendWeeklySummary,
+                            ColumnType::Boolean,
+                        )
+                        .not_null()
+                        .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserData::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            UserData::LastWeeklySummaryDate,
+                            ColumnType::DateTime,
+                        )
+                        .null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // The number of new, changed and removed shifts found by the run
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(LogbookEntry::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            LogbookEntry::ChangedShifts,
+                            ColumnType::BigInteger,
+                        )
+                        .not_null()
+                        .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserProperties::Table)
+                    .drop_column(UserProperties::SendWeeklySummary)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserData::Table)
+                    .drop_column(UserData::LastWeeklySummaryDate)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(LogbookEntry::Table)
+                    .drop_column(LogbookEntry::ChangedShifts)
+                    .to_owned(),
+            )
+            .await
+    }
+}
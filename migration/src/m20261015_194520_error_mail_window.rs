@@ -0,0 +1,39 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20251006_143409_general_settings::GeneralPropertiesDB;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // At most one error mail to the support address per window, 0 sends every error right away
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(GeneralPropertiesDB::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            GeneralPropertiesDB::ErrorMailWindowMinutes,
+                            ColumnType::Integer,
+                        )
+                        .not_null()
+                        .default(60),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(GeneralPropertiesDB::Table)
+                    .drop_column(GeneralPropertiesDB::ErrorMailWindowMinutes)
+                    .to_owned(),
+            )
+            .await
+    }
+}
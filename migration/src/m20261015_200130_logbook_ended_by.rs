@@ -0,0 +1,34 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20261015_101204_logbook_entry::LogbookEntry;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The category of the error which ended a failed run, empty for successful runs
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(LogbookEntry::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(LogbookEntry::EndedBy, ColumnType::Text).null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(LogbookEntry::Table)
+                    .drop_column(LogbookEntry::EndedBy)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,74 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/*
+Lets the watchdog know right away when a user or properties change, using Postgres NOTIFY on the bussie_changes channel
+Changes to users send "user:<user_name>", changes to properties send "properties"
+Only the columns which are settings fire the trigger, the dates every run writes would otherwise refresh the user after every run
+*/
+const UP: &str = r#"
+CREATE OR REPLACE FUNCTION notify_user_change() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        PERFORM pg_notify('bussie_changes', 'user:' || OLD.user_name);
+    ELSE
+        PERFORM pg_notify('bussie_changes', 'user:' || NEW.user_name);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION notify_user_properties_change() RETURNS trigger AS $$
+DECLARE
+    changed_user TEXT;
+BEGIN
+    FOR changed_user IN SELECT user_name FROM user_data WHERE user_properties = NEW.user_properties_id LOOP
+        PERFORM pg_notify('bussie_changes', 'user:' || changed_user);
+    END LOOP;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION notify_properties_change() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('bussie_changes', 'properties');
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER user_data_change
+    AFTER INSERT OR DELETE OR UPDATE OF user_name, personeelsnummer, password, email, file_name, user_properties, custom_general_properties, name
+    ON user_data FOR EACH ROW EXECUTE FUNCTION notify_user_change();
+
+CREATE TRIGGER user_properties_change
+    AFTER UPDATE ON user_properties
+    FOR EACH ROW WHEN (OLD.* IS DISTINCT FROM NEW.*) EXECUTE FUNCTION notify_user_properties_change();
+
+CREATE TRIGGER general_properties_change
+    AFTER INSERT OR UPDATE OR DELETE ON general_properties_db
+    FOR EACH STATEMENT EXECUTE FUNCTION notify_properties_change();
+"#;
+
+const DOWN: &str = r#"
+DROP TRIGGER IF EXISTS user_data_change ON user_data;
+DROP TRIGGER IF EXISTS user_properties_change ON user_properties;
+DROP TRIGGER IF EXISTS general_properties_change ON general_properties_db;
+DROP FUNCTION IF EXISTS notify_user_change();
+DROP FUNCTION IF EXISTS notify_user_properties_change();
+DROP FUNCTION IF EXISTS notify_properties_change();
+"#;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.get_connection().execute_unprepared(UP).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.get_connection().execute_unprepared(DOWN).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,39 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20251006_143409_general_settings::GeneralPropertiesDB;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Pause the monitor of a stopped user instead of deleting it, so its uptime history is kept
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(GeneralPropertiesDB::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            GeneralPropertiesDB::KumaPauseOnStop,
+                            ColumnType::Boolean,
+                        )
+                        .not_null()
+                        .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(GeneralPropertiesDB::Table)
+                    .drop_column(GeneralPropertiesDB::KumaPauseOnStop)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,39 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20251006_143409_general_settings::GeneralPropertiesDB;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The name of the Kuma monitor of a user, {app}, {group} and {user} are filled in
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(GeneralPropertiesDB::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            GeneralPropertiesDB::KumaMonitorNameTemplate,
+                            ColumnType::String(StringLen::None),
+                        )
+                        .not_null()
+                        .default("{user}"),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(GeneralPropertiesDB::Table)
+                    .drop_column(GeneralPropertiesDB::KumaMonitorNameTemplate)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,65 @@
+use sea_orm_migration::prelude::*;
+
+use crate::{
+    m20251006_143409_general_settings::GeneralPropertiesDB,
+    m20251008_194017_user_settings::UserProperties,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The public URL of the Kuma status page, written by the application once the page exists
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(GeneralPropertiesDB::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            GeneralPropertiesDB::KumaStatusPageUrl,
+                            ColumnType::String(StringLen::None),
+                        )
+                        .null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        // Whether the monitor of the user is shown on the status page
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserProperties::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            UserProperties::KumaPublicMonitor,
+                            ColumnType::Boolean,
+                        )
+                        .not_null()
+                        .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserProperties::Table)
+                    .drop_column(UserProperties::KumaPublicMonitor)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(GeneralPropertiesDB::Table)
+                    .drop_column(GeneralPropertiesDB::KumaStatusPageUrl)
+                    .to_owned(),
+            )
+            .await
+    }
+}
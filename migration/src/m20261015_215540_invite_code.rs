@@ -0,0 +1,57 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::m20251006_143409_general_settings::GeneralPropertiesDB;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(InviteCode::Table)
+                    .if_not_exists()
+                    .col(pk_auto(InviteCode::InviteCodeId))
+                    .col(string(InviteCode::Code).unique_key())
+                    .col(integer(InviteCode::GeneralPropertiesId))
+                    .col(timestamp(InviteCode::ExpiresAt))
+                    .col(timestamp(InviteCode::CreatedAt))
+                    // The user name is kept instead of a reference, so it is still known after the account is deleted
+                    .col(string_null(InviteCode::RedeemedBy))
+                    .col(timestamp_null(InviteCode::RedeemedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("invite_code_properties_fk")
+                            .from(InviteCode::Table, InviteCode::GeneralPropertiesId)
+                            .to(
+                                GeneralPropertiesDB::Table,
+                                GeneralPropertiesDB::GeneralPropertiesId,
+                            )
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(InviteCode::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum InviteCode {
+    Table,
+    InviteCodeId,
+    Code,
+    GeneralPropertiesId,
+    ExpiresAt,
+    CreatedAt,
+    RedeemedBy,
+    RedeemedAt,
+}
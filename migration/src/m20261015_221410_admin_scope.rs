@@ -0,0 +1,50 @@
+use sea_orm_migration::prelude::*;
+
+use crate::{
+    m20251006_143409_general_settings::GeneralPropertiesDB,
+    m20251110_155639_user_account::UserAccount,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Admins with a properties group only manage the users of that group, without one they manage everybody
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserAccount::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(UserAccount::PropertiesGroup, ColumnType::Integer)
+                            .null(),
+                    )
+                    .add_foreign_key(
+                        &TableForeignKey::new()
+                            .name("user_account_properties_fk")
+                            .from_tbl(UserAccount::Table)
+                            .from_col(UserAccount::PropertiesGroup)
+                            .to_tbl(GeneralPropertiesDB::Table)
+                            .to_col(GeneralPropertiesDB::GeneralPropertiesId)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .to_owned(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserAccount::Table)
+                    .drop_foreign_key(Alias::new("user_account_properties_fk"))
+                    .drop_column(UserAccount::PropertiesGroup)
+                    .to_owned(),
+            )
+            .await
+    }
+}
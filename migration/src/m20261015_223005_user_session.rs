@@ -0,0 +1,59 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::m20251110_155639_user_account::UserAccount;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Only hashes of the tokens are stored, so a leaked database can't be used to sign in
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserSession::Table)
+                    .if_not_exists()
+                    .col(pk_auto(UserSession::SessionId))
+                    .col(integer(UserSession::AccountId))
+                    .col(string(UserSession::AccessTokenHash).unique_key())
+                    .col(timestamp(UserSession::AccessExpiresAt))
+                    .col(string(UserSession::RefreshTokenHash).unique_key())
+                    .col(timestamp(UserSession::RefreshExpiresAt))
+                    // The refresh token that was rotated out last, using it again means it was stolen
+                    .col(string_null(UserSession::PreviousRefreshHash))
+                    .col(timestamp(UserSession::CreatedAt))
+                    .col(timestamp_null(UserSession::RevokedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("user_session_account_fk")
+                            .from(UserSession::Table, UserSession::AccountId)
+                            .to(UserAccount::Table, UserAccount::AccountId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UserSession::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum UserSession {
+    Table,
+    SessionId,
+    AccountId,
+    AccessTokenHash,
+    AccessExpiresAt,
+    RefreshTokenHash,
+    RefreshExpiresAt,
+    PreviousRefreshHash,
+    CreatedAt,
+    RevokedAt,
+}
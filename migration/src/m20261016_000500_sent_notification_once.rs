@@ -0,0 +1,77 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20261015_191210_sent_notification::SentNotification;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+const OLD_INDEX: &str = "sent_notification_unique_idx";
+const NEW_INDEX: &str = "sent_notification_once_idx";
+
+/*
+A change is only mailed once, no longer once per day. The day was part of the key, so a run
+just after midnight mailed the same change again
+*/
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name(OLD_INDEX)
+                    .table(SentNotification::Table)
+                    .to_owned(),
+            )
+            .await?;
+        // Only the newest record of a change is kept, the others would break the new index
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "DELETE FROM sent_notification older USING sent_notification newer
+                WHERE older.user_data_id = newer.user_data_id
+                AND older.shift_uid = newer.shift_uid
+                AND older.magic_number = newer.magic_number
+                AND older.change_type = newer.change_type
+                AND older.sent_notification_id < newer.sent_notification_id",
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name(NEW_INDEX)
+                    .table(SentNotification::Table)
+                    .unique()
+                    .col(SentNotification::UserDataId)
+                    .col(SentNotification::ShiftUid)
+                    .col(SentNotification::MagicNumber)
+                    .col(SentNotification::ChangeType)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name(NEW_INDEX)
+                    .table(SentNotification::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name(OLD_INDEX)
+                    .table(SentNotification::Table)
+                    .unique()
+                    .col(SentNotification::UserDataId)
+                    .col(SentNotification::ShiftUid)
+                    .col(SentNotification::MagicNumber)
+                    .col(SentNotification::ChangeType)
+                    .col(SentNotification::SentOn)
+                    .to_owned(),
+            )
+            .await
+    }
+}
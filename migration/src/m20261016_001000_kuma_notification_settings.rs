@@ -0,0 +1,83 @@
+use sea_orm_migration::prelude::*;
+
+use crate::{
+    m20251006_143409_general_settings::GeneralPropertiesDB,
+    m20251008_194017_user_settings::UserProperties,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+const PROVIDER_CHECK: &str = "kuma_notification_provider_check";
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Values for the Kuma notification template, a JSON object like {"telegram_bot_token": "..."}
+        // The group has the shared ones like a bot token, the values of a user are added on top, like their chat id
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(GeneralPropertiesDB::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            GeneralPropertiesDB::KumaNotificationSettings,
+                            ColumnType::String(StringLen::None),
+                        )
+                        .not_null()
+                        .default("{}"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserProperties::Table)
+                    .add_column(
+                        ColumnDef::new_with_type(
+                            UserProperties::KumaNotificationSettings,
+                            ColumnType::String(StringLen::None),
+                        )
+                        .not_null()
+                        .default("{}"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        // The provider is changed in the database directly, a typo would otherwise only show up in Kuma
+        manager
+            .get_connection()
+            .execute_unprepared(&format!(
+                "ALTER TABLE general_properties_db ADD CONSTRAINT {PROVIDER_CHECK}
+                CHECK (kuma_notification_provider IN ('smtp', 'telegram', 'ntfy', 'webhook'))"
+            ))
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(&format!(
+                "ALTER TABLE general_properties_db DROP CONSTRAINT {PROVIDER_CHECK}"
+            ))
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserProperties::Table)
+                    .drop_column(UserProperties::KumaNotificationSettings)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(GeneralPropertiesDB::Table)
+                    .drop_column(GeneralPropertiesDB::KumaNotificationSettings)
+                    .to_owned(),
+            )
+            .await
+    }
+}
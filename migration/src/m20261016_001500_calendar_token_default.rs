@@ -0,0 +1,40 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20251008_194417_user_data::UserData;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/*
+random() is not meant for secrets, the calendar token of new users now comes from gen_random_uuid which is
+cryptographically random. Existing tokens are kept so subscribed calendars keep working, they can be rotated by an admin
+*/
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserData::Table)
+                    .modify_column(ColumnDef::new(UserData::CalendarToken).default(Expr::cust(
+                        "replace(gen_random_uuid()::text || gen_random_uuid()::text, '-', '')",
+                    )))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserData::Table)
+                    .modify_column(
+                        ColumnDef::new(UserData::CalendarToken)
+                            .default(Expr::cust("md5(random()::text || clock_timestamp()::text)")),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+}
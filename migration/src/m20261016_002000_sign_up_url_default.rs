@@ -0,0 +1,48 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20251006_143409_general_settings::GeneralPropertiesDB;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+const LEGACY_SIGN_UP_URL: &str = "https://link.bussie.app/Aanmelden";
+const SIGN_UP_URL: &str = "https://bussie.app/Aanmelden";
+
+/*
+The sign up link in mails is shortened by the API itself now, so it no longer goes through link.bussie.app
+Properties which still have the old default are moved along, a custom sign up url is left alone
+*/
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        replace_sign_up_url(manager, LEGACY_SIGN_UP_URL, SIGN_UP_URL).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        replace_sign_up_url(manager, SIGN_UP_URL, LEGACY_SIGN_UP_URL).await
+    }
+}
+
+async fn replace_sign_up_url(
+    manager: &SchemaManager<'_>,
+    from: &str,
+    to: &str,
+) -> Result<(), DbErr> {
+    manager
+        .alter_table(
+            Table::alter()
+                .table(GeneralPropertiesDB::Table)
+                .modify_column(ColumnDef::new(GeneralPropertiesDB::SignUpUrl).default(to))
+                .to_owned(),
+        )
+        .await?;
+    manager
+        .exec_stmt(
+            Query::update()
+                .table(GeneralPropertiesDB::Table)
+                .value(GeneralPropertiesDB::SignUpUrl, to)
+                .and_where(Expr::col(GeneralPropertiesDB::SignUpUrl).eq(from))
+                .to_owned(),
+        )
+        .await
+}
@@ -0,0 +1,81 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20261015_133402_shift_history::ShiftHistory;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+const OLD_INDEX: &str = "shift_history_unique_idx";
+const NEW_INDEX: &str = "shift_history_uid_idx";
+
+/*
+A shift is one row in the history, identified by its uid instead of its magic number
+Before this a changed shift got a new row, because the magic number changes with the times
+*/
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name(OLD_INDEX)
+                    .table(ShiftHistory::Table)
+                    .to_owned(),
+            )
+            .await?;
+        let connection = manager.get_connection();
+        // Rows from before the uid existed get the uid the application would give them
+        connection
+            .execute_unprepared(
+                "UPDATE shift_history SET shift_uid = numbered.uid FROM (
+                    SELECT shift_history_id, shift_date::text || '-' || number || '-' ||
+                        (row_number() OVER (PARTITION BY user_data_id, shift_date, number ORDER BY start_time) - 1) AS uid
+                    FROM shift_history WHERE shift_uid = ''
+                ) numbered
+                WHERE shift_history.shift_history_id = numbered.shift_history_id",
+            )
+            .await?;
+        // Only the last seen version of a shift is kept, the others would break the new index
+        connection
+            .execute_unprepared(
+                "DELETE FROM shift_history older USING shift_history newer
+                WHERE older.user_data_id = newer.user_data_id
+                AND older.shift_uid = newer.shift_uid
+                AND (older.last_seen, older.shift_history_id) < (newer.last_seen, newer.shift_history_id)",
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name(NEW_INDEX)
+                    .table(ShiftHistory::Table)
+                    .unique()
+                    .col(ShiftHistory::UserDataId)
+                    .col(ShiftHistory::ShiftUid)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name(NEW_INDEX)
+                    .table(ShiftHistory::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name(OLD_INDEX)
+                    .table(ShiftHistory::Table)
+                    .unique()
+                    .col(ShiftHistory::UserDataId)
+                    .col(ShiftHistory::MagicNumber)
+                    .to_owned(),
+            )
+            .await
+    }
+}
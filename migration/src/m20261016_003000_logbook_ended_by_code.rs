@@ -0,0 +1,28 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// The error category which ended a run used to be stored as a JSON string, now it is only the code
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"UPDATE logbook_entry SET ended_by = trim(both '"' from ended_by) WHERE ended_by LIKE '"%'"#,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"UPDATE logbook_entry SET ended_by = '"' || ended_by || '"' WHERE ended_by NOT LIKE '"%'"#,
+            )
+            .await?;
+        Ok(())
+    }
+}
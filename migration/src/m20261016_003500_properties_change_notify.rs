@@ -0,0 +1,36 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/*
+The mail and Kuma settings are in their own tables, changing them also has to wake the watchdog
+They use the same "properties" notification as general_properties_db from the change_notify migration
+*/
+const UP: &str = r#"
+CREATE TRIGGER email_properties_change
+    AFTER INSERT OR UPDATE OR DELETE ON email_properties
+    FOR EACH STATEMENT EXECUTE FUNCTION notify_properties_change();
+
+CREATE TRIGGER kuma_properties_change
+    AFTER INSERT OR UPDATE OR DELETE ON kuma_properties
+    FOR EACH STATEMENT EXECUTE FUNCTION notify_properties_change();
+"#;
+
+const DOWN: &str = r#"
+DROP TRIGGER IF EXISTS email_properties_change ON email_properties;
+DROP TRIGGER IF EXISTS kuma_properties_change ON kuma_properties;
+"#;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.get_connection().execute_unprepared(UP).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.get_connection().execute_unprepared(DOWN).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,26 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/*
+Users which never got a donation reminder count from their signup date, so every existing user would get one right after deploying
+Starting them from now means they get their first reminder one interval after the deploy, and have time to opt out
+*/
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "UPDATE user_data SET last_donation_reminder_date = now() WHERE last_donation_reminder_date IS NULL",
+            )
+            .await?;
+        Ok(())
+    }
+
+    // The seeded dates can't be told apart from real reminders
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
@@ -0,0 +1,256 @@
+use std::{
+    future::Future,
+    io,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+};
+
+use crate::config::var;
+use axum::{
+    Extension,
+    extract::{ConnectInfo, Request, State},
+    middleware::{AddExtension, Next},
+    response::Response,
+};
+use axum_server::{accept::Accept, tls_rustls::RustlsAcceptor};
+use reqwest::StatusCode;
+use rustls::{
+    RootCertStore, ServerConfig,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    server::WebPkiClientVerifier,
+};
+use tower::Layer;
+use tracing::*;
+
+use crate::GenResult;
+
+// Only these routes are restricted, the calendar routes stay reachable for everyone
+const RESTRICTED_PREFIXES: [&str; 2] = ["/admin/", "/kuma/"];
+
+/*
+Extra protection of the admin and Kuma routes, for operators that only want the calendars to be public
+ADMIN_ALLOWED_IPS is a comma separated list of addresses or ranges (like 10.0.0.0/8), empty allows every address
+If ADMIN_CLIENT_CA is set, clients can present a certificate signed by that CA, and the restricted routes require one
+Both are checked before the API key
+*/
+#[derive(Debug, Clone, Default)]
+pub struct AdminAccess {
+    allowed_ranges: Vec<IpRange>,
+    require_client_cert: bool,
+}
+
+// Set on every connection by ClientCertAcceptor, true if the client presented a valid certificate
+#[derive(Debug, Clone, Copy)]
+pub struct ClientCertificate(pub bool);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct IpRange {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl FromStr for IpRange {
+    type Err = String;
+
+    fn from_str(range: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = range.split_once('/').unwrap_or((range, ""));
+        let network =
+            IpAddr::from_str(address.trim()).map_err(|_| format!("Invalid address {range}"))?;
+        let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix.trim() {
+            "" => max_prefix,
+            prefix => prefix
+                .parse()
+                .ok()
+                .filter(|prefix| *prefix <= max_prefix)
+                .ok_or(format!("Invalid range {range}"))?,
+        };
+        Ok(Self { network, prefix })
+    }
+}
+
+impl IpRange {
+    fn contains(&self, address: IpAddr) -> bool {
+        // An IPv4 client on a dual stack socket shows up as an IPv6 mapped address
+        let address = match address {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(address),
+            address => address,
+        };
+        match (self.network, address) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(address) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl AdminAccess {
+    pub fn from_env() -> GenResult<Self> {
+        let allowed_ranges = var("ADMIN_ALLOWED_IPS")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|range| !range.trim().is_empty())
+            .map(IpRange::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            allowed_ranges,
+            require_client_cert: client_ca_path().is_some(),
+        })
+    }
+
+    fn allows(&self, address: IpAddr, client_certificate: bool) -> Result<(), &'static str> {
+        if !self.allowed_ranges.is_empty()
+            && !self
+                .allowed_ranges
+                .iter()
+                .any(|range| range.contains(address))
+        {
+            return Err("address not in ADMIN_ALLOWED_IPS");
+        }
+        if self.require_client_cert && !client_certificate {
+            return Err("no client certificate");
+        }
+        Ok(())
+    }
+}
+
+pub fn client_ca_path() -> Option<PathBuf> {
+    var("ADMIN_CLIENT_CA")
+        .ok()
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+}
+
+pub async fn check_admin_access(
+    State(access): State<Arc<AdminAccess>>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    req: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let path = req.uri().path();
+    if RESTRICTED_PREFIXES
+        .iter()
+        .any(|prefix| path.starts_with(prefix))
+    {
+        let client_certificate = req
+            .extensions()
+            .get::<ClientCertificate>()
+            .is_some_and(|certificate| certificate.0);
+        if let Err(reason) = access.allows(address.ip(), client_certificate) {
+            warn!("Denied request to {path} from {address}: {reason}");
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+    Ok(next.run(req).await)
+}
+
+// The TLS config of the API, which also asks for a client certificate if a CA is configured
+// The certificate is optional during the handshake, so the public routes keep working without one
+pub fn tls_server_config(
+    cert: &Path,
+    key: &Path,
+    client_ca: &Path,
+) -> GenResult<Arc<ServerConfig>> {
+    let mut roots = RootCertStore::empty();
+    for ca in CertificateDer::pem_file_iter(client_ca)? {
+        roots.add(ca?)?;
+    }
+    let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+        .allow_unauthenticated()
+        .build()?;
+    let certs = CertificateDer::pem_file_iter(cert)?.collect::<Result<Vec<_>, _>>()?;
+    let key = PrivateKeyDer::from_pem_file(key)?;
+    let mut config = ServerConfig::builder()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(certs, key)?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
+}
+
+// Wraps the rustls acceptor, to tell the routes if the connection has a client certificate
+#[derive(Clone)]
+pub struct ClientCertAcceptor {
+    inner: RustlsAcceptor,
+}
+
+impl ClientCertAcceptor {
+    pub fn new(inner: RustlsAcceptor) -> Self {
+        Self { inner }
+    }
+}
+
+impl<I, S> Accept<I, S> for ClientCertAcceptor
+where
+    RustlsAcceptor: Accept<I, S, Stream = tokio_rustls::server::TlsStream<I>, Service = S>,
+    <RustlsAcceptor as Accept<I, S>>::Future: Send + 'static,
+    I: Send + 'static,
+    S: Send + 'static,
+{
+    type Stream = tokio_rustls::server::TlsStream<I>;
+    type Service = AddExtension<S, ClientCertificate>;
+    type Future = Pin<Box<dyn Future<Output = io::Result<(Self::Stream, Self::Service)>> + Send>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let accept = self.inner.accept(stream, service);
+        Box::pin(async move {
+            let (stream, service) = accept.await?;
+            let has_certificate = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .is_some_and(|certificates| !certificates.is_empty());
+            Ok((
+                stream,
+                Extension(ClientCertificate(has_certificate)).layer(service),
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowlist_matches_ranges() {
+        let access = AdminAccess {
+            allowed_ranges: vec![
+                IpRange::from_str("10.0.0.0/8").unwrap(),
+                IpRange::from_str("::1").unwrap(),
+            ],
+            require_client_cert: false,
+        };
+        assert!(access.allows("10.1.2.3".parse().unwrap(), false).is_ok());
+        assert!(
+            access
+                .allows("::ffff:10.1.2.3".parse().unwrap(), false)
+                .is_ok()
+        );
+        assert!(access.allows("::1".parse().unwrap(), false).is_ok());
+        assert!(
+            access
+                .allows("192.168.1.1".parse().unwrap(), false)
+                .is_err()
+        );
+        assert!(IpRange::from_str("10.0.0.0/33").is_err());
+        let access = AdminAccess {
+            require_client_cert: true,
+            ..Default::default()
+        };
+        assert!(
+            access
+                .allows("192.168.1.1".parse().unwrap(), false)
+                .is_err()
+        );
+        assert!(access.allows("192.168.1.1".parse().unwrap(), true).is_ok());
+    }
+}
@@ -0,0 +1,69 @@
+use entity::user_account;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+
+use crate::{GenResult, errors::OptionResult};
+
+const ADMIN_ROLE: &str = "admin";
+
+/*
+Which users an admin account of the frontend may manage
+The operator has no properties group and manages everybody, a depot coordinator only manages the drivers in their group
+The account is the one signed in to the frontend, its session token is checked. The API key is still needed for every request
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdminScope {
+    Global,
+    Group(i32),
+}
+
+impl AdminScope {
+    pub async fn load(db: &DatabaseConnection, account_name: &str) -> GenResult<Self> {
+        let account = user_account::Entity::find()
+            .filter(user_account::Column::Username.eq(account_name))
+            .one(db)
+            .await?
+            .result_reason("Account not found")?;
+        Self::of_account(&account)
+    }
+
+    fn of_account(account: &user_account::Model) -> GenResult<Self> {
+        if account.role != ADMIN_ROLE {
+            return Err("Account is not an admin".into());
+        }
+        Ok(match account.properties_group {
+            Some(group_id) => Self::Group(group_id),
+            None => Self::Global,
+        })
+    }
+
+    pub fn allows(&self, general_properties_id: i32) -> bool {
+        match self {
+            Self::Global => true,
+            Self::Group(group_id) => *group_id == general_properties_id,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scope_follows_account() {
+        let mut account = user_account::Model {
+            account_id: 1,
+            username: "depot".to_owned(),
+            password_hash: String::new(),
+            role: ADMIN_ROLE.to_owned(),
+            backend_user: None,
+            properties_group: Some(2),
+        };
+        let scope = AdminScope::of_account(&account).unwrap();
+        assert!(scope.allows(2));
+        assert!(!scope.allows(1));
+        account.properties_group = None;
+        assert!(AdminScope::of_account(&account).unwrap().allows(1));
+        account.role = "user".to_owned();
+        assert!(AdminScope::of_account(&account).is_err());
+    }
+}
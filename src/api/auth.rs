@@ -1,11 +1,19 @@
 use std::collections::HashMap;
 
+use crate::config::var;
 use axum::{extract::Request, middleware::Next, response::Response};
-use dotenvy::var;
 use reqwest::StatusCode;
 use tracing::error;
 
+// The CLI sends the key in this header, the query parameter still works for the calendar links and scripts
+pub const API_KEY_HEADER: &str = "x-api-key";
+
 pub async fn check_api_key(req: Request, next: Next) -> Result<Response, StatusCode> {
+    let header_key = req
+        .headers()
+        .get(API_KEY_HEADER)
+        .and_then(|key| key.to_str().ok())
+        .map(str::to_owned);
     let params = if let Some(query) = req.uri().query() {
         // Parse it into key-value pairs
         let params: HashMap<_, _> = url::form_urlencoded::parse(query.as_bytes())
@@ -19,8 +27,9 @@ pub async fn check_api_key(req: Request, next: Next) -> Result<Response, StatusC
 
     // requires the http crate to get the header name
     let api_key = var("API_KEY").unwrap_or_default();
-    if params
-        .get("key")
+    if header_key
+        .as_ref()
+        .or(params.get("key"))
         .is_none_or(|request_key| request_key != &api_key)
     {
         error!("Denied request for incorrect key");
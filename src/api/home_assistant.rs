@@ -0,0 +1,107 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::{Europe::Amsterdam, Tz};
+use serde::Serialize;
+
+use crate::{database::shift_history::ShiftHistoryEntry, webcom::shift::ShiftState};
+
+/*
+Payload for a REST sensor in Home Assistant, so a smart home can set an alarm or turn on the lights before a shift
+The attributes are flat, so they can be used with value_template and json_attributes without any templating
+*/
+#[derive(Debug, Serialize)]
+pub struct HomeAssistantSensor {
+    pub next_shift_start: Option<DateTime<Tz>>,
+    pub next_shift_number: Option<String>,
+    pub hours_this_week: f64,
+    pub system_ok: bool,
+}
+
+// Shifts are in Dutch time, while the server often runs in UTC
+pub fn shift_time_now() -> NaiveDateTime {
+    Utc::now().with_timezone(&Amsterdam).naive_local()
+}
+
+// Monday of the week of the date, the shifts from then on are needed for the sensor
+pub fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Duration::days(date.weekday().num_days_from_monday() as i64)
+}
+
+// First shift which has not started yet, the shifts are sorted on start like the shift history returns them
+pub fn next_shift(shifts: &[ShiftHistoryEntry], now: NaiveDateTime) -> Option<&ShiftHistoryEntry> {
+    shifts.iter().find(|shift| {
+        shift.state != ShiftState::Deleted && NaiveDateTime::new(shift.date, shift.start) > now
+    })
+}
+
+impl HomeAssistantSensor {
+    pub fn new(shifts: &[ShiftHistoryEntry], now: NaiveDateTime, system_ok: bool) -> Self {
+        let monday = week_start(now.date());
+        let working_minutes: i64 = shifts
+            .iter()
+            .filter(|shift| shift.state != ShiftState::Deleted)
+            .filter(|shift| shift.date >= monday && shift.date < monday + Duration::days(7))
+            .map(|shift| shift.working_minutes)
+            .sum();
+        let next_shift = next_shift(shifts, now);
+        Self {
+            next_shift_start: next_shift.and_then(|shift| {
+                Amsterdam
+                    .from_local_datetime(&NaiveDateTime::new(shift.date, shift.start))
+                    .earliest()
+            }),
+            next_shift_number: next_shift.map(|shift| shift.number.clone()),
+            hours_this_week: (working_minutes as f64 / 60.0 * 100.0).round() / 100.0,
+            system_ok,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveTime;
+
+    fn shift(number: &str, day: u32, state: ShiftState) -> ShiftHistoryEntry {
+        let date = NaiveDate::from_ymd_opt(2026, 10, day).unwrap();
+        ShiftHistoryEntry {
+            magic_number: 1,
+            uid: number.to_owned(),
+            number: number.to_owned(),
+            kind: "Rijdienst".to_owned(),
+            date,
+            start: NaiveTime::from_hms_opt(6, 0, 0).unwrap(),
+            end_date: date,
+            end: NaiveTime::from_hms_opt(14, 0, 0).unwrap(),
+            duration_minutes: 480,
+            working_minutes: 450,
+            state,
+        }
+    }
+
+    #[test]
+    fn sensor_has_next_shift_and_week_hours() {
+        // Thursday the 15th, the week runs from the 12th until the 18th
+        let now = NaiveDate::from_ymd_opt(2026, 10, 15)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+        let shifts = [
+            shift("1001", 12, ShiftState::Unchanged),
+            shift("1002", 15, ShiftState::Unchanged),
+            shift("1003", 16, ShiftState::Deleted),
+            shift("1004", 17, ShiftState::New),
+            shift("1005", 19, ShiftState::Unchanged),
+        ];
+        let sensor = HomeAssistantSensor::new(&shifts, now, true);
+        assert_eq!(sensor.next_shift_number.as_deref(), Some("1004"));
+        assert_eq!(
+            sensor.next_shift_start.map(|start| start.to_rfc3339()),
+            Some("2026-10-17T06:00:00+02:00".to_owned())
+        );
+        assert_eq!(sensor.hours_this_week, 22.5);
+        assert_eq!(
+            week_start(now.date()),
+            NaiveDate::from_ymd_opt(2026, 10, 12).unwrap()
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use std::fmt::Write;
+
+use crate::execution::watchdog::InstanceMap;
+use crate::health::{ApplicationLogbook, ApplicationState};
+
+// Timings of the last run of every instance, in the Prometheus text format
+pub async fn collect_metrics(instances: &InstanceMap) -> String {
+    let mut states = vec![];
+    for (user_name, instance) in instances {
+        let (user, properties) = instance.user_instance_data.get_data_local().await;
+        if let Ok(logbook) = ApplicationLogbook::load_local(&user, &properties) {
+            states.push((user_name.clone(), logbook.application_state));
+        }
+    }
+    render_metrics(&states)
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn render_metrics(states: &[(String, ApplicationState)]) -> String {
+    let mut metrics = String::new();
+    metrics.push_str("# HELP mijnbussie_run_duration_seconds Duration of the last run\n");
+    metrics.push_str("# TYPE mijnbussie_run_duration_seconds gauge\n");
+    for (user_name, state) in states {
+        _ = writeln!(
+            metrics,
+            "mijnbussie_run_duration_seconds{{user=\"{}\"}} {}",
+            escape_label(user_name),
+            state.execution_time_ms as f64 / 1000.0
+        );
+    }
+    metrics.push_str(
+        "# HELP mijnbussie_phase_duration_seconds Duration of every phase of the last run\n",
+    );
+    metrics.push_str("# TYPE mijnbussie_phase_duration_seconds gauge\n");
+    for (user_name, state) in states {
+        for timing in &state.phase_timings.0 {
+            let label = timing
+                .label
+                .as_ref()
+                .map(|label| format!(",label=\"{}\"", escape_label(label)))
+                .unwrap_or_default();
+            _ = writeln!(
+                metrics,
+                "mijnbussie_phase_duration_seconds{{user=\"{}\",phase=\"{}\"{label}}} {}",
+                escape_label(user_name),
+                timing.phase.name(),
+                timing.duration_ms as f64 / 1000.0
+            );
+        }
+    }
+    metrics.push_str(
+        "# HELP mijnbussie_slow_phases Phases of the last run which took longer than their budget\n",
+    );
+    metrics.push_str("# TYPE mijnbussie_slow_phases gauge\n");
+    for (user_name, state) in states {
+        _ = writeln!(
+            metrics,
+            "mijnbussie_slow_phases{{user=\"{}\"}} {}",
+            escape_label(user_name),
+            state.phase_timings.slow_phases().count()
+        );
+    }
+    metrics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::{Phase, PhaseTiming, PhaseTimings};
+
+    #[test]
+    fn timings_are_rendered_per_user() {
+        let mut state = ApplicationState::default();
+        state.execution_time_ms = 1500;
+        state.phase_timings = PhaseTimings(vec![
+            PhaseTiming {
+                phase: Phase::Login,
+                label: None,
+                duration_ms: 250,
+            },
+            PhaseTiming {
+                phase: Phase::MonthLoad,
+                label: Some("2026-10".to_owned()),
+                duration_ms: 30_000,
+            },
+        ]);
+        let metrics = render_metrics(&[("jan\"".to_owned(), state)]);
+        assert!(metrics.contains("mijnbussie_run_duration_seconds{user=\"jan\\\"\"} 1.5\n"));
+        assert!(metrics.contains(
+            "mijnbussie_phase_duration_seconds{user=\"jan\\\"\",phase=\"login\"} 0.25\n"
+        ));
+        assert!(metrics.contains(
+            "mijnbussie_phase_duration_seconds{user=\"jan\\\"\",phase=\"month_load\",label=\"2026-10\"} 30\n"
+        ));
+        assert!(metrics.contains("mijnbussie_slow_phases{user=\"jan\\\"\"} 1\n"));
+    }
+}
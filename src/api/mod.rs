@@ -1,2 +1,8 @@
+pub mod profile;
 pub mod route;
-mod auth;
\ No newline at end of file
+mod admin_access;
+mod admin_scope;
+pub mod auth;
+mod home_assistant;
+mod metrics;
+mod voice;
\ No newline at end of file
@@ -0,0 +1,107 @@
+use chrono::NaiveDateTime;
+use secrecy::ExposeSecret;
+use serde::Serialize;
+
+use crate::{
+    database::variables::UserData, kuma::notification_topic, webcom::deletion::StandingInformation,
+};
+
+/*
+What the API shows of a user, for the user themselves and the admin pages
+Only what is needed to show and change the settings is included, so no ids, secrets or credentials
+*/
+#[derive(Debug, Clone, Serialize)]
+pub struct UserProfile {
+    pub user_name: String,
+    pub name: Option<String>,
+    pub email: String,
+    pub file_name: String,
+    pub notifications: NotificationSettings,
+    pub schedule: ScheduleInfo,
+    pub standing: StandingInformation,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NotificationSettings {
+    pub new_shift: bool,
+    pub updated_shift: bool,
+    pub removed_shift: bool,
+    pub failed_signin: bool,
+    pub welcome: bool,
+    pub error: bool,
+    pub overtime: bool,
+    pub donation_reminder: bool,
+    pub weekly_summary: bool,
+    // Set if mails are paused because a mail to the user bounced
+    pub mail_paused_reason: Option<String>,
+    // The ntfy topic to subscribe to, if the properties group sends Kuma alerts with ntfy
+    pub kuma_topic: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduleInfo {
+    pub execution_interval_minutes: i32,
+    pub execution_minute: i32,
+    pub last_execution_date: Option<NaiveDateTime>,
+    pub last_succesfull_sign_in_date: Option<NaiveDateTime>,
+    pub creation_date: NaiveDateTime,
+}
+
+impl UserProfile {
+    pub fn new(user: &UserData, standing: StandingInformation) -> Self {
+        let properties = &user.user_properties;
+        Self {
+            user_name: user.user_name.clone(),
+            name: user
+                .name
+                .as_ref()
+                .map(|name| name.0.expose_secret().to_owned()),
+            email: mask_email(user.email.0.expose_secret()),
+            file_name: user.file_name.clone(),
+            notifications: NotificationSettings {
+                new_shift: properties.send_mail_new_shift,
+                updated_shift: properties.send_mail_updated_shift,
+                removed_shift: properties.send_mail_removed_shift,
+                failed_signin: properties.send_failed_signin_mail,
+                welcome: properties.send_welcome_mail,
+                error: properties.send_error_mail,
+                overtime: properties.send_overtime_mail,
+                donation_reminder: properties.send_donation_reminder,
+                weekly_summary: properties.send_weekly_summary,
+                mail_paused_reason: user.mail_paused_reason.clone(),
+                kuma_topic: notification_topic(user),
+            },
+            schedule: ScheduleInfo {
+                execution_interval_minutes: properties.execution_interval_minutes,
+                execution_minute: properties.execution_minute,
+                last_execution_date: user.last_execution_date,
+                last_succesfull_sign_in_date: user.last_succesfull_sign_in_date,
+                creation_date: user.creation_date,
+            },
+            standing,
+        }
+    }
+}
+
+// Only the first letter of the address and the domain are shown, enough to recognise it
+fn mask_email(email: &str) -> String {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            let first = local.chars().next().map(String::from).unwrap_or_default();
+            format!("{first}***@{domain}")
+        }
+        None => "***".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn email_is_masked() {
+        assert_eq!(mask_email("piet.jansen@example.com"), "p***@example.com");
+        assert_eq!(mask_email("@example.com"), "***@example.com");
+        assert_eq!(mask_email("not an address"), "***");
+    }
+}
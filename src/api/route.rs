@@ -1,24 +1,83 @@
+use crate::api::admin_access::{
+    AdminAccess, ClientCertAcceptor, check_admin_access, client_ca_path, tls_server_config,
+};
+use crate::api::admin_scope::AdminScope;
 use crate::api::auth::check_api_key;
-use crate::errors::OptionResult;
-use crate::execution::watchdog::{InstanceMap, RequestResponse, WatchdogRequest};
+use crate::api::home_assistant::{HomeAssistantSensor, next_shift, shift_time_now, week_start};
+use crate::api::metrics::collect_metrics;
+use crate::api::voice::{Language, VoiceAssistant, next_shift_sentence};
+use crate::config::var;
+use crate::database::calendar_fetch::{
+    find_user_by_calendar_token, record_calendar_fetch, rotate_calendar_token,
+};
+use crate::database::calendar_share::{
+    list_calendar_shares, load_buddy_shifts, share_calendar, unshare_calendar,
+};
+use crate::database::feature_flags::FeatureFlag;
+use crate::database::invite_code::{
+    create_invite_code, list_invite_codes, redeem_invite_code, release_invite_code,
+};
+use crate::database::onboarding_variants::variant_report;
+use crate::database::properties_group::PropertiesGroup;
+use crate::database::session::{
+    create_session, logout, refresh_session, revoke_account_sessions, session_account,
+};
+use crate::database::shift_history::{
+    PeriodFilter, ShiftSearch, planned_vs_actual, search_shift_history,
+};
+use crate::database::shift_notes::{ShiftNote, get_shift_note, remove_shift_note, set_shift_note};
+use crate::database::short_link::follow_short_link;
+use crate::database::swaps::load_swap_suggestions;
+use crate::database::variables::{GeneralProperties, UserData, default_properties_id};
+use crate::errors::{AppError, AppResult, FailureType, OptionResult, ResultLog};
+use crate::execution::clock::SystemClock;
+use crate::execution::disk_quota::UserDiskUsage;
+use crate::execution::instance_log::INSTANCE_SPAN;
+use crate::execution::log_level::user_log_filter;
+use crate::execution::request_queue::RequestQueue;
+use crate::execution::systemd;
+use crate::execution::timer::Schedule;
+use crate::execution::watchdog::{InstanceMap, RequestResponse, UserInstance, WatchdogRequest};
+use crate::health::{ApplicationLogbook, ApplicationStatus, ExitCodeTransition, LogbookStatus};
 use crate::kuma::{KumaAction, KumaUserRequest};
-use crate::{GenResult, StartRequest};
-use axum::extract::{Path, State};
-use axum::http::StatusCode;
-use axum::response::IntoResponse;
-use axum::routing::get;
+use crate::webcom::bounce::{BounceReport, find_user_by_email, pause_stored_user_mail};
+use crate::webcom::deletion::DeletionPreview;
+use crate::webcom::email::create_webcal_link_local;
+use crate::webcom::ical::{create_buddy_calendar, create_yearly_calendar, get_ical_path_local};
+use crate::webcom::sign_in_backfill::backfill_sign_in_dates;
+use crate::webcom::time_sheet::{create_time_sheet, month_range};
+use crate::{GenResult, InstanceRequest, StartRequest, get_database_connection, light_request};
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
 use axum::{Json, Router, middleware};
-use axum_server::tls_rustls::RustlsConfig;
+use axum_server::Handle;
+use axum_server::tls_rustls::{RustlsAcceptor, RustlsConfig};
+use chrono::NaiveDate;
+use entity::user_data;
+use secrecy::ExposeSecret;
 use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 use std::time::Duration;
 use strum_macros::EnumString;
 use tokio::sync::RwLock;
-use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::mpsc::Sender;
+use tokio::sync::oneshot;
 use tokio::time::timeout;
-use tracing::info;
+use tower_http::services::{ServeDir, ServeFile};
+use tracing::{Instrument, info, warn, warn_span};
+
+// Kept so the certificate can be swapped without restarting the server
+static TLS_CONFIG: OnceLock<RustlsConfig> = OnceLock::new();
+static PUBLIC_TLS_CONFIG: OnceLock<RustlsConfig> = OnceLock::new();
+const CERT_DIR: &str = "cert";
+const PUBLIC_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+// A full watchdog queue is waited on for a moment, the database listener queues requests as well
+const WATCHDOG_SEND_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Clone)]
 pub struct ServerConfig {
@@ -33,129 +92,1319 @@ enum Action {
     IsActive,
     Name,
     Start,
+    DryRun,
     ExitCode,
     UserData,
     Welcome,
     Calendar,
     Delete,
     Standing,
+    Earnings,
+    Rollback,
+}
+
+// Optional date range for the logbook history, both dates are inclusive
+#[derive(Debug, Deserialize)]
+struct LogbookFilter {
+    from: Option<NaiveDate>,
+    until: Option<NaiveDate>,
 }
 
-pub async fn api(instance_map: Arc<RwLock<InstanceMap>>, watchdog_sender: Sender<WatchdogRequest>) {
+/*
+Starts the API and, with PUBLIC_LISTEN_ADDR, the public listener. Returns once both are listening,
+so a missing certificate or an address that is in use stops the startup instead of only the API
+*/
+pub async fn api(
+    instance_map: Arc<RwLock<InstanceMap>>,
+    watchdog_sender: Sender<WatchdogRequest>,
+) -> GenResult<()> {
     let config = ServerConfig {
         map: instance_map,
         sender: watchdog_sender,
     };
 
-    let tls_config = RustlsConfig::from_pem_file(
-        PathBuf::from("cert").join("cert.crt"),
-        PathBuf::from("cert").join("key.key"),
-    )
-    .await
-    .expect("Missing certificate files");
+    let tls_config = load_tls_config().await?;
+    _ = TLS_CONFIG.set(tls_config.clone());
+    let admin_access = Arc::new(AdminAccess::from_env()?);
     let api_routes = Router::new()
         .route("/{user_name}/{action}", get(get_information))
+        .route("/{user_name}/exit_code/history", get(get_exit_code_history))
+        .route("/{user_name}/shifts/search", get(search_shifts))
+        .route("/{user_name}/shifts/actual", get(compare_actual_hours))
+        .route(
+            "/{user_name}/shifts/{magic_number}/note",
+            get(get_note).put(set_note).delete(remove_note),
+        )
+        .route("/{user_name}/shares", get(get_calendar_shares))
+        .route(
+            "/{user_name}/shares/{viewer_name}",
+            post(add_calendar_share).delete(remove_calendar_share),
+        )
+        .route("/{user_name}/buddies/calendar", get(get_buddy_calendar))
+        .route(
+            "/{user_name}/calendar/{year_file}",
+            get(get_yearly_calendar),
+        )
+        .route(
+            "/{user_name}/time_sheet/{year}/{month}",
+            get(get_time_sheet),
+        )
+        .route("/{user_name}/swaps", get(get_swap_suggestions))
+        .route(
+            "/{user_name}/loglevel",
+            post(set_user_log_level).delete(reset_user_log_level),
+        )
+        .route("/metrics", get(get_metrics))
+        .route(
+            "/signup/{user_name}",
+            get(get_onboarding_status).post(start_signup),
+        )
         .route("/refresh", get(refresh_users))
-        .route("/refresh/{user_name}", get(refresh_users))
+        .route("/refresh/{user_name}", get(refresh_user))
         .route("/kuma/{action}/{user_name}", get(handle_kuma_request))
+        .route("/admin/reload", post(reload_properties))
+        .route("/admin/users", get(list_users))
+        .route("/admin/users/{user_name}", get(get_user_profile))
+        .route("/admin/schedule", get(get_schedule))
+        .route("/admin/deletion_preview", get(get_deletion_preview))
+        .route("/admin/backfill_sign_in", post(backfill_sign_in))
+        .route(
+            "/admin/invites",
+            get(get_invite_codes).post(add_invite_code),
+        )
+        .route("/auth/session", post(start_session))
+        .route("/admin/sessions/{account}", delete(revoke_sessions))
+        .route("/depot/users", get(list_depot_users))
+        .route("/depot/refresh/{user_name}", get(refresh_depot_user))
+        .route("/depot/welcome/{user_name}", post(resend_depot_welcome))
+        .route("/admin/retry/{user_name}", post(retry_user))
+        .route("/admin/resume_mail/{user_name}", post(resume_mail))
+        .route(
+            "/admin/calendar_token/{user_name}",
+            post(rotate_user_calendar_token),
+        )
+        .route("/mail/bounce", post(report_bounce))
+        .route(
+            "/admin/preview_onboarding/{user_name}",
+            post(preview_user_onboarding),
+        )
+        .route(
+            "/properties",
+            get(list_properties_groups).post(create_properties_group),
+        )
+        .route(
+            "/properties/{group_id}/users/{user_name}",
+            post(assign_properties_group),
+        )
+        .route("/properties/{group_id}/flags", get(list_feature_flags))
+        .route(
+            "/properties/{group_id}/onboarding_variants",
+            get(get_onboarding_variant_report),
+        )
+        .route(
+            "/properties/{group_id}/flags/{flag}/{enabled}",
+            post(set_feature_flag),
+        )
         .layer(middleware::from_fn(check_api_key))
+        .layer(middleware::from_fn_with_state(
+            admin_access,
+            check_admin_access,
+        ))
+        .with_state(config.clone());
+
+    // These routes do not require an API key, so they must never expose user specific data
+    // The calendar, webcal redirect, Home Assistant, voice and short links are the exception, their secret token is the authentication
+    // The same goes for the auth routes, the session tokens are checked instead
+    let public_routes = Router::new()
+        .route("/status", get(get_status))
+        .route("/calendar/{calendar_file}", get(get_calendar))
+        .route("/r/{link}", get(follow_link))
+        .route("/webcal/{calendar_token}", get(get_webcal_redirect))
+        .route("/ha/{calendar_token}", get(get_home_assistant_sensor))
+        .route("/voice/{calendar_token}", get(get_voice_next_shift))
+        .route(
+            "/voice/{calendar_token}/{assistant}",
+            post(post_voice_webhook),
+        )
+        .route("/auth/refresh", post(refresh_auth_session))
+        .route("/auth/logout", post(logout_session))
+        .route("/auth/me", get(get_session_account))
         .with_state(config);
 
-    let all_routes = Router::new().nest("/api", api_routes);
+    // With PUBLIC_LISTEN_ADDR the public routes get their own listener, and the management API is not reachable there
+    // The internal listener keeps serving them too, for clients on the internal network
+    let public_handle = Handle::new();
+    if let Some(public_address) = var("PUBLIC_LISTEN_ADDR")
+        .ok()
+        .filter(|address| !address.is_empty())
+    {
+        serve_public(
+            Router::new().nest("/api", public_routes.clone()),
+            &public_address,
+            public_handle.clone(),
+        )
+        .await?;
+    }
+    // Unknown API paths get their own fallback, so they are a 404 instead of the index of the frontend
+    let mut all_routes = Router::new().nest(
+        "/api",
+        api_routes.merge(public_routes).fallback(api_not_found),
+    );
+
+    // Optionally serve the frontend, every other unknown path returns the index so the frontend can do its own routing
+    if let Ok(frontend_dir) = var("FRONTEND_DIR")
+        && !frontend_dir.is_empty()
+    {
+        info!("Serving frontend from {frontend_dir}");
+        let index = PathBuf::from(&frontend_dir).join("index.html");
+        all_routes = all_routes
+            .fallback_service(ServeDir::new(&frontend_dir).fallback(ServeFile::new(index)));
+    }
+
+    let api_address = var("API_LISTEN_ADDR")
+        .ok()
+        .filter(|address| !address.is_empty())
+        .unwrap_or("0.0.0.0:3000".to_owned());
+    let handle = Handle::new();
+    let server = axum_server::bind(SocketAddr::from_str(&api_address)?)
+        .acceptor(ClientCertAcceptor::new(RustlsAcceptor::new(tls_config)))
+        .handle(handle.clone())
+        .serve(all_routes.into_make_service_with_connect_info::<SocketAddr>());
+    tokio::spawn(async move {
+        server.await.warn("Serving API");
+        // The public listener stops with the API, open requests get a moment to finish
+        public_handle.graceful_shutdown(Some(PUBLIC_SHUTDOWN_TIMEOUT));
+    });
+    // None if the server stopped before it was listening, like when the address is in use
+    handle
+        .listening()
+        .await
+        .result_reason(&format!("Could not listen on {api_address}"))?;
+    info!("Serving API on {api_address}");
+    systemd::api_listening();
+    Ok(())
+}
+
+// Only serves the calendar, webcal, short link and session routes, with its own certificate
+async fn serve_public(
+    public_routes: Router,
+    address: &str,
+    handle: Handle<SocketAddr>,
+) -> GenResult<()> {
+    let (cert, key) = cert_paths(&public_cert_dir());
+    let tls_config = RustlsConfig::from_pem_file(cert, key).await?;
+    _ = PUBLIC_TLS_CONFIG.set(tls_config.clone());
+    let server = axum_server::bind_rustls(SocketAddr::from_str(address)?, tls_config)
+        .handle(handle.clone())
+        .serve(public_routes.into_make_service());
+    tokio::spawn(async move { server.await.warn("Serving public routes") });
+    handle
+        .listening()
+        .await
+        .result_reason(&format!("Could not listen on {address}"))?;
+    info!("Serving public routes on {address}");
+    Ok(())
+}
+
+// The public listener can have its own certificate, for the domain the calendars are served on
+fn public_cert_dir() -> String {
+    var("PUBLIC_CERT_DIR")
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .unwrap_or(CERT_DIR.to_owned())
+}
+
+fn cert_paths(cert_dir: &str) -> (PathBuf, PathBuf) {
+    (
+        PathBuf::from(cert_dir).join("cert.crt"),
+        PathBuf::from(cert_dir).join("key.key"),
+    )
+}
+
+async fn load_tls_config() -> GenResult<RustlsConfig> {
+    let (cert, key) = cert_paths(CERT_DIR);
+    Ok(match client_ca_path() {
+        Some(client_ca) => RustlsConfig::from_config(tls_server_config(&cert, &key, &client_ca)?),
+        None => RustlsConfig::from_pem_file(cert, key).await?,
+    })
+}
+
+// Only done on a SIGHUP, so only on unix
+#[cfg(unix)]
+pub async fn reload_certificates() -> GenResult<()> {
+    let tls_config = TLS_CONFIG.get().result_reason("API not started yet")?;
+    let (cert, key) = cert_paths(CERT_DIR);
+    match client_ca_path() {
+        Some(client_ca) => {
+            tls_config.reload_from_config(tls_server_config(&cert, &key, &client_ca)?)
+        }
+        None => tls_config.reload_from_pem_file(cert, key).await?,
+    }
+    if let Some(public_tls_config) = PUBLIC_TLS_CONFIG.get() {
+        let (cert, key) = cert_paths(&public_cert_dir());
+        public_tls_config.reload_from_pem_file(cert, key).await?;
+    }
+    info!("Reloaded certificates");
+    Ok(())
+}
+
+async fn send_to_watchdog(
+    sender: &Sender<WatchdogRequest>,
+    request: WatchdogRequest,
+) -> AppResult<()> {
+    timeout(WATCHDOG_SEND_TIMEOUT, sender.send(request))
+        .await?
+        .map_err(|_| "Watchdog is not running")?;
+    Ok(())
+}
+
+async fn refresh_users(State(data): State<ServerConfig>) -> impl IntoResponse {
+    match send_to_watchdog(&data.sender, WatchdogRequest::AllUser).await {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(err) => err.into_response(),
+    }
+}
 
-    axum_server::bind_rustls(
-        std::net::SocketAddr::from_str("0.0.0.0:3000").unwrap(),
-        tls_config,
+async fn refresh_user(
+    State(data): State<ServerConfig>,
+    Path(user_name): Path<String>,
+) -> impl IntoResponse {
+    refresh_response(&data, user_name).await
+}
+
+// Waits for the watchdog to refresh the user, and returns what changed
+async fn refresh_response(data: &ServerConfig, user_name: String) -> Response {
+    let (sender, receiver) = oneshot::channel();
+    if let Err(err) = send_to_watchdog(
+        &data.sender,
+        WatchdogRequest::SingleUser(user_name, Some(sender)),
     )
-    .serve(all_routes.into_make_service())
     .await
-    .unwrap();
+    {
+        return err.into_response();
+    }
+    // Adding a user to Kuma can take a while
+    match timeout(Duration::from_secs(60), receiver).await {
+        Ok(Ok(report)) => (StatusCode::OK, Json(report)).into_response(),
+        Ok(Err(_)) => AppError::from("Refresh failed").into_response(),
+        Err(err) => AppError::from(err).into_response(),
+    }
+}
+
+async fn reload_properties(State(data): State<ServerConfig>) -> impl IntoResponse {
+    match send_to_watchdog(&data.sender, WatchdogRequest::ReloadProperties).await {
+        Ok(()) => (StatusCode::OK, Json("OK".to_string())).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+// Optional group to copy when creating a new properties group
+#[derive(Debug, Deserialize)]
+struct CreateGroupQuery {
+    source: Option<i32>,
+}
+
+async fn list_properties_groups() -> impl IntoResponse {
+    let db = get_database_connection().await;
+    match PropertiesGroup::list(&db).await {
+        Ok(groups) => (StatusCode::OK, Json(groups)).into_response(),
+        Err(err) => AppError::from(err).into_response(),
+    }
+}
+
+async fn create_properties_group(Query(query): Query<CreateGroupQuery>) -> impl IntoResponse {
+    let db = get_database_connection().await;
+    match PropertiesGroup::create(&db, query.source).await {
+        Ok(group_id) => (StatusCode::OK, Json(group_id)).into_response(),
+        Err(err) => AppError::from(err).into_response(),
+    }
 }
 
-async fn refresh_users(
+// Use "default" as group id to move a user back to the default properties
+async fn assign_properties_group(
     State(data): State<ServerConfig>,
-    user_name: Option<Path<String>>,
+    Path((group_id, user_name)): Path<(String, String)>,
 ) -> impl IntoResponse {
-    let send = data
-        .sender
-        .try_send(
-            user_name
-                .and_then(|path| Some(WatchdogRequest::SingleUser(path.to_string())))
-                .unwrap_or(WatchdogRequest::AllUser),
+    let group_id = match group_id.as_str() {
+        "default" => None,
+        id => match id.parse::<i32>() {
+            Ok(id) => Some(id),
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json("Invalid group id".to_string()),
+                )
+                    .into_response();
+            }
+        },
+    };
+    let db = get_database_connection().await;
+    if let Err(err) = PropertiesGroup::assign_user(&db, &user_name, group_id).await {
+        return (StatusCode::BAD_REQUEST, Json(err.to_string())).into_response();
+    }
+    // Let the watchdog restart the instance with the new properties
+    match send_to_watchdog(&data.sender, WatchdogRequest::SingleUser(user_name, None)).await {
+        Ok(()) => (StatusCode::OK, Json("OK".to_string())).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn list_feature_flags(Path(group_id): Path<i32>) -> impl IntoResponse {
+    let db = get_database_connection().await;
+    match FeatureFlag::list(&db, group_id).await {
+        Ok(flags) => (StatusCode::OK, Json(flags)).into_response(),
+        Err(err) => AppError::from(err).into_response(),
+    }
+}
+
+async fn get_onboarding_variant_report(Path(group_id): Path<i32>) -> impl IntoResponse {
+    let db = get_database_connection().await;
+    match variant_report(&db, group_id).await {
+        Ok(report) => (StatusCode::OK, Json(report)).into_response(),
+        Err(err) => AppError::from(err).into_response(),
+    }
+}
+
+async fn set_feature_flag(
+    Path((group_id, flag, enabled)): Path<(i32, FeatureFlag, bool)>,
+) -> impl IntoResponse {
+    let db = get_database_connection().await;
+    match flag.set(&db, group_id, enabled).await {
+        Ok(_) => (StatusCode::OK, Json("OK".to_string())).into_response(),
+        Err(err) => AppError::from(err).into_response(),
+    }
+}
+
+// Finds the user and their current settings belonging to a calendar token
+async fn find_calendar_owner(
+    data: &ServerConfig,
+    calendar_token: &str,
+) -> Result<(user_data::Model, UserData, GeneralProperties), Response> {
+    let db = get_database_connection().await;
+    let not_found = || {
+        (
+            StatusCode::NOT_FOUND,
+            Json("Calendar not found".to_string()),
         )
-        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err.to_string())));
-    send.into_response()
+            .into_response()
+    };
+    let user = match find_user_by_calendar_token(&db, calendar_token).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return Err(not_found()),
+        Err(err) => return Err(AppError::from(err).into_response()),
+    };
+    // The lock is not held while loading the data
+    let instance_data = data
+        .map
+        .read()
+        .await
+        .get(&user.user_name)
+        .map(|instance| instance.user_instance_data.clone())
+        .ok_or_else(not_found)?;
+    let (user_data, properties) = instance_data.get_data_local().await;
+    Ok((user, user_data, properties))
+}
+
+// REST sensor for Home Assistant, uses the calendar token so the smart home never needs the API key
+async fn get_home_assistant_sensor(
+    State(data): State<ServerConfig>,
+    Path(calendar_token): Path<String>,
+) -> impl IntoResponse {
+    let (user, _user_data, _properties) = match find_calendar_owner(&data, &calendar_token).await {
+        Ok(owner) => owner,
+        Err(response) => return response,
+    };
+    let system_ok = data
+        .map
+        .read()
+        .await
+        .get(&user.user_name)
+        .is_some_and(|instance| instance.status.last_exit_code() == FailureType::OK);
+    let now = shift_time_now();
+    let search = ShiftSearch {
+        from: Some(week_start(now.date())),
+        ..Default::default()
+    };
+    match search_shift_history(&user.user_name, &search).await {
+        Ok(shifts) => (
+            StatusCode::OK,
+            Json(HomeAssistantSensor::new(&shifts, now, system_ok)),
+        )
+            .into_response(),
+        Err(err) => AppError::from(err).into_response(),
+    }
+}
+
+// One sentence about the next shift of the owner of the calendar token, in the language of the user
+async fn voice_sentence(data: &ServerConfig, calendar_token: &str) -> Result<String, Response> {
+    let (user, user_data, _properties) = find_calendar_owner(data, calendar_token).await?;
+    let now = shift_time_now();
+    let search = ShiftSearch {
+        from: Some(now.date()),
+        ..Default::default()
+    };
+    let language = Language::from_setting(&user_data.user_properties.language);
+    match search_shift_history(&user.user_name, &search).await {
+        Ok(shifts) => Ok(next_shift_sentence(next_shift(&shifts, now), now, language)),
+        Err(err) => Err(AppError::from(err).into_response()),
+    }
+}
+
+// The sentence as plain text, for assistants that can read out any URL
+async fn get_voice_next_shift(
+    State(data): State<ServerConfig>,
+    Path(calendar_token): Path<String>,
+) -> impl IntoResponse {
+    match voice_sentence(&data, &calendar_token).await {
+        Ok(sentence) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            sentence,
+        )
+            .into_response(),
+        Err(response) => response,
+    }
+}
+
+// The webhook of an Alexa skill or Dialogflow agent, the content of the request does not matter
+async fn post_voice_webhook(
+    State(data): State<ServerConfig>,
+    Path((calendar_token, assistant)): Path<(String, VoiceAssistant)>,
+) -> impl IntoResponse {
+    match voice_sentence(&data, &calendar_token).await {
+        Ok(sentence) => (StatusCode::OK, Json(assistant.response(&sentence))).into_response(),
+        Err(response) => response,
+    }
+}
+
+// A lot of email clients don't open webcal links, so mails link here and the browser opens the calendar app
+async fn get_webcal_redirect(
+    State(data): State<ServerConfig>,
+    Path(calendar_token): Path<String>,
+) -> impl IntoResponse {
+    let (_user, user_data, properties) = match find_calendar_owner(&data, &calendar_token).await {
+        Ok(owner) => owner,
+        Err(response) => return response,
+    };
+    match create_webcal_link_local(&user_data, &properties) {
+        Ok(webcal_link) => (StatusCode::FOUND, [(header::LOCATION, webcal_link)]).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+// Short links from mails, unknown or incorrectly signed links are all a 404
+async fn follow_link(Path(link): Path<String>) -> impl IntoResponse {
+    match follow_short_link(&get_database_connection().await, &link).await {
+        Ok(Some(target)) => (StatusCode::FOUND, [(header::LOCATION, target)]).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, Json("Link not found".to_string())).into_response(),
+        Err(err) => AppError::from(err).into_response(),
+    }
+}
+
+// Serves the calendar to calendar clients and records the fetch
+async fn get_calendar(
+    State(data): State<ServerConfig>,
+    Path(calendar_file): Path<String>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let calendar_token = calendar_file.trim_end_matches(".ics");
+    let (user, user_data, properties) = match find_calendar_owner(&data, calendar_token).await {
+        Ok(owner) => owner,
+        Err(response) => return response,
+    };
+    let calendar =
+        match tokio::fs::read_to_string(get_ical_path_local(&user_data, &properties)).await {
+            Ok(calendar) => calendar,
+            Err(_) => {
+                return (
+                    StatusCode::NOT_FOUND,
+                    Json("Calendar not created yet".to_string()),
+                )
+                    .into_response();
+            }
+        };
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|user_agent| user_agent.to_str().ok())
+        .map(str::to_owned);
+    record_calendar_fetch(&get_database_connection().await, user, user_agent)
+        .await
+        .warn("Recording calendar fetch");
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "text/calendar; charset=utf-8")],
+        calendar,
+    )
+        .into_response()
+}
+
+async fn get_status(State(data): State<ServerConfig>) -> impl IntoResponse {
+    let status = ApplicationStatus::collect(&*data.map.read().await);
+    (StatusCode::OK, Json(status)).into_response()
+}
+
+// Every running instance with the disk usage of its directory
+async fn list_users(State(data): State<ServerConfig>) -> impl IntoResponse {
+    scoped_users(&data, AdminScope::Global).await
+}
+
+async fn scoped_users(data: &ServerConfig, scope: AdminScope) -> Response {
+    let mut users = vec![];
+    for instance in data.map.read().await.values() {
+        let (user, properties) = instance.user_instance_data.get_data_local().await;
+        if scope.allows(properties.general_properties_id) {
+            users.push(UserDiskUsage::collect(&user, &properties));
+        }
+    }
+    users.sort_by(|a, b| a.user_name.cmp(&b.user_name));
+    (StatusCode::OK, Json(users)).into_response()
+}
+
+/*
+The depot routes are for admin accounts of the frontend, the account is the one of the session in the bearer token
+A depot coordinator only sees and manages the users in their own properties group
+*/
+async fn load_scope(headers: &HeaderMap) -> Result<AdminScope, Response> {
+    let access_token = bearer_token(headers).ok_or_else(|| unauthorized("Missing access token"))?;
+    let db = get_database_connection().await;
+    let account = session_account(&db, access_token)
+        .await
+        .map_err(unauthorized)?;
+    AdminScope::load(&db, &account.username)
+        .await
+        .map_err(|err| (StatusCode::FORBIDDEN, Json(err.to_string())).into_response())
+}
+
+// Users outside of the scope are reported as not found, so other depots can't be probed for user names
+async fn check_scope(
+    data: &ServerConfig,
+    headers: &HeaderMap,
+    user_name: &str,
+) -> Result<(), Response> {
+    let scope = load_scope(headers).await?;
+    let group_id = match data.map.read().await.get(user_name) {
+        Some(instance) => Some(
+            instance
+                .user_instance_data
+                .general_settings
+                .read()
+                .await
+                .general_properties_id,
+        ),
+        None => None,
+    };
+    match group_id {
+        Some(group_id) if scope.allows(group_id) => Ok(()),
+        _ => Err((StatusCode::NOT_FOUND, Json("User not found".to_string())).into_response()),
+    }
+}
+
+async fn list_depot_users(
+    State(data): State<ServerConfig>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match load_scope(&headers).await {
+        Ok(scope) => scoped_users(&data, scope).await,
+        Err(response) => response,
+    }
+}
+
+async fn refresh_depot_user(
+    State(data): State<ServerConfig>,
+    headers: HeaderMap,
+    Path(user_name): Path<String>,
+) -> impl IntoResponse {
+    match check_scope(&data, &headers, &user_name).await {
+        Ok(()) => refresh_response(&data, user_name).await,
+        Err(response) => response,
+    }
+}
+
+async fn resend_depot_welcome(
+    State(data): State<ServerConfig>,
+    headers: HeaderMap,
+    Path(user_name): Path<String>,
+) -> impl IntoResponse {
+    match check_scope(&data, &headers, &user_name).await {
+        Ok(()) => instance_request(&data, &user_name, StartRequest::Welcome).await,
+        Err(response) => response,
+    }
+}
+
+// The same profile the user sees, without any secrets
+async fn get_user_profile(
+    State(data): State<ServerConfig>,
+    Path(user_name): Path<String>,
+) -> impl IntoResponse {
+    instance_request(&data, &user_name, StartRequest::UserData).await
+}
+
+// When every instance runs next, and when the timer last checked them
+async fn get_schedule(State(data): State<ServerConfig>) -> impl IntoResponse {
+    let schedule = Schedule::collect(&SystemClock, &*data.map.read().await).await;
+    (StatusCode::OK, Json(schedule)).into_response()
+}
+
+// Behind the API key, as the metrics contain user names
+async fn get_metrics(State(data): State<ServerConfig>) -> impl IntoResponse {
+    let metrics = collect_metrics(&*data.map.read().await).await;
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics,
+    )
+        .into_response()
 }
 
 async fn get_information(
     State(data): State<ServerConfig>,
     Path((user_name, action)): Path<(String, Action)>,
+    Query(filter): Query<LogbookFilter>,
+) -> impl IntoResponse {
+    // A logbook request with a date range is served from the database instead of the instance
+    if action == Action::Logbook && (filter.from.is_some() || filter.until.is_some()) {
+        let from = filter.from.and_then(|date| date.and_hms_opt(0, 0, 0));
+        let until = filter.until.and_then(|date| date.and_hms_opt(23, 59, 59));
+        return match ApplicationLogbook::load_history(&user_name, from, until).await {
+            Ok(history) => {
+                let history: Vec<LogbookStatus> = history.into_iter().map(Into::into).collect();
+                (StatusCode::OK, Json(history)).into_response()
+            }
+            Err(err) => AppError::from(err).into_response(),
+        };
+    }
+    match data.map.read().await.get(&user_name) {
+        Some(instance) => match send_request(action, instance).await {
+            Ok(response) => (StatusCode::OK, Json(response)).into_response(),
+            Err(err) => err.into_response(),
+        },
+        None => (StatusCode::BAD_REQUEST, Json("User not found".to_string())).into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PreviewQuery {
+    #[serde(default)]
+    send: bool,
+}
+
+// Shows the onboarding of a user with their settings, the mails go to the support address
+async fn preview_user_onboarding(
+    State(data): State<ServerConfig>,
+    Path(user_name): Path<String>,
+    Query(query): Query<PreviewQuery>,
+) -> impl IntoResponse {
+    instance_request(
+        &data,
+        &user_name,
+        StartRequest::PreviewOnboarding(query.send),
+    )
+    .await
+}
+
+// Accounts that are going to be warned or deleted, the soonest deletion first
+async fn get_deletion_preview(State(data): State<ServerConfig>) -> impl IntoResponse {
+    let mut previews = vec![];
+    for instance in data.map.read().await.values() {
+        let (user, properties) = instance.user_instance_data.get_data_local().await;
+        previews.extend(DeletionPreview::get_local(&user, &properties));
+    }
+    previews.sort_by_key(|preview| preview.deletion_date);
+    (StatusCode::OK, Json(previews)).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateInvite {
+    // The default group if not set
+    general_properties_id: Option<i32>,
+    #[serde(default = "default_invite_days")]
+    valid_days: i64,
+}
+
+fn default_invite_days() -> i64 {
+    14
+}
+
+async fn get_invite_codes() -> impl IntoResponse {
+    let db = get_database_connection().await;
+    match list_invite_codes(&db).await {
+        Ok(invites) => (StatusCode::OK, Json(invites)).into_response(),
+        Err(err) => AppError::from(err).into_response(),
+    }
+}
+
+async fn add_invite_code(Json(invite): Json<CreateInvite>) -> impl IntoResponse {
+    let db = get_database_connection().await;
+    let group_id = invite
+        .general_properties_id
+        .unwrap_or(default_properties_id());
+    match create_invite_code(&db, group_id, chrono::Duration::days(invite.valid_days)).await {
+        Ok(invite) => (StatusCode::OK, Json(invite)).into_response(),
+        Err(err) => (StatusCode::BAD_REQUEST, Json(err.to_string())).into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SessionRequest {
+    username: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RefreshRequest {
+    refresh_token: String,
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+fn unauthorized(reason: impl ToString) -> Response {
+    (StatusCode::UNAUTHORIZED, Json(reason.to_string())).into_response()
+}
+
+// The frontend has already checked the password, this only hands out the tokens
+async fn start_session(Json(request): Json<SessionRequest>) -> impl IntoResponse {
+    let db = get_database_connection().await;
+    match create_session(&db, &request.username).await {
+        Ok(tokens) => (StatusCode::OK, Json(tokens)).into_response(),
+        Err(err) => (StatusCode::BAD_REQUEST, Json(err.to_string())).into_response(),
+    }
+}
+
+async fn refresh_auth_session(Json(request): Json<RefreshRequest>) -> impl IntoResponse {
+    let db = get_database_connection().await;
+    match refresh_session(&db, &request.refresh_token).await {
+        Ok(tokens) => (StatusCode::OK, Json(tokens)).into_response(),
+        Err(err) => unauthorized(err),
+    }
+}
+
+async fn logout_session(headers: HeaderMap) -> impl IntoResponse {
+    let Some(access_token) = bearer_token(&headers) else {
+        return unauthorized("Missing access token");
+    };
+    let db = get_database_connection().await;
+    match logout(&db, access_token).await {
+        Ok(()) => (StatusCode::OK, Json("Logged out".to_string())).into_response(),
+        Err(err) => unauthorized(err),
+    }
+}
+
+async fn get_session_account(headers: HeaderMap) -> impl IntoResponse {
+    let Some(access_token) = bearer_token(&headers) else {
+        return unauthorized("Missing access token");
+    };
+    let db = get_database_connection().await;
+    match session_account(&db, access_token).await {
+        Ok(account) => (StatusCode::OK, Json(account)).into_response(),
+        Err(err) => unauthorized(err),
+    }
+}
+
+async fn revoke_sessions(Path(account): Path<String>) -> impl IntoResponse {
+    let db = get_database_connection().await;
+    match revoke_account_sessions(&db, &account).await {
+        Ok(revoked) => (StatusCode::OK, Json(revoked)).into_response(),
+        Err(err) => (StatusCode::BAD_REQUEST, Json(err.to_string())).into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BackfillQuery {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+// Fills in the missing sign in dates of old accounts, with dry_run only the report is returned
+async fn backfill_sign_in(
+    State(data): State<ServerConfig>,
+    Query(query): Query<BackfillQuery>,
+) -> impl IntoResponse {
+    match backfill_sign_in_dates(&*data.map.read().await, query.dry_run).await {
+        Ok(report) => (StatusCode::OK, Json(report)).into_response(),
+        Err(err) => AppError::from(err).into_response(),
+    }
+}
+
+// Clears the sign in failure counter of a parked user and runs it right away
+async fn retry_user(
+    State(data): State<ServerConfig>,
+    Path(user_name): Path<String>,
 ) -> impl IntoResponse {
     match data.map.read().await.get(&user_name) {
         Some(instance) => {
-            match send_request(
-                action,
-                &instance.request_sender,
-                &mut *instance.response_receiver.write().await,
-            )
-            .await
-            {
+            match request_response(StartRequest::Retry, &instance.request_queue).await {
                 Ok(response) => (StatusCode::OK, Json(response)).into_response(),
-                Err(err) => {
-                    (StatusCode::INTERNAL_SERVER_ERROR, Json(err.to_string())).into_response()
-                }
+                Err(err) => err.into_response(),
             }
         }
         None => (StatusCode::BAD_REQUEST, Json("User not found".to_string())).into_response(),
     }
 }
 
-async fn send_request(
-    action: Action,
-    request_sender: &Sender<StartRequest>,
-    response_receiver: &mut Receiver<RequestResponse>,
-) -> GenResult<RequestResponse> {
+async fn api_not_found() -> impl IntoResponse {
+    (StatusCode::NOT_FOUND, Json("Not found".to_string()))
+}
+
+/*
+Webhook for the mail provider, called when a mail to a user bounced or was marked as spam
+The user is found by their address, which is encrypted in the database so the loaded users are searched
+Users without a running instance are looked up in the database and paused directly
+*/
+async fn report_bounce(
+    State(data): State<ServerConfig>,
+    Json(report): Json<BounceReport>,
+) -> impl IntoResponse {
+    if !report.pauses_mail() {
+        info!("Ignoring soft bounce");
+        return (StatusCode::OK, Json("Soft bounces are ignored".to_string())).into_response();
+    }
+    let mut request_queue = None;
+    for instance in data.map.read().await.values() {
+        let user = instance.user_instance_data.user_data.read().await;
+        if user
+            .email
+            .0
+            .expose_secret()
+            .eq_ignore_ascii_case(&report.email)
+        {
+            request_queue = Some(instance.request_queue.clone());
+            break;
+        }
+    }
+    let Some(request_queue) = request_queue else {
+        return match find_user_by_email(&report.email).await {
+            Ok(Some(user_id)) => match pause_stored_user_mail(&report, user_id).await {
+                Ok(()) => (StatusCode::OK, Json("Mails paused".to_string())).into_response(),
+                Err(err) => AppError::from(err).into_response(),
+            },
+            Ok(None) => (StatusCode::NOT_FOUND, Json("User not found".to_string())).into_response(),
+            Err(err) => AppError::from(err).into_response(),
+        };
+    };
+    match request_response(StartRequest::MailBounced(report), &request_queue).await {
+        Ok(response) => (StatusCode::OK, Json(response)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn resume_mail(
+    State(data): State<ServerConfig>,
+    Path(user_name): Path<String>,
+) -> impl IntoResponse {
+    instance_request(&data, &user_name, StartRequest::ResumeMail).await
+}
+
+/*
+Gives the user a new calendar token, for when a calendar link was shared by accident. Returns the new webcal link
+The Kuma notification is created again, as its ntfy topic is derived from the token
+*/
+async fn rotate_user_calendar_token(
+    State(data): State<ServerConfig>,
+    Path(user_name): Path<String>,
+) -> impl IntoResponse {
+    let Some(user_instance_data) = data
+        .map
+        .read()
+        .await
+        .get(&user_name)
+        .map(|instance| instance.user_instance_data.clone())
+    else {
+        return (StatusCode::BAD_REQUEST, Json("User not found".to_string())).into_response();
+    };
+    let db = get_database_connection().await;
+    let calendar_token = match rotate_calendar_token(&db, &user_name).await {
+        Ok(Some(calendar_token)) => calendar_token,
+        Ok(None) => {
+            return (StatusCode::BAD_REQUEST, Json("User not found".to_string())).into_response();
+        }
+        Err(err) => return AppError::from(err).into_response(),
+    };
+    user_instance_data.user_data.write().await.calendar_token = calendar_token;
+    send_to_watchdog(
+        &data.sender,
+        WatchdogRequest::KumaRequest(
+            (KumaAction::Reset, KumaUserRequest::Users(vec![user_name])),
+            None,
+        ),
+    )
+    .await
+    .warn("Queueing kuma reset after calendar token rotation");
+    let (user, properties) = user_instance_data.get_data_local().await;
+    match create_webcal_link_local(&user, &properties) {
+        Ok(link) => (StatusCode::OK, Json(link)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct LogLevelQuery {
+    level: String,
+}
+
+// Only changes the log of this user, the level is checked here so a typo does not reach the instance
+async fn set_user_log_level(
+    State(data): State<ServerConfig>,
+    Path(user_name): Path<String>,
+    Query(query): Query<LogLevelQuery>,
+) -> impl IntoResponse {
+    if let Err(err) = user_log_filter(Some(&query.level)) {
+        return (StatusCode::BAD_REQUEST, Json(err.to_string())).into_response();
+    }
+    instance_request(
+        &data,
+        &user_name,
+        StartRequest::SetLogLevel(Some(query.level)),
+    )
+    .await
+}
+
+async fn reset_user_log_level(
+    State(data): State<ServerConfig>,
+    Path(user_name): Path<String>,
+) -> impl IntoResponse {
+    instance_request(&data, &user_name, StartRequest::SetLogLevel(None)).await
+}
+
+// Waits until the watchdog has imported the user
+async fn wait_for_instance(map: &RwLock<InstanceMap>, user_name: &str) -> bool {
+    for _ in 0..60 {
+        if map.read().await.contains_key(user_name) {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    false
+}
+
+// Sends a request to the instance of the user and returns its response
+async fn instance_request(
+    data: &ServerConfig,
+    user_name: &str,
+    start_request: StartRequest,
+) -> axum::response::Response {
+    match data.map.read().await.get(user_name) {
+        Some(instance) => match instance_response(start_request, instance).await {
+            Ok(response) => (StatusCode::OK, Json(response)).into_response(),
+            Err(err) => err.into_response(),
+        },
+        None => (StatusCode::BAD_REQUEST, Json("User not found".to_string())).into_response(),
+    }
+}
+
+/*
+Called once a new user is created, imports the user and starts the first run
+The welcome mail is sent by that run once the calendar exists, the status can be followed with a GET
+*/
+#[derive(Debug, Deserialize)]
+struct SignupQuery {
+    invite: Option<String>,
+}
+
+// New users need an invite code, users that are already running only get their onboarding again
+async fn start_signup(
+    State(data): State<ServerConfig>,
+    Path(user_name): Path<String>,
+    Query(query): Query<SignupQuery>,
+) -> impl IntoResponse {
+    if !data.map.read().await.contains_key(&user_name) {
+        let Some(invite) = query.invite else {
+            return (
+                StatusCode::FORBIDDEN,
+                Json("An invite code is needed to sign up".to_string()),
+            )
+                .into_response();
+        };
+        let db = get_database_connection().await;
+        if let Err(err) = redeem_invite_code(&db, &invite, &user_name).await {
+            warn!("Sign up of {user_name} refused: {err}");
+            return (StatusCode::FORBIDDEN, Json(err.to_string())).into_response();
+        }
+        if let Err(err) = send_to_watchdog(
+            &data.sender,
+            WatchdogRequest::SingleUser(user_name.clone(), None),
+        )
+        .await
+        {
+            // The code is not used up by a sign up that never started
+            release_invite_code(&db, &invite, &user_name)
+                .await
+                .warn("Releasing invite code");
+            return err.into_response();
+        }
+        if !wait_for_instance(&data.map, &user_name).await {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json("User could not be imported".to_string()),
+            )
+                .into_response();
+        }
+    }
+    instance_request(&data, &user_name, StartRequest::Onboarding).await
+}
+
+async fn get_onboarding_status(
+    State(data): State<ServerConfig>,
+    Path(user_name): Path<String>,
+) -> impl IntoResponse {
+    instance_request(&data, &user_name, StartRequest::OnboardingStatus).await
+}
+
+async fn get_exit_code_history(Path(user_name): Path<String>) -> impl IntoResponse {
+    match ExitCodeTransition::load_history(&user_name).await {
+        Ok(history) => (StatusCode::OK, Json(history)).into_response(),
+        Err(err) => AppError::from(err).into_response(),
+    }
+}
+
+async fn search_shifts(
+    Path(user_name): Path<String>,
+    Query(search): Query<ShiftSearch>,
+) -> impl IntoResponse {
+    match search_shift_history(&user_name, &search).await {
+        Ok(shifts) => (StatusCode::OK, Json(shifts)).into_response(),
+        Err(err) => AppError::from(err).into_response(),
+    }
+}
+
+async fn compare_actual_hours(
+    Path(user_name): Path<String>,
+    Query(filter): Query<PeriodFilter>,
+) -> impl IntoResponse {
+    match planned_vs_actual(&user_name, &filter).await {
+        Ok(periods) => (StatusCode::OK, Json(periods)).into_response(),
+        Err(err) => AppError::from(err).into_response(),
+    }
+}
+
+async fn get_calendar_shares(Path(user_name): Path<String>) -> impl IntoResponse {
+    match list_calendar_shares(&user_name).await {
+        Ok(shares) => (StatusCode::OK, Json(shares)).into_response(),
+        Err(err) => AppError::from(err).into_response(),
+    }
+}
+
+async fn add_calendar_share(
+    Path((user_name, viewer_name)): Path<(String, String)>,
+) -> impl IntoResponse {
+    match share_calendar(&user_name, &viewer_name).await {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(err) => (StatusCode::BAD_REQUEST, Json(err.to_string())).into_response(),
+    }
+}
+
+async fn remove_calendar_share(
+    Path((user_name, viewer_name)): Path<(String, String)>,
+) -> impl IntoResponse {
+    match unshare_calendar(&user_name, &viewer_name).await {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(err) => AppError::from(err).into_response(),
+    }
+}
+
+// Combined feed of all calendars shared with this user
+async fn get_buddy_calendar(Path(user_name): Path<String>) -> impl IntoResponse {
+    match load_buddy_shifts(&user_name).await {
+        Ok(shifts) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "text/calendar; charset=utf-8")],
+            create_buddy_calendar(&user_name, &shifts),
+        )
+            .into_response(),
+        Err(err) => AppError::from(err).into_response(),
+    }
+}
+
+// Calendar of one year from the shift history, /calendar/2025.ics
+async fn get_yearly_calendar(
+    Path((user_name, year_file)): Path<(String, String)>,
+) -> impl IntoResponse {
+    let Some((year, from, to)) = year_file
+        .trim_end_matches(".ics")
+        .parse::<i32>()
+        .ok()
+        .and_then(|year| {
+            Some((
+                year,
+                NaiveDate::from_ymd_opt(year, 1, 1)?,
+                NaiveDate::from_ymd_opt(year, 12, 31)?,
+            ))
+        })
+    else {
+        return (StatusCode::BAD_REQUEST, Json("Invalid year".to_string())).into_response();
+    };
+    let search = ShiftSearch {
+        from: Some(from),
+        to: Some(to),
+        ..Default::default()
+    };
+    match search_shift_history(&user_name, &search).await {
+        Ok(shifts) => (
+            StatusCode::OK,
+            [
+                (
+                    header::CONTENT_TYPE,
+                    "text/calendar; charset=utf-8".to_string(),
+                ),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{user_name}-{year}.ics\""),
+                ),
+            ],
+            create_yearly_calendar(&user_name, year, &shifts),
+        )
+            .into_response(),
+        Err(err) => AppError::from(err).into_response(),
+    }
+}
+
+// Printable PDF of the shifts of one month, with the totals and room to sign it
+async fn get_time_sheet(
+    Path((user_name, year, month)): Path<(String, i32, u32)>,
+) -> impl IntoResponse {
+    let Some((from, to)) = month_range(year, month) else {
+        return (StatusCode::BAD_REQUEST, Json("Invalid month".to_string())).into_response();
+    };
+    let search = ShiftSearch {
+        from: Some(from),
+        to: Some(to),
+        ..Default::default()
+    };
+    match search_shift_history(&user_name, &search).await {
+        Ok(shifts) => (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, "application/pdf".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{user_name}-{year}-{month:02}.pdf\""),
+                ),
+            ],
+            create_time_sheet(&user_name, year, month, &shifts),
+        )
+            .into_response(),
+        Err(err) => AppError::from(err).into_response(),
+    }
+}
+
+async fn get_swap_suggestions(Path(user_name): Path<String>) -> impl IntoResponse {
+    match load_swap_suggestions(&user_name).await {
+        Ok(swaps) => (StatusCode::OK, Json(swaps)).into_response(),
+        Err(err) => (StatusCode::BAD_REQUEST, Json(err.to_string())).into_response(),
+    }
+}
+
+async fn get_note(Path((user_name, magic_number)): Path<(String, i64)>) -> impl IntoResponse {
+    match get_shift_note(&user_name, magic_number).await {
+        Ok(Some(note)) => (StatusCode::OK, Json(note)).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, Json("No note for this shift")).into_response(),
+        Err(err) => AppError::from(err).into_response(),
+    }
+}
+
+async fn set_note(
+    Path((user_name, magic_number)): Path<(String, i64)>,
+    Json(note): Json<ShiftNote>,
+) -> impl IntoResponse {
+    match set_shift_note(&user_name, magic_number, note).await {
+        Ok(_) => (StatusCode::OK, Json("OK".to_string())),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, Json(err.to_string())),
+    }
+}
+
+async fn remove_note(Path((user_name, magic_number)): Path<(String, i64)>) -> impl IntoResponse {
+    match remove_shift_note(&user_name, magic_number).await {
+        Ok(_) => (StatusCode::OK, Json("OK".to_string())),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, Json(err.to_string())),
+    }
+}
+
+async fn send_request(action: Action, instance: &UserInstance) -> AppResult<RequestResponse> {
     let start_request = match action {
         Action::Logbook => StartRequest::Logbook,
         Action::IsActive => StartRequest::IsActive,
         Action::Name => StartRequest::Name,
         Action::Start => StartRequest::Api,
+        Action::DryRun => StartRequest::DryRun,
         Action::ExitCode => StartRequest::ExitCode,
         Action::UserData => StartRequest::UserData,
         Action::Welcome => StartRequest::Welcome,
         Action::Calendar => StartRequest::Calendar,
         Action::Delete => StartRequest::Delete,
         Action::Standing => StartRequest::Standing,
+        Action::Earnings => StartRequest::Earnings,
+        Action::Rollback => StartRequest::RollbackCalendar,
     };
-    request_sender.try_send(start_request)?;
-    let response = timeout(Duration::from_secs(10), response_receiver.recv())
+    instance_response(start_request, instance).await
+}
+
+// Read only requests are answered directly, so they don't wait for a busy instance loop
+// They still run in the span of the instance, so their logs end up in the log of the user
+async fn instance_response(
+    start_request: StartRequest,
+    instance: &UserInstance,
+) -> AppResult<RequestResponse> {
+    if start_request.is_light() {
+        let user_name = instance
+            .user_instance_data
+            .user_data
+            .read()
+            .await
+            .user_name
+            .clone();
+        return Ok(light_request(
+            &start_request,
+            &instance.user_instance_data,
+            &instance.status,
+        )
+        .instrument(warn_span!(INSTANCE_SPAN, user_name))
+        .await
+        .result_reason("No response")?);
+    }
+    request_response(start_request, &instance.request_queue).await
+}
+
+async fn request_response(
+    start_request: StartRequest,
+    request_queue: &RequestQueue,
+) -> AppResult<RequestResponse> {
+    let (request, response_receiver) = InstanceRequest::with_response(start_request);
+    request_queue.push(request)?;
+    // The instance drops the oneshot without answering if a request has no response
+    let response = timeout(Duration::from_secs(10), response_receiver)
         .await?
-        .result_reason("No response")?;
+        .map_err(|_| "No response")?;
 
     Ok(response)
 }
 
+// Waits for the watchdog to run the action, and returns what happened to every user
 async fn handle_kuma_request(
     State(data): State<ServerConfig>,
     Path((action, user_name)): Path<(KumaAction, String)>,
 ) -> impl IntoResponse {
     info!("Kuma request");
-    match handle_kuma(data.sender, user_name, action).await {
-        Ok(_) => (StatusCode::OK, Json("OK".to_string())),
-        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, Json(err.to_string())),
-    }
-}
-
-async fn handle_kuma(
-    channel: Sender<WatchdogRequest>,
-    user_name: String,
-    action: KumaAction,
-) -> GenResult<()> {
-    let user_name = match user_name {
+    let users = match user_name {
         user if user == "all" => KumaUserRequest::All,
         user => KumaUserRequest::Users(vec![user]),
     };
-    let kuma_request = (action, user_name);
-    channel.try_send(WatchdogRequest::KumaRequest(kuma_request))?;
-    Ok(())
+    let (sender, receiver) = oneshot::channel();
+    if let Err(err) = send_to_watchdog(
+        &data.sender,
+        WatchdogRequest::KumaRequest((action, users), Some(sender)),
+    )
+    .await
+    {
+        return err.into_response();
+    }
+    // Every user is a few Kuma calls, so all users can take minutes
+    match timeout(Duration::from_secs(60 * 5), receiver).await {
+        Ok(Ok(report)) => (StatusCode::OK, Json(report)).into_response(),
+        Ok(Err(_)) => AppError::from("Kuma run failed").into_response(),
+        Err(err) => AppError::from(err).into_response(),
+    }
 }
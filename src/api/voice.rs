@@ -0,0 +1,213 @@
+use std::str::FromStr;
+
+use chrono::{Datelike, NaiveDateTime, Weekday};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use strum_macros::EnumString;
+
+use crate::database::shift_history::ShiftHistoryEntry;
+
+// Shifts further away than this are said with their date, closer ones only with the day
+const WEEKDAY_ONLY_DAYS: i64 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, EnumString)]
+#[strum(ascii_case_insensitive)]
+pub enum Language {
+    #[default]
+    Nl,
+    En,
+}
+
+impl Language {
+    // Unknown languages fall back to Dutch, like all other texts
+    pub fn from_setting(language: &str) -> Self {
+        Self::from_str(language).unwrap_or_default()
+    }
+
+    fn weekday(&self, weekday: Weekday) -> &'static str {
+        let index = weekday.num_days_from_monday() as usize;
+        match self {
+            Self::Nl => [
+                "maandag",
+                "dinsdag",
+                "woensdag",
+                "donderdag",
+                "vrijdag",
+                "zaterdag",
+                "zondag",
+            ][index],
+            Self::En => [
+                "Monday",
+                "Tuesday",
+                "Wednesday",
+                "Thursday",
+                "Friday",
+                "Saturday",
+                "Sunday",
+            ][index],
+        }
+    }
+
+    fn month(&self, month: u32) -> &'static str {
+        let index = month as usize - 1;
+        match self {
+            Self::Nl => [
+                "januari",
+                "februari",
+                "maart",
+                "april",
+                "mei",
+                "juni",
+                "juli",
+                "augustus",
+                "september",
+                "oktober",
+                "november",
+                "december",
+            ][index],
+            Self::En => [
+                "January",
+                "February",
+                "March",
+                "April",
+                "May",
+                "June",
+                "July",
+                "August",
+                "September",
+                "October",
+                "November",
+                "December",
+            ][index],
+        }
+    }
+}
+
+// The voice assistants POST their webhook request as JSON, and read the answer from a JSON body in their own shape
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VoiceAssistant {
+    Alexa,
+    // Google Assistant through a Dialogflow fulfillment webhook
+    Google,
+}
+
+impl VoiceAssistant {
+    pub fn response(&self, sentence: &str) -> Value {
+        match self {
+            Self::Alexa => json!({
+                "version": "1.0",
+                "response": {
+                    "outputSpeech": { "type": "PlainText", "text": sentence },
+                    "shouldEndSession": true
+                }
+            }),
+            Self::Google => json!({ "fulfillmentText": sentence }),
+        }
+    }
+}
+
+/*
+The answer of a voice assistant to "when is my next shift", as one sentence that can be read out loud
+Close shifts are named by day, like "morgen" or "donderdag", so it sounds natural
+*/
+pub fn next_shift_sentence(
+    next_shift: Option<&ShiftHistoryEntry>,
+    now: NaiveDateTime,
+    language: Language,
+) -> String {
+    let Some(shift) = next_shift else {
+        return match language {
+            Language::Nl => "Er staan geen diensten meer in je rooster".to_owned(),
+            Language::En => "There are no upcoming shifts in your roster".to_owned(),
+        };
+    };
+    let days_until = (shift.date - now.date()).num_days();
+    let day = match (days_until, language) {
+        (0, Language::Nl) => "vandaag".to_owned(),
+        (0, Language::En) => "today".to_owned(),
+        (1, Language::Nl) => "morgen".to_owned(),
+        (1, Language::En) => "tomorrow".to_owned(),
+        (days, language) if days <= WEEKDAY_ONLY_DAYS => {
+            language.weekday(shift.date.weekday()).to_owned()
+        }
+        (_, Language::Nl) => format!(
+            "{} {} {}",
+            language.weekday(shift.date.weekday()),
+            shift.date.day(),
+            language.month(shift.date.month())
+        ),
+        (_, Language::En) => format!(
+            "{} {} {}",
+            language.weekday(shift.date.weekday()),
+            language.month(shift.date.month()),
+            shift.date.day()
+        ),
+    };
+    let start = shift.start.format("%H:%M");
+    let end = shift.end.format("%H:%M");
+    match language {
+        Language::Nl => format!(
+            "Je volgende dienst is {}, {day} van {start} tot {end}",
+            shift.number
+        ),
+        Language::En => format!(
+            "Your next shift is {}, {day} from {start} to {end}",
+            shift.number
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webcom::shift::ShiftState;
+    use chrono::{NaiveDate, NaiveTime};
+
+    #[test]
+    fn sentence_names_the_day() {
+        let now = NaiveDate::from_ymd_opt(2026, 10, 13)
+            .unwrap()
+            .and_hms_opt(20, 0, 0)
+            .unwrap();
+        let mut shift = ShiftHistoryEntry {
+            magic_number: 1,
+            uid: "V2309".to_owned(),
+            number: "V2309".to_owned(),
+            kind: "Rijdienst".to_owned(),
+            date: NaiveDate::from_ymd_opt(2026, 10, 15).unwrap(),
+            start: NaiveTime::from_hms_opt(6, 14, 0).unwrap(),
+            end_date: NaiveDate::from_ymd_opt(2026, 10, 15).unwrap(),
+            end: NaiveTime::from_hms_opt(13, 54, 0).unwrap(),
+            duration_minutes: 460,
+            working_minutes: 460,
+            state: ShiftState::Unchanged,
+        };
+        assert_eq!(
+            next_shift_sentence(Some(&shift), now, Language::Nl),
+            "Je volgende dienst is V2309, donderdag van 06:14 tot 13:54"
+        );
+        assert_eq!(
+            next_shift_sentence(Some(&shift), now, Language::from_setting("EN")),
+            "Your next shift is V2309, Thursday from 06:14 to 13:54"
+        );
+        shift.date = NaiveDate::from_ymd_opt(2026, 10, 14).unwrap();
+        assert!(next_shift_sentence(Some(&shift), now, Language::Nl).contains("morgen van"));
+        shift.date = NaiveDate::from_ymd_opt(2026, 10, 29).unwrap();
+        assert!(
+            next_shift_sentence(Some(&shift), now, Language::from_setting("fr"))
+                .contains("donderdag 29 oktober")
+        );
+    }
+
+    #[test]
+    fn webhook_responses_have_the_shape_of_the_assistant() {
+        let alexa = VoiceAssistant::Alexa.response("Hallo");
+        assert_eq!(alexa["response"]["outputSpeech"]["text"], "Hallo");
+        assert_eq!(alexa["version"], "1.0");
+        let google = VoiceAssistant::Google.response("Hallo");
+        assert_eq!(google["fulfillmentText"], "Hallo");
+        let assistant: VoiceAssistant = serde_json::from_str("\"alexa\"").unwrap();
+        assert_eq!(assistant, VoiceAssistant::Alexa);
+    }
+}
@@ -0,0 +1,244 @@
+use std::io::{self, BufRead, Write};
+
+use crate::config::var;
+use clap::{Parser, Subcommand};
+use entity::{general_properties_db, user_data, user_properties};
+use migration::{Migrator, MigratorTrait};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, EntityTrait, IntoActiveModel, QueryOrder, TransactionTrait,
+};
+
+use crate::{
+    GenResult,
+    api::auth::API_KEY_HEADER,
+    database::{
+        secret::Secret,
+        state_file::{read_rotated_state_files, write_rotated_state_files},
+    },
+    execution::single_user::run_single_user,
+    get_database_connection,
+};
+
+const DEFAULT_API_URL: &str = "https://localhost:3000";
+
+/// Administration of Mijn Bussie. Without a subcommand the application itself is started
+#[derive(Parser)]
+#[command(name = "mijnbussie", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Accept an invalid certificate of the API, like the self signed one it uses most of the time
+    #[arg(long, global = true)]
+    pub insecure: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Manage the users in the database
+    User {
+        #[command(subcommand)]
+        action: UserCommand,
+    },
+    /// Start a run for a user on the running application
+    Run {
+        user_name: String,
+        /// Only show what would happen, without sending mails or changing the calendar
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Run the user defined by the SINGLE_USER_ settings once and exit, without starting the application
+    Single {
+        /// Only show what would happen, without sending mails or changing the calendar
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Send the welcome mail of a user again, to test the mail settings
+    SendTestMail { user_name: String },
+    /// Apply all pending database migrations
+    Migrate,
+    /// Encrypt all user secrets with a new password secret, which is read from stdin
+    RotateSecret,
+}
+
+#[derive(Subcommand)]
+pub enum UserCommand {
+    /// Add a new user, the running application picks it up directly. The password is read from stdin
+    Add {
+        user_name: String,
+        personeelsnummer: String,
+        email: String,
+    },
+    /// Remove a user, this also removes all files of the user
+    Remove { user_name: String },
+    /// List all users
+    List,
+}
+
+pub async fn run_command(command: Command, insecure: bool) -> GenResult<()> {
+    match command {
+        Command::User {
+            action:
+                UserCommand::Add {
+                    user_name,
+                    personeelsnummer,
+                    email,
+                },
+        } => {
+            let password = read_secret("Webcom password")?;
+            add_user(user_name, &personeelsnummer, &password, &email, insecure).await
+        }
+        Command::User {
+            action: UserCommand::Remove { user_name },
+        } => api_request(&format!("{user_name}/delete"), insecure).await,
+        Command::User {
+            action: UserCommand::List,
+        } => list_users().await,
+        Command::Run { user_name, dry_run } => {
+            let action = if dry_run { "dry_run" } else { "start" };
+            api_request(&format!("{user_name}/{action}"), insecure).await
+        }
+        Command::Single { dry_run } => run_single_user(dry_run).await,
+        Command::SendTestMail { user_name } => {
+            api_request(&format!("{user_name}/welcome"), insecure).await
+        }
+        Command::Migrate => {
+            Migrator::up(&get_database_connection().await, None).await?;
+            println!("All migrations applied");
+            Ok(())
+        }
+        Command::RotateSecret => rotate_secret(&read_secret("New password secret")?).await,
+    }
+}
+
+// Secrets are not taken as arguments, those end up in the shell history and are visible in ps
+// The prompt goes to stderr, so the secret can also be piped in by a script
+fn read_secret(prompt: &str) -> GenResult<String> {
+    eprint!("{prompt}: ");
+    io::stderr().flush()?;
+    let mut secret = String::new();
+    io::stdin().lock().read_line(&mut secret)?;
+    let secret = secret.trim_end_matches(['\r', '\n']).to_owned();
+    if secret.is_empty() {
+        return Err(format!("{prompt} can not be empty").into());
+    }
+    Ok(secret)
+}
+
+// Send a request to the API of the running application
+async fn api_request(path: &str, insecure: bool) -> GenResult<()> {
+    let api_url = var("CLI_API_URL").unwrap_or(DEFAULT_API_URL.to_owned());
+    let api_key = var("API_KEY")?;
+    let client = reqwest::Client::builder()
+        .danger_accept_invalid_certs(insecure)
+        .build()?;
+    // A header instead of the query, so the key does not end up in access logs
+    let response = client
+        .get(format!("{api_url}/api/{path}"))
+        .header(API_KEY_HEADER, api_key)
+        .send()
+        .await?;
+    let status = response.status();
+    println!("{status}: {}", response.text().await?);
+    match status.is_success() {
+        true => Ok(()),
+        false => Err(format!("Request failed with status {status}").into()),
+    }
+}
+
+async fn add_user(
+    user_name: String,
+    personeelsnummer: &str,
+    password: &str,
+    email: &str,
+    insecure: bool,
+) -> GenResult<()> {
+    let db = get_database_connection().await;
+    // All properties of the user get the default values of the database
+    let properties = user_properties::ActiveModel {
+        ..Default::default()
+    }
+    .insert(&db)
+    .await?;
+    user_data::ActiveModel {
+        user_name: Set(user_name.clone()),
+        personeelsnummer: Set(Secret::encrypt_value(personeelsnummer)?),
+        password: Set(Secret::encrypt_value(password)?),
+        email: Set(Secret::encrypt_value(email)?),
+        file_name: Set(String::new()),
+        user_properties: Set(properties.user_properties_id),
+        activated: Set(true),
+        ..Default::default()
+    }
+    .insert(&db)
+    .await?;
+    println!("Added user {user_name}");
+    // Not being able to reach the application is not a problem, the user will be picked up at the next watchdog cycle
+    if api_request("refresh", insecure).await.is_err() {
+        println!("Could not reach Mijn Bussie, the user will be started at the next refresh");
+    }
+    Ok(())
+}
+
+async fn list_users() -> GenResult<()> {
+    let db = get_database_connection().await;
+    let users = user_data::Entity::find()
+        .order_by_asc(user_data::Column::UserName)
+        .all(&db)
+        .await?;
+    for user in users {
+        let last_execution = user
+            .last_execution_date
+            .map(|date| date.to_string())
+            .unwrap_or("nooit".to_owned());
+        println!("{} (laatst uitgevoerd: {last_execution})", user.user_name);
+    }
+    Ok(())
+}
+
+/*
+Decrypt all secrets with the current secret and encrypt them with the new one, the database in a single transaction
+The encrypted state files of ENCRYPT_STATE_FILES are rotated too. They are all decrypted before the database is changed,
+so a file that can't be read stops the rotation before anything is written
+*/
+async fn rotate_secret(new_secret: &str) -> GenResult<()> {
+    // Other backends manage their own keys
+    if var("SECRET_BACKEND").is_ok_and(|backend| backend != "local") {
+        return Err("Rotating the secret is only possible with the local secret backend".into());
+    }
+    let old_secret = var("PASSWORD_SECRET")?;
+    let db = get_database_connection().await;
+    let rotate =
+        |value: &str| Secret::encrypt_with(&Secret::decrypt_with(value, &old_secret)?, new_secret);
+    let file_targets: Vec<String> = general_properties_db::Entity::find()
+        .all(&db)
+        .await?
+        .into_iter()
+        .map(|properties| properties.file_target)
+        .collect();
+    let state_files = read_rotated_state_files(&file_targets, &rotate)?;
+    let transaction = db.begin().await?;
+    let users = user_data::Entity::find().all(&transaction).await?;
+    let user_count = users.len();
+    for user in users {
+        let name = match &user.name {
+            Some(name) => Some(rotate(name)?),
+            None => None,
+        };
+        let personeelsnummer = rotate(&user.personeelsnummer)?;
+        let password = rotate(&user.password)?;
+        let email = rotate(&user.email)?;
+        let mut active_user = user.into_active_model();
+        active_user.personeelsnummer = Set(personeelsnummer);
+        active_user.password = Set(password);
+        active_user.email = Set(email);
+        active_user.name = Set(name);
+        active_user.update(&transaction).await?;
+    }
+    transaction.commit().await?;
+    let file_count = state_files.len();
+    write_rotated_state_files(state_files)?;
+    println!(
+        "Rotated the secrets of {user_count} users and {file_count} state files. Set PASSWORD_SECRET to the new secret before restarting Mijn Bussie"
+    );
+    Ok(())
+}
@@ -0,0 +1,243 @@
+use std::{
+    collections::HashMap,
+    env::VarError,
+    path::PathBuf,
+    sync::{LazyLock, RwLock},
+    time::Duration,
+};
+
+use sea_orm::Database;
+use tokio::{net::TcpStream, time::timeout};
+use tracing::*;
+
+use crate::{
+    GenResult,
+    database::variables::GeneralProperties,
+    webcom::mail_transport::{smtp_port, validate_mail_transport},
+};
+
+// Not config.toml, that is the config of the Selenium nodes
+const DEFAULT_CONFIG_FILE: &str = "./mijn_bussie.toml";
+const REQUIRED_VARIABLES: [&str; 4] =
+    ["DATABASE_URL", "SELENIUM_URL", "PASSWORD_SECRET", "API_KEY"];
+const TEMPLATE_DIR: &str = "templates";
+// Every template that is loaded somewhere, a missing one would otherwise only be noticed when a mail is sent
+const REQUIRED_TEMPLATES: &[&str] = &[
+    "email_base.html",
+    "changed_shift.html",
+    "shift_table.html",
+    "removed_shift_base.html",
+    "onboarding_base.html",
+    "potential_account_deletion.html",
+    "inform_account_deletion.html",
+    "new_password_failed.html",
+    "failed_signin.html",
+    "signin_succesful.html",
+    "kuma_offline.html",
+    "kuma_online.html",
+    "kuma_notification_ntfy.json",
+    "kuma_notification_telegram.json",
+    "kuma_notification_webhook.json",
+    "overtime.html",
+    "reserve_call_out.html",
+    "stale_calendar.html",
+    "donation_reminder.html",
+    "new_user.html",
+    "mail_paused.html",
+    "admin_alert.html",
+    "weekly_summary.html",
+];
+const SMTP_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/*
+The .env file as it was read by the last reload. Changing the process env while other threads read it is not safe,
+so a reload only replaces this map. Its values go over the env of the process, like dotenv_override does at startup
+*/
+static RELOADED_ENV: LazyLock<RwLock<HashMap<String, String>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+// Read a setting, use this instead of dotenvy::var or std::env::var so reloaded values are seen
+pub fn var(key: &str) -> Result<String, VarError> {
+    if let Some(value) = RELOADED_ENV
+        .read()
+        .ok()
+        .and_then(|reloaded_env| reloaded_env.get(key).cloned())
+    {
+        return Ok(value);
+    }
+    std::env::var(key)
+}
+
+// Read the .env file again, settings that are read when they are used pick up the new values
+// Only done on a SIGHUP, so only on unix
+#[cfg(unix)]
+pub fn reload_env_file() -> GenResult<()> {
+    let values = dotenvy::dotenv_iter()?.collect::<Result<HashMap<_, _>, _>>()?;
+    info!("Reloaded {} settings from the env file", values.len());
+    *RELOADED_ENV
+        .write()
+        .map_err(|_| "Reloaded env lock is poisoned")? = values;
+    Ok(())
+}
+
+/*
+Load the optional TOML config file. Every key in the file is used as an env variable,
+but only if it is not already set by the env or .env file. So the env always overrides the config file
+*/
+pub fn load_config_file() -> GenResult<()> {
+    let path = PathBuf::from(var("CONFIG_FILE").unwrap_or(DEFAULT_CONFIG_FILE.to_owned()));
+    if !path.exists() {
+        debug!("No config file found at {path:?}");
+        return Ok(());
+    }
+    let config: toml::Table = std::fs::read_to_string(&path)?.parse()?;
+    for (key, value) in config {
+        let key = key.to_uppercase();
+        if var(&key).is_ok() {
+            continue;
+        }
+        let value = match value {
+            toml::Value::String(value) => value,
+            value => value.to_string(),
+        };
+        // SAFETY: This is called from main before the tokio runtime is started, so there are no other threads yet
+        unsafe { std::env::set_var(key, value) };
+    }
+    info!("Loaded config file {path:?}");
+    Ok(())
+}
+
+/*
+Check all settings needed to run before starting anything.
+All problems are collected so they can be reported at once, instead of panicking on the first one
+*/
+pub async fn validate_startup() -> GenResult<()> {
+    let mut problems: Vec<String> = vec![];
+
+    for variable in REQUIRED_VARIABLES {
+        if var(variable).is_ok_and(|value| !value.is_empty()) {
+            continue;
+        }
+        problems.push(format!("Setting {variable} is missing"));
+    }
+
+    problems.append(&mut validate_files());
+    problems.extend(validate_mail_transport());
+    problems.append(&mut validate_database_settings().await);
+
+    if problems.is_empty() {
+        return Ok(());
+    }
+    for problem in &problems {
+        error!("{problem}");
+    }
+    Err(format!(
+        "Startup validation found {} problems: {}",
+        problems.len(),
+        problems.join(", ")
+    )
+    .into())
+}
+
+// Paths are always built with PathBuf, so the separator is right on every OS
+pub fn template_path(template: &str) -> PathBuf {
+    PathBuf::from(TEMPLATE_DIR).join(template)
+}
+
+// Public URL of the API, without a trailing slash. Links in mails only point to the API if it is set
+// CALENDAR_API_URL is the old name, deployments which still have it set keep working
+pub fn public_api_url() -> Option<String> {
+    ["PUBLIC_API_URL", "CALENDAR_API_URL"]
+        .into_iter()
+        .filter_map(|key| var(key).ok())
+        .find(|url| !url.is_empty())
+        .map(|url| url.trim_end_matches('/').to_owned())
+}
+
+// Check if all templates and certificate files exist and are readable
+pub fn validate_files() -> Vec<String> {
+    let mut problems = vec![];
+    let files = REQUIRED_TEMPLATES
+        .iter()
+        .map(|template| template_path(template))
+        .chain([
+            PathBuf::from("cert").join("cert.crt"),
+            PathBuf::from("cert").join("key.key"),
+        ]);
+    for file in files {
+        if let Err(err) = std::fs::read(&file) {
+            problems.push(format!("File {file:?} can not be read: {err}"));
+        }
+    }
+    problems
+}
+
+// The file target must be a directory that can be written to, otherwise no user can save anything
+pub fn validate_file_target(file_target: &str) -> Option<String> {
+    if file_target.is_empty() {
+        return Some("File target is not set".to_owned());
+    }
+    let path = PathBuf::from(file_target);
+    if let Err(err) = std::fs::create_dir_all(&path) {
+        return Some(format!("File target {path:?} can not be created: {err}"));
+    }
+    let test_file = path.join(".write_test");
+    match std::fs::write(&test_file, []) {
+        Ok(_) => {
+            _ = std::fs::remove_file(test_file);
+            None
+        }
+        Err(err) => Some(format!("File target {path:?} is not writable: {err}")),
+    }
+}
+
+// Check the settings that are stored in the database, like the default properties and the SMTP server
+async fn validate_database_settings() -> Vec<String> {
+    let Ok(database_url) = var("DATABASE_URL") else {
+        return vec![];
+    };
+    let db = match Database::connect(&database_url).await {
+        Ok(db) => db,
+        Err(err) => return vec![format!("Could not connect to database: {err}")],
+    };
+    let properties_id = var("DEFAULT_PROPERTIES_ID")
+        .ok()
+        .and_then(|id| id.parse::<i32>().ok())
+        .unwrap_or(1);
+    let properties = match GeneralProperties::get(&db, properties_id).await {
+        Ok(Some(properties)) => properties,
+        Ok(None) => {
+            return vec![format!(
+                "Default properties with id {properties_id} do not exist"
+            )];
+        }
+        // The tables might not exist yet if the migrations have not been run before
+        Err(err) => {
+            warn!("Could not load default properties, skipping validation. Error: {err}");
+            return vec![];
+        }
+    };
+
+    let mut problems = vec![];
+    let smtp_server = &properties.general_email_properties.smtp_server;
+    if smtp_server.is_empty() {
+        problems.push("SMTP server is not set".to_owned());
+    } else {
+        // The SMTP server being down for a moment should not stop the application, mails are retried anyway
+        let smtp_port = smtp_port();
+        match timeout(
+            SMTP_CONNECT_TIMEOUT,
+            TcpStream::connect((smtp_server.as_str(), smtp_port)),
+        )
+        .await
+        {
+            Ok(Ok(_)) => (),
+            Ok(Err(err)) => warn!("SMTP server {smtp_server}:{smtp_port} is unreachable: {err}"),
+            Err(_) => warn!("SMTP server {smtp_server}:{smtp_port} did not respond"),
+        }
+    }
+    if let Some(problem) = validate_file_target(&properties.file_target) {
+        problems.push(problem);
+    }
+    problems
+}
@@ -0,0 +1,103 @@
+use chrono::{Duration, NaiveDateTime};
+use entity::{calendar_fetch, user_data};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait,
+    IntoActiveModel, QueryFilter, QueryOrder, sea_query::Expr,
+};
+use serde::Serialize;
+
+use crate::GenResult;
+
+// Calendar clients fetch every few hours, so only recent fetches are kept
+const FETCH_RETENTION: Duration = Duration::days(90);
+
+/*
+Every time a calendar client fetches the calendar through the API it is stored, with the user agent of the client
+This tells support whether a user ever actually subscribed to their calendar, and with which app
+*/
+#[derive(Debug, Clone, Serialize)]
+pub struct CalendarFetch {
+    pub fetched_at: NaiveDateTime,
+    pub user_agent: Option<String>,
+}
+
+pub async fn record_calendar_fetch(
+    db: &DatabaseConnection,
+    user: user_data::Model,
+    user_agent: Option<String>,
+) -> GenResult<()> {
+    let now = chrono::offset::Utc::now().naive_utc();
+    let user_data_id = user.user_data_id;
+    calendar_fetch::ActiveModel {
+        user_data_id: Set(user_data_id),
+        user_agent: Set(user_agent),
+        fetched_at: Set(now),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+    if user.first_calendar_fetch_date.is_none() {
+        let mut active_user = user.into_active_model();
+        active_user.first_calendar_fetch_date = Set(Some(now));
+        user_data::Entity::update(active_user)
+            .validate()?
+            .exec(db)
+            .await?;
+    }
+    calendar_fetch::Entity::delete_many()
+        .filter(calendar_fetch::Column::UserDataId.eq(user_data_id))
+        .filter(calendar_fetch::Column::FetchedAt.lt(now - FETCH_RETENTION))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+pub async fn last_calendar_fetch(
+    db: &DatabaseConnection,
+    user_data_id: i32,
+) -> GenResult<Option<CalendarFetch>> {
+    Ok(calendar_fetch::Entity::find()
+        .filter(calendar_fetch::Column::UserDataId.eq(user_data_id))
+        .order_by_desc(calendar_fetch::Column::FetchedAt)
+        .one(db)
+        .await?
+        .map(|fetch| CalendarFetch {
+            fetched_at: fetch.fetched_at,
+            user_agent: fetch.user_agent,
+        }))
+}
+
+pub async fn find_user_by_calendar_token(
+    db: &DatabaseConnection,
+    calendar_token: &str,
+) -> GenResult<Option<user_data::Model>> {
+    Ok(user_data::Entity::find()
+        .filter(user_data::Column::CalendarToken.eq(calendar_token))
+        .one(db)
+        .await?)
+}
+
+// 32 random bytes as hex, like the default of the column
+fn new_calendar_token() -> String {
+    rand::random::<[u8; 32]>()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+// Gives the user a new calendar token, the links with the old token stop working right away
+pub async fn rotate_calendar_token(
+    db: &DatabaseConnection,
+    user_name: &str,
+) -> GenResult<Option<String>> {
+    let calendar_token = new_calendar_token();
+    let result = user_data::Entity::update_many()
+        .col_expr(
+            user_data::Column::CalendarToken,
+            Expr::value(calendar_token.clone()),
+        )
+        .filter(user_data::Column::UserName.eq(user_name))
+        .exec(db)
+        .await?;
+    Ok((result.rows_affected != 0).then_some(calendar_token))
+}
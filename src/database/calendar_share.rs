@@ -0,0 +1,142 @@
+use chrono::{Days, Local, NaiveDate, NaiveTime};
+use entity::{calendar_share, shift_history};
+use sea_orm::{
+    ActiveValue::Set,
+    ColumnTrait, EntityTrait, QueryFilter, QueryOrder,
+    sea_query::{ExprTrait, OnConflict},
+};
+use serde::Serialize;
+
+use crate::{
+    GenResult,
+    database::{shift_notes::get_user_id, variables::UserData},
+    get_database_connection,
+    health::ApplicationLogbook,
+    webcom::shift::ShiftState,
+};
+
+// How far back the shared calendar goes, older shifts are not useful for coordinating
+const SHARED_DAYS_BACK: u64 = 7;
+
+#[derive(Debug, Default, Serialize)]
+pub struct CalendarShares {
+    // Users who can see the calendar of this user
+    pub shared_with: Vec<String>,
+    // Users whose calendar this user can see
+    pub shared_by: Vec<String>,
+}
+
+/*
+A shift of a colleague, filtered so only the number and times are left
+Kind, location and links to the shift sheet are left out on purpose
+The owner is shown by user name, the real name is only known to the application and the owner
+*/
+#[derive(Debug, Clone, Serialize)]
+pub struct BuddyShift {
+    // Stays the same between fetches, so calendar clients update the event instead of adding it again
+    pub uid: String,
+    pub owner: String,
+    pub number: String,
+    pub date: NaiveDate,
+    pub start: NaiveTime,
+    pub end_date: NaiveDate,
+    pub end: NaiveTime,
+}
+
+pub async fn share_calendar(owner_name: &str, viewer_name: &str) -> GenResult<()> {
+    let db = get_database_connection().await;
+    let owner_id = get_user_id(&db, owner_name).await?;
+    let viewer_id = get_user_id(&db, viewer_name).await?;
+    if owner_id == viewer_id {
+        return Err("A calendar can not be shared with its owner".into());
+    }
+    calendar_share::Entity::insert(calendar_share::ActiveModel {
+        owner_user_data_id: Set(owner_id),
+        viewer_user_data_id: Set(viewer_id),
+        created_at: Set(ApplicationLogbook::get_naive_datetime()),
+        ..Default::default()
+    })
+    .on_conflict(
+        OnConflict::columns([
+            calendar_share::Column::OwnerUserDataId,
+            calendar_share::Column::ViewerUserDataId,
+        ])
+        .do_nothing()
+        .to_owned(),
+    )
+    .do_nothing()
+    .exec(&db)
+    .await?;
+    Ok(())
+}
+
+pub async fn unshare_calendar(owner_name: &str, viewer_name: &str) -> GenResult<()> {
+    let db = get_database_connection().await;
+    let owner_id = get_user_id(&db, owner_name).await?;
+    let viewer_id = get_user_id(&db, viewer_name).await?;
+    calendar_share::Entity::delete_many()
+        .filter(calendar_share::Column::OwnerUserDataId.eq(owner_id))
+        .filter(calendar_share::Column::ViewerUserDataId.eq(viewer_id))
+        .exec(&db)
+        .await?;
+    Ok(())
+}
+
+pub async fn list_calendar_shares(user_name: &str) -> GenResult<CalendarShares> {
+    let db = get_database_connection().await;
+    let user_id = get_user_id(&db, user_name).await?;
+    let mut shares = CalendarShares::default();
+    for share in calendar_share::Entity::find()
+        .filter(
+            calendar_share::Column::OwnerUserDataId
+                .eq(user_id)
+                .or(calendar_share::Column::ViewerUserDataId.eq(user_id)),
+        )
+        .all(&db)
+        .await?
+    {
+        if share.owner_user_data_id == user_id {
+            if let Some(viewer) = UserData::get_id(&db, share.viewer_user_data_id).await? {
+                shares.shared_with.push(viewer.user_name);
+            }
+        } else if let Some(owner) = UserData::get_id(&db, share.owner_user_data_id).await? {
+            shares.shared_by.push(owner.user_name);
+        }
+    }
+    Ok(shares)
+}
+
+// All recent and upcoming shifts of the users who shared their calendar with this user
+pub async fn load_buddy_shifts(viewer_name: &str) -> GenResult<Vec<BuddyShift>> {
+    let db = get_database_connection().await;
+    let viewer_id = get_user_id(&db, viewer_name).await?;
+    let deleted_state = serde_json::to_string(&ShiftState::Deleted)?;
+    let first_date = Local::now().date_naive() - Days::new(SHARED_DAYS_BACK);
+    let mut buddy_shifts = vec![];
+    for share in calendar_share::Entity::find()
+        .filter(calendar_share::Column::ViewerUserDataId.eq(viewer_id))
+        .all(&db)
+        .await?
+    {
+        let Some(owner) = UserData::get_id(&db, share.owner_user_data_id).await? else {
+            continue;
+        };
+        let shifts = shift_history::Entity::find()
+            .filter(shift_history::Column::UserDataId.eq(owner.id))
+            .filter(shift_history::Column::ShiftDate.gte(first_date))
+            .filter(shift_history::Column::State.ne(deleted_state.clone()))
+            .order_by_asc(shift_history::Column::ShiftDate)
+            .all(&db)
+            .await?;
+        buddy_shifts.extend(shifts.into_iter().map(|shift| BuddyShift {
+            uid: format!("buddy-{}-{}", owner.id, shift.shift_uid),
+            owner: owner.user_name.clone(),
+            number: shift.number,
+            date: shift.shift_date,
+            start: shift.start_time,
+            end_date: shift.end_date,
+            end: shift.end_time,
+        }));
+    }
+    Ok(buddy_shifts)
+}
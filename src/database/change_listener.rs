@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use crate::config::var;
+use sea_orm::sqlx::postgres::PgListener;
+use tokio::{sync::mpsc::Sender, time::sleep};
+use tracing::*;
+
+use crate::{GenResult, errors::ResultLog, execution::watchdog::WatchdogRequest};
+
+// The channel the triggers of the change_notify and properties_change_notify migrations send on
+const CHANGE_CHANNEL: &str = "bussie_changes";
+// How long to wait before listening again after the connection is lost
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/*
+Wakes the watchdog as soon as a user or properties change in the database, so a new signup does not wait for the next refresh
+The refresh every WATCHDOG_INTERVAL stays, in case a notification is missed while reconnecting
+*/
+pub async fn listen_for_changes(watchdog_sender: Sender<WatchdogRequest>) {
+    loop {
+        listen(&watchdog_sender)
+            .await
+            .warn("Listening for database changes");
+        if watchdog_sender.is_closed() {
+            return;
+        }
+        sleep(RECONNECT_DELAY).await;
+    }
+}
+
+async fn listen(watchdog_sender: &Sender<WatchdogRequest>) -> GenResult<()> {
+    let mut listener = PgListener::connect(&var("DATABASE_URL")?).await?;
+    listener.listen(CHANGE_CHANNEL).await?;
+    info!("Listening for database changes");
+    loop {
+        let notification = listener.recv().await?;
+        let Some(request) = change_request(notification.payload()) else {
+            warn!("Unknown database change {}", notification.payload());
+            continue;
+        };
+        debug!("Database changed, sending {request:?}");
+        watchdog_sender.send(request).await?;
+    }
+}
+
+fn change_request(payload: &str) -> Option<WatchdogRequest> {
+    match payload.split_once(':') {
+        Some(("user", user_name)) if !user_name.is_empty() => {
+            Some(WatchdogRequest::SingleUser(user_name.to_owned(), None))
+        }
+        _ if payload == "properties" => Some(WatchdogRequest::ReloadProperties),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_is_turned_into_request() {
+        assert!(matches!(
+            change_request("user:piet"),
+            Some(WatchdogRequest::SingleUser(user_name, None)) if user_name == "piet"
+        ));
+        assert!(matches!(
+            change_request("properties"),
+            Some(WatchdogRequest::ReloadProperties)
+        ));
+        assert!(change_request("user:").is_none());
+        assert!(change_request("something else").is_none());
+    }
+}
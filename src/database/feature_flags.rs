@@ -0,0 +1,103 @@
+use entity::feature_flag;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait,
+    IntoActiveModel, QueryFilter,
+};
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+use tracing::*;
+
+use crate::{GenResult, database::variables::GeneralProperties, get_data, get_database_connection};
+
+/*
+Flags to roll out experimental parts of Mijn Bussie to a single properties group first.
+A flag that is not in the database is disabled
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, Display, EnumString)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum FeatureFlag {
+    HttpScraper,
+    DigestMode,
+    Caldav,
+    // Reading the signed off duties, the page is not available for every depot yet
+    SignedOffDuties,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeatureFlagState {
+    pub flag: String,
+    pub enabled: bool,
+}
+
+impl FeatureFlag {
+    // Check the flag for the properties of the current instance
+    pub async fn is_enabled(self) -> bool {
+        let (_user, properties) = get_data();
+        self.is_enabled_local(&properties).await
+    }
+
+    pub async fn is_enabled_local(self, properties: &GeneralProperties) -> bool {
+        let db = get_database_connection().await;
+        match feature_flag::Entity::find()
+            .filter(feature_flag::Column::GeneralPropertiesId.eq(properties.general_properties_id))
+            .filter(feature_flag::Column::FlagName.eq(self.to_string()))
+            .one(&db)
+            .await
+        {
+            Ok(flag) => flag.is_some_and(|flag| flag.enabled),
+            Err(err) => {
+                warn!("Could not load feature flag {self}, treating it as disabled. Error: {err}");
+                false
+            }
+        }
+    }
+
+    pub async fn set(
+        self,
+        db: &DatabaseConnection,
+        general_properties_id: i32,
+        enabled: bool,
+    ) -> GenResult<()> {
+        let existing_flag = feature_flag::Entity::find()
+            .filter(feature_flag::Column::GeneralPropertiesId.eq(general_properties_id))
+            .filter(feature_flag::Column::FlagName.eq(self.to_string()))
+            .one(db)
+            .await?;
+        match existing_flag {
+            Some(flag) => {
+                let mut active_flag = flag.into_active_model();
+                active_flag.enabled = Set(enabled);
+                active_flag.update(db).await?;
+            }
+            None => {
+                feature_flag::ActiveModel {
+                    general_properties_id: Set(general_properties_id),
+                    flag_name: Set(self.to_string()),
+                    enabled: Set(enabled),
+                    ..Default::default()
+                }
+                .insert(db)
+                .await?;
+            }
+        }
+        info!("Feature flag {self} set to {enabled} for properties group {general_properties_id}");
+        Ok(())
+    }
+
+    pub async fn list(
+        db: &DatabaseConnection,
+        general_properties_id: i32,
+    ) -> GenResult<Vec<FeatureFlagState>> {
+        Ok(feature_flag::Entity::find()
+            .filter(feature_flag::Column::GeneralPropertiesId.eq(general_properties_id))
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|flag| FeatureFlagState {
+                flag: flag.flag_name,
+                enabled: flag.enabled,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,178 @@
+use chrono::{Duration, NaiveDateTime};
+use entity::{general_properties_db, invite_code, user_data};
+use rand::distr::{Alphanumeric, SampleString};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder, TransactionTrait, sea_query::Expr,
+};
+
+use crate::{
+    GenResult, database::variables::default_properties_id, errors::OptionResult,
+    health::ApplicationLogbook,
+};
+
+const CODE_LENGTH: usize = 10;
+// How long the invite in the mail to a deleted user can be used to sign up again
+pub const DELETED_USER_INVITE_DURATION: Duration = Duration::days(60);
+
+/*
+Signing up needs an invite code, so the sign up link can be shared without letting anybody register
+A code can be used once, and puts the new user in the properties group it was made for
+*/
+pub async fn create_invite_code(
+    db: &DatabaseConnection,
+    group_id: i32,
+    valid_for: Duration,
+) -> GenResult<invite_code::Model> {
+    general_properties_db::Entity::find_by_id(group_id)
+        .one(db)
+        .await?
+        .result_reason("Properties group not found")?;
+    let now = ApplicationLogbook::get_naive_datetime();
+    // The generator can't be held over an await
+    let code = Alphanumeric.sample_string(&mut rand::rng(), CODE_LENGTH);
+    Ok(invite_code::ActiveModel {
+        code: Set(code),
+        general_properties_id: Set(group_id),
+        expires_at: Set(now + valid_for),
+        created_at: Set(now),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?)
+}
+
+pub async fn list_invite_codes(db: &DatabaseConnection) -> GenResult<Vec<invite_code::Model>> {
+    Ok(invite_code::Entity::find()
+        .order_by_desc(invite_code::Column::CreatedAt)
+        .all(db)
+        .await?)
+}
+
+fn check_usable(invite: &invite_code::Model, now: NaiveDateTime) -> Result<(), &'static str> {
+    if invite.redeemed_by.is_some() {
+        Err("Invite code has already been used")
+    } else if invite.expires_at < now {
+        Err("Invite code has expired")
+    } else {
+        Ok(())
+    }
+}
+
+/*
+Users added by the frontend are not activated, so the watchdog doesn't start them before they redeemed a code
+Redeeming claims the code, moves the user to the group of the code and activates it, in one transaction
+If the code can't be used the user that was waiting for it is removed again
+*/
+pub async fn redeem_invite_code(
+    db: &DatabaseConnection,
+    code: &str,
+    user_name: &str,
+) -> GenResult<()> {
+    let result = claim_invite_code(db, code, user_name).await;
+    if result.is_err() {
+        user_data::Entity::delete_many()
+            .filter(user_data::Column::UserName.eq(user_name))
+            .filter(user_data::Column::Activated.eq(false))
+            .exec(db)
+            .await?;
+    }
+    result
+}
+
+async fn claim_invite_code(db: &DatabaseConnection, code: &str, user_name: &str) -> GenResult<()> {
+    let invite = invite_code::Entity::find()
+        .filter(invite_code::Column::Code.eq(code))
+        .one(db)
+        .await?
+        .result_reason("Unknown invite code")?;
+    let now = ApplicationLogbook::get_naive_datetime();
+    check_usable(&invite, now)?;
+    let group_id = Some(invite.general_properties_id).filter(|id| *id != default_properties_id());
+    let transaction = db.begin().await?;
+    // Only one sign up can claim the code, even if two use it at the same time
+    let claimed = invite_code::Entity::update_many()
+        .col_expr(invite_code::Column::RedeemedBy, Expr::value(user_name))
+        .col_expr(invite_code::Column::RedeemedAt, Expr::value(now))
+        .filter(invite_code::Column::InviteCodeId.eq(invite.invite_code_id))
+        .filter(invite_code::Column::RedeemedBy.is_null())
+        .exec(&transaction)
+        .await?;
+    if claimed.rows_affected == 0 {
+        return Err("Invite code has already been used".into());
+    }
+    let activated = user_data::Entity::update_many()
+        .col_expr(
+            user_data::Column::CustomGeneralProperties,
+            Expr::value(group_id),
+        )
+        .col_expr(user_data::Column::Activated, Expr::value(true))
+        .filter(user_data::Column::UserName.eq(user_name))
+        .exec(&transaction)
+        .await?;
+    if activated.rows_affected == 0 {
+        return Err("User not found".into());
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+// Undoes redeem_invite_code if the sign up could not be finished, so the user can try again with the same code
+pub async fn release_invite_code(
+    db: &DatabaseConnection,
+    code: &str,
+    user_name: &str,
+) -> GenResult<()> {
+    let transaction = db.begin().await?;
+    invite_code::Entity::update_many()
+        .col_expr(
+            invite_code::Column::RedeemedBy,
+            Expr::value(Option::<String>::None),
+        )
+        .col_expr(
+            invite_code::Column::RedeemedAt,
+            Expr::value(Option::<NaiveDateTime>::None),
+        )
+        .filter(invite_code::Column::Code.eq(code))
+        .filter(invite_code::Column::RedeemedBy.eq(user_name))
+        .exec(&transaction)
+        .await?;
+    user_data::Entity::update_many()
+        .col_expr(
+            user_data::Column::CustomGeneralProperties,
+            Expr::value(Option::<i32>::None),
+        )
+        .col_expr(user_data::Column::Activated, Expr::value(false))
+        .filter(user_data::Column::UserName.eq(user_name))
+        .exec(&transaction)
+        .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn used_or_expired_codes_are_refused() {
+        let now = NaiveDateTime::default() + Duration::days(365);
+        let mut invite = invite_code::Model {
+            invite_code_id: 1,
+            code: "abcdefghij".to_owned(),
+            general_properties_id: 1,
+            expires_at: now + Duration::days(1),
+            created_at: now,
+            redeemed_by: None,
+            redeemed_at: None,
+        };
+        assert!(check_usable(&invite, now).is_ok());
+        invite.expires_at = now - Duration::minutes(1);
+        assert_eq!(check_usable(&invite, now), Err("Invite code has expired"));
+        invite.redeemed_by = Some("piet".to_owned());
+        assert_eq!(
+            check_usable(&invite, now),
+            Err("Invite code has already been used")
+        );
+    }
+}
@@ -1,2 +1,17 @@
+pub mod calendar_fetch;
+pub mod calendar_share;
+pub mod change_listener;
+pub mod feature_flags;
+pub mod invite_code;
+pub mod onboarding_variants;
+pub mod properties_group;
 pub mod secret;
+pub mod secret_backend;
+pub mod sent_notifications;
+pub mod session;
+pub mod shift_history;
+pub mod shift_notes;
+pub mod short_link;
+pub mod state_file;
+pub mod swaps;
 pub mod variables;
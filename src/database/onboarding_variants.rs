@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+
+use entity::{onboarding_variant, user_data};
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter,
+};
+use serde::Serialize;
+use tracing::*;
+
+use crate::{
+    GenResult, config::template_path, database::variables::default_properties_id,
+    errors::ResultLog, get_data, get_database_connection,
+};
+
+pub const DEFAULT_ONBOARDING_TEMPLATE: &str = "onboarding_base.html";
+
+/*
+A properties group can have multiple welcome mail templates, every new user gets one of them at random based on the weights
+The template a user got is stored, so the operator can compare how many users of every variant actually subscribe to their calendar
+Without variants everyone gets the default template
+*/
+fn pick_variant(variants: &[onboarding_variant::Model], roll: u32) -> Option<&str> {
+    let mut remaining = roll;
+    for variant in variants.iter().filter(|variant| variant.weight > 0) {
+        if remaining < variant.weight as u32 {
+            return Some(&variant.template);
+        }
+        remaining -= variant.weight as u32;
+    }
+    None
+}
+
+fn total_weight(variants: &[onboarding_variant::Model]) -> u32 {
+    variants
+        .iter()
+        .map(|variant| variant.weight.max(0) as u32)
+        .sum()
+}
+
+// The template of the welcome mail of the current user, a user keeps the variant they got the first time
+pub async fn welcome_template() -> GenResult<String> {
+    let (user, properties) = get_data();
+    let db = get_database_connection().await;
+    let Some(user_model) = user_data::Entity::find_by_id(user.id).one(&db).await? else {
+        return Ok(DEFAULT_ONBOARDING_TEMPLATE.to_owned());
+    };
+    if let Some(template) = &user_model.onboarding_variant
+        && template_path(template).exists()
+    {
+        return Ok(template.clone());
+    }
+    let variants = onboarding_variant::Entity::find()
+        .filter(
+            onboarding_variant::Column::GeneralPropertiesId.eq(properties.general_properties_id),
+        )
+        .all(&db)
+        .await?;
+    let total_weight = total_weight(&variants);
+    if total_weight == 0 {
+        return Ok(DEFAULT_ONBOARDING_TEMPLATE.to_owned());
+    }
+    let template = match pick_variant(&variants, rand::random_range(0..total_weight)) {
+        Some(template) if template_path(template).exists() => template.to_owned(),
+        Some(template) => {
+            warn!("Onboarding template {template} does not exist, using the default");
+            return Ok(DEFAULT_ONBOARDING_TEMPLATE.to_owned());
+        }
+        None => return Ok(DEFAULT_ONBOARDING_TEMPLATE.to_owned()),
+    };
+    info!("Using onboarding variant {template}");
+    let mut active_user = user_model.into_active_model();
+    active_user.onboarding_variant = Set(Some(template.clone()));
+    user_data::Entity::update(active_user)
+        .validate()?
+        .exec(&db)
+        .await?;
+    Ok(template)
+}
+
+// A welcome mail with the default template is still better than no welcome mail at all
+pub async fn load_welcome_template() -> String {
+    welcome_template()
+        .await
+        .warn_owned("Choosing onboarding variant")
+        .unwrap_or(DEFAULT_ONBOARDING_TEMPLATE.to_owned())
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct VariantReport {
+    pub users: usize,
+    // Users whose calendar was fetched by a calendar client at least once
+    pub activated: usize,
+}
+
+// Activation per variant, of the users in the properties group
+pub async fn variant_report(
+    db: &DatabaseConnection,
+    general_properties_id: i32,
+) -> GenResult<BTreeMap<String, VariantReport>> {
+    let is_default_group = general_properties_id == default_properties_id();
+    let users = user_data::Entity::find()
+        .filter(user_data::Column::OnboardingVariant.is_not_null())
+        .all(db)
+        .await?;
+    let mut report: BTreeMap<String, VariantReport> = BTreeMap::new();
+    for user in users {
+        let in_group = match user.custom_general_properties {
+            Some(group) => group == general_properties_id,
+            None => is_default_group,
+        };
+        let Some(variant) = user.onboarding_variant.filter(|_| in_group) else {
+            continue;
+        };
+        let entry = report.entry(variant).or_default();
+        entry.users += 1;
+        if user.first_calendar_fetch_date.is_some() {
+            entry.activated += 1;
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variant(template: &str, weight: i32) -> onboarding_variant::Model {
+        onboarding_variant::Model {
+            onboarding_variant_id: 1,
+            general_properties_id: 1,
+            template: template.to_owned(),
+            weight,
+        }
+    }
+
+    #[test]
+    fn variants_are_picked_by_weight() {
+        let variants = vec![
+            variant("a.html", 1),
+            variant("b.html", 0),
+            variant("c.html", 3),
+        ];
+        assert_eq!(total_weight(&variants), 4);
+        assert_eq!(pick_variant(&variants, 0), Some("a.html"));
+        assert_eq!(pick_variant(&variants, 1), Some("c.html"));
+        assert_eq!(pick_variant(&variants, 3), Some("c.html"));
+        assert_eq!(pick_variant(&variants, 4), None);
+    }
+}
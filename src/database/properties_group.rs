@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use entity::{general_properties_db, user_data};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::NotSet, ActiveValue::Set, ColumnTrait, DatabaseConnection,
+    EntityTrait, IntoActiveModel, QueryFilter, QuerySelect,
+};
+use serde::Serialize;
+
+use crate::{GenResult, database::variables::default_properties_id, errors::OptionResult};
+
+// A set of general properties, with the users that use it
+#[derive(Debug, Serialize)]
+pub struct PropertiesGroup {
+    pub general_properties_id: i32,
+    pub is_default: bool,
+    pub users: Vec<String>,
+}
+
+impl PropertiesGroup {
+    pub async fn list(db: &DatabaseConnection) -> GenResult<Vec<PropertiesGroup>> {
+        let default_id = default_properties_id();
+        let mut users_per_group: HashMap<i32, Vec<String>> = HashMap::new();
+        let users: Vec<(String, Option<i32>)> = user_data::Entity::find()
+            .select_only()
+            .column(user_data::Column::UserName)
+            .column(user_data::Column::CustomGeneralProperties)
+            .into_tuple()
+            .all(db)
+            .await?;
+        for (user_name, group_id) in users {
+            users_per_group
+                .entry(group_id.unwrap_or(default_id))
+                .or_default()
+                .push(user_name);
+        }
+        let group_ids: Vec<i32> = general_properties_db::Entity::find()
+            .select_only()
+            .column(general_properties_db::Column::GeneralPropertiesId)
+            .into_tuple()
+            .all(db)
+            .await?;
+        Ok(group_ids
+            .into_iter()
+            .map(|id| PropertiesGroup {
+                general_properties_id: id,
+                is_default: id == default_id,
+                users: users_per_group.remove(&id).unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    // Create a new group as a copy of an existing one, the default group if none is given
+    pub async fn create(db: &DatabaseConnection, source_id: Option<i32>) -> GenResult<i32> {
+        let source =
+            general_properties_db::Entity::find_by_id(source_id.unwrap_or(default_properties_id()))
+                .one(db)
+                .await?
+                .result_reason("Source properties not found")?;
+        let mut new_group = source.into_active_model();
+        new_group.general_properties_id = NotSet;
+        let new_group = new_group.insert(db).await?;
+        Ok(new_group.general_properties_id)
+    }
+
+    // Assign a user to a group. None moves the user back to the default group
+    pub async fn assign_user(
+        db: &DatabaseConnection,
+        user_name: &str,
+        group_id: Option<i32>,
+    ) -> GenResult<()> {
+        if let Some(group_id) = group_id {
+            general_properties_db::Entity::find_by_id(group_id)
+                .one(db)
+                .await?
+                .result_reason("Properties group not found")?;
+        }
+        let user = user_data::Entity::find()
+            .filter(user_data::Column::UserName.eq(user_name))
+            .one(db)
+            .await?
+            .result_reason("User not found")?;
+        let mut active_user = user.into_active_model();
+        active_user.custom_general_properties = Set(group_id);
+        active_user.update(db).await?;
+        Ok(())
+    }
+}
@@ -1,10 +1,9 @@
 use base64::{Engine, prelude::BASE64_STANDARD_NO_PAD};
-use dotenvy::var;
 use sea_orm::{QueryResult, TryGetError, TryGetable, Value, sea_query};
 use secrecy::{ExposeSecret, SecretString};
 use serde::{Serialize, Serializer};
 
-use crate::{GenResult, errors::OptionResult};
+use crate::{GenResult, database::secret_backend::secret_backend, errors::OptionResult};
 
 /// A `SecretString` wrapper that automatically decodes using the `$PASSWORD_SECRET`
 #[derive(Clone, Debug)]
@@ -90,21 +89,29 @@ impl Serialize for Secret {
 
 impl Secret {
     fn decrypt_value(value: String) -> GenResult<String> {
-        let secret_string = var("PASSWORD_SECRET")?;
-        let secret = secret_string.as_bytes();
+        secret_backend()?.decrypt(&value)
+    }
+
+    pub fn encrypt_value(value: &str) -> GenResult<String> {
+        secret_backend()?.encrypt(value)
+    }
+
+    // Decrypt using a specific secret, instead of the one from the env
+    pub fn decrypt_with(value: &str, secret: &str) -> GenResult<String> {
         let value = String::from_utf8(
-            simplestcrypt::deserialize_and_decrypt(secret, &BASE64_STANDARD_NO_PAD.decode(value)?)
-                .ok()
-                .result_reason("Could not deserialize password")?,
+            simplestcrypt::deserialize_and_decrypt(
+                secret.as_bytes(),
+                &BASE64_STANDARD_NO_PAD.decode(value)?,
+            )
+            .ok()
+            .result_reason("Could not deserialize password")?,
         )?;
         Ok(value)
     }
 
-    pub fn encrypt_value(value: &str) -> GenResult<String> {
-        let secret_string = var("PASSWORD_SECRET")?;
-        let secret = secret_string.as_bytes();
+    pub fn encrypt_with(value: &str, secret: &str) -> GenResult<String> {
         let value = BASE64_STANDARD_NO_PAD.encode(
-            simplestcrypt::encrypt_and_serialize(secret, value.as_bytes())
+            simplestcrypt::encrypt_and_serialize(secret.as_bytes(), value.as_bytes())
                 .ok()
                 .result_reason("Failed to encode password")?,
         );
@@ -0,0 +1,199 @@
+use std::sync::OnceLock;
+
+use crate::config::var;
+use base64::{Engine, prelude::BASE64_STANDARD};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::runtime::Handle;
+use tracing::*;
+
+use crate::{GenResult, database::secret::Secret, errors::OptionResult};
+
+static SECRET_BACKEND: OnceLock<Box<dyn SecretBackend>> = OnceLock::new();
+
+// Where the secrets in the database are encrypted and decrypted
+pub trait SecretBackend: Send + Sync {
+    fn encrypt(&self, value: &str) -> GenResult<String>;
+    fn decrypt(&self, value: &str) -> GenResult<String>;
+}
+
+/*
+Loads the backend set by SECRET_BACKEND once
+- local: encrypted with PASSWORD_SECRET, the default
+- vault: every value is encrypted by the transit engine of Vault, PASSWORD_SECRET is not used
+- envelope: PASSWORD_SECRET is a data key that is itself encrypted by Vault, it is decrypted once and then used locally
+There is no AWS KMS backend, it would need the AWS SDK for only a few deployments
+A KMS key can still be used through Vault, by backing the transit key with it as a managed key
+*/
+pub fn secret_backend() -> GenResult<&'static dyn SecretBackend> {
+    if let Some(backend) = SECRET_BACKEND.get() {
+        return Ok(backend.as_ref());
+    }
+    let backend = load_backend(&var("SECRET_BACKEND").unwrap_or("local".to_owned()))?;
+    Ok(SECRET_BACKEND.get_or_init(|| backend).as_ref())
+}
+
+fn load_backend(name: &str) -> GenResult<Box<dyn SecretBackend>> {
+    info!("Using {name} secret backend");
+    Ok(match name {
+        "local" => Box::new(LocalBackend {
+            key: var("PASSWORD_SECRET")?,
+        }),
+        "vault" => Box::new(VaultTransitBackend::from_env()?),
+        "envelope" => Box::new(LocalBackend::unwrap_key(
+            &var("PASSWORD_SECRET")?,
+            &VaultTransitBackend::from_env()?,
+        )?),
+        "kms" => {
+            return Err(
+                "The kms secret backend is not supported, use vault with a KMS managed key".into(),
+            );
+        }
+        _ => return Err(format!("Unknown secret backend {name}").into()),
+    })
+}
+
+pub struct LocalBackend {
+    key: String,
+}
+
+impl LocalBackend {
+    // The key is stored encrypted by another backend, only the decrypted key is kept in memory
+    pub fn unwrap_key(wrapped_key: &str, key_backend: &dyn SecretBackend) -> GenResult<Self> {
+        Ok(Self {
+            key: key_backend.decrypt(wrapped_key)?,
+        })
+    }
+}
+
+impl SecretBackend for LocalBackend {
+    fn encrypt(&self, value: &str) -> GenResult<String> {
+        Secret::encrypt_with(value, &self.key)
+    }
+
+    fn decrypt(&self, value: &str) -> GenResult<String> {
+        Secret::decrypt_with(value, &self.key)
+    }
+}
+
+#[derive(Deserialize)]
+struct VaultResponse {
+    data: VaultData,
+}
+
+#[derive(Deserialize)]
+struct VaultData {
+    ciphertext: Option<String>,
+    plaintext: Option<String>,
+}
+
+pub struct VaultTransitBackend {
+    address: String,
+    token: String,
+    key_name: String,
+    client: reqwest::Client,
+}
+
+impl VaultTransitBackend {
+    fn from_env() -> GenResult<Self> {
+        Ok(Self {
+            address: var("VAULT_ADDR")?.trim_end_matches('/').to_owned(),
+            token: var("VAULT_TOKEN")?,
+            key_name: var("VAULT_TRANSIT_KEY").unwrap_or("mijn_bussie".to_owned()),
+            client: reqwest::Client::new(),
+        })
+    }
+
+    async fn request(&self, operation: &str, body: serde_json::Value) -> GenResult<VaultData> {
+        let response = self
+            .client
+            .post(format!(
+                "{}/v1/transit/{operation}/{}",
+                self.address, self.key_name
+            ))
+            .header("X-Vault-Token", &self.token)
+            .body(body.to_string())
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        Ok(serde_json::from_str::<VaultResponse>(&response)?.data)
+    }
+}
+
+impl SecretBackend for VaultTransitBackend {
+    fn encrypt(&self, value: &str) -> GenResult<String> {
+        let body = json!({ "plaintext": BASE64_STANDARD.encode(value) });
+        run_blocking(self.request("encrypt", body))?
+            .ciphertext
+            .result_reason("Vault did not return a ciphertext")
+    }
+
+    fn decrypt(&self, value: &str) -> GenResult<String> {
+        let body = json!({ "ciphertext": value });
+        let plaintext = run_blocking(self.request("decrypt", body))?
+            .plaintext
+            .result_reason("Vault did not return a plaintext")?;
+        Ok(String::from_utf8(BASE64_STANDARD.decode(plaintext)?)?)
+    }
+}
+
+// Secrets are decrypted while reading from the database, which is not async
+fn run_blocking<T>(future: impl Future<Output = GenResult<T>>) -> GenResult<T> {
+    match Handle::try_current() {
+        Ok(handle) => tokio::task::block_in_place(|| handle.block_on(future)),
+        Err(_) => tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(future),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Stands in for Vault, so the envelope can be tested without a server
+    struct ReversingBackend;
+
+    impl SecretBackend for ReversingBackend {
+        fn encrypt(&self, value: &str) -> GenResult<String> {
+            Ok(value.chars().rev().collect())
+        }
+
+        fn decrypt(&self, value: &str) -> GenResult<String> {
+            Ok(value.chars().rev().collect())
+        }
+    }
+
+    #[test]
+    fn local_backend_round_trips() {
+        let backend = LocalBackend {
+            key: "test_key".to_owned(),
+        };
+        let encrypted = backend.encrypt("wachtwoord").unwrap();
+        assert_ne!(encrypted, "wachtwoord");
+        assert_eq!(backend.decrypt(&encrypted).unwrap(), "wachtwoord");
+    }
+
+    #[test]
+    fn envelope_uses_unwrapped_key() {
+        let wrapped_key = ReversingBackend.encrypt("data_key").unwrap();
+        let backend = LocalBackend::unwrap_key(&wrapped_key, &ReversingBackend).unwrap();
+        let encrypted = backend.encrypt("wachtwoord").unwrap();
+        assert_eq!(
+            Secret::decrypt_with(&encrypted, "data_key").unwrap(),
+            "wachtwoord"
+        );
+    }
+
+    #[test]
+    fn unknown_backend_is_refused() {
+        assert!(load_backend("keychain").is_err());
+        let Err(err) = load_backend("kms") else {
+            panic!("kms backend should not load");
+        };
+        assert!(err.to_string().contains("not supported"));
+    }
+}
@@ -0,0 +1,155 @@
+use chrono::{Days, NaiveDate};
+use entity::sent_notification;
+use sea_orm::{ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, sea_query::OnConflict};
+use tokio::runtime::Handle;
+use tracing::*;
+
+use crate::{
+    GenResult, database::shift_history::to_naive_date, errors::ResultLog, get_data,
+    get_database_connection, is_dry_run, webcom::shift::Shift,
+};
+
+// Records are only needed for runs right after each other, so they are not kept long
+const RECORD_RETENTION_DAYS: u64 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeType {
+    New,
+    Updated,
+    Removed,
+    CallOut,
+}
+
+impl ChangeType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::New => "new",
+            Self::Updated => "updated",
+            Self::Removed => "removed",
+            Self::CallOut => "call_out",
+        }
+    }
+}
+
+/*
+A Force run right after a Timer run can find the same change again, because the previous shifts are not written yet
+A change is recorded once the mail about it is sent, and a recorded change is not mailed again
+The magic number is part of the record, so a shift which really changes twice is still mailed twice
+*/
+async fn filter_unsent(
+    user_data_id: i32,
+    shifts: Vec<&Shift>,
+    change_type: ChangeType,
+    today: NaiveDate,
+) -> GenResult<Vec<&Shift>> {
+    let db = get_database_connection().await;
+    sent_notification::Entity::delete_many()
+        .filter(sent_notification::Column::UserDataId.eq(user_data_id))
+        .filter(sent_notification::Column::SentOn.lt(today - Days::new(RECORD_RETENTION_DAYS)))
+        .exec(&db)
+        .await?;
+    let sent: Vec<(String, i64)> = sent_notification::Entity::find()
+        .filter(sent_notification::Column::UserDataId.eq(user_data_id))
+        .filter(sent_notification::Column::ChangeType.eq(change_type.as_str()))
+        .all(&db)
+        .await?
+        .into_iter()
+        .map(|record| (record.shift_uid, record.magic_number))
+        .collect();
+    let mut unsent = vec![];
+    for shift in shifts {
+        if sent.contains(&(shift.uid(), shift.magic_number)) {
+            info!(
+                "Already mailed about {} shift {}, skipping",
+                change_type.as_str(),
+                shift.number
+            );
+        } else {
+            unsent.push(shift);
+        }
+    }
+    Ok(unsent)
+}
+
+async fn record_notifications(
+    user_data_id: i32,
+    shifts: &[&Shift],
+    change_type: ChangeType,
+    today: NaiveDate,
+) -> GenResult<()> {
+    let db = get_database_connection().await;
+    let records = shifts.iter().map(|shift| sent_notification::ActiveModel {
+        user_data_id: Set(user_data_id),
+        shift_uid: Set(shift.uid()),
+        magic_number: Set(shift.magic_number),
+        change_type: Set(change_type.as_str().to_owned()),
+        sent_on: Set(today),
+        ..Default::default()
+    });
+    sent_notification::Entity::insert_many(records)
+        .on_conflict(
+            OnConflict::columns([
+                sent_notification::Column::UserDataId,
+                sent_notification::Column::ShiftUid,
+                sent_notification::Column::MagicNumber,
+                sent_notification::Column::ChangeType,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
+        .do_nothing()
+        .exec(&db)
+        .await?;
+    Ok(())
+}
+
+// Single user mode has no database row for the user, and a dry run does not really send anything
+fn with_user_records<F, T>(records: impl FnOnce(i32) -> F) -> Option<GenResult<T>>
+where
+    F: Future<Output = GenResult<T>>,
+{
+    let (user, _properties) = get_data();
+    if user.id == 0 || is_dry_run() {
+        return None;
+    }
+    let handle = Handle::try_current().ok()?;
+    Some(tokio::task::block_in_place(|| {
+        handle.block_on(records(user.id))
+    }))
+}
+
+/*
+The shifts which were not mailed about yet, the rest is left out
+If the records can not be reached everything is returned, a mail twice is better than no mail
+*/
+pub fn unsent_shifts(
+    shifts: Vec<&Shift>,
+    change_type: ChangeType,
+    today: time::Date,
+) -> Vec<&Shift> {
+    if shifts.is_empty() {
+        return shifts;
+    }
+    with_user_records(|user_data_id| {
+        filter_unsent(
+            user_data_id,
+            shifts.clone(),
+            change_type,
+            to_naive_date(today),
+        )
+    })
+    .and_then(|unsent| unsent.warn_owned("Checking sent notifications").ok())
+    .unwrap_or(shifts)
+}
+
+// Called after the mail is sent, a mail which failed is tried again in the next run
+pub fn mark_sent(shifts: &[&Shift], change_type: ChangeType, today: time::Date) {
+    if shifts.is_empty() {
+        return;
+    }
+    if let Some(recorded) = with_user_records(|user_data_id| {
+        record_notifications(user_data_id, shifts, change_type, to_naive_date(today))
+    }) {
+        recorded.warn("Recording sent notifications");
+    }
+}
@@ -0,0 +1,285 @@
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use chrono::{Duration, NaiveDateTime};
+use entity::{user_account, user_session};
+use rand::distr::{Alphanumeric, SampleString};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, DatabaseConnection, EntityTrait,
+    IntoActiveModel, QueryFilter, sea_query::Expr,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tracing::*;
+
+use crate::{
+    GenResult,
+    errors::{OptionResult, ResultLog},
+    health::ApplicationLogbook,
+};
+
+const TOKEN_LENGTH: usize = 40;
+const ACCESS_TOKEN_DURATION: Duration = Duration::minutes(15);
+const REFRESH_TOKEN_DURATION: Duration = Duration::days(30);
+// Ended sessions are kept a while, so a stolen refresh token which is used late is still recognised
+const ENDED_SESSION_RETENTION: Duration = Duration::days(7);
+
+/*
+Sessions of the frontend. The frontend checks the password and asks for a session, after that it only uses the tokens
+The short lived access token is checked on every request, the refresh token gets a new pair and is replaced every time.
+Sessions are stored in the database, so logging out or revoking an account stops the tokens right away
+*/
+#[derive(Debug, Serialize)]
+pub struct SessionTokens {
+    pub access_token: String,
+    pub access_expires_at: NaiveDateTime,
+    pub refresh_token: String,
+    pub refresh_expires_at: NaiveDateTime,
+}
+
+// The account behind a valid access token
+#[derive(Debug, Serialize)]
+pub struct SessionAccount {
+    pub username: String,
+    pub role: String,
+    pub backend_user: Option<String>,
+    pub properties_group: Option<i32>,
+}
+
+fn hash_token(token: &str) -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+}
+
+fn new_token() -> String {
+    Alphanumeric.sample_string(&mut rand::rng(), TOKEN_LENGTH)
+}
+
+fn new_tokens(now: NaiveDateTime) -> SessionTokens {
+    SessionTokens {
+        access_token: new_token(),
+        access_expires_at: now + ACCESS_TOKEN_DURATION,
+        refresh_token: new_token(),
+        refresh_expires_at: now + REFRESH_TOKEN_DURATION,
+    }
+}
+
+fn is_active(session: &user_session::Model, expires_at: NaiveDateTime, now: NaiveDateTime) -> bool {
+    session.revoked_at.is_none() && expires_at > now
+}
+
+pub async fn create_session(db: &DatabaseConnection, username: &str) -> GenResult<SessionTokens> {
+    let account = user_account::Entity::find()
+        .filter(user_account::Column::Username.eq(username))
+        .one(db)
+        .await?
+        .result_reason("Account not found")?;
+    let now = ApplicationLogbook::get_naive_datetime();
+    prune_sessions(db, now)
+        .await
+        .warn("Removing ended sessions");
+    let tokens = new_tokens(now);
+    user_session::ActiveModel {
+        account_id: Set(account.account_id),
+        access_token_hash: Set(hash_token(&tokens.access_token)),
+        access_expires_at: Set(tokens.access_expires_at),
+        refresh_token_hash: Set(hash_token(&tokens.refresh_token)),
+        refresh_expires_at: Set(tokens.refresh_expires_at),
+        created_at: Set(now),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+    Ok(tokens)
+}
+
+// Replaces both tokens. A refresh token that was already replaced revokes the session, someone else has a copy of it
+pub async fn refresh_session(
+    db: &DatabaseConnection,
+    refresh_token: &str,
+) -> GenResult<SessionTokens> {
+    let hash = hash_token(refresh_token);
+    let now = ApplicationLogbook::get_naive_datetime();
+    if let Some(reused) = user_session::Entity::find()
+        .filter(user_session::Column::PreviousRefreshHash.eq(&hash))
+        .one(db)
+        .await?
+    {
+        warn!(
+            "Refresh token of session {} was used twice, revoking it",
+            reused.session_id
+        );
+        revoke(db, reused, now).await?;
+        return Err("Session has been revoked".into());
+    }
+    let session = user_session::Entity::find()
+        .filter(user_session::Column::RefreshTokenHash.eq(&hash))
+        .one(db)
+        .await?
+        .result_reason("Unknown session")?;
+    if !is_active(&session, session.refresh_expires_at, now) {
+        return Err("Session has expired".into());
+    }
+    let tokens = new_tokens(now);
+    // Only replaced if nobody else replaced it in the meantime, two refreshes at once mean the token is copied
+    let rotated = user_session::Entity::update_many()
+        .col_expr(
+            user_session::Column::AccessTokenHash,
+            Expr::value(hash_token(&tokens.access_token)),
+        )
+        .col_expr(
+            user_session::Column::AccessExpiresAt,
+            Expr::value(tokens.access_expires_at),
+        )
+        .col_expr(
+            user_session::Column::RefreshTokenHash,
+            Expr::value(hash_token(&tokens.refresh_token)),
+        )
+        .col_expr(
+            user_session::Column::RefreshExpiresAt,
+            Expr::value(tokens.refresh_expires_at),
+        )
+        .col_expr(
+            user_session::Column::PreviousRefreshHash,
+            Expr::value(Some(hash.clone())),
+        )
+        .filter(user_session::Column::SessionId.eq(session.session_id))
+        .filter(user_session::Column::RefreshTokenHash.eq(&hash))
+        .exec(db)
+        .await?;
+    if rotated.rows_affected == 0 {
+        warn!(
+            "Refresh token of session {} was used twice at the same time, revoking it",
+            session.session_id
+        );
+        revoke(db, session, now).await?;
+        return Err("Session has been revoked".into());
+    }
+    Ok(tokens)
+}
+
+// Sessions that expired or were revoked are removed after a while, they can't be used anymore anyway
+async fn prune_sessions(db: &DatabaseConnection, now: NaiveDateTime) -> GenResult<()> {
+    let ended_before = now - ENDED_SESSION_RETENTION;
+    let pruned = user_session::Entity::delete_many()
+        .filter(
+            Condition::any()
+                .add(user_session::Column::RefreshExpiresAt.lt(ended_before))
+                .add(user_session::Column::RevokedAt.lt(ended_before)),
+        )
+        .exec(db)
+        .await?;
+    if pruned.rows_affected > 0 {
+        debug!("Removed {} ended sessions", pruned.rows_affected);
+    }
+    Ok(())
+}
+
+async fn find_by_access_token(
+    db: &DatabaseConnection,
+    access_token: &str,
+) -> GenResult<user_session::Model> {
+    let session = user_session::Entity::find()
+        .filter(user_session::Column::AccessTokenHash.eq(hash_token(access_token)))
+        .one(db)
+        .await?
+        .result_reason("Unknown session")?;
+    if !is_active(
+        &session,
+        session.access_expires_at,
+        ApplicationLogbook::get_naive_datetime(),
+    ) {
+        return Err("Session has expired".into());
+    }
+    Ok(session)
+}
+
+pub async fn session_account(
+    db: &DatabaseConnection,
+    access_token: &str,
+) -> GenResult<SessionAccount> {
+    let session = find_by_access_token(db, access_token).await?;
+    let account = user_account::Entity::find_by_id(session.account_id)
+        .one(db)
+        .await?
+        .result_reason("Account not found")?;
+    Ok(SessionAccount {
+        username: account.username,
+        role: account.role,
+        backend_user: account.backend_user,
+        properties_group: account.properties_group,
+    })
+}
+
+async fn revoke(
+    db: &DatabaseConnection,
+    session: user_session::Model,
+    now: NaiveDateTime,
+) -> GenResult<()> {
+    let mut active_session = session.into_active_model();
+    active_session.revoked_at = Set(Some(now));
+    active_session.update(db).await?;
+    Ok(())
+}
+
+pub async fn logout(db: &DatabaseConnection, access_token: &str) -> GenResult<()> {
+    let session = find_by_access_token(db, access_token).await?;
+    revoke(db, session, ApplicationLogbook::get_naive_datetime()).await
+}
+
+// Ends every session of an account, for when a password leaked. Returns how many sessions were still active
+pub async fn revoke_account_sessions(db: &DatabaseConnection, username: &str) -> GenResult<u64> {
+    let account = user_account::Entity::find()
+        .filter(user_account::Column::Username.eq(username))
+        .one(db)
+        .await?
+        .result_reason("Account not found")?;
+    let revoked = user_session::Entity::update_many()
+        .col_expr(
+            user_session::Column::RevokedAt,
+            Expr::value(ApplicationLogbook::get_naive_datetime()),
+        )
+        .filter(user_session::Column::AccountId.eq(account.account_id))
+        .filter(user_session::Column::RevokedAt.is_null())
+        .exec(db)
+        .await?;
+    info!(
+        "Revoked {} sessions of account {username}",
+        revoked.rows_affected
+    );
+    Ok(revoked.rows_affected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn revoked_or_expired_sessions_are_refused() {
+        let now = NaiveDateTime::default() + Duration::days(365);
+        let tokens = new_tokens(now);
+        assert_ne!(tokens.access_token, tokens.refresh_token);
+        assert_ne!(hash_token(&tokens.access_token), tokens.access_token);
+        let mut session = user_session::Model {
+            session_id: 1,
+            account_id: 1,
+            access_token_hash: hash_token(&tokens.access_token),
+            access_expires_at: tokens.access_expires_at,
+            refresh_token_hash: hash_token(&tokens.refresh_token),
+            refresh_expires_at: tokens.refresh_expires_at,
+            previous_refresh_hash: None,
+            created_at: now,
+            revoked_at: None,
+        };
+        assert!(is_active(&session, session.access_expires_at, now));
+        assert!(!is_active(
+            &session,
+            session.access_expires_at,
+            now + Duration::minutes(16)
+        ));
+        assert!(is_active(
+            &session,
+            session.refresh_expires_at,
+            now + Duration::days(29)
+        ));
+        session.revoked_at = Some(now);
+        assert!(!is_active(&session, session.refresh_expires_at, now));
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::HashSet;
+
+use chrono::{Datelike, NaiveDate, NaiveTime};
+use entity::{shift_history, user_data};
+use sea_orm::{
+    ActiveValue::Set,
+    ColumnTrait, EntityTrait, QueryFilter, QueryOrder,
+    sea_query::{Expr, OnConflict},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    GenResult, get_data, get_database_connection,
+    health::ApplicationLogbook,
+    webcom::{
+        shift::{Shift, ShiftState},
+        signed_off::SignedOffDuty,
+    },
+};
+
+// Filter for searching through the shift history, every field is optional
+#[derive(Debug, Default, Deserialize)]
+pub struct ShiftSearch {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    // Matches the kind of shift, like Rijdienst
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub min_hours: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShiftHistoryEntry {
+    pub magic_number: i64,
+    pub uid: String,
+    pub number: String,
+    pub kind: String,
+    pub date: NaiveDate,
+    pub start: NaiveTime,
+    pub end_date: NaiveDate,
+    pub end: NaiveTime,
+    pub duration_minutes: i64,
+    pub working_minutes: i64,
+    pub state: ShiftState,
+}
+
+// Range of the planned vs actual report, both dates are optional
+#[derive(Debug, Default, Deserialize)]
+pub struct PeriodFilter {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+// A shift which took longer than the hours that are paid for it
+#[derive(Debug, Serialize)]
+pub struct Overrun {
+    pub magic_number: i64,
+    pub number: String,
+    pub date: NaiveDate,
+    pub working_minutes: i64,
+    pub actual_minutes: i64,
+}
+
+// Planned and actually worked minutes of a single pay period (month)
+#[derive(Debug, Serialize)]
+pub struct PeriodComparison {
+    pub year: i32,
+    pub month: u32,
+    pub planned_minutes: i64,
+    pub working_minutes: i64,
+    pub actual_minutes: i64,
+    // Shifts in this period without signed off times are not counted in actual_minutes
+    pub shifts_without_actual: usize,
+    pub overruns: Vec<Overrun>,
+}
+
+pub fn to_naive_date(date: time::Date) -> NaiveDate {
+    NaiveDate::from_ymd_opt(date.year(), date.month() as u32, date.day() as u32).unwrap_or_default()
+}
+
+fn to_naive_time(time: time::Time) -> NaiveTime {
+    NaiveTime::from_hms_opt(time.hour().into(), time.minute().into(), 0).unwrap_or_default()
+}
+
+/*
+Store the relevant shifts of the current instance in the history
+Every known shift is updated with its latest state. Shifts in the history which fall within the period of the given shifts,
+but are not in there anymore, are marked as deleted
+*/
+pub async fn save_shift_history(shifts: &[Shift]) -> GenResult<()> {
+    if shifts.is_empty() {
+        return Ok(());
+    }
+    let (user, _properties) = get_data();
+    let db = get_database_connection().await;
+    let now = ApplicationLogbook::get_naive_datetime();
+    // A row can only be upserted once per statement, so every uid is only sent once
+    let mut uids = HashSet::new();
+    let models =
+        shifts
+            .iter()
+            .filter(|shift| uids.insert(shift.uid()))
+            .map(|shift| -> GenResult<_> {
+                Ok(shift_history::ActiveModel {
+                    user_data_id: Set(user.id),
+                    magic_number: Set(shift.magic_number),
+                    shift_uid: Set(shift.uid()),
+                    number: Set(shift.number.clone()),
+                    kind: Set(shift.kind.clone()),
+                    shift_date: Set(to_naive_date(shift.date)),
+                    start_time: Set(to_naive_time(shift.start)),
+                    end_date: Set(to_naive_date(shift.end_date)),
+                    end_time: Set(to_naive_time(shift.end)),
+                    duration_minutes: Set(shift.duration.whole_minutes()),
+                    working_minutes: Set(shift.working_hours().whole_minutes()),
+                    state: Set(serde_json::to_string(&shift.state)?),
+                    last_seen: Set(now),
+                    ..Default::default()
+                })
+            });
+    shift_history::Entity::insert_many(models.collect::<GenResult<Vec<_>>>()?)
+        .on_conflict(
+            OnConflict::columns([
+                shift_history::Column::UserDataId,
+                shift_history::Column::ShiftUid,
+            ])
+            .update_columns([
+                shift_history::Column::MagicNumber,
+                shift_history::Column::Number,
+                shift_history::Column::Kind,
+                shift_history::Column::ShiftDate,
+                shift_history::Column::StartTime,
+                shift_history::Column::EndDate,
+                shift_history::Column::EndTime,
+                shift_history::Column::DurationMinutes,
+                shift_history::Column::WorkingMinutes,
+                shift_history::Column::State,
+                shift_history::Column::LastSeen,
+            ])
+            .to_owned(),
+        )
+        .exec(&db)
+        .await?;
+
+    // Everything in the same period that was not seen just now must have been removed
+    let first_date = shifts.iter().map(|shift| shift.date).min().unwrap();
+    let last_date = shifts.iter().map(|shift| shift.date).max().unwrap();
+    shift_history::Entity::update_many()
+        .col_expr(
+            shift_history::Column::State,
+            Expr::value(serde_json::to_string(&ShiftState::Deleted)?),
+        )
+        .filter(shift_history::Column::UserDataId.eq(user.id))
+        .filter(
+            shift_history::Column::ShiftDate
+                .between(to_naive_date(first_date), to_naive_date(last_date)),
+        )
+        .filter(shift_history::Column::LastSeen.lt(now))
+        .exec(&db)
+        .await?;
+    Ok(())
+}
+
+pub async fn search_shift_history(
+    user_name: &str,
+    search: &ShiftSearch,
+) -> GenResult<Vec<ShiftHistoryEntry>> {
+    let db = get_database_connection().await;
+    let mut query = shift_history::Entity::find()
+        .inner_join(user_data::Entity)
+        .filter(user_data::Column::UserName.eq(user_name));
+    if let Some(from) = search.from {
+        query = query.filter(shift_history::Column::ShiftDate.gte(from));
+    }
+    if let Some(to) = search.to {
+        query = query.filter(shift_history::Column::ShiftDate.lte(to));
+    }
+    if let Some(kind) = &search.kind {
+        query = query.filter(shift_history::Column::Kind.contains(kind));
+    }
+    if let Some(min_hours) = search.min_hours {
+        query = query
+            .filter(shift_history::Column::DurationMinutes.gte((min_hours * 60.0).ceil() as i64));
+    }
+    let shifts = query
+        .order_by_asc(shift_history::Column::ShiftDate)
+        .order_by_asc(shift_history::Column::StartTime)
+        .all(&db)
+        .await?;
+    Ok(shifts
+        .into_iter()
+        .map(|shift| ShiftHistoryEntry {
+            magic_number: shift.magic_number,
+            uid: shift.shift_uid,
+            number: shift.number,
+            kind: shift.kind,
+            date: shift.shift_date,
+            start: shift.start_time,
+            end_date: shift.end_date,
+            end: shift.end_time,
+            duration_minutes: shift.duration_minutes,
+            working_minutes: shift.working_minutes,
+            state: serde_json::from_str(&shift.state).unwrap_or_default(),
+        })
+        .collect())
+}
+
+/*
+Store the signed off times of past shifts
+A shift is matched on its date and number, signed off duties which are not in the history are ignored
+*/
+pub async fn save_actual_times(duties: &[SignedOffDuty]) -> GenResult<()> {
+    let (user, _properties) = get_data();
+    let db = get_database_connection().await;
+    for duty in duties {
+        shift_history::Entity::update_many()
+            .col_expr(
+                shift_history::Column::ActualStartTime,
+                Expr::value(to_naive_time(duty.start)),
+            )
+            .col_expr(
+                shift_history::Column::ActualEndTime,
+                Expr::value(to_naive_time(duty.end)),
+            )
+            .filter(shift_history::Column::UserDataId.eq(user.id))
+            .filter(shift_history::Column::ShiftDate.eq(to_naive_date(duty.date)))
+            .filter(shift_history::Column::Number.eq(&duty.number))
+            .exec(&db)
+            .await?;
+    }
+    Ok(())
+}
+
+// Minutes between the signed off start and end time, a shift can end after midnight
+fn actual_minutes(start: NaiveTime, end: NaiveTime) -> i64 {
+    let minutes = (end - start).num_minutes();
+    if minutes < 0 {
+        minutes + 24 * 60
+    } else {
+        minutes
+    }
+}
+
+// Compare the planned hours with the signed off hours of every month in the period
+pub async fn planned_vs_actual(
+    user_name: &str,
+    filter: &PeriodFilter,
+) -> GenResult<Vec<PeriodComparison>> {
+    let db = get_database_connection().await;
+    let deleted_state = serde_json::to_string(&ShiftState::Deleted)?;
+    let mut query = shift_history::Entity::find()
+        .inner_join(user_data::Entity)
+        .filter(user_data::Column::UserName.eq(user_name))
+        .filter(shift_history::Column::State.ne(deleted_state));
+    if let Some(from) = filter.from {
+        query = query.filter(shift_history::Column::ShiftDate.gte(from));
+    }
+    if let Some(to) = filter.to {
+        query = query.filter(shift_history::Column::ShiftDate.lte(to));
+    }
+    let shifts = query
+        .order_by_asc(shift_history::Column::ShiftDate)
+        .all(&db)
+        .await?;
+    let mut periods: Vec<PeriodComparison> = vec![];
+    for shift in shifts {
+        let (year, month) = (shift.shift_date.year(), shift.shift_date.month());
+        if periods
+            .last()
+            .is_none_or(|period| (period.year, period.month) != (year, month))
+        {
+            periods.push(PeriodComparison {
+                year,
+                month,
+                planned_minutes: 0,
+                working_minutes: 0,
+                actual_minutes: 0,
+                shifts_without_actual: 0,
+                overruns: vec![],
+            });
+        }
+        let period = periods.last_mut().unwrap();
+        period.planned_minutes += shift.duration_minutes;
+        period.working_minutes += shift.working_minutes;
+        let (Some(start), Some(end)) = (shift.actual_start_time, shift.actual_end_time) else {
+            period.shifts_without_actual += 1;
+            continue;
+        };
+        let actual = actual_minutes(start, end);
+        period.actual_minutes += actual;
+        if actual > shift.working_minutes {
+            period.overruns.push(Overrun {
+                magic_number: shift.magic_number,
+                number: shift.number,
+                date: shift.shift_date,
+                working_minutes: shift.working_minutes,
+                actual_minutes: actual,
+            });
+        }
+    }
+    Ok(periods)
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use entity::{shift_note, user_data};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait,
+    IntoActiveModel, QueryFilter,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{GenResult, errors::OptionResult, get_database_connection, health::ApplicationLogbook};
+
+// A note and tags a user attached to a shift, the shift is found by its magic number
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShiftNote {
+    #[serde(default)]
+    pub note: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl ShiftNote {
+    // Lines added to the description of the shift in the calendar
+    pub fn description(&self) -> String {
+        let mut description = String::new();
+        if !self.note.is_empty() {
+            description.push_str(&format!("\nNotitie • {}", self.note));
+        }
+        if !self.tags.is_empty() {
+            description.push_str(&format!("\nTags • {}", self.tags.join(", ")));
+        }
+        description
+    }
+
+    fn from_model(model: shift_note::Model) -> Self {
+        Self {
+            note: model.note,
+            tags: serde_json::from_str(&model.tags).unwrap_or_default(),
+        }
+    }
+}
+
+pub(crate) async fn get_user_id(db: &DatabaseConnection, user_name: &str) -> GenResult<i32> {
+    Ok(user_data::Entity::find()
+        .filter(user_data::Column::UserName.eq(user_name))
+        .one(db)
+        .await?
+        .result_reason("User not found")?
+        .user_data_id)
+}
+
+async fn find_note(
+    db: &DatabaseConnection,
+    user_id: i32,
+    magic_number: i64,
+) -> GenResult<Option<shift_note::Model>> {
+    Ok(shift_note::Entity::find()
+        .filter(shift_note::Column::UserDataId.eq(user_id))
+        .filter(shift_note::Column::MagicNumber.eq(magic_number))
+        .one(db)
+        .await?)
+}
+
+pub async fn get_shift_note(user_name: &str, magic_number: i64) -> GenResult<Option<ShiftNote>> {
+    let db = get_database_connection().await;
+    let user_id = get_user_id(&db, user_name).await?;
+    Ok(find_note(&db, user_id, magic_number)
+        .await?
+        .map(ShiftNote::from_model))
+}
+
+pub async fn set_shift_note(user_name: &str, magic_number: i64, note: ShiftNote) -> GenResult<()> {
+    let db = get_database_connection().await;
+    let user_id = get_user_id(&db, user_name).await?;
+    let tags = serde_json::to_string(&note.tags)?;
+    let now = ApplicationLogbook::get_naive_datetime();
+    match find_note(&db, user_id, magic_number).await? {
+        Some(existing_note) => {
+            let mut active_note = existing_note.into_active_model();
+            active_note.note = Set(note.note);
+            active_note.tags = Set(tags);
+            active_note.updated_at = Set(now);
+            active_note.update(&db).await?;
+        }
+        None => {
+            shift_note::ActiveModel {
+                user_data_id: Set(user_id),
+                magic_number: Set(magic_number),
+                note: Set(note.note),
+                tags: Set(tags),
+                updated_at: Set(now),
+                ..Default::default()
+            }
+            .insert(&db)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn remove_shift_note(user_name: &str, magic_number: i64) -> GenResult<()> {
+    let db = get_database_connection().await;
+    let user_id = get_user_id(&db, user_name).await?;
+    shift_note::Entity::delete_many()
+        .filter(shift_note::Column::UserDataId.eq(user_id))
+        .filter(shift_note::Column::MagicNumber.eq(magic_number))
+        .exec(&db)
+        .await?;
+    Ok(())
+}
+
+// All notes of a user, by the magic number of the shift
+pub async fn load_shift_notes(user_id: i32) -> GenResult<HashMap<i64, ShiftNote>> {
+    let db = get_database_connection().await;
+    Ok(shift_note::Entity::find()
+        .filter(shift_note::Column::UserDataId.eq(user_id))
+        .all(&db)
+        .await?
+        .into_iter()
+        .map(|model| (model.magic_number, ShiftNote::from_model(model)))
+        .collect())
+}
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+
+use crate::config::var;
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use entity::short_link;
+use hmac::{Hmac, Mac};
+use rand::distr::{Alphanumeric, SampleString};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait,
+    IntoActiveModel, QueryFilter,
+};
+use sha2::Sha256;
+use tokio::runtime::Handle;
+
+use crate::{
+    GenResult, config::public_api_url, errors::ResultLog, get_data, get_database_connection,
+};
+
+const TOKEN_LENGTH: usize = 8;
+const SIGNATURE_LENGTH: usize = 8;
+
+/*
+Long links in mails (Webcomm PDFs, the webcal rewrite, the sign up page) are replaced by a short link to the API
+The target is stored in the database and every click is counted. The token is signed with SHORT_LINK_SECRET,
+so made up links are rejected without looking them up
+*/
+fn sign_token(token: &str, key: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(token.as_bytes());
+    let signature = BASE64_URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+    signature[..SIGNATURE_LENGTH].to_owned()
+}
+
+// Returns the token of the link if the signature is correct
+fn verify_link<'a>(link: &'a str, key: &str) -> Option<&'a str> {
+    if link.len() != TOKEN_LENGTH + SIGNATURE_LENGTH || !link.is_ascii() {
+        return None;
+    }
+    let (token, signature) = link.split_at(TOKEN_LENGTH);
+    (sign_token(token, key) == signature).then_some(token)
+}
+
+// Without a secret links are not shortened, and no short link is followed
+fn link_secret() -> Option<String> {
+    var("SHORT_LINK_SECRET")
+        .ok()
+        .filter(|secret| !secret.is_empty())
+}
+
+/*
+Short links of all targets in one go, so a mail with a lot of shifts does not do a query for every shift
+Links to the same target of the same user are reused, so sending the same shift again does not create new links
+*/
+pub async fn create_short_links(
+    db: &DatabaseConnection,
+    user_data_id: Option<i32>,
+    targets: &[String],
+    secret: &str,
+) -> GenResult<HashMap<String, String>> {
+    let mut tokens: HashMap<String, String> = short_link::Entity::find()
+        .filter(match user_data_id {
+            Some(id) => short_link::Column::UserDataId.eq(id),
+            None => short_link::Column::UserDataId.is_null(),
+        })
+        .filter(short_link::Column::Target.is_in(targets))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|link| (link.target, link.token))
+        .collect();
+    let mut new_links = vec![];
+    for target in targets {
+        if tokens.contains_key(target) {
+            continue;
+        }
+        let token = Alphanumeric.sample_string(&mut rand::rng(), TOKEN_LENGTH);
+        new_links.push(short_link::ActiveModel {
+            token: Set(token.clone()),
+            target: Set(target.clone()),
+            user_data_id: Set(user_data_id),
+            created_at: Set(chrono::offset::Utc::now().naive_utc()),
+            ..Default::default()
+        });
+        tokens.insert(target.clone(), token);
+    }
+    if !new_links.is_empty() {
+        short_link::Entity::insert_many(new_links).exec(db).await?;
+    }
+    Ok(tokens
+        .into_iter()
+        .map(|(target, token)| {
+            let signature = sign_token(&token, secret);
+            (target, format!("{token}{signature}"))
+        })
+        .collect())
+}
+
+/*
+Shortens the links of a mail of the current user. The map only has the links which could be shortened,
+without a public API url or a secret, or if anything fails, the original links should be used
+Mails are created in sync code, so the database is reached by blocking on the runtime once for the whole mail
+*/
+pub fn shorten_links(targets: &[String]) -> HashMap<String, String> {
+    let (user, _properties) = get_data();
+    // Single user mode has no database row for the user
+    shorten(targets, Some(user.id).filter(|id| *id != 0))
+}
+
+pub fn shorten_link(target: &str) -> String {
+    let target = target.to_owned();
+    shorten_links(std::slice::from_ref(&target))
+        .remove(&target)
+        .unwrap_or(target)
+}
+
+// For links which are the same for everyone, like the sign up page. These also work after the user is deleted
+pub fn shorten_shared_link(target: &str) -> String {
+    let target = target.to_owned();
+    shorten(std::slice::from_ref(&target), None)
+        .remove(&target)
+        .unwrap_or(target)
+}
+
+fn shorten(targets: &[String], user_data_id: Option<i32>) -> HashMap<String, String> {
+    let mut targets: Vec<String> = targets
+        .iter()
+        .filter(|target| !target.is_empty())
+        .cloned()
+        .collect();
+    targets.sort();
+    targets.dedup();
+    if targets.is_empty() {
+        return HashMap::new();
+    }
+    let (Some(api_url), Some(secret)) = (public_api_url(), link_secret()) else {
+        return HashMap::new();
+    };
+    let Ok(handle) = Handle::try_current() else {
+        return HashMap::new();
+    };
+    tokio::task::block_in_place(|| {
+        handle.block_on(async {
+            create_short_links(
+                &get_database_connection().await,
+                user_data_id,
+                &targets,
+                &secret,
+            )
+            .await
+        })
+    })
+    .warn_owned("Creating short links")
+    .unwrap_or_default()
+    .into_iter()
+    .map(|(target, link)| (target, format!("{api_url}/api/r/{link}")))
+    .collect()
+}
+
+// Counts the click and returns where the link points to
+pub async fn follow_short_link(db: &DatabaseConnection, link: &str) -> GenResult<Option<String>> {
+    let Some(secret) = link_secret() else {
+        return Ok(None);
+    };
+    let Some(token) = verify_link(link, &secret) else {
+        return Ok(None);
+    };
+    let Some(short_link) = short_link::Entity::find()
+        .filter(short_link::Column::Token.eq(token))
+        .one(db)
+        .await?
+    else {
+        return Ok(None);
+    };
+    let target = short_link.target.clone();
+    let clicks = short_link.clicks;
+    let mut active_link = short_link.into_active_model();
+    active_link.clicks = Set(clicks + 1);
+    active_link.last_clicked_at = Set(Some(chrono::offset::Utc::now().naive_utc()));
+    active_link.update(db).await?;
+    Ok(Some(target))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_signed_links_are_accepted() {
+        let link = format!("abcd1234{}", sign_token("abcd1234", "key"));
+        assert_eq!(verify_link(&link, "key"), Some("abcd1234"));
+        assert_eq!(verify_link(&link, "other key"), None);
+        assert_eq!(verify_link("abcd1234AAAAAAAA", "key"), None);
+        assert_eq!(verify_link("abcd1234", "key"), None);
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::config::var;
+
+use crate::{
+    GenResult,
+    database::secret_backend::{SecretBackend, secret_backend},
+    execution::permissions::write_private_file,
+};
+
+// Encrypted files start with this, so plaintext files of before the encryption was enabled can still be read
+const ENCRYPTED_PREFIX: &str = "MIJN-BUSSIE-ENCRYPTED:";
+
+/*
+The state files of a user contain roster data, these can optionally be encrypted on disk with the secret backend
+Set ENCRYPT_STATE_FILES to true to enable it, existing files are encrypted the next time they are written
+*/
+fn encryption_backend() -> GenResult<Option<&'static dyn SecretBackend>> {
+    if var("ENCRYPT_STATE_FILES").is_ok_and(|encrypt| encrypt == "true") {
+        Ok(Some(secret_backend()?))
+    } else {
+        Ok(None)
+    }
+}
+
+fn encode(contents: &str, backend: Option<&dyn SecretBackend>) -> GenResult<String> {
+    Ok(match backend {
+        Some(backend) => format!("{ENCRYPTED_PREFIX}{}", backend.encrypt(contents)?),
+        None => contents.to_owned(),
+    })
+}
+
+// Encrypted files can always be read, even if encryption was disabled again
+fn decode(text: String) -> GenResult<String> {
+    match text.strip_prefix(ENCRYPTED_PREFIX) {
+        Some(encrypted) => secret_backend()?.decrypt(encrypted.trim_end()),
+        None => Ok(text),
+    }
+}
+
+pub fn read_state_file(path: impl AsRef<Path>) -> GenResult<String> {
+    decode(fs::read_to_string(path)?)
+}
+
+pub fn write_state_file(path: impl AsRef<Path>, contents: impl AsRef<str>) -> GenResult<()> {
+    write_private_file(path, encode(contents.as_ref(), encryption_backend()?)?)
+}
+
+/*
+Used when the secret is rotated. Every encrypted state file in the user directories of the file targets is decrypted
+and encrypted again with `rotate`, only in memory. Plaintext files are left alone
+*/
+pub fn read_rotated_state_files(
+    file_targets: &[String],
+    rotate: &dyn Fn(&str) -> GenResult<String>,
+) -> GenResult<Vec<(PathBuf, String)>> {
+    let mut rotated = vec![];
+    for file_target in file_targets {
+        let Ok(user_dirs) = fs::read_dir(file_target) else {
+            continue;
+        };
+        for user_dir in user_dirs {
+            let user_dir = user_dir?.path();
+            if !user_dir.is_dir() {
+                continue;
+            }
+            for file in fs::read_dir(&user_dir)? {
+                let path = file?.path();
+                if !path.is_file() {
+                    continue;
+                }
+                // Calendars and other files that are not text are never encrypted
+                let Ok(text) = fs::read_to_string(&path) else {
+                    continue;
+                };
+                if let Some(encrypted) = text.strip_prefix(ENCRYPTED_PREFIX) {
+                    let contents = format!("{ENCRYPTED_PREFIX}{}", rotate(encrypted.trim_end())?);
+                    rotated.push((path, contents));
+                }
+            }
+        }
+    }
+    Ok(rotated)
+}
+
+pub fn write_rotated_state_files(files: Vec<(PathBuf, String)>) -> GenResult<()> {
+    for (path, contents) in files {
+        write_private_file(path, contents)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct ReversingBackend;
+
+    impl SecretBackend for ReversingBackend {
+        fn encrypt(&self, value: &str) -> GenResult<String> {
+            Ok(value.chars().rev().collect())
+        }
+
+        fn decrypt(&self, value: &str) -> GenResult<String> {
+            Ok(value.chars().rev().collect())
+        }
+    }
+
+    #[test]
+    fn encrypted_files_are_prefixed() {
+        let encoded = encode("{\"retry_count\":1}", Some(&ReversingBackend)).unwrap();
+        assert_eq!(encoded, format!("{ENCRYPTED_PREFIX}}}1:\"tnuoc_yrter\"{{"));
+    }
+
+    #[test]
+    fn plaintext_files_are_read_as_is() {
+        let text = "{\"retry_count\":1}".to_owned();
+        assert_eq!(encode(&text, None).unwrap(), text);
+        assert_eq!(decode(text.clone()).unwrap(), text);
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{Local, NaiveDate, NaiveTime};
+use entity::{shift_history, user_data, user_properties};
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QuerySelect, RelationTrait};
+use serde::Serialize;
+
+use crate::{GenResult, get_database_connection, webcom::shift::ShiftState};
+
+// The part of a shift from the history that is needed to suggest swaps
+#[derive(Debug, Clone)]
+pub struct ScheduledShift {
+    pub number: String,
+    pub date: NaiveDate,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+// A shift of one user on a day where others are free, so they could take it over
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SwapSuggestion {
+    pub date: NaiveDate,
+    pub worker: String,
+    pub number: String,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub free: Vec<String>,
+}
+
+/*
+Finds the days where either the user works and colleagues are free, or a colleague works and the user is free
+Someone only counts as free on a day that falls within their known schedule, after the last known shift nothing is known
+*/
+pub fn suggest_swaps(
+    user_name: &str,
+    schedules: &HashMap<String, Vec<ScheduledShift>>,
+    from: NaiveDate,
+) -> Vec<SwapSuggestion> {
+    let horizons: HashMap<&str, NaiveDate> = schedules
+        .iter()
+        .filter_map(|(name, shifts)| Some((name.as_str(), shifts.iter().map(|s| s.date).max()?)))
+        .collect();
+    let is_free = |name: &str, date: NaiveDate| {
+        horizons.get(name).is_some_and(|horizon| date <= *horizon)
+            && !schedules[name].iter().any(|shift| shift.date == date)
+    };
+    let mut suggestions: BTreeMap<(NaiveDate, String, NaiveTime), SwapSuggestion> = BTreeMap::new();
+    for (worker, shifts) in schedules {
+        for shift in shifts.iter().filter(|shift| shift.date >= from) {
+            let mut free: Vec<String> = if worker == user_name {
+                schedules
+                    .keys()
+                    .filter(|name| *name != user_name && is_free(name, shift.date))
+                    .cloned()
+                    .collect()
+            } else if is_free(user_name, shift.date) {
+                vec![user_name.to_owned()]
+            } else {
+                vec![]
+            };
+            if free.is_empty() {
+                continue;
+            }
+            free.sort();
+            suggestions.insert(
+                (shift.date, worker.clone(), shift.start),
+                SwapSuggestion {
+                    date: shift.date,
+                    worker: worker.clone(),
+                    number: shift.number.clone(),
+                    start: shift.start,
+                    end: shift.end,
+                    free,
+                },
+            );
+        }
+    }
+    suggestions.into_values().collect()
+}
+
+// Upcoming swap possibilities between this user and every other user who opted in
+pub async fn load_swap_suggestions(user_name: &str) -> GenResult<Vec<SwapSuggestion>> {
+    let db = get_database_connection().await;
+    let opted_in: Vec<(i32, String)> = user_data::Entity::find()
+        .join(
+            sea_orm::JoinType::InnerJoin,
+            user_data::Relation::UserProperties.def(),
+        )
+        .filter(user_properties::Column::SuggestSwaps.eq(true))
+        .select_only()
+        .column(user_data::Column::UserDataId)
+        .column(user_data::Column::UserName)
+        .into_tuple()
+        .all(&db)
+        .await?;
+    if !opted_in.iter().any(|(_, name)| name == user_name) {
+        return Err("User has not opted in to swap suggestions".into());
+    }
+    let today = Local::now().date_naive();
+    let deleted_state = serde_json::to_string(&ShiftState::Deleted)?;
+    let mut schedules = HashMap::new();
+    for (user_id, name) in opted_in {
+        let shifts = shift_history::Entity::find()
+            .filter(shift_history::Column::UserDataId.eq(user_id))
+            .filter(shift_history::Column::ShiftDate.gte(today))
+            .filter(shift_history::Column::State.ne(deleted_state.clone()))
+            .all(&db)
+            .await?
+            .into_iter()
+            .map(|shift| ScheduledShift {
+                number: shift.number,
+                date: shift.shift_date,
+                start: shift.start_time,
+                end: shift.end_time,
+            })
+            .collect();
+        schedules.insert(name, shifts);
+    }
+    Ok(suggest_swaps(user_name, &schedules, today))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shift(number: &str, day: u32) -> ScheduledShift {
+        ScheduledShift {
+            number: number.to_owned(),
+            date: NaiveDate::from_ymd_opt(2025, 6, day).unwrap(),
+            start: NaiveTime::from_hms_opt(6, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(13, 40, 0).unwrap(),
+        }
+    }
+
+    fn from() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 6, 1).unwrap()
+    }
+
+    #[test]
+    fn swaps_go_both_ways() {
+        let schedules = HashMap::from([
+            (
+                "piet".to_owned(),
+                vec![shift("V2309", 2), shift("V2309", 4)],
+            ),
+            (
+                "klaas".to_owned(),
+                vec![shift("V1001", 3), shift("V1001", 4)],
+            ),
+        ]);
+        let swaps = suggest_swaps("piet", &schedules, from());
+        assert_eq!(swaps.len(), 2);
+        assert_eq!(swaps[0].worker, "piet");
+        assert_eq!(swaps[0].free, vec!["klaas".to_owned()]);
+        assert_eq!(swaps[1].worker, "klaas");
+        assert_eq!(swaps[1].free, vec!["piet".to_owned()]);
+    }
+
+    #[test]
+    fn unknown_days_are_not_free() {
+        let schedules = HashMap::from([
+            ("piet".to_owned(), vec![shift("V2309", 5)]),
+            ("klaas".to_owned(), vec![shift("V1001", 2)]),
+        ]);
+        let swaps = suggest_swaps("piet", &schedules, from());
+        // Klaas his schedule is not known on the 5th, piet is free on the 2nd
+        assert_eq!(swaps.len(), 1);
+        assert_eq!(swaps[0].worker, "klaas");
+        assert_eq!(swaps[0].free, vec!["piet".to_owned()]);
+    }
+
+    #[test]
+    fn both_working_is_no_swap() {
+        let schedules = HashMap::from([
+            ("piet".to_owned(), vec![shift("V2309", 2)]),
+            ("klaas".to_owned(), vec![shift("V1001", 2)]),
+        ]);
+        assert!(suggest_swaps("piet", &schedules, from()).is_empty());
+    }
+}
@@ -1,11 +1,11 @@
+use crate::config::var;
 use chrono::NaiveDateTime;
-use dotenvy::var;
 use entity::{
     donation_text, email_properties, general_properties_db, kuma_properties, user_data,
     user_properties,
 };
 use sea_orm::RelationTrait;
-use sea_orm::{ColumnTrait, QuerySelect};
+use sea_orm::{ColumnTrait, PaginatorTrait, QuerySelect};
 use sea_orm::{DatabaseConnection, DerivePartialModel, EntityTrait, QueryFilter};
 use serde::Serialize;
 use std::sync::Arc;
@@ -54,10 +54,17 @@ impl UserInstanceData {
         }
     }
 
-    pub async fn update_user(&self, db: &DatabaseConnection) -> GenResult<()> {
+    // Returns true if the user moved to a different properties group. The instance has to be restarted then,
+    // because instances in the default group share their properties
+    pub async fn update_user(&self, db: &DatabaseConnection) -> GenResult<bool> {
         let username = self.user_data.read().await.user_name.clone();
         let userdata = UserData::get_from_username(db, &username).await?;
         if let Some(user_data) = userdata {
+            if user_data.custom_general_properties
+                != self.user_data.read().await.custom_general_properties
+            {
+                return Ok(true);
+            }
             *self.user_data.write().await = user_data.clone();
             let custom_properties_id = user_data.custom_general_properties.clone();
             if let Some(custom_id) = custom_properties_id
@@ -66,7 +73,7 @@ impl UserInstanceData {
                 *self.general_settings.write().await = custom_properties;
             }
         }
-        Ok(())
+        Ok(false)
     }
 }
 
@@ -87,6 +94,25 @@ pub struct GeneralProperties {
     pub support_mail: String,
     pub password_reset_link: String,
     pub sign_up_url: String,
+    pub evening_surcharge_start_hour: i32,
+    pub evening_surcharge_percent: i32,
+    pub weekend_surcharge_percent: i32,
+    pub stale_calendar_hours: i32,
+    pub donation_reminder_months: i32,
+    // Push a down heartbeat when all tries failed, instead of no heartbeat at all
+    pub heartbeat_on_tries_exceeded: bool,
+    // Errors of all users are combined into one mail to the support address per window
+    pub error_mail_window_minutes: i32,
+    // Stopped users get their Kuma monitor paused instead of deleted, it is only deleted with the account
+    pub kuma_pause_on_stop: bool,
+    // The name of the Kuma monitor of a user, like "{app}-{group}-{user}"
+    pub kuma_monitor_name_template: String,
+    // How Kuma alerts the users of this group, see KumaNotificationProvider
+    pub kuma_notification_provider: String,
+    // Values for the notification template, the values in the user properties are added on top
+    pub kuma_notification_settings: String,
+    // Kept up to date by the Kuma reconciliation, the welcome mail links to it
+    pub kuma_status_page_url: Option<String>,
     #[sea_orm(nested)]
     pub kuma_properties: KumaProperties,
     #[sea_orm(nested, alias = "general_email")]
@@ -117,16 +143,20 @@ impl GeneralProperties {
     }
 
     pub async fn load_default_preferences(db: &DatabaseConnection) -> GenResult<GeneralProperties> {
-        let properties_id = var("DEFAULT_PROPERTIES_ID")
-            .ok()
-            .and_then(|s| s.parse::<i32>().ok())
-            .unwrap_or(1);
+        let properties_id = default_properties_id();
         Ok(GeneralProperties::get(db, properties_id)
             .await?
             .expect("No default properties"))
     }
 }
 
+pub fn default_properties_id() -> i32 {
+    var("DEFAULT_PROPERTIES_ID")
+        .ok()
+        .and_then(|s| s.parse::<i32>().ok())
+        .unwrap_or(1)
+}
+
 #[allow(dead_code)]
 #[derive(DerivePartialModel, Debug, Clone)]
 #[sea_orm(entity = "kuma_properties::Entity")]
@@ -158,11 +188,19 @@ pub struct UserData {
     pub file_name: String,
     #[sea_orm(nested)]
     pub user_properties: user_properties::Model,
-    custom_general_properties: Option<i32>,
+    pub custom_general_properties: Option<i32>,
     pub last_succesfull_sign_in_date: Option<NaiveDateTime>,
     pub last_system_execution_date: Option<NaiveDateTime>,
     pub last_execution_date: Option<NaiveDateTime>,
     pub creation_date: NaiveDateTime,
+    pub last_calendar_write_date: Option<NaiveDateTime>,
+    pub last_donation_reminder_date: Option<NaiveDateTime>,
+    // Secret part of the URL the calendar is served on by the API
+    pub calendar_token: String,
+    // Set if a mail to the user bounced, mails to the user are not sent until it is cleared
+    pub mail_paused_date: Option<NaiveDateTime>,
+    pub mail_paused_reason: Option<String>,
+    pub last_weekly_summary_date: Option<NaiveDateTime>,
 }
 
 impl UserData {
@@ -172,6 +210,7 @@ impl UserData {
     ) -> GenResult<Option<Self>> {
         if let Some(id) = user_data::Entity::find()
             .filter(user_data::Column::UserName.contains(username))
+            .filter(user_data::Column::Activated.eq(true))
             .column(user_data::Column::UserDataId)
             .into_tuple::<i32>()
             .one(db)
@@ -196,13 +235,175 @@ impl UserData {
             .await?)
     }
 
+    // Admins can tune these per user, for flaky accounts or heavy rosters. Otherwise the general properties are used
+    pub fn execution_retry_count(&self, properties: &GeneralProperties) -> i32 {
+        self.user_properties
+            .execution_retry_count
+            .unwrap_or(properties.execution_retry_count)
+    }
+
+    pub fn expected_execution_time_seconds(&self, properties: &GeneralProperties) -> i32 {
+        self.user_properties
+            .expected_execution_time_seconds
+            .unwrap_or(properties.expected_execution_time_seconds)
+    }
+
     pub async fn get_all_usernames(db: &DatabaseConnection) -> GenResult<Vec<String>> {
         let data: Vec<String> = user_data::Entity::find()
             .select_only()
             .column(user_data::Column::UserName)
+            // Users of the frontend which have not redeemed an invite code yet are not started
+            .filter(user_data::Column::Activated.eq(true))
             .into_tuple()
             .all(db)
             .await?;
         Ok(data)
     }
+
+    // Also finds users which are not activated, unlike get_from_username
+    pub async fn username_exists(db: &DatabaseConnection, username: &str) -> GenResult<bool> {
+        Ok(user_data::Entity::find()
+            .filter(user_data::Column::UserName.eq(username))
+            .count(db)
+            .await?
+            > 0)
+    }
+}
+
+// Example data, so code that needs an instance context can be tested without a database
+#[cfg(test)]
+impl UserData {
+    pub fn example() -> Self {
+        use secrecy::SecretString;
+        let secret = |value: &str| Secret(SecretString::new(value.into()));
+        UserData {
+            id: 1,
+            user_name: "test_user".to_owned(),
+            personeelsnummer: secret("12345"),
+            password: secret("password"),
+            email: secret("test@example.com"),
+            name: Some(secret("Test")),
+            file_name: String::new(),
+            user_properties: user_properties::Model {
+                user_properties_id: 1,
+                execution_interval_minutes: 120,
+                execution_minute: 0,
+                send_mail_new_shift: true,
+                send_mail_updated_shift: true,
+                send_mail_removed_shift: true,
+                send_failed_signin_mail: true,
+                send_welcome_mail: true,
+                send_error_mail: true,
+                split_night_shift: false,
+                stop_midnight_shift: false,
+                auto_delete_account: true,
+                max_weekly_hours: 48,
+                send_overtime_mail: true,
+                min_rest_hours: 11,
+                hourly_wage_cents: 2000,
+                suggest_swaps: false,
+                show_broken_pause: false,
+                send_donation_reminder: true,
+                execution_retry_count: None,
+                expected_execution_time_seconds: None,
+                send_weekly_summary: false,
+                log_level: None,
+                kuma_public_monitor: false,
+                language: "nl".to_owned(),
+                kuma_notification_settings: "{}".to_owned(),
+            },
+            custom_general_properties: None,
+            last_succesfull_sign_in_date: None,
+            last_system_execution_date: None,
+            last_execution_date: None,
+            creation_date: NaiveDateTime::default(),
+            last_calendar_write_date: None,
+            last_donation_reminder_date: None,
+            calendar_token: "test_token".to_owned(),
+            mail_paused_date: None,
+            mail_paused_reason: None,
+            last_weekly_summary_date: None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl GeneralProperties {
+    pub fn example() -> Self {
+        let email_properties = email_properties::Model {
+            email_id: 1,
+            mail_from: "bussie@example.com".to_owned(),
+            smtp_server: "smtp.example.com".to_owned(),
+            smtp_username: "bussie".to_owned(),
+            smtp_password: "password".to_owned(),
+            smtp_pool_max_size: 4,
+            smtp_pool_idle_timeout_seconds: 60,
+        };
+        GeneralProperties {
+            general_properties_id: 1,
+            calendar_target: "https://example.com/".to_owned(),
+            file_target: std::env::temp_dir()
+                .join("mijn_bussie_test")
+                .to_string_lossy()
+                .to_string(),
+            ical_domain: "https://example.com/".to_owned(),
+            webcal_domain: "webcal://example.com/".to_owned(),
+            pdf_shift_domain: String::new(),
+            signin_fail_execution_reduce: 2,
+            signin_fail_mail_reduce: 2,
+            expected_execution_time_seconds: 60,
+            execution_retry_count: 3,
+            support_mail: "support@example.com".to_owned(),
+            password_reset_link: "https://example.com/reset".to_owned(),
+            sign_up_url: "https://example.com/signup".to_owned(),
+            evening_surcharge_start_hour: 19,
+            evening_surcharge_percent: 20,
+            weekend_surcharge_percent: 50,
+            stale_calendar_hours: 48,
+            donation_reminder_months: 6,
+            heartbeat_on_tries_exceeded: false,
+            error_mail_window_minutes: 60,
+            kuma_pause_on_stop: false,
+            kuma_monitor_name_template: "{user}".to_owned(),
+            kuma_notification_provider: "smtp".to_owned(),
+            kuma_notification_settings: "{}".to_owned(),
+            kuma_status_page_url: None,
+            kuma_properties: KumaProperties {
+                domain: String::new(),
+                username: String::new(),
+                password: String::new(),
+                hearbeat_retry: 1,
+                offline_mail_resend_hours: 24,
+                kuma_email_properties: email_properties.clone(),
+                mail_port: 465,
+                use_ssl: true,
+            },
+            general_email_properties: email_properties,
+            donation_text: donation_text::Model {
+                donation_id: 1,
+                donate_link: String::new(),
+                donate_service_name: String::new(),
+                donate_text: String::new(),
+                iban: String::new(),
+                iban_name: String::new(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_overrides_take_precedence() {
+        let properties = GeneralProperties::example();
+        let mut user = UserData::example();
+        assert_eq!(user.execution_retry_count(&properties), 3);
+        assert_eq!(user.expected_execution_time_seconds(&properties), 60);
+        user.user_properties.execution_retry_count = Some(6);
+        user.user_properties.expected_execution_time_seconds = Some(300);
+        assert_eq!(user.execution_retry_count(&properties), 6);
+        assert_eq!(user.expected_execution_time_seconds(&properties), 300);
+    }
 }
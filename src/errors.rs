@@ -1,14 +1,23 @@
+use chrono::NaiveDateTime;
+
 use crate::{
-    GenResult, create_path, get_data, set_strict_file_permissions,
+    GenError, GenResult, create_path,
+    database::state_file::{read_state_file, write_state_file},
+    get_data, is_dry_run,
     webcom::{email, webcom::ResumeReason},
 };
+use axum::{
+    Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
 use secrecy::ExposeSecret;
 use serde::{Deserialize, Serialize};
 use std::{
     fmt::Display,
-    fs::write,
     hash::{DefaultHasher, Hash, Hasher},
 };
+use strum_macros::{EnumString, IntoStaticStr};
 use thirtyfour::{By, WebDriver};
 use thiserror::Error;
 use tracing::*;
@@ -60,6 +69,256 @@ pub enum FailureType {
     OK,
 }
 
+impl SignInFailure {
+    pub fn code(&self) -> &'static str {
+        match self {
+            SignInFailure::TooManyTries => "too_many_tries",
+            SignInFailure::IncorrectCredentials => "incorrect_credentials",
+            SignInFailure::WebcomDown => "webcom_down",
+            SignInFailure::Other(_) => "other",
+            SignInFailure::Unknown => "unknown",
+        }
+    }
+}
+
+impl FailureType {
+    pub fn code(&self) -> &'static str {
+        match self {
+            FailureType::TriesExceeded => "tries_exceeded",
+            FailureType::GeckoEngine => "gecko_engine",
+            FailureType::SignInFailed(_) => "sign_in_failed",
+            FailureType::ConnectError => "connect_error",
+            FailureType::Other(_) => "other",
+            FailureType::OK => "ok",
+        }
+    }
+
+    pub fn status(&self) -> FailureStatus {
+        FailureStatus {
+            code: self.code(),
+            message: self.to_string(),
+            sign_in_failure: match self {
+                FailureType::SignInFailed(failure) => Some(SignInStatus {
+                    code: failure.code(),
+                    message: failure.to_string(),
+                }),
+                _ => None,
+            },
+        }
+    }
+}
+
+/*
+How an exit code is shown by the API. FailureType itself is stored in the logbook and database, so its format can not change
+Frontends switch on the code, the message is the Dutch text which used to be the only thing shown
+*/
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct FailureStatus {
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sign_in_failure: Option<SignInStatus>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct SignInStatus {
+    pub code: &'static str,
+    pub message: String,
+}
+
+pub type AppResult<T> = Result<T, AppError>;
+
+// What kind of error it is, this decides which status code the API returns
+// Stored in the logbook by its code, like "template"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumString, IntoStaticStr)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ErrorCategory {
+    Webcom,
+    Browser,
+    Database,
+    Io,
+    Mail,
+    Template,
+    Parse,
+    Http,
+    Timeout,
+    Other,
+}
+
+// Whether an error can go away by itself, only transient errors are worth trying again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorClass {
+    Transient,
+    Permanent,
+}
+
+/*
+The errors of the application, sorted by where they come from
+The wrapped errors keep their own text, so logs and error mails look the same as before
+Everything which is still a GenError can be converted, the known error types inside are recognised
+*/
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error(transparent)]
+    Failure(#[from] FailureType),
+    // Boxed, the error of the browser is a lot bigger than the others
+    #[error(transparent)]
+    WebDriver(Box<thirtyfour::error::WebDriverError>),
+    #[error(transparent)]
+    Database(#[from] sea_orm::DbErr),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Smtp(#[from] lettre::transport::smtp::Error),
+    #[error(transparent)]
+    MailBuild(#[from] lettre::error::Error),
+    #[error(transparent)]
+    Address(#[from] lettre::address::AddressError),
+    #[error(transparent)]
+    Template(#[from] strfmt::FmtError),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Url(#[from] url::ParseError),
+    #[error(transparent)]
+    TimeFormat(#[from] time::error::Format),
+    #[error(transparent)]
+    LocalTime(#[from] time::error::IndeterminateOffset),
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error("Geen antwoord binnen de tijd")]
+    Timeout(#[from] tokio::time::error::Elapsed),
+    #[error("{0}")]
+    Other(GenError),
+}
+
+impl AppError {
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            AppError::Failure(_) => ErrorCategory::Webcom,
+            AppError::WebDriver(_) => ErrorCategory::Browser,
+            AppError::Database(_) => ErrorCategory::Database,
+            AppError::Io(_) => ErrorCategory::Io,
+            AppError::Smtp(_) | AppError::MailBuild(_) | AppError::Address(_) => {
+                ErrorCategory::Mail
+            }
+            AppError::Template(_) | AppError::TimeFormat(_) => ErrorCategory::Template,
+            AppError::Json(_) | AppError::Url(_) => ErrorCategory::Parse,
+            AppError::Http(_) => ErrorCategory::Http,
+            AppError::Timeout(_) => ErrorCategory::Timeout,
+            AppError::LocalTime(_) | AppError::Other(_) => ErrorCategory::Other,
+        }
+    }
+
+    /*
+    A broken template or an invalid address fails the same way every time, retrying only hides the bug
+    A failed sign in or an unreachable Webcomm are not solved by trying again right away, so those are permanent for this run
+    Unknown errors are assumed to be transient, most of those come from a page which did not load correctly
+    */
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            AppError::Failure(FailureType::SignInFailed(_) | FailureType::ConnectError)
+            | AppError::Template(_)
+            | AppError::MailBuild(_)
+            | AppError::Address(_)
+            | AppError::Json(_)
+            | AppError::Url(_)
+            | AppError::TimeFormat(_)
+            | AppError::LocalTime(_) => ErrorClass::Permanent,
+            AppError::Failure(_)
+            | AppError::WebDriver(_)
+            | AppError::Database(_)
+            | AppError::Io(_)
+            | AppError::Smtp(_)
+            | AppError::Http(_)
+            | AppError::Timeout(_)
+            | AppError::Other(_) => ErrorClass::Transient,
+        }
+    }
+
+    pub fn retry(&self) -> bool {
+        self.class() == ErrorClass::Transient
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        match self.category() {
+            ErrorCategory::Webcom | ErrorCategory::Browser | ErrorCategory::Mail => {
+                StatusCode::BAD_GATEWAY
+            }
+            ErrorCategory::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            // Parse errors come from data the application read itself, not from the request
+            ErrorCategory::Parse
+            | ErrorCategory::Database
+            | ErrorCategory::Io
+            | ErrorCategory::Template
+            | ErrorCategory::Http
+            | ErrorCategory::Other => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        (self.status_code(), Json(self.to_string())).into_response()
+    }
+}
+
+impl From<GenError> for AppError {
+    fn from(error: GenError) -> Self {
+        // Tries every known type in turn, the error is handed back if it is not that type
+        macro_rules! recognise {
+            ($error:ident, $($kind:ty),+) => {
+                $(let $error = match $error.downcast::<$kind>() {
+                    Ok(known) => return AppError::from(*known),
+                    Err(error) => error,
+                };)+
+            };
+        }
+        let error = match error.downcast::<AppError>() {
+            Ok(app_error) => return *app_error,
+            Err(error) => error,
+        };
+        recognise!(
+            error,
+            FailureType,
+            thirtyfour::error::WebDriverError,
+            sea_orm::DbErr,
+            std::io::Error,
+            lettre::transport::smtp::Error,
+            lettre::error::Error,
+            lettre::address::AddressError,
+            strfmt::FmtError,
+            serde_json::Error,
+            url::ParseError,
+            time::error::Format,
+            time::error::IndeterminateOffset,
+            reqwest::Error,
+            tokio::time::error::Elapsed
+        );
+        AppError::Other(error)
+    }
+}
+
+impl From<thirtyfour::error::WebDriverError> for AppError {
+    fn from(error: thirtyfour::error::WebDriverError) -> Self {
+        AppError::WebDriver(Box::new(error))
+    }
+}
+
+impl From<&str> for AppError {
+    fn from(error: &str) -> Self {
+        AppError::Other(error.into())
+    }
+}
+
+impl From<String> for AppError {
+    fn from(error: String) -> Self {
+        AppError::Other(error.into())
+    }
+}
+
 pub trait OptionResult<T> {
     fn result(self) -> GenResult<T>;
     fn result_reason(self, reason: &str) -> GenResult<T>;
@@ -116,6 +375,24 @@ fn get_sign_in_error_type(text: &str) -> SignInFailure {
     }
 }
 
+/*
+Why runs of a user are skipped because of failed sign ins
+Incorrect credentials are parked until the password changes, so there is no next attempt for those
+*/
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ParkState {
+    pub reason: SignInFailure,
+    pub retry_count: i32,
+    pub runs_until_next_attempt: Option<i32>,
+    pub next_attempt: Option<NaiveDateTime>,
+}
+
+// Every run increases the counter, only when the counter is a multiple of the reduce value sign in is tried again
+fn runs_until_attempt(retry_count: i32, sign_in_attempt_reduce: i32) -> i32 {
+    let reduce = sign_in_attempt_reduce.max(1);
+    reduce - retry_count.rem_euclid(reduce)
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct IncorrectCredentialsCount {
     pub retry_count: i32,
@@ -127,7 +404,7 @@ impl IncorrectCredentialsCount {
     pub fn load() -> IncorrectCredentialsCount {
         let path = create_path("sign_in_failure_count.json");
         || -> GenResult<IncorrectCredentialsCount> {
-            let failure_count_json = std::fs::read_to_string(path)?;
+            let failure_count_json = read_state_file(path)?;
             Ok(serde_json::from_str::<IncorrectCredentialsCount>(
                 &failure_count_json,
             )?)
@@ -136,11 +413,12 @@ impl IncorrectCredentialsCount {
     }
 
     fn save(&self) -> GenResult<()> {
+        if is_dry_run() {
+            return Ok(());
+        }
         let path = create_path("sign_in_failure_count.json");
         let failure_counter_serialised = serde_json::to_string(self)?;
-        write(path.clone(), failure_counter_serialised.as_bytes())
-            .warn("saving incorrect credentials");
-        set_strict_file_permissions(&path).warn("setting incorrect credentials permissions");
+        write_state_file(&path, failure_counter_serialised).warn("saving incorrect credentials");
         Ok(())
     }
 
@@ -152,17 +430,54 @@ impl IncorrectCredentialsCount {
         Ok(hasher.finish())
     }
 
+    // The password was incorrect, but it has been changed since
+    pub fn has_new_password(&self) -> bool {
+        if let Some(previous_password_hash) = self.previous_password_hash
+            && let Ok(current_password_hash) = Self::get_password_hash()
+            && previous_password_hash != current_password_hash
+            && self.error == Some(SignInFailure::IncorrectCredentials)
+        {
+            return true;
+        }
+        false
+    }
+
+    pub fn park_state(&self) -> Option<ParkState> {
+        let (user, properties) = get_data();
+        let reason = self.error.clone()?;
+        let (runs_until_next_attempt, next_attempt) = match reason {
+            SignInFailure::IncorrectCredentials => (None, None),
+            _ => {
+                let runs =
+                    runs_until_attempt(self.retry_count, properties.signin_fail_execution_reduce);
+                let interval = user.user_properties.execution_interval_minutes.max(60) as i64;
+                let next_attempt = chrono::offset::Utc::now().naive_utc()
+                    + chrono::Duration::minutes(interval * runs as i64);
+                (Some(runs), Some(next_attempt))
+            }
+        };
+        Some(ParkState {
+            reason,
+            retry_count: self.retry_count,
+            runs_until_next_attempt,
+            next_attempt,
+        })
+    }
+
+    // The error is kept, so a successful retry still sends the sign in succesful mail
+    pub fn reset_retry_count() -> GenResult<()> {
+        let mut counter = Self::load();
+        counter.retry_count = 0;
+        counter.save()
+    }
+
     pub fn sign_in_failed_check(&mut self) -> ResumeReason {
         let (_user, properties) = get_data();
         let resend_error_mail_count = properties.signin_fail_mail_reduce;
         let sign_in_attempt_reduce = properties.signin_fail_execution_reduce;
         let return_value: ResumeReason;
 
-        if let Some(previous_password_hash) = self.previous_password_hash
-            && let Ok(current_password_hash) = Self::get_password_hash()
-            && previous_password_hash != current_password_hash
-            && self.error == Some(SignInFailure::IncorrectCredentials)
-        {
+        if self.has_new_password() {
             info!("Password hash has changed, resuming execution");
             return ResumeReason::NewPassword;
         }
@@ -288,3 +603,95 @@ impl<T: std::fmt::Debug> ToString for GenResult<T> {
         format!("{:?}", self)
     }
 }
+
+impl<T: std::fmt::Debug> ToString for AppResult<T> {
+    fn to_string(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boxed_errors_keep_their_category() {
+        let error: GenError = FailureType::ConnectError.into();
+        let error = AppError::from(error);
+        assert_eq!(error.category(), ErrorCategory::Webcom);
+        assert!(!error.retry());
+        assert_eq!(error.status_code(), StatusCode::BAD_GATEWAY);
+
+        let error: GenError = std::io::Error::other("disk full").into();
+        let error = AppError::from(error);
+        assert_eq!(error.category(), ErrorCategory::Io);
+        assert_eq!(error.to_string(), "disk full");
+        assert!(error.retry());
+
+        // Converting back and forth does not lose the type
+        let error: GenError = AppError::from(FailureType::TriesExceeded).into();
+        assert!(matches!(
+            AppError::from(error),
+            AppError::Failure(FailureType::TriesExceeded)
+        ));
+        assert_eq!(
+            AppError::from("Geen fout banner gevonden").category(),
+            ErrorCategory::Other
+        );
+
+        let error: GenError = url::Url::parse("geen url").unwrap_err().into();
+        let error = AppError::from(error);
+        assert_eq!(error.category(), ErrorCategory::Parse);
+        assert_eq!(error.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn category_is_stored_by_its_code() {
+        let code: &str = ErrorCategory::Template.into();
+        assert_eq!(code, "template");
+        assert_eq!(
+            <ErrorCategory as std::str::FromStr>::from_str("template"),
+            Ok(ErrorCategory::Template)
+        );
+    }
+
+    #[test]
+    fn code_errors_are_not_retried() {
+        let template_error: GenError = strfmt::FmtError::KeyError("name".to_owned()).into();
+        assert_eq!(
+            AppError::from(template_error).class(),
+            ErrorClass::Permanent
+        );
+        let sign_in = AppError::from(FailureType::SignInFailed(SignInFailure::WebcomDown));
+        assert!(!sign_in.retry());
+        assert!(AppError::from("Element niet gevonden").retry());
+        assert!(AppError::from(FailureType::TriesExceeded).retry());
+    }
+
+    #[test]
+    fn failure_status_has_stable_codes() {
+        let status = FailureType::SignInFailed(SignInFailure::IncorrectCredentials).status();
+        assert_eq!(
+            serde_json::to_value(&status).unwrap(),
+            serde_json::json!({
+                "code": "sign_in_failed",
+                "message": "Mijn Bussie kon niet inloggen. Fout: Inloggegevens kloppen niet",
+                "sign_in_failure": {
+                    "code": "incorrect_credentials",
+                    "message": "Inloggegevens kloppen niet"
+                }
+            })
+        );
+        let status = serde_json::to_value(FailureType::OK.status()).unwrap();
+        assert_eq!(status["code"], "ok");
+        assert!(status.get("sign_in_failure").is_none());
+    }
+
+    #[test]
+    fn attempt_is_allowed_on_multiple_of_reduce() {
+        assert_eq!(runs_until_attempt(0, 3), 3);
+        assert_eq!(runs_until_attempt(2, 3), 1);
+        assert_eq!(runs_until_attempt(4, 3), 2);
+        assert_eq!(runs_until_attempt(5, 0), 1);
+    }
+}
@@ -0,0 +1,119 @@
+use std::{
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::config::var;
+
+// Shared by all instances, they all use the same Selenium server
+pub static SELENIUM_BREAKER: LazyLock<Mutex<CircuitBreaker>> =
+    LazyLock::new(|| Mutex::new(CircuitBreaker::from_env()));
+
+/*
+Stops every instance from trying to start a browser when Selenium keeps failing
+After a number of failures in a row, no attempts are made until the cool-down passed. Then one attempt is let through,
+the others keep waiting until it succeeded. If it fails the breaker opens again right away. The admin is only alerted the first time it opens
+*/
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+    alerted: bool,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cooldown,
+            consecutive_failures: 0,
+            open_until: None,
+            alerted: false,
+        }
+    }
+
+    fn from_env() -> Self {
+        let threshold = var("SELENIUM_BREAKER_FAILURES")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(5);
+        let cooldown_minutes = var("SELENIUM_BREAKER_COOLDOWN_MINUTES")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(15);
+        Self::new(threshold, Duration::from_secs(cooldown_minutes * 60))
+    }
+
+    /*
+    How long attempts are still paused, if they are
+    The first call after the cool-down is the trial attempt, it pauses the others for another cool-down
+    So if the trial never reports back, the next trial is made after that cool-down
+    */
+    pub fn try_attempt(&mut self, now: Instant) -> Option<Duration> {
+        match self.open_until {
+            Some(open_until) if now < open_until => Some(open_until - now),
+            Some(_) => {
+                self.open_until = Some(now + self.cooldown);
+                None
+            }
+            None => None,
+        }
+    }
+
+    // Returns true if this failure opened the breaker and the admin was not alerted yet
+    pub fn record_failure(&mut self, now: Instant) -> bool {
+        self.consecutive_failures += 1;
+        if self.consecutive_failures < self.threshold {
+            return false;
+        }
+        self.open_until = Some(now + self.cooldown);
+        !std::mem::replace(&mut self.alerted, true)
+    }
+
+    // Returns true if the breaker was open before
+    pub fn record_success(&mut self) -> bool {
+        let was_open = self.alerted;
+        self.consecutive_failures = 0;
+        self.open_until = None;
+        self.alerted = false;
+        was_open
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_after_consecutive_failures() {
+        let start = Instant::now();
+        let cooldown = Duration::from_secs(600);
+        let mut breaker = CircuitBreaker::new(3, cooldown);
+        assert!(!breaker.record_failure(start));
+        assert!(!breaker.record_failure(start));
+        assert_eq!(breaker.try_attempt(start), None);
+        assert!(breaker.record_failure(start));
+        assert_eq!(breaker.try_attempt(start), Some(cooldown));
+        // Only one attempt is let through after the cool-down
+        assert_eq!(breaker.try_attempt(start + cooldown), None);
+        assert_eq!(breaker.try_attempt(start + cooldown), Some(cooldown));
+        assert!(breaker.record_success());
+        assert_eq!(breaker.try_attempt(start + cooldown), None);
+    }
+
+    #[test]
+    fn admin_is_alerted_once() {
+        let start = Instant::now();
+        let cooldown = Duration::from_secs(600);
+        let mut breaker = CircuitBreaker::new(1, cooldown);
+        assert!(breaker.record_failure(start));
+        // The attempt after the cool-down failed as well
+        assert!(!breaker.record_failure(start + cooldown));
+        assert!(breaker.try_attempt(start + cooldown).is_some());
+        assert!(breaker.record_success());
+        assert!(!breaker.record_success());
+        assert!(breaker.record_failure(start + cooldown * 3));
+    }
+}
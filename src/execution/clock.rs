@@ -0,0 +1,48 @@
+use chrono::NaiveDateTime;
+use time::{OffsetDateTime, Time};
+use tracing::*;
+
+use crate::health::ApplicationLogbook;
+
+// Source of the current time for the scheduler, so it can be replaced in tests
+pub trait Clock: Send + Sync {
+    // Local wall clock time, used for scheduling executions
+    fn time(&self) -> Time;
+    // UTC timestamp, used to compare against stored execution dates
+    fn naive_utc(&self) -> NaiveDateTime;
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn time(&self) -> Time {
+        let time = OffsetDateTime::now_local()
+            .unwrap_or(OffsetDateTime::now_utc())
+            .time();
+        debug!("system time: {:?}", time);
+        time
+    }
+
+    fn naive_utc(&self) -> NaiveDateTime {
+        ApplicationLogbook::get_naive_datetime()
+    }
+}
+
+#[cfg(test)]
+#[derive(Clone, Copy)]
+pub struct MockClock {
+    pub time: Time,
+    pub naive_utc: NaiveDateTime,
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn time(&self) -> Time {
+        self.time
+    }
+
+    fn naive_utc(&self) -> NaiveDateTime {
+        self.naive_utc
+    }
+}
@@ -0,0 +1,259 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::config::var;
+use serde::Serialize;
+use tracing::*;
+
+use crate::{
+    GenResult, create_path, create_path_local,
+    database::variables::{GeneralProperties, UserData},
+    errors::ResultLog,
+};
+
+const DEFAULT_QUOTA_MB: u64 = 100;
+const DEFAULT_FILE_RETENTION_DAYS: u64 = 30;
+// Files left behind by a write that was interrupted
+const PARTIAL_FILE_EXTENSION: &str = "tmp";
+const PARTIAL_FILE_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+// Folders of which the files are only kept for reference, these can be removed if the quota is exceeded
+const DISPOSABLE_DIRS: [&str; 3] = ["mails", "dry_run_mails", "logs"];
+
+pub struct DiskQuota {
+    max_bytes: u64,
+    retention: Duration,
+}
+
+impl DiskQuota {
+    pub fn from_env() -> Self {
+        let max_mb = var("USER_DISK_QUOTA_MB")
+            .ok()
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(DEFAULT_QUOTA_MB);
+        let retention_days = var("USER_FILE_RETENTION_DAYS")
+            .ok()
+            .and_then(|days| days.parse().ok())
+            .unwrap_or(DEFAULT_FILE_RETENTION_DAYS);
+        Self {
+            max_bytes: max_mb * 1024 * 1024,
+            retention: Duration::from_secs(retention_days * 24 * 60 * 60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct StoredFile {
+    path: PathBuf,
+    modified: SystemTime,
+    size: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserDiskUsage {
+    pub user_name: String,
+    pub used_bytes: u64,
+    pub quota_bytes: u64,
+    pub over_quota: bool,
+}
+
+impl UserDiskUsage {
+    pub fn collect(user: &UserData, properties: &GeneralProperties) -> Self {
+        let quota = DiskQuota::from_env();
+        let used_bytes = directory_size(&create_path_local(user, properties, ""));
+        Self {
+            user_name: user.user_name.clone(),
+            used_bytes,
+            quota_bytes: quota.max_bytes,
+            over_quota: used_bytes > quota.max_bytes,
+        }
+    }
+}
+
+pub fn directory_size(path: &Path) -> u64 {
+    list_files(path).iter().map(|file| file.size).sum()
+}
+
+fn list_files(path: &Path) -> Vec<StoredFile> {
+    let Ok(entries) = fs::read_dir(path) else {
+        return vec![];
+    };
+    let mut files = vec![];
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            files.extend(list_files(&path));
+        } else {
+            files.push(StoredFile {
+                path,
+                modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                size: metadata.len(),
+            });
+        }
+    }
+    files
+}
+
+/*
+Picks the files to remove so the directory fits in the quota again, the oldest files go first
+Only disposable files are passed, the state of the instance is never removed
+*/
+fn files_over_quota(
+    mut disposable: Vec<StoredFile>,
+    total_size: u64,
+    max_bytes: u64,
+) -> Vec<StoredFile> {
+    disposable.sort_by_key(|file| file.modified);
+    let mut total_size = total_size;
+    let mut remove = vec![];
+    for file in disposable {
+        if total_size <= max_bytes {
+            break;
+        }
+        total_size = total_size.saturating_sub(file.size);
+        remove.push(file);
+    }
+    remove
+}
+
+fn is_partial(file: &StoredFile) -> bool {
+    file.path
+        .extension()
+        .is_some_and(|extension| extension == PARTIAL_FILE_EXTENSION)
+}
+
+// Removes all partial files regardless of their age, only safe once nothing writes to the directory anymore
+pub fn remove_partial_files(directory: &Path) -> usize {
+    let partial_files: Vec<StoredFile> = list_files(directory)
+        .into_iter()
+        .filter(is_partial)
+        .collect();
+    for file in &partial_files {
+        remove_file(file, "instance stopped");
+    }
+    partial_files.len()
+}
+
+fn remove_file(file: &StoredFile, reason: &str) {
+    debug!("Removing {:?}, {reason}", file.path);
+    fs::remove_file(&file.path).warn("Removing file from user directory");
+}
+
+/*
+Keeps the directory of the user within bounds
+Partial files of interrupted writes and disposable files older than the retention are removed,
+if the directory is still larger than the quota the oldest disposable files are removed as well
+Should run after the logs are rotated, so the log of today is not compressed away
+*/
+pub fn enforce_disk_quota() -> GenResult<()> {
+    let quota = DiskQuota::from_env();
+    let user_dir = create_path("");
+    let now = SystemTime::now();
+    let age = |file: &StoredFile| now.duration_since(file.modified).unwrap_or_default();
+
+    for file in list_files(&user_dir) {
+        if is_partial(&file) && age(&file) > PARTIAL_FILE_MAX_AGE {
+            remove_file(&file, "partial file");
+        }
+    }
+
+    let current_log_name = format!("log.{}", chrono::Utc::now().format("%Y-%m-%d"));
+    let mut disposable = vec![];
+    for directory in DISPOSABLE_DIRS {
+        for file in list_files(&user_dir.join(directory)) {
+            if age(&file) > quota.retention {
+                remove_file(&file, "older than retention");
+            } else if file
+                .path
+                .file_name()
+                .is_none_or(|name| name.to_string_lossy() != current_log_name)
+            {
+                disposable.push(file);
+            }
+        }
+    }
+
+    let total_size = directory_size(&user_dir);
+    if total_size <= quota.max_bytes {
+        return Ok(());
+    }
+    warn!(
+        "User directory uses {} MB, more than the quota of {} MB",
+        total_size / 1024 / 1024,
+        quota.max_bytes / 1024 / 1024
+    );
+    for file in files_over_quota(disposable, total_size, quota.max_bytes) {
+        remove_file(&file, "disk quota exceeded");
+    }
+    let total_size = directory_size(&user_dir);
+    if total_size > quota.max_bytes {
+        return Err(format!(
+            "User directory still uses {total_size} bytes after cleanup, quota is {}",
+            quota.max_bytes
+        )
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, age_secs: u64, size: u64) -> StoredFile {
+        StoredFile {
+            path: PathBuf::from(name),
+            modified: SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000 - age_secs),
+            size,
+        }
+    }
+
+    #[test]
+    fn oldest_files_are_removed_first() {
+        let files = vec![
+            file("new", 10, 40),
+            file("old", 1000, 40),
+            file("middle", 100, 40),
+        ];
+        let removed: Vec<_> = files_over_quota(files, 200, 130)
+            .into_iter()
+            .map(|file| file.path)
+            .collect();
+        assert_eq!(removed, vec![PathBuf::from("old"), PathBuf::from("middle")]);
+    }
+
+    #[test]
+    fn nothing_is_removed_within_quota() {
+        let files = vec![file("a", 10, 40)];
+        assert!(files_over_quota(files, 100, 100).is_empty());
+    }
+
+    #[test]
+    fn partial_files_are_removed() {
+        let dir = std::env::temp_dir().join("mijn_bussie_partial_files_test");
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("mails")).unwrap();
+        fs::write(dir.join("logbook.json"), [0; 10]).unwrap();
+        fs::write(dir.join("logbook.json.tmp"), [0; 5]).unwrap();
+        fs::write(dir.join("mails").join("mail.eml.tmp"), [0; 5]).unwrap();
+        assert_eq!(remove_partial_files(&dir), 2);
+        assert_eq!(directory_size(&dir), 10);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn nested_files_are_counted() {
+        let dir = std::env::temp_dir().join("mijn_bussie_disk_quota_test");
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("logs")).unwrap();
+        fs::write(dir.join("logbook.json"), [0; 10]).unwrap();
+        fs::write(dir.join("logs").join("log.2026-10-15"), [0; 25]).unwrap();
+        assert_eq!(directory_size(&dir), 35);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
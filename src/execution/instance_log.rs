@@ -0,0 +1,274 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    io::Write,
+    path::Path,
+    sync::{LazyLock, Mutex, RwLock},
+};
+
+use tracing::{
+    Event, Metadata, Subscriber,
+    field::{Field, Visit},
+    level_filters::LevelFilter,
+    span::{Attributes, Id},
+};
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+use tracing_subscriber::{
+    EnvFilter, Layer,
+    layer::Context,
+    registry::{LookupSpan, SpanRef},
+};
+
+use crate::GenResult;
+
+// The name of the span every user instance runs in, its user_name field decides which log file an event goes to
+pub const INSTANCE_SPAN: &str = "Instance";
+
+// The log files of all running instances, by user name
+static INSTANCE_LOGS: LazyLock<RwLock<HashMap<String, InstanceLog>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+// The most verbose level any instance logs at, so events nobody wants are skipped early
+static MAX_INSTANCE_LEVEL: LazyLock<RwLock<LevelFilter>> =
+    LazyLock::new(|| RwLock::new(LevelFilter::OFF));
+
+struct InstanceLog {
+    writer: Mutex<Box<dyn Write + Send + Sync>>,
+    filter: EnvFilter,
+    // Flushes the file when the instance stops
+    _guard: Option<WorkerGuard>,
+}
+
+// Stored in the extensions of the instance span
+struct InstanceName(String);
+
+/*
+One subscriber for the whole application. Events in the span of an instance are written to the log file of that user,
+everything else goes to stdout. Because the user is found through the span, events of tasks spawned by an instance
+end up in the right file as long as the task is instrumented with the span, on whatever thread or runtime it runs
+Only the level directives of the log level of a user are used, directives on span fields are ignored
+*/
+pub struct InstanceLogLayer;
+
+// Removes the log of the instance once it stops
+pub struct InstanceLogHandle(String);
+
+impl Drop for InstanceLogHandle {
+    fn drop(&mut self) {
+        if let Ok(mut logs) = INSTANCE_LOGS.write() {
+            logs.remove(&self.0);
+        }
+        update_max_level();
+    }
+}
+
+pub fn open_instance_log(
+    user_name: &str,
+    directory: &Path,
+    filter: EnvFilter,
+) -> InstanceLogHandle {
+    let appender = tracing_appender::rolling::daily(directory, "log");
+    let (non_blocking, guard) = NonBlocking::new(appender);
+    register_instance_log(user_name, Box::new(non_blocking), filter, Some(guard))
+}
+
+fn register_instance_log(
+    user_name: &str,
+    writer: Box<dyn Write + Send + Sync>,
+    filter: EnvFilter,
+    guard: Option<WorkerGuard>,
+) -> InstanceLogHandle {
+    if let Ok(mut logs) = INSTANCE_LOGS.write() {
+        logs.insert(
+            user_name.to_owned(),
+            InstanceLog {
+                writer: Mutex::new(writer),
+                filter,
+                _guard: guard,
+            },
+        );
+    }
+    update_max_level();
+    InstanceLogHandle(user_name.to_owned())
+}
+
+pub fn set_instance_log_filter(user_name: &str, filter: EnvFilter) -> GenResult<()> {
+    INSTANCE_LOGS
+        .write()
+        .map_err(|_| "Instance logs lock poisoned")?
+        .get_mut(user_name)
+        .ok_or("Instance has no log")?
+        .filter = filter;
+    update_max_level();
+    Ok(())
+}
+
+fn update_max_level() {
+    let max_level = INSTANCE_LOGS
+        .read()
+        .map(|logs| {
+            logs.values()
+                .map(|log| log.filter.max_level_hint().unwrap_or(LevelFilter::TRACE))
+                .max()
+                .unwrap_or(LevelFilter::OFF)
+        })
+        .unwrap_or(LevelFilter::TRACE);
+    if let Ok(mut level) = MAX_INSTANCE_LEVEL.write() {
+        *level = max_level;
+    }
+    // Callsites cache whether they are enabled, so they need to ask again
+    tracing::callsite::rebuild_interest_cache();
+}
+
+// The filter of the layer, spans are always needed to know which instance an event belongs to
+pub fn instance_log_enabled(metadata: &Metadata<'_>) -> bool {
+    metadata.is_span()
+        || MAX_INSTANCE_LEVEL
+            .read()
+            .is_ok_and(|level| metadata.level() <= &*level)
+}
+
+// Whether the event happens in the span of an instance, those are not written to stdout
+pub fn in_instance_span<S>(context: &Context<'_, S>) -> bool
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    context.lookup_current().is_some_and(|span| {
+        span.scope()
+            .any(|span| span.metadata().name() == INSTANCE_SPAN)
+    })
+}
+
+fn instance_name<S>(span: &SpanRef<'_, S>) -> Option<String>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    span.extensions()
+        .get::<InstanceName>()
+        .map(|name| name.0.clone())
+}
+
+impl<S> Layer<S> for InstanceLogLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attributes: &Attributes<'_>, id: &Id, context: Context<'_, S>) {
+        if attributes.metadata().name() != INSTANCE_SPAN {
+            return;
+        }
+        let mut visitor = LineVisitor::default();
+        attributes.record(&mut visitor);
+        if let (Some(user_name), Some(span)) = (visitor.user_name, context.span(id)) {
+            span.extensions_mut().insert(InstanceName(user_name));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, context: Context<'_, S>) {
+        let Some(user_name) = context
+            .event_scope(event)
+            .and_then(|mut scope| scope.find_map(|span| instance_name(&span)))
+        else {
+            return;
+        };
+        let Ok(logs) = INSTANCE_LOGS.read() else {
+            return;
+        };
+        let Some(log) = logs.get(&user_name) else {
+            return;
+        };
+        if !Layer::<S>::enabled(&log.filter, event.metadata(), context.clone()) {
+            return;
+        }
+        let mut visitor = LineVisitor::default();
+        event.record(&mut visitor);
+        if let Ok(mut writer) = log.writer.lock() {
+            _ = writer.write_all(visitor.line(event.metadata()).as_bytes());
+        }
+    }
+}
+
+#[derive(Default)]
+struct LineVisitor {
+    message: String,
+    fields: String,
+    user_name: Option<String>,
+}
+
+impl LineVisitor {
+    // The same layout as the fmt layer, without colors
+    fn line(&self, metadata: &Metadata<'_>) -> String {
+        format!(
+            "{} {:>5} {}: {}{}\n",
+            chrono::offset::Utc::now().format("%Y-%m-%dT%H:%M:%S%.6fZ"),
+            metadata.level(),
+            metadata.target(),
+            self.message,
+            self.fields
+        )
+    }
+}
+
+impl Visit for LineVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.message = value.to_owned(),
+            "user_name" => self.user_name = Some(value.to_owned()),
+            name => self.fields.push_str(&format!(" {name}={value}")),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        match field.name() {
+            "message" => self.message = format!("{value:?}"),
+            "user_name" => self.user_name = Some(format!("{value:?}").trim_matches('"').to_owned()),
+            name => self.fields.push_str(&format!(" {name}={value:?}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tracing::*;
+    use tracing_subscriber::{Registry, layer::SubscriberExt};
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn events_are_routed_by_instance_span() {
+        let buffer = SharedBuffer::default();
+        let _handle = register_instance_log(
+            "log_test_user",
+            Box::new(buffer.clone()),
+            EnvFilter::new("info"),
+            None,
+        );
+        let subscriber = Registry::default().with(InstanceLogLayer);
+        tracing::subscriber::with_default(subscriber, || {
+            warn_span!(INSTANCE_SPAN, user_name = "log_test_user").in_scope(|| {
+                info_span!("webcom").in_scope(|| info!(shifts = 3, "Found shifts"));
+                debug!("Too verbose");
+            });
+            warn_span!(INSTANCE_SPAN, user_name = "other_user").in_scope(|| warn!("Other user"));
+            warn!("Outside of any instance");
+        });
+        let log = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(log.lines().count(), 1);
+        assert!(
+            log.contains(
+                " INFO mijn_bussie::execution::instance_log::tests: Found shifts shifts=3"
+            )
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use tokio::task::{AbortHandle, JoinHandle};
+
+use crate::errors::FailureType;
+
+/*
+The state of an instance which read only requests need, kept up to date by the instance loop
+These requests are answered without going through the loop, so they don't wait while the loop is busy
+*/
+#[derive(Debug, Default)]
+pub struct InstanceStatus {
+    webcom_thread: Mutex<Option<AbortHandle>>,
+    last_exit_code: Mutex<FailureType>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl InstanceStatus {
+    // Called by the loop after every request
+    pub fn update(
+        &self,
+        webcom_thread: &Option<JoinHandle<FailureType>>,
+        last_exit_code: &FailureType,
+    ) {
+        *lock(&self.webcom_thread) = webcom_thread.as_ref().map(JoinHandle::abort_handle);
+        *lock(&self.last_exit_code) = last_exit_code.clone();
+    }
+
+    pub fn is_active(&self) -> bool {
+        lock(&self.webcom_thread)
+            .as_ref()
+            .is_some_and(|thread| !thread.is_finished())
+    }
+
+    pub fn last_exit_code(&self) -> FailureType {
+        lock(&self.last_exit_code).clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn status_follows_webcom_thread() {
+        let status = InstanceStatus::default();
+        assert!(!status.is_active());
+        let (finish, finished) = oneshot::channel::<()>();
+        let thread = Some(tokio::spawn(async move {
+            _ = finished.await;
+            FailureType::OK
+        }));
+        status.update(&thread, &FailureType::TriesExceeded);
+        assert!(status.is_active());
+        assert_eq!(status.last_exit_code(), FailureType::TriesExceeded);
+        finish.send(()).unwrap();
+        if let Some(thread) = thread {
+            _ = thread.await;
+        }
+        assert!(!status.is_active());
+    }
+}
@@ -0,0 +1,63 @@
+use entity::user_properties;
+use sea_orm::{ActiveValue::Set, EntityTrait, IntoActiveModel};
+use tracing::{level_filters::LevelFilter, *};
+use tracing_subscriber::EnvFilter;
+
+use crate::{
+    GenResult,
+    database::variables::{ThreadShare, UserData},
+    execution::instance_log::set_instance_log_filter,
+    get_database_connection,
+};
+
+/*
+The filter for the log of one user. Without a log level of the user RUST_LOG is used, and otherwise only warnings
+The log level can be anything RUST_LOG accepts, so also "mijn_bussie::webcom=debug"
+*/
+pub fn user_log_filter(log_level: Option<&str>) -> GenResult<EnvFilter> {
+    let builder = EnvFilter::builder().with_default_directive(LevelFilter::WARN.into());
+    Ok(match log_level {
+        Some(log_level) => builder.parse(log_level)?,
+        None => builder.from_env()?,
+    })
+}
+
+// Changes the log level of a running instance, and stores it so it is used again after a restart
+pub async fn set_log_level(
+    log_level: Option<String>,
+    user_data: ThreadShare<UserData>,
+) -> GenResult<()> {
+    let filter = user_log_filter(log_level.as_deref())?;
+    let properties_id = {
+        let mut user = user_data.write().await;
+        set_instance_log_filter(&user.user_name, filter)?;
+        user.user_properties.log_level = log_level.clone();
+        user.user_properties.user_properties_id
+    };
+    warn!("Log level changed to {log_level:?}");
+    let db = get_database_connection().await;
+    if let Some(properties) = user_properties::Entity::find_by_id(properties_id)
+        .one(&db)
+        .await?
+    {
+        let mut active_properties = properties.into_active_model();
+        active_properties.log_level = Set(log_level);
+        user_properties::Entity::update(active_properties)
+            .validate()?
+            .exec(&db)
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_level_of_user_is_used() {
+        let filter = user_log_filter(Some("debug")).unwrap();
+        assert_eq!(filter.max_level_hint(), Some(LevelFilter::DEBUG));
+        assert!(user_log_filter(Some("mijn_bussie=nonsense")).is_err());
+    }
+}
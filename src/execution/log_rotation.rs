@@ -0,0 +1,109 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, copy},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::config::var;
+use flate2::{Compression, write::GzEncoder};
+use tracing::*;
+
+use crate::{GenResult, create_path, errors::ResultLog};
+
+const DEFAULT_RETENTION_DAYS: u64 = 14;
+const DEFAULT_MAX_SIZE_MB: u64 = 50;
+
+struct LogRetention {
+    max_age: Duration,
+    max_size_bytes: u64,
+}
+
+impl LogRetention {
+    fn from_env() -> Self {
+        let max_days = var("LOG_RETENTION_DAYS")
+            .ok()
+            .and_then(|days| days.parse().ok())
+            .unwrap_or(DEFAULT_RETENTION_DAYS);
+        let max_size_mb = var("LOG_MAX_SIZE_MB")
+            .ok()
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(DEFAULT_MAX_SIZE_MB);
+        Self {
+            max_age: Duration::from_secs(max_days * 24 * 60 * 60),
+            max_size_bytes: max_size_mb * 1024 * 1024,
+        }
+    }
+}
+
+/*
+The daily log files are never removed by tracing_appender, so this cleans them up.
+Log files of previous days get compressed, and files older than the retention or exceeding the max size of the log dir are removed.
+The log file of today is still being written to, so that one is never touched
+*/
+pub fn rotate_logs() -> GenResult<()> {
+    let retention = LogRetention::from_env();
+    let log_dir = create_path("logs");
+    let current_log_name = format!("log.{}", chrono::Utc::now().format("%Y-%m-%d"));
+
+    for path in list_log_files(&log_dir)? {
+        let is_compressed = path.extension().is_some_and(|extension| extension == "gz");
+        let is_current = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy() == current_log_name);
+        if !is_compressed && !is_current {
+            compress_log_file(&path).warn("Compressing log file");
+        }
+    }
+
+    let now = SystemTime::now();
+    let mut log_files = vec![];
+    for path in list_log_files(&log_dir)? {
+        let metadata = fs::metadata(&path)?;
+        let age = now.duration_since(metadata.modified()?).unwrap_or_default();
+        if age > retention.max_age {
+            debug!("Removing expired log file {path:?}");
+            fs::remove_file(&path).warn("Removing expired log file");
+        } else {
+            log_files.push((path, metadata.len()));
+        }
+    }
+
+    // The file names contain the date, so sorting them puts the oldest first
+    log_files.sort();
+    let mut total_size: u64 = log_files.iter().map(|(_, size)| size).sum();
+    for (path, size) in log_files {
+        if total_size <= retention.max_size_bytes {
+            break;
+        }
+        if path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy() == current_log_name)
+        {
+            continue;
+        }
+        debug!("Removing log file {path:?}, log dir is too large");
+        fs::remove_file(&path).warn("Removing log file exceeding max size");
+        total_size -= size;
+    }
+    Ok(())
+}
+
+fn list_log_files(log_dir: &Path) -> GenResult<Vec<PathBuf>> {
+    Ok(fs::read_dir(log_dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect())
+}
+
+fn compress_log_file(path: &Path) -> GenResult<()> {
+    let mut compressed_name = path.as_os_str().to_owned();
+    compressed_name.push(".gz");
+    let mut input = BufReader::new(File::open(path)?);
+    let mut encoder = GzEncoder::new(File::create(&compressed_name)?, Compression::default());
+    copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+    fs::remove_file(path)?;
+    Ok(())
+}
@@ -1,2 +1,16 @@
+pub mod circuit_breaker;
+pub mod clock;
+pub mod disk_quota;
+pub mod instance_log;
+pub mod instance_status;
+pub mod log_level;
+pub mod log_rotation;
+pub mod permissions;
+pub mod refresh_report;
+pub mod request_queue;
+pub mod shutdown;
+pub mod signals;
+pub mod single_user;
+pub mod systemd;
 pub mod timer;
 pub mod watchdog;
@@ -0,0 +1,212 @@
+use std::{
+    collections::HashSet,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+#[cfg(unix)]
+use std::{
+    fs::set_permissions,
+    os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt},
+};
+
+use entity::general_properties_db;
+use sea_orm::{DatabaseConnection, EntityTrait};
+use tracing::*;
+
+use crate::{GenResult, errors::ResultLog};
+
+// Only the user running Mijn Bussie may read the files of users, they contain personal data
+pub const STRICT_FILE_MODE: u32 = 0o600;
+pub const STRICT_DIR_MODE: u32 = 0o700;
+
+// Returns true if the permissions had to be changed
+#[cfg(unix)]
+pub fn set_strict_permissions(path: &Path) -> GenResult<bool> {
+    let metadata = fs::metadata(path)?;
+    let mode = if metadata.is_dir() {
+        STRICT_DIR_MODE
+    } else {
+        STRICT_FILE_MODE
+    };
+    if metadata.permissions().mode() & 0o777 == mode {
+        return Ok(false);
+    }
+    set_permissions(path, fs::Permissions::from_mode(mode))?;
+    Ok(true)
+}
+
+// Other OSes have no unix modes, files there just get the permissions of the directory they are in
+#[cfg(not(unix))]
+pub fn set_strict_permissions(path: &Path) -> GenResult<bool> {
+    fs::metadata(path)?;
+    Ok(false)
+}
+
+/*
+Every file in a user directory should be written with this, the file is created with strict permissions
+so it is never readable by others, not even for a moment. Existing files are fixed as well
+*/
+pub fn write_private_file(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> GenResult<()> {
+    let path = path.as_ref();
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(STRICT_FILE_MODE);
+    let mut file = options.open(path)?;
+    file.write_all(contents.as_ref())?;
+    set_strict_permissions(path)?;
+    Ok(())
+}
+
+/*
+Fixes the permissions of everything in the directory, returns how many had to be fixed
+Public directories like the calendar target are skipped, and the directories they are in keep their permissions
+so the web server can still reach them
+*/
+pub fn audit_permissions(directory: &Path, public: &[PathBuf]) -> GenResult<usize> {
+    if public.iter().any(|path| path == directory) {
+        return Ok(0);
+    }
+    let mut fixed = 0;
+    if !public.iter().any(|path| path.starts_with(directory)) {
+        fixed += usize::from(set_strict_permissions(directory)?);
+    }
+    for entry in fs::read_dir(directory)?.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        if path.is_dir() {
+            fixed += audit_permissions(&path, public)?;
+        } else if set_strict_permissions(&path)
+            .warn_owned("Fixing file permissions")
+            .unwrap_or(false)
+        {
+            fixed += 1;
+        }
+    }
+    Ok(fixed)
+}
+
+/*
+Files created before the permissions were enforced, or by something else like the log appender, are fixed at startup
+The calendars have to be readable by the web server, so the calendar targets are left alone even if they are in a file target
+*/
+pub async fn audit_user_directories(db: &DatabaseConnection) -> GenResult<()> {
+    if cfg!(not(unix)) {
+        warn!(
+            "File permissions can not be enforced on this OS, make sure the user directories are private"
+        );
+        return Ok(());
+    }
+    let properties = general_properties_db::Entity::find().all(db).await?;
+    // Canonical, so they can be compared with the paths found in the file targets
+    let calendar_targets: Vec<PathBuf> = properties
+        .iter()
+        .filter_map(|properties| fs::canonicalize(&properties.calendar_target).ok())
+        .collect();
+    let file_targets: HashSet<String> = properties
+        .into_iter()
+        .map(|properties| properties.file_target)
+        .collect();
+    for file_target in file_targets {
+        let Ok(path) = fs::canonicalize(&file_target) else {
+            continue;
+        };
+        let fixed = audit_permissions(&path, &calendar_targets)?;
+        if fixed > 0 {
+            warn!("Fixed the permissions of {fixed} files in {file_target}");
+        }
+    }
+    Ok(())
+}
+
+// Only linux has /proc, elsewhere the owner of the env file is not checked
+#[cfg(unix)]
+fn current_uid() -> GenResult<Option<u32>> {
+    if cfg!(target_os = "linux") {
+        Ok(Some(fs::metadata("/proc/self")?.uid()))
+    } else {
+        Ok(None)
+    }
+}
+
+// The env file contains the database password and encryption key
+#[cfg(unix)]
+#[allow(dead_code)]
+pub fn check_env_permissions() -> GenResult<()> {
+    let uid = current_uid().warn_owned("Failed to get uid")?;
+    let metadata = fs::metadata(".env")?;
+    let file_mode = metadata.permissions().mode() & 0o777;
+    let file_owner = metadata.uid();
+    if file_mode == STRICT_FILE_MODE && uid.is_none_or(|uid| uid == file_owner) {
+        Ok(())
+    } else {
+        Err(format!(
+            "INCORRECT PERMISSIONS FOR ENV. Should be {STRICT_FILE_MODE:o}, is {file_mode:o}. File owner should be {uid:?}, is {file_owner}"
+        )
+        .into())
+    }
+}
+
+#[cfg(not(unix))]
+#[allow(dead_code)]
+pub fn check_env_permissions() -> GenResult<()> {
+    warn!(
+        "Permissions of the env file can not be checked on this OS, make sure only you can read it"
+    );
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[test]
+    fn audit_fixes_loose_permissions() {
+        let dir = std::env::temp_dir().join("mijn_bussie_permissions_test");
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("logs")).unwrap();
+        let log = dir.join("logs").join("log.2026-10-15");
+        fs::write(&log, "log").unwrap();
+        set_permissions(&log, fs::Permissions::from_mode(0o644)).unwrap();
+        set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert!(audit_permissions(&dir, &[]).unwrap() >= 2);
+        assert_eq!(mode(&log), STRICT_FILE_MODE);
+        assert_eq!(mode(&dir), STRICT_DIR_MODE);
+        assert_eq!(audit_permissions(&dir, &[]).unwrap(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn audit_leaves_calendar_target_readable() {
+        let dir = std::env::temp_dir().join("mijn_bussie_permissions_calendar_test");
+        _ = fs::remove_dir_all(&dir);
+        let calendars = dir.join("calendars");
+        fs::create_dir_all(&calendars).unwrap();
+        let calendar = calendars.join("user.ics");
+        fs::write(&calendar, "calendar").unwrap();
+        set_permissions(&calendar, fs::Permissions::from_mode(0o644)).unwrap();
+        set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert_eq!(
+            audit_permissions(&dir, std::slice::from_ref(&calendars)).unwrap(),
+            0
+        );
+        assert_eq!(mode(&calendar), 0o644);
+        assert_eq!(mode(&dir), 0o755);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn private_files_are_created_strict() {
+        let path = std::env::temp_dir().join("mijn_bussie_private_file_test.json");
+        _ = fs::remove_file(&path);
+        write_private_file(&path, "{}").unwrap();
+        assert_eq!(mode(&path), STRICT_FILE_MODE);
+        fs::remove_file(&path).unwrap();
+    }
+}
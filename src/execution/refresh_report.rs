@@ -0,0 +1,113 @@
+use secrecy::ExposeSecret;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::database::variables::UserData;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RefreshAction {
+    Added,
+    Refreshed,
+    Removed,
+    // The user is not in the database and was not running either
+    NotFound,
+}
+
+/*
+What a refresh of a single user changed, so the frontend can check a saved setting was picked up
+Secrets are only reported by name, never by value
+*/
+#[derive(Debug, Clone, Serialize)]
+pub struct RefreshReport {
+    pub user_name: String,
+    pub action: RefreshAction,
+    pub changed_fields: Vec<String>,
+    pub properties_group_changed: bool,
+    pub kuma_updated: bool,
+    pub kuma_error: Option<String>,
+}
+
+impl RefreshReport {
+    pub fn new(user_name: &str, action: RefreshAction) -> Self {
+        Self {
+            user_name: user_name.to_owned(),
+            action,
+            changed_fields: vec![],
+            properties_group_changed: false,
+            kuma_updated: false,
+            kuma_error: None,
+        }
+    }
+
+    pub fn compare(mut self, before: &UserData, after: &UserData) -> Self {
+        self.changed_fields = changed_fields(before, after);
+        self.properties_group_changed =
+            before.custom_general_properties != after.custom_general_properties;
+        self
+    }
+}
+
+// Only the settings of the user are compared, the dates a run changes are left out
+fn changed_fields(before: &UserData, after: &UserData) -> Vec<String> {
+    let mut changed = vec![];
+    let secrets = [
+        (
+            "personeelsnummer",
+            &before.personeelsnummer,
+            &after.personeelsnummer,
+        ),
+        ("password", &before.password, &after.password),
+        ("email", &before.email, &after.email),
+    ];
+    for (field, before, after) in secrets {
+        if before.0.expose_secret() != after.0.expose_secret() {
+            changed.push(field.to_owned());
+        }
+    }
+    let name = |user: &UserData| {
+        user.name
+            .as_ref()
+            .map(|name| name.0.expose_secret().to_owned())
+    };
+    if name(before) != name(after) {
+        changed.push("name".to_owned());
+    }
+    if before.file_name != after.file_name {
+        changed.push("file_name".to_owned());
+    }
+    if let (Ok(Value::Object(before)), Ok(Value::Object(after))) = (
+        serde_json::to_value(&before.user_properties),
+        serde_json::to_value(&after.user_properties),
+    ) {
+        for (field, value) in after {
+            if field != "user_properties_id" && before.get(&field) != Some(&value) {
+                changed.push(field);
+            }
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::secret::Secret;
+    use secrecy::SecretString;
+
+    #[test]
+    fn changed_settings_are_reported() {
+        let before = UserData::example();
+        let mut after = before.clone();
+        after.password = Secret(SecretString::new("new password".into()));
+        after.user_properties.send_mail_new_shift = !before.user_properties.send_mail_new_shift;
+        after.user_properties.user_properties_id += 1;
+        after.last_execution_date = Some(chrono::offset::Utc::now().naive_utc());
+        after.custom_general_properties = Some(2);
+        let report = RefreshReport::new("piet", RefreshAction::Refreshed).compare(&before, &after);
+        assert_eq!(report.changed_fields, ["password", "send_mail_new_shift"]);
+        assert!(report.properties_group_changed);
+        let report = RefreshReport::new("piet", RefreshAction::Refreshed).compare(&before, &before);
+        assert!(report.changed_fields.is_empty());
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    collections::VecDeque,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use tokio::sync::Notify;
+use tracing::*;
+
+use crate::{GenResult, InstanceRequest, StartRequest};
+
+// Room for a few requests, so API requests arriving at the same time do not fail
+const REQUEST_QUEUE_SIZE: usize = 8;
+
+/*
+The requests waiting to be handled by an instance
+Multiple timer requests are coalesced into one, as running twice in a row is useless
+Deleting an account, shutting down and the exit code of a finished run are never dropped, even if the queue is full
+*/
+#[derive(Debug, Default)]
+pub struct RequestQueue {
+    requests: Mutex<VecDeque<InstanceRequest>>,
+    notify: Notify,
+}
+
+impl RequestQueue {
+    fn lock(&self) -> MutexGuard<'_, VecDeque<InstanceRequest>> {
+        self.requests.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn push(&self, request: InstanceRequest) -> GenResult<()> {
+        let mut requests = self.lock();
+        match request.start_request {
+            StartRequest::Timer
+                if requests
+                    .iter()
+                    .any(|queued| queued.start_request == StartRequest::Timer) =>
+            {
+                debug!("Timer request already queued, coalescing");
+                return Ok(());
+            }
+            StartRequest::Delete
+            | StartRequest::Shutdown
+            | StartRequest::ExecutionFinished(_)
+            | StartRequest::DryRunFinished(_) => {}
+            _ if requests.len() >= REQUEST_QUEUE_SIZE => {
+                return Err(format!(
+                    "Request queue is full, dropping {:?}",
+                    request.start_request
+                )
+                .into());
+            }
+            _ => (),
+        }
+        requests.push_back(request);
+        drop(requests);
+        self.notify.notify_one();
+        Ok(())
+    }
+
+    fn try_pop(&self) -> Option<InstanceRequest> {
+        self.lock().pop_front()
+    }
+
+    // Waits until there is a request
+    pub async fn pop(&self) -> InstanceRequest {
+        loop {
+            if let Some(request) = self.try_pop() {
+                return request;
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    // How many requests are waiting
+    pub fn depth(&self) -> usize {
+        self.lock().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::FailureType;
+
+    fn queued(queue: &RequestQueue) -> Vec<StartRequest> {
+        queue
+            .lock()
+            .iter()
+            .map(|request| request.start_request.clone())
+            .collect()
+    }
+
+    #[test]
+    fn timer_requests_are_coalesced() {
+        let queue = RequestQueue::default();
+        queue
+            .push(InstanceRequest::new(StartRequest::Timer))
+            .unwrap();
+        queue.push(InstanceRequest::new(StartRequest::Api)).unwrap();
+        queue
+            .push(InstanceRequest::new(StartRequest::Timer))
+            .unwrap();
+        assert_eq!(queued(&queue), vec![StartRequest::Timer, StartRequest::Api]);
+    }
+
+    #[test]
+    fn delete_is_never_dropped() {
+        let queue = RequestQueue::default();
+        for _ in 0..REQUEST_QUEUE_SIZE {
+            queue
+                .push(InstanceRequest::new(StartRequest::Logbook))
+                .unwrap();
+        }
+        assert!(
+            queue
+                .push(InstanceRequest::new(StartRequest::Name))
+                .is_err()
+        );
+        queue
+            .push(InstanceRequest::new(StartRequest::Delete))
+            .unwrap();
+        queue
+            .push(InstanceRequest::new(StartRequest::ExecutionFinished(
+                FailureType::OK,
+            )))
+            .unwrap();
+        assert_eq!(queue.depth(), REQUEST_QUEUE_SIZE + 2);
+    }
+
+    #[tokio::test]
+    async fn requests_are_handled_in_order() {
+        let queue = RequestQueue::default();
+        queue
+            .push(InstanceRequest::new(StartRequest::Name))
+            .unwrap();
+        queue
+            .push(InstanceRequest::new(StartRequest::Logbook))
+            .unwrap();
+        assert_eq!(queue.pop().await.start_request, StartRequest::Name);
+        assert_eq!(queue.pop().await.start_request, StartRequest::Logbook);
+        assert_eq!(queue.depth(), 0);
+    }
+}
@@ -0,0 +1,29 @@
+use std::{fs, time::Duration};
+
+use tracing::*;
+
+use crate::{
+    create_path, errors::ResultLog, execution::disk_quota::remove_partial_files,
+    webcom::calendar_file::with_suffix, webcom::ical::get_ical_path,
+};
+
+// How long a running scrape gets to finish after a shutdown request, before it is aborted
+pub const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(120);
+// The watchdog waits a bit longer than the grace period, so the cleanup has time to finish
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(180);
+
+/*
+Runs when an instance stops, once its scrape finished or was aborted
+Partial files of interrupted writes are removed, the Kuma monitor is paused by the watchdog which stops the instance
+*/
+pub async fn cleanup_instance() {
+    let removed = remove_partial_files(&create_path(""));
+    if removed > 0 {
+        info!("Removed {removed} partial files");
+    }
+    // The calendar is only written next to itself if the calendar directory is on another filesystem
+    let partial_calendar = with_suffix(&get_ical_path(), ".tmp");
+    if partial_calendar.exists() {
+        fs::remove_file(&partial_calendar).warn("Removing partial calendar");
+    }
+}
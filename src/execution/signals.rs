@@ -0,0 +1,45 @@
+#[cfg(unix)]
+use tokio::signal::unix::{SignalKind, signal};
+use tracing::*;
+
+#[cfg(unix)]
+use crate::{
+    api::route::reload_certificates,
+    config::{reload_env_file, validate_files},
+    errors::ResultLog,
+};
+
+/*
+Reload everything that can safely be reloaded without restarting, when a SIGHUP is received.
+This way a renewed certificate or edited template does not require killing running scrapes
+*/
+#[cfg(unix)]
+pub async fn handle_sighup() {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(err) => {
+            error!("Could not listen for SIGHUP: {err}");
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        warn!("Received SIGHUP, reloading");
+        reload().await;
+    }
+}
+
+// There is no SIGHUP outside of unix, only the properties can be reloaded there, with the admin reload endpoint
+#[cfg(not(unix))]
+pub async fn handle_sighup() {
+    info!("SIGHUP is not available on this OS, reloading on a signal is disabled");
+}
+
+#[cfg(unix)]
+async fn reload() {
+    reload_env_file().warn("Reloading ENV file");
+    // Templates are read from disk for every mail, so they only have to be checked
+    for problem in validate_files() {
+        error!("{problem}");
+    }
+    reload_certificates().await.warn("Reloading certificates");
+}
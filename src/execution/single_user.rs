@@ -0,0 +1,117 @@
+use std::{cell::RefCell, sync::Arc};
+
+use crate::config::var;
+use chrono::NaiveDateTime;
+use entity::user_properties;
+use secrecy::SecretString;
+use tracing::*;
+
+use crate::{
+    DRY_RUN, GENERAL_PROPERTIES, GenResult, NAME, StartRequest, USER_PROPERTIES,
+    database::{
+        secret::Secret,
+        variables::{GeneralProperties, UserData},
+    },
+    errors::{FailureType, ResultLog},
+    execution::{
+        disk_quota::enforce_disk_quota, log_rotation::rotate_logs, request_queue::RequestQueue,
+    },
+    get_database_connection,
+    webcom::webcom::webcom_instance,
+};
+
+/*
+Runs one user once and exits, without the watchdog, API or timer. Meant for running from cron in a container,
+or for debugging a single user. The user is not read from the database but defined with SINGLE_USER_ variables,
+the general properties and the history of the user are still in the database
+*/
+pub async fn run_single_user(dry_run: bool) -> GenResult<()> {
+    let user = user_from_env()?;
+    let db = get_database_connection().await;
+    let properties = GeneralProperties::load_default_preferences(&db).await?;
+    info!("Running {} once", user.user_name);
+
+    let exit_code = USER_PROPERTIES
+        .scope(
+            RefCell::new(Some(Arc::new(user))),
+            GENERAL_PROPERTIES.scope(
+                RefCell::new(Some(Arc::new(properties))),
+                NAME.scope(RefCell::new(None), DRY_RUN.scope(dry_run, run_once())),
+            ),
+        )
+        .await;
+    match exit_code {
+        FailureType::OK => Ok(()),
+        exit_code => Err(format!("Run failed: {exit_code:?}").into()),
+    }
+}
+
+async fn run_once() -> FailureType {
+    // Nobody handles the requests a run sends back, its exit code is returned directly
+    let request_queue = Arc::new(RequestQueue::default());
+    let exit_code = webcom_instance(StartRequest::Single, request_queue).await;
+    rotate_logs().warn("Rotating log files");
+    enforce_disk_quota().warn("Enforcing disk quota");
+    exit_code
+}
+
+fn user_from_env() -> GenResult<UserData> {
+    let secret = |name: &str| -> GenResult<Secret> {
+        Ok(Secret(SecretString::new(
+            var(name)
+                .map_err(|_| format!("Setting {name} is missing"))?
+                .into(),
+        )))
+    };
+    let send_mail = var("SINGLE_USER_SEND_MAIL").is_ok_and(|value| value == "true");
+    Ok(UserData {
+        // There is no database row, so nothing can be found with this id
+        id: 0,
+        user_name: var("SINGLE_USER_NAME").map_err(|_| "Setting SINGLE_USER_NAME is missing")?,
+        personeelsnummer: secret("SINGLE_USER_PERSONEELSNUMMER")?,
+        password: secret("SINGLE_USER_PASSWORD")?,
+        email: secret("SINGLE_USER_EMAIL")?,
+        name: None,
+        file_name: var("SINGLE_USER_FILE_NAME").unwrap_or_default(),
+        user_properties: user_properties::Model {
+            user_properties_id: 0,
+            execution_interval_minutes: 7200,
+            execution_minute: 0,
+            send_mail_new_shift: send_mail,
+            send_mail_updated_shift: send_mail,
+            send_mail_removed_shift: send_mail,
+            send_failed_signin_mail: send_mail,
+            send_welcome_mail: false,
+            send_error_mail: send_mail,
+            split_night_shift: var("SINGLE_USER_SPLIT_NIGHT_SHIFT")
+                .is_ok_and(|value| value == "true"),
+            stop_midnight_shift: false,
+            auto_delete_account: false,
+            max_weekly_hours: 48,
+            send_overtime_mail: false,
+            min_rest_hours: 11,
+            hourly_wage_cents: 0,
+            suggest_swaps: false,
+            show_broken_pause: false,
+            send_donation_reminder: false,
+            execution_retry_count: None,
+            expected_execution_time_seconds: None,
+            send_weekly_summary: false,
+            log_level: None,
+            kuma_public_monitor: false,
+            language: "nl".to_owned(),
+            kuma_notification_settings: "{}".to_owned(),
+        },
+        custom_general_properties: None,
+        last_succesfull_sign_in_date: None,
+        last_system_execution_date: None,
+        last_execution_date: None,
+        creation_date: NaiveDateTime::default(),
+        last_calendar_write_date: None,
+        last_donation_reminder_date: None,
+        calendar_token: String::new(),
+        mail_paused_date: None,
+        mail_paused_reason: None,
+        last_weekly_summary_date: None,
+    })
+}
@@ -0,0 +1,41 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use sd_notify::NotifyState;
+use tracing::*;
+
+use crate::errors::ResultLog;
+
+/*
+Optional systemd integration. If Mijn Bussie is not started by systemd with Type=notify, NOTIFY_SOCKET is not set
+and all of these calls do nothing.
+READY is only sent once the API is listening and the first watchdog cycle has started all instances
+*/
+static API_LISTENING: AtomicBool = AtomicBool::new(false);
+static FIRST_CYCLE_DONE: AtomicBool = AtomicBool::new(false);
+static READY_SENT: AtomicBool = AtomicBool::new(false);
+
+pub fn api_listening() {
+    API_LISTENING.store(true, Ordering::Relaxed);
+    notify_ready_if_done();
+}
+
+pub fn watchdog_cycle_done() {
+    FIRST_CYCLE_DONE.store(true, Ordering::Relaxed);
+    notify_ready_if_done();
+    ping_watchdog();
+}
+
+// Let systemd know this loop is still running, so a wedged process gets restarted
+pub fn ping_watchdog() {
+    sd_notify::notify(false, &[NotifyState::Watchdog]).warn("Sending systemd watchdog ping");
+}
+
+fn notify_ready_if_done() {
+    if API_LISTENING.load(Ordering::Relaxed)
+        && FIRST_CYCLE_DONE.load(Ordering::Relaxed)
+        && !READY_SENT.swap(true, Ordering::Relaxed)
+    {
+        info!("Startup complete, notifying systemd");
+        sd_notify::notify(false, &[NotifyState::Ready]).warn("Sending systemd ready");
+    }
+}
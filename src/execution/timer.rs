@@ -1,32 +1,49 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, RwLock as StdRwLock},
+};
 
+use crate::config::var;
 use crate::{
-    GenResult, StartRequest, database::variables::UserData, execution::watchdog::InstanceMap,
-    health::ApplicationLogbook,
+    GenResult, InstanceRequest, StartRequest,
+    database::variables::UserData,
+    execution::{
+        clock::{Clock, SystemClock},
+        systemd,
+        watchdog::InstanceMap,
+    },
 };
 use chrono::NaiveDateTime;
-use time::{Duration, OffsetDateTime, Time};
+use serde::Serialize;
+use time::{Duration, Time};
 use tokio::{sync::RwLock, time::sleep};
 use tracing::*;
 
+const DEFAULT_SPREAD_MINUTES: i32 = 30;
+const DEFAULT_MAX_USERS_PER_MINUTE: usize = 2;
+
+// When the timer loop last checked the instances, to see if the timer itself is still alive
+static LAST_TIMER_TICK: LazyLock<StdRwLock<Option<NaiveDateTime>>> =
+    LazyLock::new(|| StdRwLock::new(None));
+
 pub fn get_system_time() -> Time {
-    let time = OffsetDateTime::now_local()
-        .unwrap_or(OffsetDateTime::now_utc())
-        .time();
-    debug!("system time: {:?}", time);
-    time
+    SystemClock.time()
 }
 
-fn get_system_time_zero_seconds() -> Time {
-    let mut current_system_time = get_system_time();
+fn get_system_time_zero_seconds(clock: &dyn Clock) -> Time {
+    let mut current_system_time = clock.time();
     if let Ok(zerod_system_time) = current_system_time.replace_second(0) {
         current_system_time = zerod_system_time
     }
     current_system_time
 }
 
-fn calculate_first_execution_time_simple(execution_interval: i32, execution_minute: i32) -> Time {
-    let current_system_time = get_system_time_zero_seconds();
+fn calculate_first_execution_time_simple(
+    clock: &dyn Clock,
+    execution_interval: i32,
+    execution_minute: i32,
+) -> Time {
+    let current_system_time = get_system_time_zero_seconds(clock);
 
     let mut interval_hours = execution_interval / 60;
     interval_hours = if interval_hours == 0 {
@@ -57,18 +74,20 @@ fn calculate_first_execution_time_simple(execution_interval: i32, execution_minu
 // If this is within the execution time interval, the time will be restored
 // Otherwise a random interval will be chosen
 pub async fn calculate_initial_execution_time(
+    clock: &dyn Clock,
     last_execution_timestamp: Option<NaiveDateTime>,
     execution_interval: i32,
     execution_minute: i32,
 ) -> Time {
     if last_execution_timestamp.is_none() {
         debug!("User has no execution timestamp, randomly generating");
-        return calculate_first_execution_time_simple(execution_interval, execution_minute);
+        return calculate_first_execution_time_simple(clock, execution_interval, execution_minute);
     }
 
     let next_execution_time;
-    let current_system_time = get_system_time_zero_seconds();
-    let elapsed_minutes_since_last_execution = ApplicationLogbook::get_naive_datetime()
+    let current_system_time = get_system_time_zero_seconds(clock);
+    let elapsed_minutes_since_last_execution = clock
+        .naive_utc()
         .signed_duration_since(last_execution_timestamp.unwrap())
         .num_minutes();
     debug!("User was last executed {elapsed_minutes_since_last_execution} minutes ago...");
@@ -84,7 +103,7 @@ pub async fn calculate_initial_execution_time(
         next_execution_time = current_system_time + Duration::minutes(time_until_next_execution)
     } else {
         next_execution_time =
-            calculate_first_execution_time_simple(execution_interval, execution_minute);
+            calculate_first_execution_time_simple(clock, execution_interval, execution_minute);
         debug!("This is within this users execution interval window of {execution_interval} mins");
     }
     debug!(
@@ -96,8 +115,16 @@ pub async fn calculate_initial_execution_time(
     next_execution_time
 }
 
-async fn calculate_next_execution_time(data: Arc<RwLock<UserData>>) -> Time {
-    let mut current_system_time = get_system_time();
+/*
+The minute is read from the user data every time, so a changed execution minute is used from the next execution on
+The offset of the instance is added to it, which is not 0 if the instance was spread
+*/
+async fn calculate_next_execution_time(
+    clock: &dyn Clock,
+    data: Arc<RwLock<UserData>>,
+    minute_offset: i32,
+) -> Time {
+    let mut current_system_time = clock.time();
     if let Ok(zerod_system_time) = current_system_time.replace_second(0) {
         current_system_time = zerod_system_time;
     }
@@ -106,8 +133,7 @@ async fn calculate_next_execution_time(data: Arc<RwLock<UserData>>) -> Time {
     if interval_hours == 0 {
         interval_hours += 1
     }
-    let execution_minute = user_properties.execution_minute;
-    _ = user_properties;
+    let execution_minute = (user_properties.execution_minute + minute_offset).rem_euclid(60);
 
     let next_execution_time = current_system_time + Duration::hours(interval_hours.into());
     next_execution_time
@@ -115,10 +141,13 @@ async fn calculate_next_execution_time(data: Arc<RwLock<UserData>>) -> Time {
         .unwrap_or(next_execution_time)
 }
 
-pub async fn execution_timer(instances: Arc<RwLock<InstanceMap>>) -> GenResult<()> {
+pub async fn execution_timer(
+    instances: Arc<RwLock<InstanceMap>>,
+    clock: impl Clock,
+) -> GenResult<()> {
     let mut first = true;
     loop {
-        let current_system_time = get_system_time();
+        let current_system_time = clock.time();
         if !first {
             let sleep_time = 60 - current_system_time.second() as u64 + 1;
             debug!("timer sleeping for {sleep_time} seconds");
@@ -127,6 +156,10 @@ pub async fn execution_timer(instances: Arc<RwLock<InstanceMap>>) -> GenResult<(
             first = false;
         }
         let instances = &mut *instances.write().await;
+        systemd::ping_watchdog();
+        if let Ok(mut last_tick) = LAST_TIMER_TICK.write() {
+            *last_tick = Some(clock.naive_utc());
+        }
 
         let system_time_hm = (current_system_time.hour(), current_system_time.minute());
         for instance in instances.iter_mut() {
@@ -135,10 +168,17 @@ pub async fn execution_timer(instances: Arc<RwLock<InstanceMap>>) -> GenResult<(
             if instance_time_hm == system_time_hm {
                 let user_name = instance.0;
                 debug!("Starting instance {user_name}");
-                _ = instance.1.request_sender.try_send(StartRequest::Timer);
-                instance.1.execution_time =
-                    calculate_next_execution_time(instance.1.user_instance_data.user_data.clone())
-                        .await;
+                _ = instance
+                    .1
+                    .request_queue
+                    .push(InstanceRequest::new(StartRequest::Timer));
+                instance.1.execution_time = calculate_next_execution_time(
+                    &clock,
+                    instance.1.user_instance_data.user_data.clone(),
+                    instance.1.minute_offset,
+                )
+                .await;
+                instance.1.execution_minute = instance.1.execution_time.minute() as i32;
                 debug!(
                     "Executing user {user_name} at {} next",
                     instance.1.execution_time
@@ -147,3 +187,325 @@ pub async fn execution_timer(instances: Arc<RwLock<InstanceMap>>) -> GenResult<(
         }
     }
 }
+
+/*
+Users starting on the same minute all open a browser session at the same time.
+New instances, and instances on a minute used by too many others, are moved to the least used minute
+within the spread window after their preferred minute. Instances are only ever moved forward and the window
+is never larger than the interval, so the interval of a user is respected
+*/
+struct SlotRequest {
+    user_name: String,
+    preferred_minute: i32,
+    // None for new instances, which do not have a minute yet
+    current_minute: Option<i32>,
+    interval: i32,
+}
+
+fn spread_window(interval: i32) -> i32 {
+    var("SCHEDULE_SPREAD_MINUTES")
+        .ok()
+        .and_then(|minutes| minutes.parse().ok())
+        .unwrap_or(DEFAULT_SPREAD_MINUTES)
+        .min(interval)
+        .clamp(1, 60)
+}
+
+fn max_users_per_minute() -> usize {
+    var("SCHEDULE_MAX_USERS_PER_MINUTE")
+        .ok()
+        .and_then(|users| users.parse().ok())
+        .unwrap_or(DEFAULT_MAX_USERS_PER_MINUTE)
+        .max(1)
+}
+
+// The least used minute in the window, on a tie the one closest to the preferred minute
+fn least_used_minute(occupancy: &[usize; 60], preferred_minute: i32, window: i32) -> i32 {
+    (0..window)
+        .map(|offset| (preferred_minute + offset).rem_euclid(60))
+        .min_by_key(|minute| occupancy[*minute as usize])
+        .unwrap_or(preferred_minute)
+}
+
+// Returns the instances which have to move, with their new minute
+fn plan_slots(
+    mut requests: Vec<SlotRequest>,
+    max_per_minute: usize,
+    window: impl Fn(i32) -> i32,
+) -> HashMap<String, i32> {
+    requests.sort_by(|a, b| a.user_name.cmp(&b.user_name));
+    let mut occupancy = [0usize; 60];
+    let mut to_move = vec![];
+    for request in requests {
+        match request.current_minute {
+            Some(minute) if occupancy[minute as usize] < max_per_minute => {
+                occupancy[minute as usize] += 1
+            }
+            _ => to_move.push(request),
+        }
+    }
+    // Existing instances first, so new instances never push them away from their minute
+    to_move.sort_by_key(|request| request.current_minute.is_none());
+    let mut moves = HashMap::new();
+    for request in to_move {
+        let minute = least_used_minute(
+            &occupancy,
+            request.preferred_minute,
+            window(request.interval),
+        );
+        occupancy[minute as usize] += 1;
+        if request.current_minute != Some(minute) {
+            moves.insert(request.user_name, minute);
+        }
+    }
+    moves
+}
+
+// Moves the execution forward to the minute, never back
+fn move_to_minute(execution_time: Time, minute: i32) -> Time {
+    let offset = (minute - execution_time.minute() as i32).rem_euclid(60);
+    execution_time + Duration::minutes(offset.into())
+}
+
+pub async fn spread_schedules(instances: &mut InstanceMap, new_instances: &[String]) {
+    let mut requests = vec![];
+    let mut preferred_minutes = HashMap::new();
+    for (user_name, instance) in instances.iter() {
+        let user_properties = instance
+            .user_instance_data
+            .user_data
+            .read()
+            .await
+            .user_properties
+            .clone();
+        preferred_minutes.insert(
+            user_name.clone(),
+            user_properties.execution_minute.rem_euclid(60),
+        );
+        requests.push(SlotRequest {
+            user_name: user_name.clone(),
+            preferred_minute: user_properties.execution_minute.rem_euclid(60),
+            current_minute: (!new_instances.contains(user_name))
+                .then_some(instance.execution_minute.rem_euclid(60)),
+            interval: user_properties.execution_interval_minutes,
+        });
+    }
+    for (user_name, minute) in plan_slots(requests, max_users_per_minute(), spread_window) {
+        let Some(instance) = instances.get_mut(&user_name) else {
+            continue;
+        };
+        // Restored executions are not aligned to the minute, moving those would only delay them
+        if instance.execution_time.minute() as i32 == instance.execution_minute {
+            instance.execution_time = move_to_minute(instance.execution_time, minute);
+        }
+        debug!(
+            "Moving {user_name} from minute {} to {minute}",
+            instance.execution_minute
+        );
+        instance.execution_minute = minute;
+        instance.minute_offset =
+            (minute - preferred_minutes.get(&user_name).copied().unwrap_or(minute)).rem_euclid(60);
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct InstanceSchedule {
+    pub user_name: String,
+    pub next_execution: Time,
+    pub execution_minute: i32,
+    pub minutes_until_execution: i64,
+    // The execution time was missed, so it only runs again the next day
+    pub overdue: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Schedule {
+    pub last_tick: Option<NaiveDateTime>,
+    pub current_time: Time,
+    pub instances: Vec<InstanceSchedule>,
+}
+
+/*
+Execution times are only a time of day, so a time that already passed looks like it is almost a day away
+The next execution is never further away than the interval, or two hours for the first execution
+so anything further away than that plus a margin was missed by the timer
+*/
+fn is_overdue(minutes_until_execution: i64, execution_interval: i32) -> bool {
+    let max_wait_minutes = (execution_interval as i64).max(2 * 60) + 60;
+    minutes_until_execution > max_wait_minutes
+}
+
+impl Schedule {
+    pub async fn collect(clock: &dyn Clock, instances: &InstanceMap) -> Self {
+        let current_time = get_system_time_zero_seconds(clock);
+        let mut schedule = Schedule {
+            last_tick: LAST_TIMER_TICK.read().ok().and_then(|tick| *tick),
+            current_time,
+            instances: vec![],
+        };
+        for (user_name, instance) in instances {
+            let execution_interval = instance
+                .user_instance_data
+                .user_data
+                .read()
+                .await
+                .user_properties
+                .execution_interval_minutes;
+            let minutes_until_execution = current_time
+                .duration_until(instance.execution_time)
+                .whole_minutes();
+            schedule.instances.push(InstanceSchedule {
+                user_name: user_name.clone(),
+                next_execution: instance.execution_time,
+                execution_minute: instance.execution_minute,
+                minutes_until_execution,
+                overdue: is_overdue(minutes_until_execution, execution_interval),
+            });
+        }
+        schedule
+            .instances
+            .sort_by_key(|instance| instance.minutes_until_execution);
+        schedule
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution::clock::MockClock;
+    use time::macros::time;
+
+    fn mock_clock(time: Time) -> MockClock {
+        MockClock {
+            time,
+            naive_utc: chrono::NaiveDate::from_ymd_opt(2025, 6, 29)
+                .unwrap()
+                .and_hms_opt(time.hour().into(), time.minute().into(), 0)
+                .unwrap(),
+        }
+    }
+
+    fn user_with_interval(interval_minutes: i32, execution_minute: i32) -> Arc<RwLock<UserData>> {
+        let mut user = UserData::example();
+        user.user_properties.execution_interval_minutes = interval_minutes;
+        user.user_properties.execution_minute = execution_minute;
+        Arc::new(RwLock::new(user))
+    }
+
+    #[test]
+    fn missed_execution_is_overdue() {
+        let current_time = time!(12:00);
+        let missed = current_time.duration_until(time!(11:58)).whole_minutes();
+        assert!(is_overdue(missed, 120));
+        let upcoming = current_time.duration_until(time!(13:15)).whole_minutes();
+        assert!(!is_overdue(upcoming, 120));
+        // Long intervals can legitimately be far away
+        let far = current_time.duration_until(time!(22:00)).whole_minutes();
+        assert!(!is_overdue(far, 12 * 60));
+    }
+
+    #[tokio::test]
+    async fn next_execution_aligns_to_execution_minute() {
+        let clock = mock_clock(time!(10:37:12));
+        let next = calculate_next_execution_time(&clock, user_with_interval(120, 15), 0).await;
+        assert_eq!(next, time!(12:15));
+    }
+
+    #[tokio::test]
+    async fn next_execution_rolls_over_midnight() {
+        let clock = mock_clock(time!(23:30));
+        let next = calculate_next_execution_time(&clock, user_with_interval(120, 5), 0).await;
+        assert_eq!(next, time!(01:05));
+    }
+
+    #[tokio::test]
+    async fn short_interval_is_at_least_an_hour() {
+        let clock = mock_clock(time!(08:00));
+        let next = calculate_next_execution_time(&clock, user_with_interval(30, 0), 0).await;
+        assert_eq!(next, time!(09:00));
+    }
+
+    #[tokio::test]
+    async fn next_execution_uses_instance_offset() {
+        let clock = mock_clock(time!(10:37:12));
+        let next = calculate_next_execution_time(&clock, user_with_interval(120, 15), 27).await;
+        assert_eq!(next, time!(12:42));
+        let next = calculate_next_execution_time(&clock, user_with_interval(120, 50), 27).await;
+        assert_eq!(next, time!(12:17));
+    }
+
+    #[tokio::test]
+    async fn next_execution_follows_changed_minute() {
+        let clock = mock_clock(time!(10:37:12));
+        let user = user_with_interval(120, 15);
+        user.write().await.user_properties.execution_minute = 45;
+        let next = calculate_next_execution_time(&clock, user, 0).await;
+        assert_eq!(next, time!(12:45));
+    }
+
+    fn slot(user_name: &str, preferred_minute: i32, current_minute: Option<i32>) -> SlotRequest {
+        SlotRequest {
+            user_name: user_name.to_owned(),
+            preferred_minute,
+            current_minute,
+            interval: 120,
+        }
+    }
+
+    #[test]
+    fn new_instances_are_spread() {
+        let requests = (0..4)
+            .map(|user| slot(&format!("user{user}"), 0, None))
+            .collect();
+        let moves = plan_slots(requests, 2, |_| 30);
+        let mut minutes: Vec<i32> = moves.values().copied().collect();
+        minutes.sort();
+        assert_eq!(minutes, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn crowded_minutes_are_rebalanced() {
+        let requests = vec![
+            slot("a", 0, Some(0)),
+            slot("b", 0, Some(0)),
+            slot("c", 0, Some(0)),
+            slot("d", 5, Some(5)),
+        ];
+        let moves = plan_slots(requests, 2, |_| 30);
+        // Only the instance over the limit moves, to the first free minute
+        assert_eq!(moves, HashMap::from([("c".to_owned(), 1)]));
+    }
+
+    #[test]
+    fn window_wraps_and_is_limited() {
+        let mut occupancy = [1usize; 60];
+        occupancy[2] = 0;
+        occupancy[40] = 0;
+        assert_eq!(least_used_minute(&occupancy, 58, 5), 2);
+        assert_eq!(least_used_minute(&occupancy, 58, 1), 58);
+    }
+
+    #[test]
+    fn instances_only_move_forward() {
+        assert_eq!(move_to_minute(time!(12:10), 15), time!(12:15));
+        assert_eq!(move_to_minute(time!(12:10), 5), time!(13:05));
+        assert_eq!(move_to_minute(time!(23:50), 5), time!(00:05));
+    }
+
+    #[tokio::test]
+    async fn restart_restores_execution_within_interval() {
+        let clock = mock_clock(time!(12:00));
+        let last_execution = clock.naive_utc - chrono::Duration::minutes(30);
+        let next = calculate_initial_execution_time(&clock, Some(last_execution), 120, 0).await;
+        assert_eq!(next, time!(13:30));
+    }
+
+    #[tokio::test]
+    async fn restart_after_interval_schedules_within_two_hours() {
+        let clock = mock_clock(time!(12:00));
+        let last_execution = clock.naive_utc - chrono::Duration::minutes(300);
+        let next = calculate_initial_execution_time(&clock, Some(last_execution), 120, 10).await;
+        let minutes_until = (next - time!(12:00)).whole_minutes();
+        assert!((0..=2 * 60 + 10).contains(&minutes_until));
+    }
+}
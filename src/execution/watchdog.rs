@@ -1,29 +1,46 @@
 use std::{
+    any::Any,
     cell::RefCell,
     collections::HashMap,
     sync::{Arc, LazyLock},
     time::Duration,
 };
 
+use crate::api::profile::UserProfile;
+use crate::webcom::earnings::MonthEarnings;
+use crate::webcom::email::OnboardingPreview;
 use crate::{
-    GENERAL_PROPERTIES, GenResult, NAME, StartRequest, USER_PROPERTIES,
+    GENERAL_PROPERTIES, GenResult, InstanceRequest, NAME, StartRequest, USER_PROPERTIES,
     database::variables::{GeneralProperties, ThreadShare, UserData, UserInstanceData},
-    execution::timer::{calculate_initial_execution_time, get_system_time},
+    execution::{
+        clock::SystemClock,
+        instance_log::INSTANCE_SPAN,
+        instance_status::InstanceStatus,
+        refresh_report::{RefreshAction, RefreshReport},
+        request_queue::RequestQueue,
+        shutdown::SHUTDOWN_TIMEOUT,
+        systemd,
+        timer::{calculate_initial_execution_time, get_system_time, spread_schedules},
+    },
     kuma, user_instance,
 };
-use crate::{errors::FailureType, kuma::KumaUserRequest};
+use crate::{
+    errors::FailureStatus,
+    kuma::{KumaOutcome, KumaReport, KumaUserRequest},
+};
 use crate::{errors::ResultLog, kuma::KumaAction};
-use crate::{health::ApplicationLogbook, webcom::deletion::StandingInformation};
+use crate::{
+    health::{ApplicationLogbook, LogbookStatus},
+    webcom::deletion::StandingInformation,
+};
 use sea_orm::DatabaseConnection;
+use secrecy::ExposeSecret;
 use serde::Serialize;
 use time::Time;
 use tokio::{
-    sync::{
-        RwLock,
-        mpsc::{Receiver, Sender, channel},
-    },
+    sync::{RwLock, mpsc::Receiver, oneshot},
     task::JoinHandle,
-    time::timeout,
+    time::{Instant, sleep, timeout, timeout_at},
 };
 use tracing::*;
 use tracing_futures::Instrument;
@@ -35,40 +52,61 @@ enum InstanceState {
     Remove,
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Debug)]
 pub enum WatchdogRequest {
-    SingleUser(String),
-    KumaRequest((KumaAction, KumaUserRequest)),
+    // The report of what changed is sent back if there is a sender
+    SingleUser(String, Option<oneshot::Sender<RefreshReport>>),
+    // The report of every user is sent back if there is a sender
+    KumaRequest(
+        (KumaAction, KumaUserRequest),
+        Option<oneshot::Sender<KumaReport>>,
+    ),
+    ReloadProperties,
     AllUser,
     FirstTime,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub enum RequestResponse {
-    Logbook(ApplicationLogbook),
+    Logbook(LogbookStatus),
     Name(String),
     Active(bool),
-    ExitCode(FailureType),
-    UserData(UserData),
+    ExitCode(FailureStatus),
+    UserData(UserProfile),
     GenResponse(String),
     InstanceStanding(StandingInformation),
+    Earnings(Vec<MonthEarnings>),
+    Onboarding(OnboardingStatus),
+    OnboardingPreview(OnboardingPreview),
+}
+
+// How far a new user is, the welcome mail is only sent once the calendar exists
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub enum OnboardingStatus {
+    Pending,
+    Scraping,
+    Ready,
+    Failed(FailureStatus),
 }
 
 pub struct UserInstance {
     pub user_instance_data: UserInstanceData,
     pub thread_handle: JoinHandle<()>,
-    pub request_sender: Arc<Sender<StartRequest>>,
-    pub response_receiver: RwLock<Receiver<RequestResponse>>,
+    pub request_queue: Arc<RequestQueue>,
+    pub status: Arc<InstanceStatus>,
     pub execution_time: Time,
+    // The minute the instance runs on, the preferred minute of the user unless it was spread
+    pub execution_minute: i32,
+    // How many minutes after the preferred minute of the user the instance was spread to
+    pub minute_offset: i32,
 }
 
 impl UserInstance {
     pub async fn new(user_data: UserInstanceData) -> Self {
         let user_name = user_data.user_data.read().await.user_name.clone();
-        let span = warn_span!("Instance", user_name);
-        let request_channel = channel(1);
-        let request_sender_arc = Arc::new(request_channel.0);
-        let response_channel = channel(1);
+        let span = warn_span!(INSTANCE_SPAN, user_name);
+        let request_queue = Arc::new(RequestQueue::default());
+        let status = Arc::new(InstanceStatus::default());
         let data_clone = user_data.clone();
         let thread = tokio::spawn(
             USER_PROPERTIES.scope(
@@ -77,13 +115,8 @@ impl UserInstance {
                     RefCell::new(None),
                     NAME.scope(
                         RefCell::new(None),
-                        user_instance(
-                            request_channel.1,
-                            response_channel.0,
-                            request_sender_arc.clone(),
-                            data_clone,
-                        )
-                        .instrument(span),
+                        user_instance(request_queue.clone(), data_clone, status.clone())
+                            .instrument(span),
                     ),
                 ),
             ),
@@ -91,6 +124,7 @@ impl UserInstance {
 
         let user_data_clone = user_data.user_data.read().await.clone();
         let execution_time = calculate_initial_execution_time(
+            &SystemClock,
             user_data_clone.last_system_execution_date,
             user_data_clone.user_properties.execution_interval_minutes,
             user_data_clone.user_properties.execution_minute,
@@ -107,13 +141,22 @@ impl UserInstance {
         Self {
             user_instance_data: user_data,
             thread_handle: thread,
-            request_sender: request_sender_arc,
-            response_receiver: RwLock::new(response_channel.1),
+            request_queue,
+            status,
             execution_time,
+            execution_minute: user_data_clone.user_properties.execution_minute,
+            minute_offset: 0,
         }
     }
 }
 
+// The watchdog refreshes all users at least this often
+pub const WATCHDOG_INTERVAL: Duration = Duration::from_secs(60 * 30);
+// The API and the database listener share the queue, a burst of database changes should not fill it for the API
+pub const WATCHDOG_QUEUE_SIZE: usize = 64;
+// How often the watchdog looks for instances that crashed
+const CRASH_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
 type InstanceName = String;
 
 pub type InstanceMap = HashMap<InstanceName, UserInstance>;
@@ -128,53 +171,126 @@ pub async fn watchdog(
     db: &DatabaseConnection,
     receiver: &mut Receiver<WatchdogRequest>,
 ) -> GenResult<()> {
+    let mut next_refresh = Instant::now() + WATCHDOG_INTERVAL;
     loop {
-        // Update all users in the database every 30 minutes
-        let channel_wait = timeout(Duration::from_secs(60 * 30), receiver.recv()).await;
-        if let Ok(Some(ref request)) = channel_wait
-            && let WatchdogRequest::SingleUser(user) = request
-        {
+        // Update all users in the database every 30 minutes, in the meantime check for crashed instances every minute
+        let channel_wait = tokio::select! {
+            wait = timeout_at(next_refresh, receiver.recv()) => wait,
+            _ = sleep(CRASH_CHECK_INTERVAL) => {
+                restart_crashed_instances(&mut *instances.write().await).await;
+                continue;
+            }
+        };
+        if let Ok(Some(WatchdogRequest::SingleUser(user, respond_to))) = channel_wait {
             info!("Updating user because of request {user}");
-            update_individual_user(
-                db,
-                vec![user.clone()],
-                &mut *instances.clone().write().await,
-            )
-            .await
-            .warn("deleting individual user");
-        } else if let Ok(Some(WatchdogRequest::KumaRequest(ref request))) = channel_wait {
+            if let Ok(mut reports) = update_individual_user(db, vec![user], &instances)
+                .await
+                .warn_owned("Updating individual user")
+                && let Some(respond_to) = respond_to
+                && let Some(report) = reports.pop()
+            {
+                _ = respond_to.send(report);
+            }
+        } else if let Ok(Some(WatchdogRequest::KumaRequest(request, respond_to))) = channel_wait {
             let general_properties = GeneralProperties::load_default_preferences(db).await?;
-            kuma::manage_users(
-                vec![request.clone()],
-                &*instances.read().await,
-                &general_properties,
-            )
-            .await
-            .warn("Api kuma run");
-        } else if channel_wait == Ok(None) {
+            // The instances are only locked while copying their data, a Kuma run can take a while
+            let active_users = kuma::active_users(&*instances.read().await).await;
+            let report =
+                kuma::manage_users(vec![request], &active_users, &general_properties).await;
+            if let Some(respond_to) = respond_to {
+                _ = respond_to.send(report);
+            }
+        } else if let Ok(Some(WatchdogRequest::ReloadProperties)) = channel_wait {
+            info!("Reloading general properties");
+            reload_properties(db, &*instances.read().await)
+                .await
+                .warn("Reloading properties");
+        } else if matches!(channel_wait, Ok(None)) {
             return Err("Notification channel closed".into());
         } else {
             debug!("Updating users");
+            next_refresh = Instant::now() + WATCHDOG_INTERVAL;
             let users = UserData::get_all_usernames(db).await?;
             start_stop_instances(
                 db,
                 instances.clone(),
                 &users,
-                channel_wait.eq(&Ok(Some(WatchdogRequest::FirstTime))),
+                matches!(channel_wait, Ok(Some(WatchdogRequest::FirstTime))),
             )
             .await?;
             debug!("Users: {users:#?}");
         }
+        send_orchestrator_heartbeat(instances.read().await.len()).await;
+        systemd::watchdog_cycle_done();
+    }
+}
+
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "No panic message".to_owned()
+    }
+}
+
+/*
+Instances which panicked are started again, otherwise the user is not scheduled anymore
+Instances which stopped on their own, like after a deletion, are removed and left to the next refresh
+*/
+async fn restart_crashed_instances(active_instances: &mut InstanceMap) {
+    let finished_instances: Vec<InstanceName> = active_instances
+        .iter()
+        .filter(|(_, instance)| instance.thread_handle.is_finished())
+        .map(|(name, _)| name.clone())
+        .collect();
+    for instance_name in finished_instances {
+        let Some(instance) = active_instances.remove(&instance_name) else {
+            continue;
+        };
+        let user_instance_data = instance.user_instance_data.clone();
+        match instance.thread_handle.await {
+            Err(error) if error.is_panic() => {
+                error!(
+                    "Instance {instance_name} panicked: {}",
+                    panic_message(error.into_panic())
+                );
+                let (user, properties) = user_instance_data.get_data_local().await;
+                if let Ok(restarts) = ApplicationLogbook::save_instance_restart(&user, &properties)
+                    .warn_owned("Counting instance restart")
+                {
+                    warn!("Restarting instance {instance_name}, restart number {restarts}");
+                }
+                let new_instance = UserInstance::new(user_instance_data).await;
+                active_instances.insert(instance_name.clone(), new_instance);
+                spread_schedules(active_instances, &[instance_name]).await;
+            }
+            Err(error) => warn!("Instance {instance_name} was cancelled: {error}"),
+            Ok(()) => info!("Instance {instance_name} stopped"),
+        }
+    }
+}
+
+// Let Kuma know the watchdog is still alive. This is spawned so a slow Kuma can't block the watchdog
+async fn send_orchestrator_heartbeat(instance_count: usize) {
+    if let Some(properties) = DEFAULT_PROPERTIES.read().await.clone() {
+        let properties = properties.read().await.clone();
+        tokio::spawn(async move {
+            kuma::send_orchestrator_heartbeat(&properties, instance_count)
+                .await
+                .warn("Sending orchestrator heartbeat")
+        });
     }
 }
 
 async fn start_stop_instances(
     db: &DatabaseConnection,
-    active_instances: Arc<RwLock<InstanceMap>>,
+    instances: Arc<RwLock<InstanceMap>>,
     db_users: &Vec<String>,
     first_run: bool,
 ) -> GenResult<()> {
-    let mut active_instances = active_instances.write().await;
+    let mut active_instances = instances.write().await;
     let mut instances_state: HashMap<InstanceName, InstanceState> = HashMap::new();
     for active_instance in &mut *active_instances {
         instances_state.insert(active_instance.0.to_owned(), InstanceState::Remove);
@@ -199,25 +315,28 @@ async fn start_stop_instances(
     let instances_to_add =
         get_equal_instances(InstanceState::New, &instances_state, &active_instances);
     add_instances(db, &instances_to_add, &mut active_instances).await;
-    refresh_instances(db, &instances_to_refresh, &mut active_instances).await;
+    let stopping = refresh_instances(db, &instances_to_refresh, &mut active_instances).await;
     if !first_run {
         kuma::manage_users(
             vec![
                 (
                     KumaAction::Delete,
-                    KumaUserRequest::Users(instances_to_remove.clone()),
+                    KumaUserRequest::Users(
+                        monitors_to_delete(db, &instances_to_remove, &active_instances).await,
+                    ),
                 ),
                 (KumaAction::Add, KumaUserRequest::Users(instances_to_add)),
             ],
-            &active_instances,
+            &kuma::active_users(&active_instances).await,
             &default_preferences,
         )
-        .await
-        .warn("Kuma run");
+        .await;
     } else {
         debug!("Skipped kuma due to first run");
     }
     stop_instances(&instances_to_remove, &mut active_instances);
+    drop(active_instances);
+    restart_instances(db, stopping, &instances).await;
     Ok(())
 }
 
@@ -237,68 +356,226 @@ async fn get_default_preferences(db: &DatabaseConnection) -> GenResult<GeneralPr
     }
 }
 
+// Instances using the default properties share them, so only custom properties have to be reloaded per instance
+async fn reload_properties(
+    db: &DatabaseConnection,
+    active_instances: &InstanceMap,
+) -> GenResult<()> {
+    get_default_preferences(db).await?;
+    for instance in active_instances.values() {
+        if let Ok(true) = instance
+            .user_instance_data
+            .update_user(db)
+            .await
+            .warn_owned("Reloading user properties")
+        {
+            // Moving to another group is picked up by the next refresh of the user
+            info!("User changed properties group, will be restarted on the next refresh");
+        }
+    }
+    Ok(())
+}
+
+// Returns what changed for every user, users which could not be added are reported as not found
 async fn update_individual_user(
     db: &DatabaseConnection,
     user_names: Vec<String>,
-    active_instances: &mut InstanceMap,
-) -> GenResult<()> {
+    instances: &RwLock<InstanceMap>,
+) -> GenResult<Vec<RefreshReport>> {
     let mut instances_to_add = vec![];
     let mut instances_to_refresh = vec![];
     let mut instances_to_remove = vec![];
+    let mut previous_data = HashMap::new();
 
     let default_preferences = get_default_preferences(db).await?;
 
+    let mut active_instances = instances.write().await;
     for user in user_names {
         if !active_instances.contains_key(&user) {
             instances_to_add.push(user);
         } else if UserData::get_from_username(db, &user).await?.is_none() {
             instances_to_remove.push(user);
         } else {
+            if let Some(instance) = active_instances.get(&user) {
+                let data = instance.user_instance_data.user_data.read().await.clone();
+                previous_data.insert(user.clone(), data);
+            }
             instances_to_refresh.push(user);
         }
     }
-    add_instances(db, &instances_to_add, active_instances).await;
-    refresh_instances(db, &instances_to_refresh, active_instances).await;
-    kuma::manage_users(
+    add_instances(db, &instances_to_add, &mut active_instances).await;
+    let stopping = refresh_instances(db, &instances_to_refresh, &mut active_instances).await;
+    let kuma_report = kuma::manage_users(
         vec![
             (
                 KumaAction::Delete,
-                KumaUserRequest::Users(instances_to_remove.clone()),
+                KumaUserRequest::Users(
+                    monitors_to_delete(db, &instances_to_remove, &active_instances).await,
+                ),
+            ),
+            (
+                KumaAction::Add,
+                KumaUserRequest::Users(instances_to_add.clone()),
             ),
-            (KumaAction::Add, KumaUserRequest::Users(instances_to_add)),
         ],
-        active_instances,
+        &kuma::active_users(&active_instances).await,
         &default_preferences,
     )
-    .await
-    .warn("Kuma run individual");
-    stop_instances(&instances_to_remove, active_instances);
-    Ok(())
+    .await;
+    stop_instances(&instances_to_remove, &mut active_instances);
+    drop(active_instances);
+    restart_instances(db, stopping, instances).await;
+    let active_instances = instances.read().await;
+
+    let mut reports = vec![];
+    for user in &instances_to_add {
+        let action = match active_instances.contains_key(user) {
+            true => RefreshAction::Added,
+            false => RefreshAction::NotFound,
+        };
+        reports.push(RefreshReport::new(user, action));
+    }
+    for user in &instances_to_refresh {
+        let mut report = RefreshReport::new(user, RefreshAction::Refreshed);
+        if let Some(before) = previous_data.get(user)
+            && let Some(instance) = active_instances.get(user)
+        {
+            report = report.compare(before, &*instance.user_instance_data.user_data.read().await);
+        }
+        reports.push(report);
+    }
+    for user in &instances_to_remove {
+        reports.push(RefreshReport::new(user, RefreshAction::Removed));
+    }
+    // Kuma only changes when a user is added or removed
+    for report in &mut reports {
+        if matches!(report.action, RefreshAction::Added | RefreshAction::Removed) {
+            match kuma_report.outcome(&report.user_name) {
+                Some(KumaOutcome::Done) => report.kuma_updated = true,
+                Some(KumaOutcome::Queued { error }) => report.kuma_error = Some(error.clone()),
+                _ => (),
+            }
+        }
+    }
+    Ok(reports)
+}
+
+/*
+Users in a properties group that keeps the Kuma history only get their monitor paused by stop_instances
+That is only for users which still exist, a user removed from the database some other way than delete_account
+would otherwise leave a paused monitor behind forever
+*/
+async fn monitors_to_delete(
+    db: &DatabaseConnection,
+    instances_to_remove: &[String],
+    active_instances: &InstanceMap,
+) -> Vec<String> {
+    let mut monitors = vec![];
+    for instance_name in instances_to_remove {
+        let pause_on_stop = match active_instances.get(instance_name) {
+            Some(instance) => {
+                instance
+                    .user_instance_data
+                    .general_settings
+                    .read()
+                    .await
+                    .kuma_pause_on_stop
+            }
+            None => false,
+        };
+        // If it can't be checked the monitor is kept, the reconciliation reports it if the user is gone
+        let delete = !pause_on_stop
+            || !UserData::username_exists(db, instance_name)
+                .await
+                .warn_owned("Checking if stopped user still exists")
+                .unwrap_or(true);
+        if delete {
+            monitors.push(instance_name.clone());
+        }
+    }
+    monitors
 }
 
 fn stop_instances(instances_to_stop: &Vec<String>, active_instances: &mut InstanceMap) {
     for instance_name in instances_to_stop {
-        if let Some(instance) = active_instances.get(instance_name) {
-            instance.thread_handle.abort_handle().abort();
+        warn!("Stopping instance: {instance_name}");
+        if let Some(instance) = active_instances.remove(instance_name) {
+            tokio::spawn(stop_instance(instance_name.clone(), instance, true));
         }
-        warn!("Deleting instance: {instance_name}");
-        active_instances.remove(instance_name);
     }
 }
 
+/*
+Asks the instance to stop, so a running scrape can finish and the instance can clean up after itself
+It is only aborted if it did not stop in time
+*/
+async fn stop_instance(instance_name: String, mut instance: UserInstance, pause_monitor: bool) {
+    if pause_monitor {
+        let (user, properties) = instance.user_instance_data.get_data_local().await;
+        // Paused right away, so Kuma does not report the user as down while it stops
+        kuma::set_user_monitor_paused(&user.user_name, &properties.kuma_properties, true)
+            .await
+            .warn("Pausing kuma monitor");
+    }
+    if instance
+        .request_queue
+        .push(InstanceRequest::new(StartRequest::Shutdown))
+        .warn_owned("Sending shutdown request")
+        .is_ok()
+        && timeout(SHUTDOWN_TIMEOUT, &mut instance.thread_handle)
+            .await
+            .is_ok()
+    {
+        info!("Instance {instance_name} shut down");
+        return;
+    }
+    warn!("Instance {instance_name} did not shut down in time, aborting it");
+    instance.thread_handle.abort();
+}
+
+// Instances which changed properties group and are stopping, restart_instances starts them again
+type StoppingInstances = Vec<(InstanceName, JoinHandle<()>)>;
+
 async fn refresh_instances(
     db: &DatabaseConnection,
     instances_to_refresh: &Vec<String>,
     active_instances: &mut InstanceMap,
-) {
+) -> StoppingInstances {
     let mut instances_to_add = vec![];
+    let mut instances_to_restart = vec![];
     for insance_name in instances_to_refresh {
         if let Some(instance) = active_instances.get_mut(insance_name) {
-            instance
+            let previous_password = instance
+                .user_instance_data
+                .user_data
+                .read()
+                .await
+                .password
+                .clone();
+            if let Ok(true) = instance
                 .user_instance_data
                 .update_user(db)
                 .await
-                .warn("Updating User");
+                .warn_owned("Updating User")
+            {
+                instances_to_restart.push(insance_name.clone());
+            } else if previous_password.0.expose_secret()
+                != instance
+                    .user_instance_data
+                    .user_data
+                    .read()
+                    .await
+                    .password
+                    .0
+                    .expose_secret()
+            {
+                // The instance only signs in if the previous password was incorrect
+                info!("Password of {insance_name} changed, verifying login");
+                instance
+                    .request_queue
+                    .push(InstanceRequest::new(StartRequest::VerifyLogin))
+                    .warn("Sending login check request");
+            }
         } else {
             instances_to_add.push(insance_name.clone());
         }
@@ -306,6 +583,42 @@ async fn refresh_instances(
     if !instances_to_add.is_empty() {
         add_instances(db, &instances_to_add, active_instances).await;
     }
+    if !instances_to_restart.is_empty() {
+        info!(
+            "Restarting {} instances that changed properties group",
+            instances_to_restart.len()
+        );
+    }
+    // The new instance resumes the monitor, so it should not be paused by the old one
+    instances_to_restart
+        .into_iter()
+        .filter_map(|instance_name| {
+            let instance = active_instances.remove(&instance_name)?;
+            warn!("Stopping instance: {instance_name}");
+            let stop = tokio::spawn(stop_instance(instance_name.clone(), instance, false));
+            Some((instance_name, stop))
+        })
+        .collect()
+}
+
+/*
+The old instance has to be gone before the new one starts, its cleanup would otherwise remove the partial files of the new one
+Stopping can take up to SHUTDOWN_TIMEOUT, so it is waited for without holding the lock on the instances
+*/
+async fn restart_instances(
+    db: &DatabaseConnection,
+    stopping: StoppingInstances,
+    instances: &RwLock<InstanceMap>,
+) {
+    if stopping.is_empty() {
+        return;
+    }
+    let mut instances_to_add = vec![];
+    for (instance_name, stop) in stopping {
+        stop.await.warn("Waiting for instance to stop");
+        instances_to_add.push(instance_name);
+    }
+    add_instances(db, &instances_to_add, &mut *instances.write().await).await;
 }
 
 async fn add_instances(
@@ -335,6 +648,7 @@ async fn add_instances(
             None => warn!("Failed to add user {new_user}"),
         };
     }
+    spread_schedules(active_instances, instances_to_add).await;
 }
 
 fn get_equal_instances(
@@ -358,3 +672,28 @@ fn get_equal_instances(
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn crash_with_message() {
+        panic!("Notification channel closed");
+    }
+
+    async fn crash_with_number() {
+        std::panic::panic_any(5);
+    }
+
+    #[tokio::test]
+    async fn panic_message_is_found() {
+        let error = tokio::spawn(crash_with_message()).await.unwrap_err();
+        assert!(error.is_panic());
+        assert_eq!(
+            panic_message(error.into_panic()),
+            "Notification channel closed"
+        );
+        let error = tokio::spawn(crash_with_number()).await.unwrap_err();
+        assert_eq!(panic_message(error.into_panic()), "No panic message");
+    }
+}
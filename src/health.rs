@@ -1,21 +1,48 @@
 use std::{
-    fs::{read_to_string, write},
     path::PathBuf,
-    time::SystemTime,
+    str::FromStr,
+    sync::{LazyLock, Mutex, RwLock},
+    time::{Duration, Instant, SystemTime},
 };
 
 use crate::{
-    FailureType, GenResult, create_path,
+    FailureType, GenResult, create_path, create_path_local,
+    database::state_file::{read_state_file, write_state_file},
+    database::variables::{GeneralProperties, UserData},
     errors::SignInFailure,
-    get_data,
+    errors::{ErrorCategory, FailureStatus, ResultLog},
+    execution::permissions::write_private_file,
+    execution::watchdog::InstanceMap,
+    get_data, get_database_connection, is_dry_run,
+    kuma::{KumaDrift, get_push_token},
+    webcom::calendar_file::write_atomic,
+    webcom::email::send_stale_calendar_mail,
     webcom::ical::{CALENDAR_VERSION, get_ical_path, load_ical_file},
     webcom::shift::Shift,
 };
 use chrono::NaiveDateTime;
+use entity::{exit_code_transition, logbook_entry, user_data};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder,
+};
 use serde::{Deserialize, Serialize};
 use tracing::*;
 use url::Url;
 
+// Runs older than this are removed from the logbook history
+const LOGBOOK_RETENTION: chrono::Duration = chrono::Duration::days(365);
+
+// Last time any instance found Webcomm to be down, loaded from the logbook entries at startup
+static LAST_WEBCOM_OUTAGE: LazyLock<RwLock<Option<NaiveDateTime>>> =
+    LazyLock::new(|| RwLock::new(None));
+
+/*
+The logbook file is written by the instance itself, and by the watchdog and Kuma reconciliation from outside of it
+Every read-modify-write of the file is done while holding this lock, so none of the writers lose the changes of another
+*/
+static LOGBOOK_FILE_LOCK: Mutex<()> = Mutex::new(());
+
 #[derive(Default, Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct ApplicationLogbook {
     pub state: FailureType,
@@ -24,6 +51,36 @@ pub struct ApplicationLogbook {
     #[serde(default)]
     pub execution_timestamp: NaiveDateTime,
     pub application_state: ApplicationState,
+    // Differences found between this user and Kuma during the last reconciliation
+    #[serde(default)]
+    pub kuma_drift: Vec<KumaDrift>,
+    // How often the instance crashed and was started again by the watchdog
+    #[serde(default)]
+    pub instance_restarts: u64,
+}
+
+// How the API shows a logbook. The exit code is a FailureStatus, the file and database keep the FailureType itself
+#[derive(Debug, Serialize, Clone)]
+pub struct LogbookStatus {
+    pub state: FailureStatus,
+    pub repeat_count: u64,
+    pub execution_timestamp: NaiveDateTime,
+    pub application_state: ApplicationState,
+    pub kuma_drift: Vec<KumaDrift>,
+    pub instance_restarts: u64,
+}
+
+impl From<ApplicationLogbook> for LogbookStatus {
+    fn from(logbook: ApplicationLogbook) -> Self {
+        Self {
+            state: logbook.state.status(),
+            repeat_count: logbook.repeat_count,
+            execution_timestamp: logbook.execution_timestamp,
+            application_state: logbook.application_state,
+            kuma_drift: logbook.kuma_drift,
+            instance_restarts: logbook.instance_restarts,
+        }
+    }
 }
 
 impl ApplicationLogbook {
@@ -36,7 +93,7 @@ impl ApplicationLogbook {
         let path = ApplicationLogbook::create_path();
         // This match statement tries to load the previous logbook, otherwise it creates a new one
         let mut logbook = match || -> GenResult<Self> {
-            let logbook_string = read_to_string(path)?;
+            let logbook_string = read_state_file(path)?;
             Ok(serde_json::from_str(&logbook_string)?)
         }() {
             Ok(logbook) => logbook,
@@ -66,6 +123,10 @@ impl ApplicationLogbook {
         self.application_state.failed_broken_shifts = number_of_failed_broken_shifts;
     }
 
+    pub fn add_skipped_month(&mut self, month: String) {
+        self.application_state.skipped_months.push(month);
+    }
+
     pub fn add_failed_shifts(&mut self, number: u64, replace: bool) {
         match replace {
             true => self.application_state.failed_shifts = number,
@@ -93,13 +154,290 @@ impl ApplicationLogbook {
         };
         self.application_state.calendar_version = CALENDAR_VERSION.to_owned();
         self.state = state.clone();
-        write(path, serde_json::to_string_pretty(&self)?)?;
+        if *state == FailureType::SignInFailed(SignInFailure::WebcomDown)
+            && let Ok(mut last_outage) = LAST_WEBCOM_OUTAGE.write()
+        {
+            *last_outage = Some(self.execution_timestamp);
+        }
+        let _lock = LOGBOOK_FILE_LOCK
+            .lock()
+            .map_err(|_| "Logbook lock poisoned")?;
+        // The fields kept up to date from outside of the instance could have changed since the logbook was loaded
+        if let Ok(logbook_string) = read_state_file(&path)
+            && let Ok(on_disk) = serde_json::from_str::<Self>(&logbook_string)
+        {
+            self.kuma_drift = on_disk.kuma_drift;
+            self.instance_restarts = on_disk.instance_restarts;
+        }
+        write_state_file(path, serde_json::to_string_pretty(&self)?)?;
+        Ok(())
+    }
+
+    // Write the result of a Kuma reconciliation to the logbook of a user.
+    // This is done from outside of the instance, so the path has to be created locally
+    pub fn save_kuma_drift(
+        user: &UserData,
+        properties: &GeneralProperties,
+        drift: Vec<KumaDrift>,
+    ) -> GenResult<()> {
+        let _lock = LOGBOOK_FILE_LOCK
+            .lock()
+            .map_err(|_| "Logbook lock poisoned")?;
+        let mut logbook = Self::load_local(user, properties)?;
+        if logbook.kuma_drift == drift {
+            return Ok(());
+        }
+        logbook.kuma_drift = drift;
+        let path = create_path_local(user, properties, "logbook.json");
+        write_state_file(path, serde_json::to_string_pretty(&logbook)?)?;
         Ok(())
     }
 
+    // Count a restart of a crashed instance, also done from outside of the instance
+    pub fn save_instance_restart(
+        user: &UserData,
+        properties: &GeneralProperties,
+    ) -> GenResult<u64> {
+        let _lock = LOGBOOK_FILE_LOCK
+            .lock()
+            .map_err(|_| "Logbook lock poisoned")?;
+        let mut logbook = Self::load_local(user, properties)?;
+        logbook.instance_restarts += 1;
+        let path = create_path_local(user, properties, "logbook.json");
+        write_state_file(path, serde_json::to_string_pretty(&logbook)?)?;
+        Ok(logbook.instance_restarts)
+    }
+
+    // Read the logbook of a user from outside of the instance. A missing logbook results in a default one
+    pub fn load_local(user: &UserData, properties: &GeneralProperties) -> GenResult<Self> {
+        let path = create_path_local(user, properties, "logbook.json");
+        Ok(match read_state_file(&path) {
+            Ok(logbook_string) => serde_json::from_str(&logbook_string)?,
+            Err(_) => ApplicationLogbook::default(),
+        })
+    }
+
     fn create_path() -> PathBuf {
         create_path("logbook.json")
     }
+
+    // The file on disk only contains the latest run, the database keeps every run until the retention has passed
+    pub async fn save_to_database(&self) -> GenResult<()> {
+        let (user, _properties) = get_data();
+        let db = get_database_connection().await;
+        self.insert_entry(&db, user.id).await?;
+        logbook_entry::Entity::delete_many()
+            .filter(logbook_entry::Column::UserDataId.eq(user.id))
+            .filter(
+                logbook_entry::Column::ExecutionTimestamp
+                    .lt(Self::get_naive_datetime() - LOGBOOK_RETENTION),
+            )
+            .exec(&db)
+            .await?;
+        Ok(())
+    }
+
+    /*
+    Logbooks from before the history was kept in the database only exist as logbook.json
+    The run in there becomes the first entry of the history, users which already have a history are skipped
+    */
+    pub async fn import_file_logbook() -> GenResult<()> {
+        let logbook = Self::load();
+        if logbook.execution_timestamp == NaiveDateTime::default() || is_dry_run() {
+            return Ok(());
+        }
+        let (user, _properties) = get_data();
+        let db = get_database_connection().await;
+        let has_history = logbook_entry::Entity::find()
+            .filter(logbook_entry::Column::UserDataId.eq(user.id))
+            .one(&db)
+            .await?
+            .is_some();
+        if !has_history {
+            info!("Importing logbook file into the database");
+            logbook.insert_entry(&db, user.id).await?;
+        }
+        Ok(())
+    }
+
+    async fn insert_entry(&self, db: &DatabaseConnection, user_data_id: i32) -> GenResult<()> {
+        let state = &self.application_state;
+        logbook_entry::ActiveModel {
+            user_data_id: Set(user_data_id),
+            execution_timestamp: Set(self.execution_timestamp),
+            state: Set(serde_json::to_string(&self.state)?),
+            repeat_count: Set(self.repeat_count as i64),
+            execution_time_ms: Set(state.execution_time_ms as i64),
+            shifts: Set(state.shifts as i64),
+            broken_shifts: Set(state.broken_shifts as i64),
+            non_relevant_shifts: Set(state.non_relevant_shifts as i64),
+            failed_shifts: Set(state.failed_shifts as i64),
+            failed_broken_shifts: Set(state.failed_broken_shifts as i64),
+            calendar_version: Set(state.calendar_version.clone()),
+            changed_shifts: Set(state.changed_shifts as i64),
+            ended_by: Set(state
+                .ended_by
+                .map(|category| <&'static str>::from(category).to_owned())),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        Ok(())
+    }
+
+    // Load all logbook entries of a user from the database, optionally within a time range
+    pub async fn load_history(
+        user_name: &str,
+        from: Option<NaiveDateTime>,
+        until: Option<NaiveDateTime>,
+    ) -> GenResult<Vec<ApplicationLogbook>> {
+        let db = get_database_connection().await;
+        let mut query = logbook_entry::Entity::find()
+            .inner_join(user_data::Entity)
+            .filter(user_data::Column::UserName.eq(user_name));
+        if let Some(from) = from {
+            query = query.filter(logbook_entry::Column::ExecutionTimestamp.gte(from));
+        }
+        if let Some(until) = until {
+            query = query.filter(logbook_entry::Column::ExecutionTimestamp.lte(until));
+        }
+        let entries = query
+            .order_by_asc(logbook_entry::Column::ExecutionTimestamp)
+            .all(&db)
+            .await?;
+        Ok(entries
+            .into_iter()
+            .filter_map(|entry| {
+                Some(ApplicationLogbook {
+                    state: serde_json::from_str(&entry.state).ok()?,
+                    repeat_count: entry.repeat_count as u64,
+                    execution_timestamp: entry.execution_timestamp,
+                    application_state: ApplicationState {
+                        system_time: None,
+                        execution_time_ms: entry.execution_time_ms as u64,
+                        shifts: entry.shifts as u64,
+                        broken_shifts: entry.broken_shifts as u64,
+                        non_relevant_shifts: entry.non_relevant_shifts as u64,
+                        failed_shifts: entry.failed_shifts as u64,
+                        failed_broken_shifts: entry.failed_broken_shifts as u64,
+                        calendar_version: entry.calendar_version,
+                        skipped_months: vec![],
+                        phase_timings: PhaseTimings::default(),
+                        changed_shifts: entry.changed_shifts as u64,
+                        ended_by: entry
+                            .ended_by
+                            .and_then(|category| ErrorCategory::from_str(&category).ok()),
+                    },
+                    kuma_drift: vec![],
+                    instance_restarts: 0,
+                })
+            })
+            .collect())
+    }
+}
+
+// A change of the exit code of a user, so it is possible to see since when something has been broken
+// The database keeps the FailureType, the API shows it as a FailureStatus
+#[derive(Debug, Serialize)]
+pub struct ExitCodeTransition {
+    pub previous_state: FailureStatus,
+    pub new_state: FailureStatus,
+    pub transition_date: NaiveDateTime,
+}
+
+impl ExitCodeTransition {
+    pub async fn record(previous_state: &FailureType, new_state: &FailureType) -> GenResult<()> {
+        let (user, _properties) = get_data();
+        let db = get_database_connection().await;
+        exit_code_transition::ActiveModel {
+            user_data_id: Set(user.id),
+            previous_state: Set(serde_json::to_string(previous_state)?),
+            new_state: Set(serde_json::to_string(new_state)?),
+            transition_date: Set(ApplicationLogbook::get_naive_datetime()),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn load_history(user_name: &str) -> GenResult<Vec<ExitCodeTransition>> {
+        let db = get_database_connection().await;
+        let transitions = exit_code_transition::Entity::find()
+            .inner_join(user_data::Entity)
+            .filter(user_data::Column::UserName.eq(user_name))
+            .order_by_asc(exit_code_transition::Column::TransitionDate)
+            .all(&db)
+            .await?;
+        Ok(transitions
+            .into_iter()
+            .filter_map(|transition| {
+                Some(ExitCodeTransition {
+                    previous_state: serde_json::from_str::<FailureType>(&transition.previous_state)
+                        .ok()?
+                        .status(),
+                    new_state: serde_json::from_str::<FailureType>(&transition.new_state)
+                        .ok()?
+                        .status(),
+                    transition_date: transition.transition_date,
+                })
+            })
+            .collect())
+    }
+}
+
+// Anonymized overview of all instances, used by the public status page
+#[derive(Default, Debug, Serialize)]
+pub struct ApplicationStatus {
+    pub version: String,
+    pub instances: usize,
+    pub ok: usize,
+    pub sign_in_failed: usize,
+    pub erroring: usize,
+    pub last_webcom_outage: Option<NaiveDateTime>,
+    // Requests waiting to be handled, summed over all instances and of the busiest instance
+    pub queued_requests: usize,
+    pub max_queue_depth: usize,
+}
+
+impl ApplicationStatus {
+    // Uses the exit code every instance keeps in memory, so no logbook has to be read for this public route
+    pub fn collect(instances: &InstanceMap) -> Self {
+        let mut status = ApplicationStatus {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            instances: instances.len(),
+            last_webcom_outage: LAST_WEBCOM_OUTAGE.read().ok().and_then(|outage| *outage),
+            ..Default::default()
+        };
+        for instance in instances.values() {
+            let queue_depth = instance.request_queue.depth();
+            status.queued_requests += queue_depth;
+            status.max_queue_depth = status.max_queue_depth.max(queue_depth);
+            match instance.status.last_exit_code() {
+                FailureType::OK => status.ok += 1,
+                FailureType::SignInFailed(_) => status.sign_in_failed += 1,
+                _ => status.erroring += 1,
+            }
+        }
+        status
+    }
+
+    // The last outage is stored with the logbook entries, so it is still known after a restart
+    // The entries keep the FailureType as JSON, so it is searched for in that format
+    pub async fn load_last_webcom_outage(db: &DatabaseConnection) -> GenResult<()> {
+        let webcom_down =
+            serde_json::to_string(&FailureType::SignInFailed(SignInFailure::WebcomDown))?;
+        let last_outage = logbook_entry::Entity::find()
+            .filter(logbook_entry::Column::State.eq(webcom_down))
+            .order_by_desc(logbook_entry::Column::ExecutionTimestamp)
+            .one(db)
+            .await?
+            .map(|entry| entry.execution_timestamp);
+        if let Ok(mut outage) = LAST_WEBCOM_OUTAGE.write() {
+            *outage = (*outage).max(last_outage);
+        }
+        Ok(())
+    }
 }
 
 #[derive(Default, Debug, Serialize, Deserialize, PartialEq, Clone)]
@@ -113,38 +451,218 @@ pub struct ApplicationState {
     pub failed_shifts: u64,
     pub failed_broken_shifts: u64,
     pub calendar_version: String,
+    // Months of which the roster did not change, so the shifts of the last run were used
+    #[serde(default)]
+    pub skipped_months: Vec<String>,
+    #[serde(default)]
+    pub phase_timings: PhaseTimings,
+    // New, changed and removed shifts found by the run, used to tell the user nothing changed this week
+    #[serde(default)]
+    pub changed_shifts: u64,
+    // The kind of error which ended the run, if it did not succeed
+    #[serde(default)]
+    pub ended_by: Option<ErrorCategory>,
+}
+
+// The parts of a run which are timed, every phase has a budget after which it is considered slow
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Login,
+    MonthLoad,
+    Diff,
+    Email,
+    BrokenShifts,
+    IcalWrite,
+}
+
+impl Phase {
+    pub fn budget(&self) -> Duration {
+        match self {
+            Phase::Login => Duration::from_secs(30),
+            Phase::MonthLoad => Duration::from_secs(20),
+            Phase::Diff => Duration::from_secs(1),
+            Phase::Email => Duration::from_secs(15),
+            Phase::BrokenShifts => Duration::from_secs(120),
+            Phase::IcalWrite => Duration::from_secs(5),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Login => "login",
+            Phase::MonthLoad => "month_load",
+            Phase::Diff => "diff",
+            Phase::Email => "email",
+            Phase::BrokenShifts => "broken_shifts",
+            Phase::IcalWrite => "ical_write",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PhaseTiming {
+    pub phase: Phase,
+    // Tells apart phases which happen multiple times in a run, like the month that was loaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub duration_ms: u64,
+}
+
+impl PhaseTiming {
+    pub fn is_slow(&self) -> bool {
+        Duration::from_millis(self.duration_ms) > self.phase.budget()
+    }
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(transparent)]
+pub struct PhaseTimings(pub Vec<PhaseTiming>);
+
+impl PhaseTimings {
+    // Records how long the phase took since it started, and warns if that is more than its budget
+    pub fn record(&mut self, phase: Phase, label: Option<String>, started: Instant) {
+        self.push(phase, label, started.elapsed());
+    }
+
+    fn push(&mut self, phase: Phase, label: Option<String>, duration: Duration) {
+        let timing = PhaseTiming {
+            phase,
+            label,
+            duration_ms: duration.as_millis() as u64,
+        };
+        if timing.is_slow() {
+            warn!(
+                "Phase {} {} took {}ms, the budget is {}ms",
+                phase.name(),
+                timing.label.as_deref().unwrap_or_default(),
+                timing.duration_ms,
+                phase.budget().as_millis()
+            );
+        }
+        self.0.push(timing);
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    pub fn slow_phases(&self) -> impl Iterator<Item = &PhaseTiming> {
+        self.0.iter().filter(|timing| timing.is_slow())
+    }
+}
+
+// When the calendar file was last written, None if there is no calendar yet
+pub fn last_calendar_write() -> Option<SystemTime> {
+    std::fs::metadata(get_ical_path()).ok()?.modified().ok()
+}
+
+// Returns how old the calendar is, but only if it is older than the configured threshold
+pub fn stale_calendar_age() -> Option<Duration> {
+    let (_user, properties) = get_data();
+    let age = SystemTime::now()
+        .duration_since(last_calendar_write()?)
+        .ok()?;
+    let threshold = Duration::from_secs(properties.stale_calendar_hours.max(1) as u64 * 3600);
+    (age > threshold).then_some(age)
+}
+
+/*
+Warn the user once if the calendar has not been updated for too long, even though the instance keeps running
+Users with incorrect credentials already get mails about that, so they are skipped
+*/
+pub fn check_stale_calendar(exit_code: &FailureType) {
+    let warning_sent_path = create_path("stale_calendar_warning_sent");
+    match stale_calendar_age() {
+        None if warning_sent_path.exists() => {
+            info!("Calendar is up to date again");
+            std::fs::remove_file(warning_sent_path).warn("Removing stale calendar warning file");
+        }
+        Some(age)
+            if !warning_sent_path.exists()
+                && exit_code != &FailureType::SignInFailed(SignInFailure::IncorrectCredentials) =>
+        {
+            warn!(
+                "Calendar has not been updated for {} hours",
+                age.as_secs() / 3600
+            );
+            send_stale_calendar_mail(age.as_secs() / 3600).warn("Sending stale calendar mail");
+            write_private_file(warning_sent_path, []).warn("Writing stale calendar warning file");
+        }
+        _ => (),
+    }
 }
 
-pub async fn send_heartbeat(reason: &FailureType) -> GenResult<()> {
-    if reason == &FailureType::TriesExceeded {
+pub async fn send_heartbeat(reason: &FailureType, logbook: &ApplicationLogbook) -> GenResult<()> {
+    let (user, properties) = get_data();
+    if reason == &FailureType::TriesExceeded && !properties.heartbeat_on_tries_exceeded {
         debug!("Not sending heartbeat due to tries exceeded");
         return Ok(());
     }
+    if is_dry_run() {
+        info!("Dry run, not sending heartbeat");
+        return Ok(());
+    }
 
-    let (user, properties) = get_data();
-    let personeelsnummer = &user.user_name;
+    let push_token = get_push_token(&user.user_name);
     let mut request_url: Url = properties.kuma_properties.domain.clone().parse()?;
-    request_url.set_path(&format!("/api/push/{personeelsnummer}"));
-    request_url.set_query(Some(&format!(
-        "status={}&msg={}&ping=",
-        match reason.clone() {
-            FailureType::GeckoEngine => "down",
-            FailureType::SignInFailed(failure)
-                if matches!(
-                    failure,
-                    SignInFailure::WebcomDown
-                        | SignInFailure::TooManyTries
-                        | SignInFailure::Other(_)
-                ) =>
-                "down",
-            _ => "up",
-        },
-        reason.to_string()
-    )));
+    request_url.set_path(&format!("/api/push/{push_token}"));
+    let status = match reason.clone() {
+        FailureType::GeckoEngine | FailureType::TriesExceeded => "down",
+        FailureType::SignInFailed(failure)
+            if matches!(
+                failure,
+                SignInFailure::WebcomDown | SignInFailure::TooManyTries | SignInFailure::Other(_)
+            ) =>
+        {
+            "down"
+        }
+        _ if stale_calendar_age().is_some() => "down",
+        _ => "up",
+    };
+    // The ping value is shown as a graph in Kuma, so use it for the duration of the run
+    let execution_time_ms = logbook.application_state.execution_time_ms;
+    let message = heartbeat_message(reason, &logbook.application_state, stale_calendar_age());
+    request_url
+        .query_pairs_mut()
+        .append_pair("status", status)
+        .append_pair("msg", &message)
+        .append_pair("ping", &execution_time_ms.to_string());
     reqwest::get(request_url).await?;
     Ok(())
 }
 
+/*
+The message is shown next to every beat in Kuma, so together with the ping graph it is a history of the runs
+The shift counts are only filled in by a run that got to the calendar, otherwise they are of the previous run
+*/
+fn heartbeat_message(
+    reason: &FailureType,
+    state: &ApplicationState,
+    stale_calendar_age: Option<Duration>,
+) -> String {
+    let mut message = format!(
+        "{reason} (uitvoertijd {:.1} s)",
+        state.execution_time_ms as f64 / 1000.0
+    );
+    if reason == &FailureType::OK {
+        message.push_str(&format!(
+            ", {} diensten gevonden, {} gebroken, {} gewijzigd",
+            state.shifts, state.broken_shifts, state.changed_shifts
+        ));
+        if state.failed_shifts > 0 {
+            message.push_str(&format!(", {} mislukt", state.failed_shifts));
+        }
+    }
+    if let Some(age) = stale_calendar_age {
+        message.push_str(&format!(
+            ", kalender al {} uur niet bijgewerkt",
+            age.as_secs() / 3600
+        ));
+    }
+    message
+}
+
 pub fn update_calendar_exit_code(
     previous_exit_code: &FailureType,
     current_exit_code: &FailureType,
@@ -159,6 +677,65 @@ pub fn update_calendar_exit_code(
         &format!("X-EXIT-CODE:{formatted_previous_exit_code}"),
         &format!("X-EXIT-CODE:{formatted_current_exit_code}"),
     );
-    write(ical_path, calendar.to_string().as_bytes())?;
+    // Only the exit code changes, so no backup is kept of this write
+    write_atomic(
+        &ical_path,
+        &create_path(""),
+        calendar.to_string().as_bytes(),
+    )?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phases_over_budget_are_slow() {
+        let mut timings = PhaseTimings::default();
+        timings.push(Phase::Diff, None, Duration::from_millis(10));
+        timings.push(
+            Phase::MonthLoad,
+            Some("2026-10".to_owned()),
+            Duration::from_secs(45),
+        );
+        timings.push(Phase::Login, None, Phase::Login.budget());
+        let slow: Vec<_> = timings.slow_phases().collect();
+        assert_eq!(slow.len(), 1);
+        assert_eq!(slow[0].phase, Phase::MonthLoad);
+        assert_eq!(slow[0].duration_ms, 45_000);
+    }
+
+    #[test]
+    fn heartbeat_message_has_shifts_of_successful_runs() {
+        let state = ApplicationState {
+            execution_time_ms: 12_345,
+            shifts: 20,
+            broken_shifts: 3,
+            changed_shifts: 1,
+            ..Default::default()
+        };
+        let message = heartbeat_message(&FailureType::OK, &state, None);
+        assert!(
+            message
+                .ends_with("(uitvoertijd 12.3 s), 20 diensten gevonden, 3 gebroken, 1 gewijzigd")
+        );
+        let message = heartbeat_message(
+            &FailureType::TriesExceeded,
+            &state,
+            Some(Duration::from_secs(3600 * 30)),
+        );
+        assert!(!message.contains("diensten gevonden"));
+        assert!(message.ends_with(", kalender al 30 uur niet bijgewerkt"));
+    }
+
+    #[test]
+    fn timings_without_label_are_serialized_without_it() {
+        let mut timings = PhaseTimings::default();
+        timings.push(Phase::IcalWrite, None, Duration::from_millis(3));
+        assert_eq!(
+            serde_json::to_string(&timings).unwrap(),
+            r#"[{"phase":"ical_write","duration_ms":3}]"#
+        );
+    }
+}
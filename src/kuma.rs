@@ -1,18 +1,35 @@
-use crate::database::variables::{GeneralProperties, UserData};
+use crate::config::template_path;
+use crate::config::var;
+use crate::database::variables::{GeneralProperties, KumaProperties, UserData};
 use crate::errors::OptionResult;
 use crate::errors::ResultLog;
-use crate::execution::watchdog::InstanceMap;
+use crate::execution::watchdog::{InstanceMap, WATCHDOG_INTERVAL};
+use crate::health::ApplicationLogbook;
+use crate::webcom::admin_webhook::send_admin_webhook;
 use crate::webcom::email::{COLOR_GREEN, COLOR_RED};
-use crate::{APPLICATION_NAME, GenResult};
-use kuma_client::monitor::{MonitorGroup, MonitorType};
+use crate::{APPLICATION_NAME, GenError, GenResult, get_database_connection, is_dry_run};
+use entity::{general_properties_db, kuma_pending_action, kuma_properties};
+use kuma_client::monitor::{Monitor, MonitorGroup, MonitorType};
+use kuma_client::status_page::{PublicGroup, PublicGroupMonitor, StatusPage};
+use kuma_client::tag::{Tag, TagDefinition};
 use kuma_client::{Client, monitor, notification};
+use sea_orm::ActiveValue::Set;
+use sea_orm::sea_query::{Expr, ExprTrait};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, QuerySelect, TransactionTrait,
+};
 use secrecy::ExposeSecret;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::fs::read_to_string;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock};
 use std::time::Duration;
 use strfmt::strfmt;
+use strum_macros::{Display, EnumString};
+use tokio::sync::{Mutex, MutexGuard, RwLock};
 use tokio::time::sleep;
 use tracing::*;
 use url::Url;
@@ -23,20 +40,170 @@ pub enum KumaUserRequest {
     Users(Vec<String>),
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
-#[serde(rename_all(deserialize = "snake_case"))]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, Display, EnumString)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
 pub enum KumaAction {
     Add,
     Reset,
     Delete,
 }
 
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KumaOutcome {
+    Done,
+    // The action failed and is retried by the pending queue
+    Queued { error: String },
+    // The instance stopped before it could be added
+    Skipped,
+}
+
+impl KumaOutcome {
+    fn from_result(result: &GenResult<()>) -> Self {
+        match result {
+            Ok(()) => Self::Done,
+            Err(err) => Self::Queued {
+                error: err.to_string(),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct KumaUserOutcome {
+    pub user_name: String,
+    pub action: KumaAction,
+    pub outcome: KumaOutcome,
+}
+
+// What happened to every user in a kuma run, every user is handled on its own so one failure does not stop the rest
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct KumaReport {
+    pub dry_run: bool,
+    pub users: Vec<KumaUserOutcome>,
+}
+
+impl KumaReport {
+    // The last outcome for the user, a reset first deletes and then adds
+    pub fn outcome(&self, user_name: &str) -> Option<&KumaOutcome> {
+        self.users
+            .iter()
+            .rev()
+            .find(|user| user.user_name == user_name)
+            .map(|user| &user.outcome)
+    }
+
+    pub fn queued(&self) -> usize {
+        self.users
+            .iter()
+            .filter(|user| matches!(user.outcome, KumaOutcome::Queued { .. }))
+            .count()
+    }
+
+    // Failed actions are queued so they are retried later
+    async fn record(&mut self, user_name: &str, action: KumaAction, result: GenResult<()>) {
+        if let Err(err) = &result {
+            warn!("Failed to {action} kuma user {user_name}, queueing. Error: {err}");
+            queue_pending_action(user_name, action)
+                .await
+                .warn("Queueing kuma action");
+        }
+        self.users.push(KumaUserOutcome {
+            user_name: user_name.to_owned(),
+            action,
+            outcome: KumaOutcome::from_result(&result),
+        });
+    }
+}
+
+/*
+How Kuma alerts a user when their monitor goes down, chosen per properties group
+Everything except smtp uses the JSON template kuma_notification_<provider>.json in the template dir,
+which is the notification config as Kuma stores it. Placeholders in its string values are filled in,
+see notification_template_values
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum KumaNotificationProvider {
+    Smtp,
+    Telegram,
+    Ntfy,
+    Webhook,
+}
+
+// Used in the ntfy template if the properties group does not set its own server
+const DEFAULT_NTFY_SERVER_URL: &str = "https://ntfy.sh";
+
+impl KumaNotificationProvider {
+    pub fn of_group(properties: &GeneralProperties) -> GenResult<Self> {
+        Self::from_str(&properties.kuma_notification_provider).map_err(|_| {
+            format!(
+                "Unknown kuma notification provider {}",
+                properties.kuma_notification_provider
+            )
+            .into()
+        })
+    }
+}
+
+// Actions that failed because Kuma was unreachable are retried with a backoff, doubling every attempt
+const PENDING_QUEUE_INTERVAL: Duration = Duration::from_secs(60);
+const PENDING_RETRY_BASE_MINUTES: i64 = 2;
+const PENDING_RETRY_MAX_MINUTES: i64 = 60 * 6;
+
+// How often all active instances are compared with the monitors in Kuma, unless KUMA_RECONCILE_INTERVAL_HOURS is set
+const DEFAULT_RECONCILIATION_INTERVAL_HOURS: u64 = 6;
+// The first comparison runs shortly after startup, so monitors of an older version are migrated right away
+const RECONCILIATION_STARTUP_DELAY: Duration = Duration::from_secs(60 * 2);
+
+// A difference between what an instance expects in Kuma, and what is actually there
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum KumaDrift {
+    MissingNotification,
+    MissingMonitor,
+    IntervalChanged { expected: i32, found: Option<i32> },
+    // The monitor was named after an older template, or renamed in the Kuma UI
+    Renamed { from: Option<String>, to: String },
+    MissingTag,
+    ProviderChanged { from: Option<String>, to: String },
+    NotificationUnlinked,
+    // Monitors and notifications of before KUMA_NAMESPACE existed
+    PushTokenChanged,
+    NotificationRenamed,
+}
+
+// The monitor for the application itself, which is pushed every watchdog cycle
+const ORCHESTRATOR_MONITOR_NAME: &str = "Mijn Bussie orchestrator";
+const ORCHESTRATOR_PUSH_TOKEN: &str = "mijn_bussie_orchestrator";
+// Cleared again when Kuma does not know the push token, so a monitor deleted in Kuma is recreated
+static ORCHESTRATOR_MONITOR_CREATED: AtomicBool = AtomicBool::new(false);
+
+// Prefixes the push tokens and notification names, so deployments sharing one Kuma don't collide
+const DEFAULT_KUMA_NAMESPACE: &str = "mijn_bussie";
+
+static KUMA_MANAGER: LazyLock<Mutex<KumaManager>> =
+    LazyLock::new(|| Mutex::new(KumaManager::default()));
+
 type UsersToRemove = Vec<String>;
 type UsersToAdd = Vec<String>;
+// The data of the active users, copied so the instances are not locked during the Kuma calls
+pub type ActiveUsers = HashMap<String, (UserData, GeneralProperties)>;
+
+pub async fn active_users(active_instances: &InstanceMap) -> ActiveUsers {
+    let mut users = HashMap::new();
+    for (user_name, instance) in active_instances {
+        users.insert(
+            user_name.clone(),
+            instance.user_instance_data.get_data_local().await,
+        );
+    }
+    users
+}
 
 fn handle_user_request(
     user_request: KumaUserRequest,
-    active_instances: &InstanceMap,
+    active_instances: &ActiveUsers,
 ) -> Vec<String> {
     match user_request {
         KumaUserRequest::All => active_instances.keys().cloned().collect(),
@@ -46,7 +213,7 @@ fn handle_user_request(
 
 fn get_users(
     actions: Vec<(KumaAction, KumaUserRequest)>,
-    active_instances: &InstanceMap,
+    active_instances: &ActiveUsers,
 ) -> (UsersToAdd, UsersToRemove) {
     let mut users_to_add = vec![];
     let mut users_to_remove = vec![];
@@ -70,14 +237,25 @@ fn get_users(
 
 pub async fn manage_users(
     actions: Vec<(KumaAction, KumaUserRequest)>,
-    active_instances: &InstanceMap,
+    active_instances: &ActiveUsers,
     properties: &GeneralProperties,
-) -> GenResult<()> {
+) -> KumaReport {
     let (instances_to_add, instances_to_remove) = get_users(actions, active_instances);
+    let mut report = KumaReport::default();
 
     if instances_to_add.is_empty() && instances_to_remove.is_empty() {
         debug!("No kuma instances to manage");
-        return Ok(());
+        return report;
+    }
+
+    if is_dry_run() {
+        info!(
+            "Dry run, not adding {} and deleting {} kuma users",
+            instances_to_add.len(),
+            instances_to_remove.len()
+        );
+        report.dry_run = true;
+        return report;
     }
 
     info!(
@@ -86,51 +264,55 @@ pub async fn manage_users(
         instances_to_remove.len()
     );
 
-    let kuma_properties = &properties.kuma_properties;
-    debug!("Logging into kuma");
-    let client = connect_to_kuma(
-        &Url::from_str(&kuma_properties.domain)?,
-        &kuma_properties.username,
-        &kuma_properties.password,
-    )
-    .await?;
-    let group_id = create_monitor_group(&client, APPLICATION_NAME).await?;
+    // If Kuma is unreachable every action fails with the same error, and all of them are queued
+    let mut manager = get_manager_with_group(&properties.kuma_properties)
+        .await
+        .map_err(|err| err.to_string());
+    if let Err(err) = &manager {
+        warn!("Kuma is unreachable, queueing all actions. Error: {err}");
+    }
+    let total = instances_to_add.len() + instances_to_remove.len();
 
     for instance_name in instances_to_remove {
-        if let Some(instance) = active_instances.get(&instance_name) {
-            let (user, _properties) = instance.user_instance_data.get_data_local().await;
-            let monitor_id = get_monitor_id(&user, &client).await;
-            if let Some(id) = monitor_id {
-                info!("Deleting monitor: {id}");
-                client.delete_monitor(id).await.warn("Deleting monitor");
-            }
-
-            let notification_id = get_notification_id(&user, &client).await;
-            if let Some(id) = notification_id {
-                info!("Deleting notification: {id}");
-                client
-                    .delete_notification(id)
-                    .await
-                    .warn("Deleting notification");
-            }
-        } else {
-            warn!("Failed to remove user {instance_name} because the instance is no longer active");
-        }
+        let result = match manager.as_mut() {
+            Ok((kuma, _)) => kuma.remove_user(&instance_name).await,
+            Err(err) => Err(err.clone().into()),
+        };
+        report
+            .record(&instance_name, KumaAction::Delete, result)
+            .await;
+        debug!("Kuma progress: {} of {total} users", report.users.len());
     }
 
     for instance_name in instances_to_add {
-        if let Some(instance) = active_instances.get(&instance_name) {
-            let (user, local_properties) = instance.user_instance_data.get_data_local().await;
-            info!("Creating kuma user: {}", user.user_name);
-            let notification_id = create_notification(&user, &local_properties, &client).await?;
-            info!("Creating monitor {}", user.user_name);
-            sleep(Duration::from_millis(100)).await;
-            create_monitor(&user, &local_properties, &client, notification_id, group_id)
-                .await
-                .warn("Creating monitor");
-        }
+        let Some((user, local_properties)) = active_instances.get(&instance_name) else {
+            report.users.push(KumaUserOutcome {
+                user_name: instance_name,
+                action: KumaAction::Add,
+                outcome: KumaOutcome::Skipped,
+            });
+            continue;
+        };
+        let result = match manager.as_mut() {
+            Ok((kuma, group_id)) => kuma.add_user(user, local_properties, *group_id).await,
+            Err(err) => Err(err.clone().into()),
+        };
+        report.record(&instance_name, KumaAction::Add, result).await;
+        debug!("Kuma progress: {} of {total} users", report.users.len());
     }
-    Ok(())
+    info!(
+        "Kuma run done, {} of {total} actions queued for a retry",
+        report.queued()
+    );
+    report
+}
+
+async fn get_manager_with_group(
+    kuma_properties: &KumaProperties,
+) -> GenResult<(MutexGuard<'static, KumaManager>, i32)> {
+    let mut kuma = KumaManager::get(kuma_properties).await?;
+    let group_id = kuma.create_monitor_group(APPLICATION_NAME).await?;
+    Ok((kuma, group_id))
 }
 
 async fn connect_to_kuma(url: &Url, username: &str, password: &str) -> GenResult<Client> {
@@ -143,84 +325,668 @@ async fn connect_to_kuma(url: &Url, username: &str, password: &str) -> GenResult
     .await?)
 }
 
-async fn get_monitor_id(user: &UserData, kuma_client: &Client) -> Option<i32> {
-    let existing_monitors = kuma_client.get_monitors().await.ok()?;
-    let user_name = &user.user_name;
-    debug!("Searching for exitisting monitors with name of {user_name}");
-    let monitor_id = existing_monitors.iter().find_map(|x| {
-        x.1.common()
-            .name()
-            .eq(&Some(user_name.to_owned()))
-            .then_some(x.0.parse::<i32>().ok()?)
-    });
-    info!("Monitor ID: {monitor_id:?}");
-    monitor_id
+fn get_heartbeat_interval(user: &UserData, properties: &GeneralProperties) -> i32 {
+    (user.user_properties.execution_interval_minutes * 60)
+        + user.expected_execution_time_seconds(properties)
 }
 
-async fn create_monitor(
-    user: &UserData,
-    properties: &GeneralProperties,
-    kuma_client: &Client,
-    notification_id: i32,
-    group_id: i32,
-) -> GenResult<i32> {
-    if let Some(id) = get_monitor_id(user, kuma_client).await {
-        info!("A monitor for that user already exists, with id {id}");
-        return Ok(id);
-    }
-    let user_name = &user.user_name;
-    let action_name = user_name.to_owned();
-    let heartbeat_interval: i32 = (user.user_properties.execution_interval_minutes * 60)
-        + properties.expected_execution_time_seconds;
-    let heartbeat_retry: i32 = properties.kuma_properties.hearbeat_retry;
-    let monitor = monitor::MonitorPush {
-        name: Some(action_name.clone()),
-        interval: Some(heartbeat_interval),
-        max_retries: Some(heartbeat_retry),
-        retry_interval: Some(heartbeat_interval),
-        push_token: Some(action_name),
-        notification_id_list: Some(HashMap::from([(notification_id.to_string(), true)])),
-        parent: Some(group_id),
+// The status page if it exists, otherwise the Kuma dashboard itself
+pub fn public_kuma_url(properties: &GeneralProperties) -> &str {
+    properties
+        .kuma_status_page_url
+        .as_deref()
+        .filter(|url| !url.is_empty())
+        .unwrap_or(&properties.kuma_properties.domain)
+}
+
+fn kuma_namespace() -> String {
+    var("KUMA_NAMESPACE")
+        .ok()
+        .filter(|namespace| !namespace.is_empty())
+        .unwrap_or(DEFAULT_KUMA_NAMESPACE.to_owned())
+}
+
+fn reconciliation_interval() -> Duration {
+    let hours = var("KUMA_RECONCILE_INTERVAL_HOURS")
+        .ok()
+        .and_then(|hours| hours.parse().ok())
+        .filter(|hours| *hours > 0)
+        .unwrap_or(DEFAULT_RECONCILIATION_INTERVAL_HOURS);
+    Duration::from_secs(60 * 60 * hours)
+}
+
+// The public status page, which shows the application group and the users who chose to be listed
+// Named after the namespace, Kuma only allows lowercase letters, numbers and dashes in a slug
+fn status_page_slug() -> String {
+    kuma_namespace().to_lowercase().replace('_', "-")
+}
+
+fn get_notification_name(user_name: &str) -> String {
+    format!("{}_{user_name}_mail", kuma_namespace())
+}
+
+// Older versions named the notification and set the push token without the namespace
+fn get_legacy_notification_name(user_name: &str) -> String {
+    format!("{user_name}_mail")
+}
+
+// The token the instance of the user pushes its heartbeat to
+pub fn get_push_token(user_name: &str) -> String {
+    format!("{}_{user_name}", kuma_namespace())
+}
+
+/*
+The name of the monitor of a user, so users of different properties groups sharing one Kuma instance don't collide
+The monitor is still found by its push token, which is always derived from the user name, so the template can be changed later on
+*/
+fn get_monitor_name(user_name: &str, properties: &GeneralProperties) -> GenResult<String> {
+    Ok(strfmt!(&properties.kuma_monitor_name_template,
+        app => APPLICATION_NAME,
+        group => properties.general_properties_id.to_string(),
+        user => user_name.to_owned()
+    )?)
+}
+
+// Every monitor gets a tag with the application name, its value is the properties group of the user
+fn get_monitor_tag(tag_id: i32, properties: &GeneralProperties) -> Tag {
+    Tag {
+        tag_id: Some(tag_id),
+        name: Some(APPLICATION_NAME.to_owned()),
+        value: Some(properties.general_properties_id.to_string()),
         ..Default::default()
-    };
-    let monitor_response = kuma_client.add_monitor(monitor).await?;
-    let monitor_id = monitor_response.common().id().result()?;
-    info!("Monitor has been created, id: {monitor_id}");
-    Ok(monitor_id)
-}
-
-async fn get_notification_id(user: &UserData, kuma_client: &Client) -> Option<i32> {
-    let existing_monitors = kuma_client.get_notifications().await.ok()?;
-    let user_name = &user.user_name;
-    let notification_name = format!("{user_name}_mail");
-    debug!("Searching for exitisting notification with name of {notification_name}");
-    let notification_id = existing_monitors
-        .iter()
-        .find(|x| x.name == Some(notification_name.clone()))
-        .and_then(|x| x.id);
-    debug!("Notification ID: {notification_id:?}");
-    notification_id
+    }
+}
+
+fn has_monitor_tag(monitor: &Monitor, properties: &GeneralProperties) -> bool {
+    let group = properties.general_properties_id.to_string();
+    monitor.common().tags().iter().any(|tag| {
+        tag.name.as_deref() == Some(APPLICATION_NAME) && tag.value.as_deref() == Some(&group)
+    })
+}
+
+fn push_token(monitor: &Monitor) -> Option<&str> {
+    match monitor {
+        Monitor::Push { value } => value.push_token.as_deref(),
+        _ => None,
+    }
+}
+
+// A notification in Kuma, with the type of provider it sends to
+#[derive(Debug, Clone)]
+struct KumaNotification {
+    id: i32,
+    provider: Option<String>,
+}
+
+/*
+Keeps one authenticated Kuma client alive between operations, together with a snapshot of all monitors and notifications.
+The snapshots are indexed by name and are thrown away after every write.
+If any Kuma call fails, the connection is dropped so the next caller reconnects
+*/
+#[derive(Default)]
+pub struct KumaManager {
+    client: Option<Client>,
+    domain: String,
+    monitors: Option<HashMap<String, Monitor>>,
+    notifications: Option<HashMap<String, KumaNotification>>,
+    tag_id: Option<i32>,
+}
+
+impl KumaManager {
+    // Lock the shared manager, connecting first if there is no client or the Kuma domain has changed
+    pub async fn get(kuma_properties: &KumaProperties) -> GenResult<MutexGuard<'static, Self>> {
+        let mut manager = KUMA_MANAGER.lock().await;
+        if manager.client.is_none() || manager.domain != kuma_properties.domain {
+            manager.reset();
+            debug!("Logging into kuma");
+            manager.client = Some(
+                connect_to_kuma(
+                    &Url::from_str(&kuma_properties.domain)?,
+                    &kuma_properties.username,
+                    &kuma_properties.password,
+                )
+                .await?,
+            );
+            manager.domain = kuma_properties.domain.clone();
+        }
+        Ok(manager)
+    }
+
+    fn client(&self) -> GenResult<&Client> {
+        self.client.as_ref().result_reason("Not connected to Kuma")
+    }
+
+    fn reset(&mut self) {
+        self.client = None;
+        self.tag_id = None;
+        self.invalidate();
+    }
+
+    fn invalidate(&mut self) {
+        self.monitors = None;
+        self.notifications = None;
+    }
+
+    // Drop the connection if a Kuma call failed, so the next call reconnects
+    fn check<T, E: Into<GenError>>(&mut self, result: Result<T, E>) -> GenResult<T> {
+        result.map_err(|err| {
+            warn!("Kuma call failed, dropping connection");
+            self.reset();
+            err.into()
+        })
+    }
+
+    pub async fn monitors(&mut self) -> GenResult<&HashMap<String, Monitor>> {
+        if self.monitors.is_none() {
+            let result = self.client()?.get_monitors().await;
+            let monitors = self.check(result)?;
+            self.monitors = Some(
+                monitors
+                    .into_values()
+                    .filter_map(|monitor| {
+                        let name = monitor.common().name().clone()?;
+                        Some((name, monitor))
+                    })
+                    .collect(),
+            );
+        }
+        self.monitors
+            .as_ref()
+            .result_reason("Getting monitor snapshot")
+    }
+
+    async fn notifications(&mut self) -> GenResult<&HashMap<String, KumaNotification>> {
+        if self.notifications.is_none() {
+            let result = self.client()?.get_notifications().await;
+            let notifications = self.check(result)?;
+            self.notifications = Some(
+                notifications
+                    .into_iter()
+                    .filter_map(|notification| {
+                        let provider = notification
+                            .config
+                            .as_ref()
+                            .and_then(|config| config.get("type"))
+                            .and_then(|provider| provider.as_str())
+                            .map(str::to_owned);
+                        Some((
+                            notification.name?,
+                            KumaNotification {
+                                id: notification.id?,
+                                provider,
+                            },
+                        ))
+                    })
+                    .collect(),
+            );
+        }
+        self.notifications
+            .as_ref()
+            .result_reason("Getting notification snapshot")
+    }
+
+    pub async fn monitor_id(&mut self, name: &str) -> Option<i32> {
+        debug!("Searching for exitisting monitors with name of {name}");
+        let monitor_id = *self.monitors().await.ok()?.get(name)?.common().id();
+        info!("Monitor ID: {monitor_id:?}");
+        monitor_id
+    }
+
+    // The monitor of a user, found by push token so it does not matter how it is named
+    // Monitors with the token of before the namespace are still found, reconciliation moves them over
+    pub async fn user_monitor(&mut self, user_name: &str) -> GenResult<Option<Monitor>> {
+        let token = get_push_token(user_name);
+        let monitors = self.monitors().await?;
+        Ok(monitors
+            .values()
+            .find(|monitor| push_token(monitor) == Some(&token))
+            .or_else(|| {
+                monitors
+                    .values()
+                    .find(|monitor| push_token(monitor) == Some(user_name))
+            })
+            .cloned())
+    }
+
+    // The id of the application tag, created if it does not exist yet
+    async fn tag_id(&mut self) -> GenResult<i32> {
+        if let Some(id) = self.tag_id {
+            return Ok(id);
+        }
+        let result = self.client()?.get_tags().await;
+        let existing_id = self
+            .check(result)?
+            .into_iter()
+            .find(|tag| tag.name.as_deref() == Some(APPLICATION_NAME))
+            .and_then(|tag| tag.tag_id);
+        let id = match existing_id {
+            Some(id) => id,
+            None => {
+                info!("Creating kuma tag {APPLICATION_NAME}");
+                let result = self
+                    .client()?
+                    .add_tag(TagDefinition {
+                        name: Some(APPLICATION_NAME.to_owned()),
+                        color: Some(COLOR_GREEN.to_owned()),
+                        ..Default::default()
+                    })
+                    .await;
+                self.check(result)?
+                    .tag_id
+                    .result_reason("Getting new tag ID")?
+            }
+        };
+        self.tag_id = Some(id);
+        Ok(id)
+    }
+
+    pub async fn notification_id(&mut self, user_name: &str) -> Option<i32> {
+        self.user_notification(user_name)
+            .await
+            .map(|notification| notification.id)
+    }
+
+    async fn user_notification(&mut self, user_name: &str) -> Option<KumaNotification> {
+        let notification_name = get_notification_name(user_name);
+        debug!("Searching for exitisting notification with name of {notification_name}");
+        let notifications = self.notifications().await.ok()?;
+        let notification = notifications
+            .get(&notification_name)
+            .or_else(|| notifications.get(&get_legacy_notification_name(user_name)))
+            .cloned();
+        debug!("Notification: {notification:?}");
+        notification
+    }
+
+    // Keep the monitor snapshot up to date after a change, so the next lookup does not fetch every monitor again
+    fn update_snapshot(&mut self, removed_id: Option<i32>, changed: Option<Monitor>) {
+        let Some(monitors) = self.monitors.as_mut() else {
+            return;
+        };
+        if let Some(id) = removed_id {
+            monitors.retain(|_, monitor| *monitor.common().id() != Some(id));
+        }
+        if let Some(monitor) = changed
+            && let Some(name) = monitor.common().name().clone()
+        {
+            monitors.insert(name, monitor);
+        }
+    }
+
+    pub async fn add_monitor<T: Into<Monitor>>(&mut self, monitor: T) -> GenResult<Monitor> {
+        let result = self.client()?.add_monitor(monitor).await;
+        let monitor = self.check(result)?;
+        self.update_snapshot(None, Some(monitor.clone()));
+        Ok(monitor)
+    }
+
+    pub async fn edit_monitor<T: Into<Monitor>>(&mut self, monitor: T) -> GenResult<()> {
+        let monitor: Monitor = monitor.into();
+        let result = self.client()?.edit_monitor(monitor.clone()).await;
+        self.check(result)?;
+        self.update_snapshot(*monitor.common().id(), Some(monitor));
+        Ok(())
+    }
+
+    pub async fn delete_monitor(&mut self, id: i32) -> GenResult<()> {
+        let result = self.client()?.delete_monitor(id).await;
+        self.check(result)?;
+        self.update_snapshot(Some(id), None);
+        Ok(())
+    }
+
+    // Returns false if there is no monitor for the user, or it already was in that state
+    pub async fn set_monitor_paused(&mut self, user_name: &str, paused: bool) -> GenResult<bool> {
+        let Some(monitor) = self.user_monitor(user_name).await? else {
+            return Ok(false);
+        };
+        let (Some(id), active) = (*monitor.common().id(), *monitor.common().active()) else {
+            return Ok(false);
+        };
+        if active == Some(!paused) {
+            return Ok(false);
+        }
+        let result = match paused {
+            true => self.client()?.pause_monitor(id).await,
+            false => self.client()?.resume_monitor(id).await,
+        };
+        self.check(result)?;
+        let mut monitor = monitor;
+        *monitor.common_mut().active_mut() = Some(!paused);
+        self.update_snapshot(Some(id), Some(monitor));
+        Ok(true)
+    }
+
+    pub async fn delete_notification(&mut self, id: i32) -> GenResult<()> {
+        let result = self.client()?.delete_notification(id).await;
+        self.invalidate();
+        self.check(result)
+    }
+
+    async fn add_user(
+        &mut self,
+        user: &UserData,
+        properties: &GeneralProperties,
+        group_id: i32,
+    ) -> GenResult<()> {
+        info!("Creating kuma user: {}", user.user_name);
+        let notification_id = self.create_notification(user, properties).await?;
+        info!("Creating monitor {}", user.user_name);
+        sleep(Duration::from_millis(100)).await;
+        self.create_monitor(user, properties, notification_id, group_id)
+            .await?;
+        Ok(())
+    }
+
+    // Only the user name is needed to remove a user, so this also works after the instance has stopped
+    async fn remove_user(&mut self, user_name: &str) -> GenResult<()> {
+        let monitor_id = self
+            .user_monitor(user_name)
+            .await?
+            .and_then(|monitor| *monitor.common().id());
+        if let Some(id) = monitor_id {
+            info!("Deleting monitor: {id}");
+            self.delete_monitor(id).await?;
+        }
+
+        for notification_name in [
+            get_notification_name(user_name),
+            get_legacy_notification_name(user_name),
+        ] {
+            let notification_id = self
+                .notifications()
+                .await?
+                .get(&notification_name)
+                .map(|notification| notification.id);
+            if let Some(id) = notification_id {
+                info!("Deleting notification: {id}");
+                self.delete_notification(id).await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn create_monitor(
+        &mut self,
+        user: &UserData,
+        properties: &GeneralProperties,
+        notification_id: i32,
+        group_id: i32,
+    ) -> GenResult<i32> {
+        let user_name = &user.user_name;
+        if let Some(id) = self
+            .user_monitor(user_name)
+            .await?
+            .and_then(|monitor| *monitor.common().id())
+        {
+            info!("A monitor for that user already exists, with id {id}");
+            return Ok(id);
+        }
+        let heartbeat_interval = get_heartbeat_interval(user, properties);
+        let heartbeat_retry: i32 = properties.kuma_properties.hearbeat_retry;
+        let tag_id = self.tag_id().await?;
+        let monitor = monitor::MonitorPush {
+            name: Some(get_monitor_name(user_name, properties)?),
+            interval: Some(heartbeat_interval),
+            max_retries: Some(heartbeat_retry),
+            retry_interval: Some(heartbeat_interval),
+            push_token: Some(get_push_token(user_name)),
+            notification_id_list: Some(HashMap::from([(notification_id.to_string(), true)])),
+            parent: Some(group_id),
+            tags: vec![get_monitor_tag(tag_id, properties)],
+            ..Default::default()
+        };
+        let monitor_response = self.add_monitor(monitor).await?;
+        let monitor_id = monitor_response.common().id().result()?;
+        info!("Monitor has been created, id: {monitor_id}");
+        Ok(monitor_id)
+    }
+
+    // Create a new notification if it does not already exist
+    pub async fn create_notification(
+        &mut self,
+        user: &UserData,
+        properties: &GeneralProperties,
+    ) -> GenResult<i32> {
+        if let Some(id) = self.notification_id(&user.user_name).await {
+            return Ok(id);
+        }
+        let user_name = &user.user_name;
+        let provider = KumaNotificationProvider::of_group(properties)?;
+        info!(
+            "Notification for user {user_name} does NOT yet exist, creating one using {provider}"
+        );
+        let config = match provider {
+            KumaNotificationProvider::Smtp => smtp_notification_config(user, properties)?,
+            provider => {
+                let template =
+                    read_to_string(template_path(&format!("kuma_notification_{provider}.json")))?;
+                fill_notification_template(
+                    &template,
+                    &notification_template_values(user, properties)?,
+                )?
+            }
+        };
+        let notification = notification::Notification {
+            name: Some(get_notification_name(user_name)),
+            config: Some(config),
+            ..Default::default()
+        };
+
+        let result = self.client()?.add_notification(notification).await;
+        self.invalidate();
+        let notification_response = self.check(result)?;
+        let id = notification_response
+            .id
+            .result_reason("Getting new notification ID")?;
+        info!("Created notification with ID {id}");
+        Ok(id)
+    }
+
+    pub async fn create_monitor_group(&mut self, group_name: &str) -> GenResult<i32> {
+        // Check if a group with the same name of "group_name" exists
+        if let Some(monitor) = self.monitors().await?.get(group_name)
+            && monitor.monitor_type() == MonitorType::Group
+        {
+            debug!(
+                "Existing monitor group has been found, ID: {:?}",
+                monitor.common().id()
+            );
+            return monitor.common().id().result_reason("Getting monitor ID");
+        }
+        info!("Monitor group has not been found");
+        // otherwise create a new one
+        let new_monitor = self
+            .add_monitor(MonitorGroup {
+                name: Some(group_name.to_string()),
+                ..Default::default()
+            })
+            .await?;
+        let id = new_monitor
+            .common()
+            .id()
+            .result_reason("Getting new monitor ID")?;
+        info!(", created new one with id {id}");
+        Ok(id)
+    }
+
+    async fn create_orchestrator_monitor(&mut self, group_id: i32) -> GenResult<i32> {
+        if let Some(id) = self.monitor_id(ORCHESTRATOR_MONITOR_NAME).await {
+            return Ok(id);
+        }
+        info!("Creating orchestrator monitor");
+        // Give the watchdog five minutes of slack before it is considered down
+        let heartbeat_interval = WATCHDOG_INTERVAL.as_secs() as i32 + 60 * 5;
+        let monitor = monitor::MonitorPush {
+            name: Some(ORCHESTRATOR_MONITOR_NAME.to_owned()),
+            interval: Some(heartbeat_interval),
+            retry_interval: Some(heartbeat_interval),
+            push_token: Some(ORCHESTRATOR_PUSH_TOKEN.to_owned()),
+            parent: Some(group_id),
+            ..Default::default()
+        };
+        let monitor_response = self.add_monitor(monitor).await?;
+        let monitor_id = monitor_response.common().id().result()?;
+        info!("Orchestrator monitor has been created, id: {monitor_id}");
+        Ok(monitor_id)
+    }
+
+    // Create the status page if it does not exist yet, and set which monitors it lists
+    async fn update_status_page(
+        &mut self,
+        group_id: i32,
+        public_monitors: Vec<i32>,
+    ) -> GenResult<()> {
+        let slug = status_page_slug();
+        let result = self.client()?.get_status_pages().await;
+        let exists = self
+            .check(result)?
+            .into_values()
+            .any(|page| page.slug.as_ref() == Some(&slug));
+        if !exists {
+            info!("Creating kuma status page {slug}");
+            let result = self
+                .client()?
+                .add_status_page(StatusPage {
+                    slug: Some(slug.clone()),
+                    title: Some(APPLICATION_NAME.to_owned()),
+                    ..Default::default()
+                })
+                .await;
+            self.check(result)?;
+        }
+
+        let mut application_monitors = vec![group_id];
+        application_monitors.extend(self.monitor_id(ORCHESTRATOR_MONITOR_NAME).await);
+        let mut groups = vec![public_group(APPLICATION_NAME, application_monitors)];
+        if !public_monitors.is_empty() {
+            groups.push(public_group("Gebruikers", public_monitors));
+        }
+        let result = self
+            .client()?
+            .edit_status_page(StatusPage {
+                slug: Some(slug),
+                title: Some(APPLICATION_NAME.to_owned()),
+                published: Some(true),
+                public_group_list: Some(groups),
+                ..Default::default()
+            })
+            .await;
+        self.check(result).map(|_| ())
+    }
+
+    async fn reconcile_user(
+        &mut self,
+        user: &UserData,
+        properties: &GeneralProperties,
+        group_id: i32,
+    ) -> GenResult<Vec<KumaDrift>> {
+        let mut drift = vec![];
+        // Kuma can't rename a notification either, one of before the namespace is created again under the new name
+        let notifications = self.notifications().await?;
+        let legacy_id = notifications
+            .get(&get_legacy_notification_name(&user.user_name))
+            .map(|notification| notification.id)
+            .filter(|_| !notifications.contains_key(&get_notification_name(&user.user_name)));
+        if let Some(id) = legacy_id {
+            drift.push(KumaDrift::NotificationRenamed);
+            self.delete_notification(id).await?;
+        }
+        let provider = KumaNotificationProvider::of_group(properties)?.to_string();
+        let notification_id = match self.user_notification(&user.user_name).await {
+            Some(notification) if notification.provider.as_ref() == Some(&provider) => {
+                notification.id
+            }
+            // The group switched to another provider, Kuma can't change the type of a notification
+            Some(notification) => {
+                drift.push(KumaDrift::ProviderChanged {
+                    from: notification.provider,
+                    to: provider,
+                });
+                self.delete_notification(notification.id).await?;
+                self.create_notification(user, properties).await?
+            }
+            None => {
+                drift.push(KumaDrift::MissingNotification);
+                self.create_notification(user, properties).await?
+            }
+        };
+
+        let expected_interval = get_heartbeat_interval(user, properties);
+        let expected_name = get_monitor_name(&user.user_name, properties)?;
+        match self.user_monitor(&user.user_name).await? {
+            Some(mut monitor) => {
+                let mut changed = false;
+                let expected_token = get_push_token(&user.user_name);
+                if let Monitor::Push { value } = &mut monitor
+                    && value.push_token.as_ref() != Some(&expected_token)
+                {
+                    drift.push(KumaDrift::PushTokenChanged);
+                    value.push_token = Some(expected_token);
+                    changed = true;
+                }
+                let found_interval = *monitor.common().interval();
+                if found_interval != Some(expected_interval) {
+                    drift.push(KumaDrift::IntervalChanged {
+                        expected: expected_interval,
+                        found: found_interval,
+                    });
+                    *monitor.common_mut().interval_mut() = Some(expected_interval);
+                    *monitor.common_mut().retry_interval_mut() = Some(expected_interval);
+                    changed = true;
+                }
+                // Monitors created before the naming template existed are moved over here
+                let found_name = monitor.common().name().clone();
+                if found_name.as_ref() != Some(&expected_name) {
+                    drift.push(KumaDrift::Renamed {
+                        from: found_name,
+                        to: expected_name.clone(),
+                    });
+                    *monitor.common_mut().name_mut() = Some(expected_name);
+                    changed = true;
+                }
+                if !has_monitor_tag(&monitor, properties) {
+                    drift.push(KumaDrift::MissingTag);
+                    let tag = get_monitor_tag(self.tag_id().await?, properties);
+                    // The tag of an older properties group is replaced
+                    monitor
+                        .common_mut()
+                        .tags_mut()
+                        .retain(|tag| tag.name.as_deref() != Some(APPLICATION_NAME));
+                    monitor.common_mut().tags_mut().push(tag);
+                    changed = true;
+                }
+                // A notification that was created again has a new id, the monitor still points to the old one
+                let notification_ids = HashMap::from([(notification_id.to_string(), true)]);
+                if monitor.common().notification_id_list().as_ref() != Some(&notification_ids) {
+                    drift.push(KumaDrift::NotificationUnlinked);
+                    *monitor.common_mut().notification_id_list_mut() = Some(notification_ids);
+                    changed = true;
+                }
+                if changed {
+                    self.edit_monitor(monitor).await?;
+                }
+            }
+            None => {
+                drift.push(KumaDrift::MissingMonitor);
+                self.create_monitor(user, properties, notification_id, group_id)
+                    .await?;
+            }
+        }
+        Ok(drift)
+    }
 }
 
-// Create a new notification if it does not already exist. The second value tells that a new notification has been created
-async fn create_notification(
+// The mail Kuma sends, in the same style as the mails of the application itself
+fn smtp_notification_config(
     user: &UserData,
     properties: &GeneralProperties,
-    kuma_client: &Client,
-) -> GenResult<i32> {
-    if let Some(id) = get_notification_id(user, kuma_client).await {
-        return Ok(id);
-    }
-    let user_name = &user.user_name;
-    info!("Notification for user {user_name} does NOT yet exist, creating one");
-    let base_html =
-        read_to_string("./templates/email_base.html").expect("Can't get email base template");
-    let offline_html =
-        read_to_string("./templates/kuma_offline.html").expect("Can't get kuma offline template");
-    let online_html =
-        read_to_string("./templates/kuma_online.html").expect("Can't get kuma online template");
-
-    let kuma_url = &properties.kuma_properties.domain;
+) -> GenResult<serde_json::Value> {
+    let base_html = read_to_string(template_path("email_base.html"))?;
+    let offline_html = read_to_string(template_path("kuma_offline.html"))?;
+    let online_html = read_to_string(template_path("kuma_online.html"))?;
+
+    let kuma_url = public_kuma_url(properties);
 
     let body_online = strfmt!(&base_html,
         content => strfmt!(&online_html,
@@ -266,47 +1032,599 @@ Mijn Bussie heeft een storing
         "htmlBody": true
 
     });
-    let notification_name = format!("{user_name}_mail");
-    let notification = notification::Notification {
-        name: Some(notification_name),
-        config: Some(config),
+    Ok(config)
+}
+
+/*
+The ntfy topic of a user. Anyone who knows a topic can read it, so it is derived from the secret calendar token
+instead of the user name. The user finds it in their profile
+*/
+pub fn notification_topic(user: &UserData) -> String {
+    Sha256::digest(format!("kuma-notification:{}", user.calendar_token))
+        .iter()
+        .take(16)
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/*
+The values of the group, like a bot token or the ntfy server, with the values of the user on top, like their chat id
+Both are JSON objects of strings, stored in kuma_notification_settings
+*/
+fn notification_template_values(
+    user: &UserData,
+    properties: &GeneralProperties,
+) -> GenResult<HashMap<String, String>> {
+    let mut values = HashMap::from([(
+        "ntfy_server_url".to_owned(),
+        DEFAULT_NTFY_SERVER_URL.to_owned(),
+    )]);
+    values.extend(serde_json::from_str::<HashMap<String, String>>(
+        &properties.kuma_notification_settings,
+    )?);
+    values.extend(serde_json::from_str::<HashMap<String, String>>(
+        &user.user_properties.kuma_notification_settings,
+    )?);
+    values.extend([
+        ("notification_topic".to_owned(), notification_topic(user)),
+        ("user_name".to_owned(), user.user_name.clone()),
+        ("email".to_owned(), user.email.0.expose_secret().to_owned()),
+        (
+            "monitor_name".to_owned(),
+            get_monitor_name(&user.user_name, properties)?,
+        ),
+        (
+            "kuma_url".to_owned(),
+            public_kuma_url(properties).to_owned(),
+        ),
+    ]);
+    Ok(values)
+}
+
+// Fill in the placeholders in every string of the template. Braces meant for Kuma itself are written double, so {{{{msg}}}} for {{msg}}
+fn fill_notification_template(
+    template: &str,
+    values: &HashMap<String, String>,
+) -> GenResult<serde_json::Value> {
+    fn fill(value: &mut serde_json::Value, values: &HashMap<String, String>) -> GenResult<()> {
+        match value {
+            serde_json::Value::String(text) => *text = strfmt(text, values)?,
+            serde_json::Value::Array(items) => {
+                for item in items {
+                    fill(item, values)?;
+                }
+            }
+            serde_json::Value::Object(fields) => {
+                for field in fields.values_mut() {
+                    fill(field, values)?;
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+    let mut config: serde_json::Value = serde_json::from_str(template)?;
+    fill(&mut config, values)?;
+    Ok(config)
+}
+
+fn public_group(name: &str, monitor_ids: Vec<i32>) -> PublicGroup {
+    PublicGroup {
+        name: Some(name.to_owned()),
+        monitor_list: Some(
+            monitor_ids
+                .into_iter()
+                .map(|id| PublicGroupMonitor {
+                    id: Some(id),
+                    ..Default::default()
+                })
+                .collect(),
+        ),
+        ..Default::default()
+    }
+}
+
+/*
+Update the status page and write its URL to every properties group using the same Kuma, so the welcome mail always links to a working page
+The monitors of users are only listed if they turned on kuma_public_monitor
+*/
+async fn sync_status_page(
+    kuma: &mut KumaManager,
+    group_id: i32,
+    users: &Vec<(UserData, GeneralProperties)>,
+    properties: &GeneralProperties,
+) -> GenResult<()> {
+    let mut public_monitors = vec![];
+    for (user, _) in users {
+        if user.user_properties.kuma_public_monitor
+            && let Some(id) = kuma
+                .user_monitor(&user.user_name)
+                .await?
+                .and_then(|monitor| *monitor.common().id())
+        {
+            public_monitors.push(id);
+        }
+    }
+    kuma.update_status_page(group_id, public_monitors).await?;
+
+    let domain = &properties.kuma_properties.domain;
+    let url = format!(
+        "{}/status/{}",
+        domain.trim_end_matches('/'),
+        status_page_slug()
+    );
+    let db = get_database_connection().await;
+    // Every properties group of which the Kuma properties point to this Kuma
+    let kuma_ids: Vec<i32> = kuma_properties::Entity::find()
+        .select_only()
+        .column(kuma_properties::Column::KumaId)
+        .filter(kuma_properties::Column::Domain.eq(domain.as_str()))
+        .into_tuple()
+        .all(&db)
+        .await?;
+    let result = general_properties_db::Entity::update_many()
+        .col_expr(
+            general_properties_db::Column::KumaStatusPageUrl,
+            Expr::value(url.as_str()),
+        )
+        .filter(general_properties_db::Column::KumaProperties.is_in(kuma_ids))
+        .filter(
+            general_properties_db::Column::KumaStatusPageUrl
+                .ne(url.as_str())
+                .or(general_properties_db::Column::KumaStatusPageUrl.is_null()),
+        )
+        .exec(&db)
+        .await?;
+    if result.rows_affected > 0 {
+        info!(
+            "Saved kuma status page url {url} for {} properties groups",
+            result.rows_affected
+        );
+    }
+    Ok(())
+}
+
+// A pending delete followed by an add has to remove the old monitor first, so it becomes a reset
+fn merge_pending_action(pending: Option<KumaAction>, action: KumaAction) -> KumaAction {
+    match (pending, action) {
+        (Some(KumaAction::Delete | KumaAction::Reset), KumaAction::Add) => KumaAction::Reset,
+        (_, action) => action,
+    }
+}
+
+// Queue an action to be retried later. Only one action per user is kept, merged with the one already pending
+async fn queue_pending_action(user_name: &str, action: KumaAction) -> GenResult<()> {
+    let db = get_database_connection().await;
+    let transaction = db.begin().await?;
+    let pending = kuma_pending_action::Entity::find()
+        .filter(kuma_pending_action::Column::UserName.eq(user_name))
+        .one(&transaction)
+        .await?
+        .and_then(|pending| KumaAction::from_str(&pending.action).ok());
+    let action = merge_pending_action(pending, action);
+    kuma_pending_action::Entity::delete_many()
+        .filter(kuma_pending_action::Column::UserName.eq(user_name))
+        .exec(&transaction)
+        .await?;
+    let now = ApplicationLogbook::get_naive_datetime();
+    kuma_pending_action::ActiveModel {
+        user_name: Set(user_name.to_owned()),
+        action: Set(action.to_string()),
+        attempts: Set(0),
+        next_attempt: Set(now + chrono::Duration::minutes(PENDING_RETRY_BASE_MINUTES)),
+        creation_date: Set(now),
         ..Default::default()
+    }
+    .insert(&transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+pub async fn kuma_pending_queue(instances: Arc<RwLock<InstanceMap>>) {
+    loop {
+        sleep(PENDING_QUEUE_INTERVAL).await;
+        retry_pending_actions(&instances)
+            .await
+            .warn("Retrying pending kuma actions");
+    }
+}
+
+async fn retry_pending_actions(instances: &RwLock<InstanceMap>) -> GenResult<()> {
+    let db = get_database_connection().await;
+    let now = ApplicationLogbook::get_naive_datetime();
+    let due_actions = kuma_pending_action::Entity::find()
+        .filter(kuma_pending_action::Column::NextAttempt.lte(now))
+        .all(&db)
+        .await?;
+    if due_actions.is_empty() || is_dry_run() {
+        return Ok(());
+    }
+    info!("Retrying {} pending kuma actions", due_actions.len());
+
+    // The data of the users is copied first, so the instances are not locked during the Kuma calls
+    let mut active_users = HashMap::new();
+    for (user_name, instance) in instances.read().await.iter() {
+        if due_actions
+            .iter()
+            .any(|pending_action| &pending_action.user_name == user_name)
+        {
+            active_users.insert(
+                user_name.clone(),
+                instance.user_instance_data.get_data_local().await,
+            );
+        }
+    }
+
+    let properties = GeneralProperties::load_default_preferences(&db).await?;
+    let mut manager = get_manager_with_group(&properties.kuma_properties)
+        .await
+        .warn_owned("Connecting to kuma for pending actions")
+        .ok();
+    for pending_action in due_actions {
+        let result = match manager.as_mut() {
+            Some((kuma, group_id)) => {
+                kuma.apply_pending_action(
+                    &pending_action,
+                    *group_id,
+                    active_users.get(&pending_action.user_name),
+                )
+                .await
+            }
+            None => Err("Kuma is unreachable".into()),
+        };
+        match result {
+            Ok(()) => {
+                info!(
+                    "Pending kuma action {} for {} succeeded",
+                    pending_action.action, pending_action.user_name
+                );
+                kuma_pending_action::Entity::delete_by_id(pending_action.pending_action_id)
+                    .exec(&db)
+                    .await?;
+            }
+            Err(err) => {
+                let attempts = pending_action.attempts + 1;
+                let backoff_minutes =
+                    (PENDING_RETRY_BASE_MINUTES << attempts.min(16)).min(PENDING_RETRY_MAX_MINUTES);
+                warn!(
+                    "Pending kuma action {} for {} failed {attempts} times, retrying in {backoff_minutes} minutes. Error: {err}",
+                    pending_action.action, pending_action.user_name
+                );
+                let mut active_action: kuma_pending_action::ActiveModel = pending_action.into();
+                active_action.attempts = Set(attempts);
+                active_action.next_attempt = Set(now + chrono::Duration::minutes(backoff_minutes));
+                active_action.update(&db).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+impl KumaManager {
+    async fn apply_pending_action(
+        &mut self,
+        pending_action: &kuma_pending_action::Model,
+        group_id: i32,
+        active_user: Option<&(UserData, GeneralProperties)>,
+    ) -> GenResult<()> {
+        let user_name = &pending_action.user_name;
+        let action = KumaAction::from_str(&pending_action.action)?;
+        if matches!(action, KumaAction::Delete | KumaAction::Reset) {
+            self.remove_user(user_name).await?;
+        }
+        if action == KumaAction::Delete {
+            return Ok(());
+        }
+        match active_user {
+            Some((user, local_properties)) => self.add_user(user, local_properties, group_id).await,
+            None => {
+                info!("Not adding {user_name} to kuma, the instance is no longer active");
+                Ok(())
+            }
+        }
+    }
+}
+
+/*
+The monitor of a stopped instance is paused, so Kuma does not report it as down
+It is resumed once the instance starts again, which keeps the uptime history of the monitor
+*/
+pub async fn set_user_monitor_paused(
+    user_name: &str,
+    kuma_properties: &KumaProperties,
+    paused: bool,
+) -> GenResult<()> {
+    if kuma_properties.domain.is_empty() || is_dry_run() {
+        return Ok(());
+    }
+    let mut kuma = KumaManager::get(kuma_properties).await?;
+    if kuma.set_monitor_paused(user_name, paused).await? {
+        info!(
+            "{} kuma monitor of {user_name}",
+            if paused { "Paused" } else { "Resumed" }
+        );
+    }
+    Ok(())
+}
+
+// Removes the monitor for good when the account is deleted, even if stopped users only get their monitor paused
+pub async fn remove_user_monitor(
+    user_name: &str,
+    kuma_properties: &KumaProperties,
+) -> GenResult<()> {
+    if kuma_properties.domain.is_empty() || is_dry_run() {
+        return Ok(());
+    }
+    let removed = match KumaManager::get(kuma_properties).await {
+        Ok(mut kuma) => kuma.remove_user(user_name).await,
+        Err(err) => Err(err),
     };
+    if let Err(err) = removed {
+        warn!("Failed to remove kuma user {user_name}, queueing. Error: {err}");
+        queue_pending_action(user_name, KumaAction::Delete).await?;
+    }
+    Ok(())
+}
 
-    let notification_response = kuma_client.add_notification(notification.clone()).await?;
-    let id = notification_response
-        .id
-        .result_reason("Getting new notification ID")?;
-    info!("Created notification with ID {id}");
-    Ok(id)
-}
-
-async fn create_monitor_group(kuma_client: &Client, group_name: &str) -> GenResult<i32> {
-    let current_monitors = kuma_client.get_monitors().await?;
-    // Check if a group with the same name of "group_name" exists
-    for (_id, monitor) in current_monitors.into_iter() {
-        if monitor.monitor_type() == MonitorType::Group {
-            if monitor.common().name() == &Some(group_name.to_string()) {
-                debug!(
-                    "Existing monitor group has been found, ID: {:?}",
-                    monitor.common().id()
+/*
+Push a heartbeat for the application itself, so an alert is sent if the whole process dies.
+The monitor is created under the application monitor group the first time.
+If HEARTBEAT_URL is set, that URL is also requested so any other uptime service can be used
+*/
+pub async fn send_orchestrator_heartbeat(
+    properties: &GeneralProperties,
+    instance_count: usize,
+) -> GenResult<()> {
+    if let Ok(heartbeat_url) = var("HEARTBEAT_URL")
+        && !heartbeat_url.is_empty()
+    {
+        reqwest::get(heartbeat_url)
+            .await
+            .warn("Sending generic heartbeat");
+    }
+
+    let kuma_properties = &properties.kuma_properties;
+    if kuma_properties.domain.is_empty() || is_dry_run() {
+        return Ok(());
+    }
+    if !ORCHESTRATOR_MONITOR_CREATED.load(Ordering::Relaxed) {
+        let mut kuma = KumaManager::get(kuma_properties).await?;
+        let group_id = kuma.create_monitor_group(APPLICATION_NAME).await?;
+        kuma.create_orchestrator_monitor(group_id).await?;
+        ORCHESTRATOR_MONITOR_CREATED.store(true, Ordering::Relaxed);
+    }
+
+    let mut request_url: Url = kuma_properties.domain.parse()?;
+    request_url.set_path(&format!("/api/push/{ORCHESTRATOR_PUSH_TOKEN}"));
+    request_url
+        .query_pairs_mut()
+        .append_pair("status", "up")
+        .append_pair("msg", &format!("{instance_count} instances actief"));
+    let response = reqwest::get(request_url).await?;
+    if !response.status().is_success() {
+        // The monitor was removed in Kuma, the next heartbeat creates it again with a fresh list of monitors
+        ORCHESTRATOR_MONITOR_CREATED.store(false, Ordering::Relaxed);
+        KUMA_MANAGER.lock().await.invalidate();
+        return Err(format!(
+            "Kuma refused the orchestrator heartbeat with {}, recreating the monitor",
+            response.status()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/*
+Periodically compare all active instances with the monitors and notifications in Kuma.
+Monitors or notifications removed in the Kuma UI are recreated, and intervals, names and tags are fixed after settings changes
+Found differences are written to the logbook of the user
+*/
+pub async fn kuma_reconciliation(instances: Arc<RwLock<InstanceMap>>) {
+    let mut wait = RECONCILIATION_STARTUP_DELAY;
+    loop {
+        sleep(wait).await;
+        wait = reconciliation_interval();
+        debug!("Starting Kuma reconciliation");
+        let mut users = vec![];
+        for instance in instances.read().await.values() {
+            users.push(instance.user_instance_data.get_data_local().await);
+        }
+        let db = get_database_connection().await;
+        let Ok(known_users) = UserData::get_all_usernames(&db)
+            .await
+            .warn_owned("Loading users for Kuma reconciliation")
+        else {
+            continue;
+        };
+        // Every Kuma is reconciled on its own, the Kuma of the default properties even if none of its users are active
+        let mut kumas: HashMap<String, (GeneralProperties, Vec<(UserData, GeneralProperties)>)> =
+            HashMap::new();
+        match GeneralProperties::load_default_preferences(&db).await {
+            Ok(properties) => {
+                kumas.insert(
+                    properties.kuma_properties.domain.clone(),
+                    (properties, vec![]),
                 );
-                return Ok(monitor.common().id().result_reason("Getting monitor ID")?);
             }
+            Err(err) => warn!("Could not load properties for Kuma reconciliation: {err}"),
+        }
+        for (user, properties) in users {
+            kumas
+                .entry(properties.kuma_properties.domain.clone())
+                .or_insert_with(|| (properties.clone(), vec![]))
+                .1
+                .push((user, properties));
+        }
+        for (properties, users) in kumas.into_values() {
+            reconcile_users(&users, &known_users, &properties)
+                .await
+                .warn("Kuma reconciliation");
         }
     }
-    info!("Monitor group has not been found");
-    // otherwise create a new one
-    let new_monitor = kuma_client
-        .add_monitor(MonitorGroup {
-            name: Some(group_name.to_string()),
-            ..Default::default()
+}
+
+async fn reconcile_users(
+    users: &Vec<(UserData, GeneralProperties)>,
+    known_users: &[String],
+    properties: &GeneralProperties,
+) -> GenResult<()> {
+    if properties.kuma_properties.domain.is_empty() {
+        debug!("Kuma is not configured, skipping reconciliation");
+        return Ok(());
+    }
+    if is_dry_run() {
+        info!("Dry run, skipping kuma reconciliation");
+        return Ok(());
+    }
+    let mut kuma = KumaManager::get(&properties.kuma_properties).await?;
+    let group_id = kuma.create_monitor_group(APPLICATION_NAME).await?;
+
+    let mut drifted_users = 0;
+    for (user, local_properties) in users {
+        let Ok(drift) = kuma
+            .reconcile_user(user, local_properties, group_id)
+            .await
+            .warn_owned("Reconciling kuma user")
+        else {
+            continue;
+        };
+        if !drift.is_empty() {
+            warn!("Kuma drift for user {}: {drift:?}", user.user_name);
+            drifted_users += 1;
+        }
+        ApplicationLogbook::save_kuma_drift(user, local_properties, drift)
+            .warn("Saving kuma drift");
+    }
+    info!(
+        "Kuma reconciliation done, {drifted_users} of {} users had drifted",
+        users.len()
+    );
+    let orphans = orphan_monitors(kuma.monitors().await?, known_users);
+    if !orphans.is_empty() {
+        let message = format!(
+            "Kuma reconciliation found {} monitors without an activated user: {}",
+            orphans.len(),
+            orphans.join(", ")
+        );
+        warn!("{message}");
+        send_admin_webhook(&message)
+            .await
+            .warn("Reporting orphan kuma monitors");
+    }
+    sync_status_page(&mut kuma, group_id, users, properties)
+        .await
+        .warn("Updating kuma status page");
+    Ok(())
+}
+
+// Monitors in the namespace of this deployment without an activated user, they are only reported and not removed
+fn orphan_monitors(monitors: &HashMap<String, Monitor>, known_users: &[String]) -> Vec<String> {
+    let prefix = format!("{}_", kuma_namespace());
+    let mut orphans: Vec<String> = monitors
+        .iter()
+        .filter(|(_, monitor)| {
+            push_token(monitor)
+                .filter(|token| *token != ORCHESTRATOR_PUSH_TOKEN)
+                .and_then(|token| token.strip_prefix(&prefix))
+                .is_some_and(|user_name| !known_users.iter().any(|known| known == user_name))
         })
-        .await?;
-    let id = new_monitor
-        .common()
-        .id()
-        .result_reason("Getting new monitor ID")?;
-    info!(", created new one with id {id}");
-    Ok(id)
+        .map(|(name, _)| name.clone())
+        .collect();
+    orphans.sort();
+    orphans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_page_is_preferred_over_dashboard() {
+        let mut properties = GeneralProperties::example();
+        properties.kuma_properties.domain = "https://kuma.example.com".to_owned();
+        assert_eq!(public_kuma_url(&properties), "https://kuma.example.com");
+        properties.kuma_status_page_url = Some(String::new());
+        assert_eq!(public_kuma_url(&properties), "https://kuma.example.com");
+        properties.kuma_status_page_url =
+            Some("https://kuma.example.com/status/mijn-bussie".to_owned());
+        assert_eq!(
+            public_kuma_url(&properties),
+            "https://kuma.example.com/status/mijn-bussie"
+        );
+    }
+
+    #[test]
+    fn notification_template_is_filled_in() {
+        let values = HashMap::from([
+            ("user_name".to_owned(), "piet".to_owned()),
+            ("monitor_name".to_owned(), "bussie-1-piet".to_owned()),
+            (
+                "webhook_url".to_owned(),
+                "https://example.com/hook".to_owned(),
+            ),
+        ]);
+        let template = read_to_string(template_path("kuma_notification_webhook.json")).unwrap();
+        let config = fill_notification_template(&template, &values).unwrap();
+        assert_eq!(config["type"], "webhook");
+        assert_eq!(config["webhookURL"], "https://example.com/hook");
+        assert_eq!(
+            config["webhookCustomBody"],
+            r#"{"user_name": "piet", "monitor": "bussie-1-piet", "msg": "{{msg}}"}"#
+        );
+        assert!(fill_notification_template(r#"{"url": "{missing}"}"#, &values).is_err());
+    }
+
+    #[test]
+    fn pending_delete_and_add_become_reset() {
+        assert_eq!(
+            merge_pending_action(Some(KumaAction::Delete), KumaAction::Add),
+            KumaAction::Reset
+        );
+        assert_eq!(
+            merge_pending_action(Some(KumaAction::Reset), KumaAction::Add),
+            KumaAction::Reset
+        );
+        assert_eq!(
+            merge_pending_action(Some(KumaAction::Add), KumaAction::Delete),
+            KumaAction::Delete
+        );
+        assert_eq!(merge_pending_action(None, KumaAction::Add), KumaAction::Add);
+    }
+
+    #[test]
+    fn last_outcome_of_user_is_reported() {
+        let mut report = KumaReport::default();
+        for (action, result) in [
+            (KumaAction::Delete, Ok(())),
+            (KumaAction::Add, Err("Kuma is unreachable".into())),
+        ] {
+            report.users.push(KumaUserOutcome {
+                user_name: "piet".to_owned(),
+                action,
+                outcome: KumaOutcome::from_result(&result),
+            });
+        }
+        assert_eq!(
+            report.outcome("piet"),
+            Some(&KumaOutcome::Queued {
+                error: "Kuma is unreachable".to_owned()
+            })
+        );
+        assert_eq!(report.outcome("klaas"), None);
+        assert_eq!(report.queued(), 1);
+    }
+
+    #[test]
+    fn monitor_name_follows_template() {
+        let mut properties = GeneralProperties::example();
+        assert_eq!(get_monitor_name("piet", &properties).unwrap(), "piet");
+        properties.kuma_monitor_name_template = "{app}-{group}-{user}".to_owned();
+        assert_eq!(
+            get_monitor_name("piet", &properties).unwrap(),
+            format!("{APPLICATION_NAME}-1-piet")
+        );
+        properties.kuma_monitor_name_template = "{unknown}".to_owned();
+        assert!(get_monitor_name("piet", &properties).is_err());
+    }
 }
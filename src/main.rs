@@ -6,30 +6,62 @@ const FALLBACK_URL: [&str; 2] = [
 ];
 const APPLICATION_NAME: &str = "Mijn Bussie";
 
+use crate::api::profile::UserProfile;
 use crate::api::route::api;
+use crate::cli::{Cli, run_command};
+use crate::config::var;
+use crate::config::{load_config_file, validate_startup};
+use crate::database::calendar_fetch::last_calendar_fetch;
+use crate::database::change_listener::listen_for_changes;
+use crate::database::onboarding_variants::load_welcome_template;
 use crate::database::secret::Secret;
 use crate::database::variables::GeneralProperties;
 use crate::database::variables::UserData;
 use crate::database::variables::UserInstanceData;
 use crate::errors::FailureType;
+use crate::errors::IncorrectCredentialsCount;
 use crate::errors::ResultLog;
 use crate::errors::SignInFailure;
 use crate::errors::ToString;
+use crate::execution::clock::SystemClock;
+use crate::execution::disk_quota::enforce_disk_quota;
+use crate::execution::instance_log::{
+    InstanceLogLayer, in_instance_span, instance_log_enabled, open_instance_log,
+};
+use crate::execution::instance_status::InstanceStatus;
+use crate::execution::log_level::{set_log_level, user_log_filter};
+use crate::execution::log_rotation::rotate_logs;
+use crate::execution::permissions::{audit_user_directories, set_strict_permissions};
+use crate::execution::request_queue::RequestQueue;
+use crate::execution::shutdown::{SHUTDOWN_GRACE_PERIOD, cleanup_instance};
+use crate::execution::signals::handle_sighup;
 use crate::execution::timer::execution_timer;
+use crate::execution::watchdog::WATCHDOG_QUEUE_SIZE;
 use crate::execution::watchdog::WatchdogRequest;
 use crate::execution::watchdog::watchdog;
-use crate::execution::watchdog::{InstanceMap, RequestResponse};
-use crate::health::ApplicationLogbook;
+use crate::execution::watchdog::{InstanceMap, OnboardingStatus, RequestResponse};
+use crate::health::{
+    ApplicationLogbook, ApplicationStatus, ExitCodeTransition, check_stale_calendar,
+};
+use crate::webcom::bounce::{BounceReport, pause_user_mail, resume_user_mail};
+use crate::webcom::calendar_file::rollback_calendar;
 use crate::webcom::deletion::StandingInformation;
 use crate::webcom::deletion::check_instance_standing;
 use crate::webcom::deletion::delete_account;
 use crate::webcom::deletion::update_instance_timestamps;
+use crate::webcom::donation::check_donation_reminder;
+use crate::webcom::earnings::{PayRules, monthly_earnings};
 use crate::webcom::email;
 use crate::webcom::email::create_calendar_link;
+use crate::webcom::error_digest::flush_error_digest;
+use crate::webcom::ical::{get_ical_path, load_known_shifts};
+use crate::webcom::mail_transport::RUN_SMTP_TRANSPORT;
+use crate::webcom::new_user::notify_new_user;
 use crate::webcom::shift::*;
 use crate::webcom::webcom::webcom_instance;
+use crate::webcom::weekly_summary::check_weekly_summary;
+use clap::Parser;
 use dotenvy::dotenv_override;
-use dotenvy::var;
 use entity::user_data;
 use migration::Migrator;
 use migration::MigratorTrait;
@@ -44,9 +76,6 @@ use secrecy::ExposeSecret;
 use serde::Serialize;
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::fs::set_permissions;
-use std::os::unix::fs::MetadataExt;
-use std::os::unix::fs::PermissionsExt;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
@@ -55,21 +84,23 @@ use tokio::runtime::Handle;
 use tokio::spawn;
 use tokio::sync::RwLock;
 use tokio::sync::mpsc::channel;
-use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::oneshot;
 use tokio::task::JoinHandle;
 use tokio::task_local;
 use tokio::time::sleep;
-use tracing::instrument::WithSubscriber;
+use tokio::time::timeout_at;
 use tracing::level_filters::LevelFilter;
 use tracing::*;
-use tracing_appender::non_blocking;
 use tracing_subscriber::EnvFilter;
 use tracing_subscriber::Layer;
 use tracing_subscriber::Registry;
+use tracing_subscriber::filter::{FilterExt, dynamic_filter_fn, filter_fn};
 use tracing_subscriber::fmt;
 use tracing_subscriber::layer::SubscriberExt;
 
 mod api;
+mod cli;
+mod config;
 mod database;
 mod errors;
 mod execution;
@@ -84,6 +115,17 @@ task_local! {
     static NAME: RefCell<Option<String>>;
     static USER_PROPERTIES: RefCell<Option<Arc<UserData>>>;
     static GENERAL_PROPERTIES: RefCell<Option<Arc<GeneralProperties>>>;
+    static DRY_RUN: bool;
+}
+
+/*
+In a dry run everything is scraped and compared, but nothing is written.
+Mails are saved to disk instead of sent, the calendar is not replaced and Kuma is not changed.
+It is either enabled for the whole application using DRY_RUN, or for a single run
+*/
+pub fn is_dry_run() -> bool {
+    var("DRY_RUN").is_ok_and(|dry_run| dry_run == "true")
+        || DRY_RUN.try_with(|dry_run| *dry_run).unwrap_or(false)
 }
 
 // Get thread specific data
@@ -129,6 +171,10 @@ fn create_shift_link(shift: &Shift, include_domain: bool) -> GenResult<String> {
 
 fn create_ical_filename() -> String {
     let (user, _properties) = get_data();
+    create_ical_filename_local(&user)
+}
+
+pub fn create_ical_filename_local(user: &UserData) -> String {
     match &user.file_name {
         value if value.is_empty() => format!("{}.ics", user.user_name),
         _ => format!("{}.ics", user.file_name),
@@ -143,6 +189,7 @@ pub fn create_path_local(
     let mut path = PathBuf::from(&properties.file_target);
     path.push(&user.user_name);
     std::fs::create_dir_all(&path).warn("Creating dirs");
+    set_strict_permissions(&path).warn("Setting user directory permissions");
     path.push(filename);
     path
 }
@@ -175,8 +222,10 @@ pub fn get_set_name_local(user: &UserData, set_new_name: Option<String>) -> Stri
         .to_owned();
 
     // Open a database connection and write the new name to the database, if a new name request is done
+    // NAME is empty at the start of every run, so the stored name is checked too. Every write wakes the watchdog
     if let Some(new_name) = set_new_name
         && Some(new_name.as_str()) != NAME.get().borrow().as_deref()
+        && Some(new_name.as_str()) != user.name.as_ref().map(|name| name.0.expose_secret())
     {
         tokio::task::block_in_place(move || {
             Handle::current().block_on(update_name(new_name, user.id))
@@ -211,7 +260,7 @@ async fn update_name(new_name: String, data_id: i32) -> GenResult<()> {
 /// spawn a new webcom instance
 async fn spawn_webcom_instance(
     start_request: &StartRequest,
-    exit_code_sender: Arc<Sender<StartRequest>>,
+    request_queue: Arc<RequestQueue>,
     thread_store: &mut Option<JoinHandle<FailureType>>,
     last_exit_code: &mut FailureType,
 ) -> bool {
@@ -223,19 +272,35 @@ async fn spawn_webcom_instance(
         *last_exit_code = thread.await.unwrap_or_default();
     }
     let (user, properties) = get_data();
-    *thread_store = Some(tokio::spawn(
-        USER_PROPERTIES
-            .scope(
-                RefCell::new(Some(user)),
-                GENERAL_PROPERTIES.scope(
-                    RefCell::new(Some(properties)),
-                    NAME.scope(
+    let dry_run = start_request == &StartRequest::DryRun;
+    // A dry run does not change the exit code of the instance
+    let previous_exit_code = last_exit_code.clone();
+    let run = USER_PROPERTIES.scope(
+        RefCell::new(Some(user)),
+        GENERAL_PROPERTIES.scope(
+            RefCell::new(Some(properties)),
+            NAME.scope(
+                RefCell::new(None),
+                DRY_RUN.scope(
+                    dry_run,
+                    RUN_SMTP_TRANSPORT.scope(
                         RefCell::new(None),
-                        webcom_instance(start_request.clone(), exit_code_sender),
+                        webcom_instance(start_request.clone(), request_queue),
                     ),
                 ),
-            )
-            .with_current_subscriber(),
+            ),
+        ),
+    );
+    *thread_store = Some(tokio::spawn(
+        async move {
+            let exit_code = run.await;
+            if dry_run {
+                previous_exit_code
+            } else {
+                exit_code
+            }
+        }
+        .in_current_span(),
     ));
     true
 }
@@ -251,6 +316,7 @@ fn is_webcom_instance_active(thread_store: &Option<JoinHandle<FailureType>>) ->
 pub enum StartRequest {
     Timer,
     Api,
+    DryRun,
     Single,
     Force,
     Logbook,
@@ -262,9 +328,81 @@ pub enum StartRequest {
     Calendar,
     Delete,
     Standing,
+    Earnings,
+    Onboarding,
+    OnboardingStatus,
+    // Only sends the mails to the support address if true
+    PreviewOnboarding(bool),
+    RollbackCalendar,
+    // Clears the sign in failure counter and runs right away
+    Retry,
+    // Only signs in, after the password changed
+    VerifyLogin,
+    // A mail to the user bounced, or the address is fixed again
+    MailBounced(BounceReport),
+    ResumeMail,
+    // None goes back to the default log level
+    SetLogLevel(Option<String>),
+    // Lets a running scrape finish, cleans up and stops the instance
+    Shutdown,
 
     // Webcom request
     ExecutionFinished(FailureType),
+    // A dry run finished, nothing of it is stored
+    DryRunFinished(FailureType),
+}
+
+impl StartRequest {
+    // Requests which don't change the instance, these are answered by light_request
+    // Requests that send a mail go through the loop, so they are not sent twice at the same time
+    pub fn is_light(&self) -> bool {
+        matches!(
+            self,
+            StartRequest::Logbook
+                | StartRequest::Name
+                | StartRequest::IsActive
+                | StartRequest::ExitCode
+                | StartRequest::UserData
+                | StartRequest::Calendar
+                | StartRequest::Standing
+                | StartRequest::Earnings
+                | StartRequest::OnboardingStatus
+                | StartRequest::PreviewOnboarding(false)
+        )
+    }
+}
+
+/*
+A request to an instance, with a oneshot to send the response back on
+Every request gets its own oneshot, so overlapping requests can never get each others response
+*/
+#[derive(Debug)]
+pub struct InstanceRequest {
+    pub start_request: StartRequest,
+    pub respond_to: Option<oneshot::Sender<RequestResponse>>,
+}
+
+impl InstanceRequest {
+    // A request nobody waits for the response of
+    pub fn new(start_request: StartRequest) -> Self {
+        Self {
+            start_request,
+            respond_to: None,
+        }
+    }
+
+    pub fn with_response(
+        start_request: StartRequest,
+    ) -> (Self, oneshot::Receiver<RequestResponse>) {
+        let (sender, receiver) = oneshot::channel();
+        (
+            Self {
+                start_request,
+                respond_to: Some(sender),
+            },
+            receiver,
+        )
+    }
 }
 
 /*
@@ -272,71 +410,162 @@ This starts the WebDriver session
 Loads the main logic, and retries if it fails
 */
 async fn user_instance(
-    mut receiver: Receiver<StartRequest>,
-    sender: Sender<RequestResponse>,
-    meta_sender: Arc<Sender<StartRequest>>,
+    request_queue: Arc<RequestQueue>,
     instance: UserInstanceData,
+    status: Arc<InstanceStatus>,
 ) {
-    let (_user, _properties) = set_data(&instance).await;
-    let tracer = tracing_appender::rolling::daily(create_path("logs"), "log");
-
-    let filter = EnvFilter::builder()
-        .with_default_directive(LevelFilter::WARN.into())
-        .from_env()
-        .unwrap();
-
-    let (non_blocking, _guard) = non_blocking::NonBlocking::new(tracer);
+    let (user, properties) = set_data(&instance).await;
+    let filter = user_log_filter(user.user_properties.log_level.as_deref())
+        .warn_owned("Parsing log level of user")
+        .unwrap_or_else(|_| user_log_filter(None).unwrap());
 
-    let subscriber = Arc::new(
-        tracing_subscriber::fmt()
-            .with_ansi(false)
-            .with_writer(non_blocking)
-            .with_env_filter(filter)
-            .finish(),
-    );
+    // Everything logged in the span of this instance ends up in this file, until the instance stops
+    let _instance_log = open_instance_log(&user.user_name, &create_path("logs"), filter);
     debug!("starting");
+    // The monitor is paused if the instance was stopped before
+    kuma::set_user_monitor_paused(&user.user_name, &properties.kuma_properties, false)
+        .await
+        .warn("Resuming kuma monitor");
 
     let mut system_request = false;
     let mut webcom_thread: Option<JoinHandle<FailureType>> = None;
+    ApplicationLogbook::import_file_logbook()
+        .await
+        .warn("Importing logbook file");
     let mut last_exit_code = ApplicationLogbook::load().state;
     let mut instance_active = true;
+    // Set once a shutdown is requested, a running scrape is aborted after this
+    let mut shutdown_deadline: Option<tokio::time::Instant> = None;
+    status.update(&webcom_thread, &last_exit_code);
 
     while instance_active {
         debug!("Waiting for notification");
-        let start_request = receiver.recv().await.expect("Notification channel closed");
+        let request = match shutdown_deadline {
+            Some(deadline) => timeout_at(deadline, request_queue.pop()).await.ok(),
+            None => Some(request_queue.pop().await),
+        };
+        let Some(InstanceRequest {
+            start_request,
+            respond_to,
+        }) = request
+        else {
+            warn!("Run did not finish within the shutdown grace period, aborting it");
+            if let Some(thread) = &webcom_thread {
+                thread.abort();
+            }
+            break;
+        };
 
         let (user, _properties) = set_data(&instance).await;
         info!("Recieved {start_request:?} request");
         let response = match start_request {
-            StartRequest::Logbook => Some(RequestResponse::Logbook(ApplicationLogbook::load())),
-            StartRequest::Name => Some(RequestResponse::Name(get_set_name(None))),
-            StartRequest::IsActive => Some(RequestResponse::Active(is_webcom_instance_active(
-                &webcom_thread,
-            ))),
-            StartRequest::Api => Some(RequestResponse::Active(
-                spawn_webcom_instance(
-                    &start_request,
-                    meta_sender.clone(),
+            // Normally answered without the loop, but they can still be queued
+            ref start_request if start_request.is_light() => {
+                light_request(start_request, &instance, &status).await
+            }
+            StartRequest::Shutdown => {
+                info!("Shutting down instance");
+                shutdown_deadline = Some(tokio::time::Instant::now() + SHUTDOWN_GRACE_PERIOD);
+                None
+            }
+            // The finished run is still handled while shutting down, but nothing new is started
+            ref start_request
+                if shutdown_deadline.is_some()
+                    && !matches!(
+                        start_request,
+                        StartRequest::ExecutionFinished(_)
+                            | StartRequest::DryRunFinished(_)
+                            | StartRequest::Delete
+                    ) =>
+            {
+                debug!("Shutting down, ignoring {start_request:?}");
+                None
+            }
+            StartRequest::Api | StartRequest::DryRun | StartRequest::VerifyLogin => {
+                Some(RequestResponse::Active(
+                    spawn_webcom_instance(
+                        &start_request,
+                        request_queue.clone(),
+                        &mut webcom_thread,
+                        &mut last_exit_code,
+                    )
+                    .await,
+                ))
+            }
+            StartRequest::Onboarding => Some(RequestResponse::Onboarding(
+                start_onboarding(
+                    request_queue.clone(),
                     &mut webcom_thread,
                     &mut last_exit_code,
                 )
-                .with_subscriber(subscriber.clone())
                 .await,
             )),
-            StartRequest::ExitCode => Some(RequestResponse::ExitCode(last_exit_code.clone())),
-            StartRequest::UserData => Some(RequestResponse::UserData(user.as_ref().clone())),
-            StartRequest::Welcome => {
-                Some(RequestResponse::GenResponse(email::send_welcome_mail(true).to_string()))
+            // The link in the welcome mail only works once the calendar exists
+            StartRequest::Welcome if !get_ical_path().exists() => {
+                Some(RequestResponse::GenResponse(
+                    "Calendar does not exist yet, the welcome mail is sent after the first run"
+                        .to_owned(),
+                ))
+            }
+            StartRequest::Welcome => Some(RequestResponse::GenResponse(
+                email::send_welcome_mail(&load_welcome_template().await).to_string(),
+            )),
+            StartRequest::PreviewOnboarding(send) => Some(preview_onboarding(send)),
+            StartRequest::Retry if is_webcom_instance_active(&webcom_thread) => {
+                Some(RequestResponse::Active(false))
             }
-            StartRequest::Calendar => return_calendar_response(),
+            StartRequest::Retry => {
+                IncorrectCredentialsCount::reset_retry_count()
+                    .warn("Resetting sign in failure counter");
+                Some(RequestResponse::Active(
+                    spawn_webcom_instance(
+                        &StartRequest::Force,
+                        request_queue.clone(),
+                        &mut webcom_thread,
+                        &mut last_exit_code,
+                    )
+                    .await,
+                ))
+            }
+            // A running instance would overwrite the restored calendar right away
+            StartRequest::RollbackCalendar if is_webcom_instance_active(&webcom_thread) => Some(
+                RequestResponse::GenResponse("Instance is running, try again later".to_owned()),
+            ),
+            StartRequest::RollbackCalendar => Some(RequestResponse::GenResponse(
+                match rollback_calendar(&get_ical_path(), &create_path("")) {
+                    Ok(backup) => format!("Restored calendar backup {backup}"),
+                    Err(err) => err.to_string(),
+                },
+            )),
             StartRequest::ExecutionFinished(ref exit_code) => {
+                notify_new_user(exit_code, &user).await;
                 update_instance_timestamps(exit_code, instance.user_data.clone(), system_request)
                     .await
                     .warn("Updating instance timestamps");
                 system_request = false;
                 check_instance_standing().await;
+                check_stale_calendar(exit_code);
+                check_donation_reminder(exit_code, instance.user_data.clone())
+                    .await
+                    .warn("Checking donation reminder");
+                check_weekly_summary(exit_code, instance.user_data.clone())
+                    .await
+                    .warn("Checking weekly summary");
+                flush_error_digest().warn("Sending buffered errors");
+                rotate_logs().warn("Rotating log files");
+                enforce_disk_quota().warn("Enforcing disk quota");
+                if exit_code != &last_exit_code {
+                    ExitCodeTransition::record(&last_exit_code, exit_code)
+                        .await
+                        .warn("Recording exit code transition");
+                }
+                let response = log_exit_code(exit_code, &last_exit_code);
                 last_exit_code = exit_code.clone();
-                log_exit_code(exit_code, &last_exit_code)
+                response
+            }
+            StartRequest::DryRunFinished(ref exit_code) => {
+                info!("Dry run finished with {exit_code:?}");
+                None
             }
             StartRequest::Delete => {
                 instance_active = false;
@@ -344,7 +573,7 @@ async fn user_instance(
                     thread.abort();
                     true
                 });
-                _ = spawn(delete_account(user.id, email::DeletedReason::Manual))
+                _ = spawn(delete_account(user.id, email::DeletedReason::Manual).in_current_span())
                     .await
                     .and_then(|result| {
                         result.warn("Account deletion");
@@ -352,35 +581,173 @@ async fn user_instance(
                     });
                 Some(RequestResponse::GenResponse("OK".to_owned()))
             }
-            StartRequest::Standing => {
-                Some(RequestResponse::InstanceStanding(StandingInformation::get()))
+            StartRequest::MailBounced(ref report) => Some(RequestResponse::GenResponse(
+                match pause_user_mail(report, instance.user_data.clone()).await {
+                    Ok(()) => "Mails paused".to_owned(),
+                    Err(err) => err.to_string(),
+                },
+            )),
+            StartRequest::SetLogLevel(ref log_level) => Some(RequestResponse::GenResponse(
+                match set_log_level(log_level.clone(), instance.user_data.clone()).await {
+                    Ok(()) => "OK".to_owned(),
+                    Err(err) => err.to_string(),
+                },
+            )),
+            StartRequest::ResumeMail => {
+                Some(RequestResponse::GenResponse(
+                    match resume_user_mail(instance.user_data.clone()).await {
+                        Ok(()) => "Mails resumed".to_owned(),
+                        Err(err) => err.to_string(),
+                    },
+                ))
             }
             _ => {
                 system_request = true;
                 spawn_webcom_instance(
                     &start_request,
-                    meta_sender.clone(),
+                    request_queue.clone(),
                     &mut webcom_thread,
                     &mut last_exit_code,
                 )
-                .with_subscriber(subscriber.clone())
                 .await;
                 None
             }
         };
-        if let Some(response) = response {
-            sender.try_send(response).info("Send response");
+        status.update(&webcom_thread, &last_exit_code);
+        // If there is no response, dropping the oneshot lets the requester know
+        if let Some(respond_to) = respond_to
+            && let Some(response) = response
+            && respond_to.send(response).is_err()
+        {
+            info!("Requester stopped waiting for the response");
         }
 
         if start_request == StartRequest::Single {
             break;
         }
+        // Stop once the run finished, the exit code of the run is handled first
+        if shutdown_deadline.is_some()
+            && (matches!(
+                start_request,
+                StartRequest::ExecutionFinished(_) | StartRequest::DryRunFinished(_)
+            ) || !is_webcom_instance_active(&webcom_thread))
+        {
+            break;
+        }
+    }
+    if shutdown_deadline.is_some() {
+        cleanup_instance().await;
+        info!("Instance stopped");
+        return;
     }
     warn!("Killing instance, bye👋");
     sleep(Duration::from_hours(12)).await;
     warn!("Manually killing instance after waiting");
 }
 
+/*
+Answers the read only requests from the shared state of the instance, without waiting for the instance loop
+A long run, or handling a finished one, would otherwise block even a request for the name
+*/
+pub async fn light_request(
+    start_request: &StartRequest,
+    instance: &UserInstanceData,
+    status: &InstanceStatus,
+) -> Option<RequestResponse> {
+    let user = Arc::new(instance.user_data.read().await.clone());
+    let properties = Arc::new(instance.general_settings.read().await.clone());
+    USER_PROPERTIES
+        .scope(
+            RefCell::new(Some(user.clone())),
+            GENERAL_PROPERTIES.scope(
+                RefCell::new(Some(properties.clone())),
+                NAME.scope(RefCell::new(None), async move {
+                    match start_request {
+                        StartRequest::Logbook => {
+                            Some(RequestResponse::Logbook(ApplicationLogbook::load().into()))
+                        }
+                        StartRequest::Name => Some(RequestResponse::Name(get_set_name(None))),
+                        StartRequest::IsActive => Some(RequestResponse::Active(status.is_active())),
+                        StartRequest::ExitCode => {
+                            Some(RequestResponse::ExitCode(status.last_exit_code().status()))
+                        }
+                        StartRequest::UserData => Some(RequestResponse::UserData(
+                            UserProfile::new(&user, load_standing(user.id).await),
+                        )),
+                        StartRequest::PreviewOnboarding(false) => Some(preview_onboarding(false)),
+                        StartRequest::OnboardingStatus => Some(RequestResponse::Onboarding(
+                            onboarding_status(status.is_active(), &status.last_exit_code()),
+                        )),
+                        StartRequest::Calendar => return_calendar_response(),
+                        StartRequest::Standing => Some(RequestResponse::InstanceStanding(
+                            load_standing(user.id).await,
+                        )),
+                        StartRequest::Earnings => return_earnings_response(&user, &properties),
+                        _ => None,
+                    }
+                }),
+            ),
+        )
+        .await
+}
+
+fn preview_onboarding(send: bool) -> RequestResponse {
+    match email::preview_onboarding(send) {
+        Ok(preview) => RequestResponse::OnboardingPreview(preview),
+        Err(err) => RequestResponse::GenResponse(err.to_string()),
+    }
+}
+
+async fn load_standing(user_id: i32) -> StandingInformation {
+    let mut standing = StandingInformation::get();
+    standing.last_calendar_fetch = last_calendar_fetch(&get_database_connection().await, user_id)
+        .await
+        .warn_owned("Loading last calendar fetch")
+        .ok()
+        .flatten();
+    standing
+}
+
+/*
+Start onboarding a new user, if there is no calendar yet a run is started first
+The run which creates the calendar sends the welcome mail, so the link in it works right away
+*/
+async fn start_onboarding(
+    request_queue: Arc<RequestQueue>,
+    thread_store: &mut Option<JoinHandle<FailureType>>,
+    last_exit_code: &mut FailureType,
+) -> OnboardingStatus {
+    if get_ical_path().exists() {
+        if !email::welcome_mail_enabled(false) {
+            return OnboardingStatus::Ready;
+        }
+        return match email::send_welcome_mail(&load_welcome_template().await) {
+            Ok(()) => OnboardingStatus::Ready,
+            Err(err) => OnboardingStatus::Failed(FailureType::Other(err.to_string()).status()),
+        };
+    }
+    spawn_webcom_instance(
+        &StartRequest::Onboarding,
+        request_queue,
+        thread_store,
+        last_exit_code,
+    )
+    .await;
+    OnboardingStatus::Scraping
+}
+
+fn onboarding_status(webcom_active: bool, last_exit_code: &FailureType) -> OnboardingStatus {
+    if webcom_active {
+        OnboardingStatus::Scraping
+    } else if get_ical_path().exists() {
+        OnboardingStatus::Ready
+    } else if last_exit_code != &FailureType::OK {
+        OnboardingStatus::Failed(last_exit_code.status())
+    } else {
+        OnboardingStatus::Pending
+    }
+}
+
 fn log_exit_code(exit_code: &FailureType, last_exit_code: &FailureType) -> Option<RequestResponse> {
     let failed_signin_type = &FailureType::SignInFailed(SignInFailure::IncorrectCredentials);
     if exit_code == failed_signin_type {
@@ -393,6 +760,20 @@ fn log_exit_code(exit_code: &FailureType, last_exit_code: &FailureType) -> Optio
     None
 }
 
+fn return_earnings_response(
+    user: &UserData,
+    properties: &GeneralProperties,
+) -> Option<RequestResponse> {
+    let rules = PayRules::new(user, properties);
+    match load_known_shifts() {
+        Ok(shifts) => Some(RequestResponse::Earnings(monthly_earnings(
+            shifts.iter(),
+            &rules,
+        ))),
+        Err(err) => Some(RequestResponse::GenResponse(err.to_string())),
+    }
+}
+
 fn return_calendar_response() -> Option<RequestResponse> {
     match create_calendar_link() {
         Ok(link) => Some(RequestResponse::GenResponse(link.to_string())),
@@ -400,78 +781,79 @@ fn return_calendar_response() -> Option<RequestResponse> {
     }
 }
 
-pub fn set_strict_file_permissions(path: &PathBuf) -> GenResult<()> {
-    let file = std::fs::File::open(&path)?;
-    let metadata = file.metadata()?;
-    let mut file_mode = metadata.permissions();
-    file_mode.set_mode(0o100600);
-    set_permissions(&path, file_mode)?;
-    Ok(())
-}
-
-#[allow(dead_code)]
-fn check_env_permissions() -> GenResult<()> {
-    let uid = std::fs::metadata("/proc/self")
-        .map(|m| m.uid())
-        .warn_owned("Failed to get uid")
-        .ok();
-    let permissions_target = 0o100600;
-    let metadata = std::fs::File::open("./.env")?.metadata()?;
-    let file_mode = metadata.permissions().mode();
-    let file_owner = metadata.uid();
-    if file_mode == permissions_target && Some(file_owner) == uid {
-        Ok(())
-    } else {
-        Err(format!(
-            "INCORRECT PERMISSIONS FOR ENV. Should be {permissions_target:o}, is {file_mode:o}. File owner should be {uid:?}, is {file_owner}"
-        )
-        .into())
-    }
-}
-
 async fn get_database_connection() -> DatabaseConnection {
     Database::connect(&var("DATABASE_URL").expect("Failed to get database URL"))
         .await
         .expect("Could not connect to database")
 }
 
-#[tokio::main]
-async fn main() -> GenResult<()> {
+// The env and config file are loaded before the runtime starts, setting env variables is only safe without other threads
+fn main() -> GenResult<()> {
     let filter = EnvFilter::builder()
         .with_default_directive(LevelFilter::INFO.into())
         .from_env()
         .unwrap();
 
+    // Logs of instances go to the log file of the user instead
     let stdout_layer = fmt::layer()
         .with_writer(std::io::stdout)
-        .with_filter(filter);
+        .with_filter(filter.and(dynamic_filter_fn(|_metadata, context| {
+            !in_instance_span(context)
+        })));
 
-    let global_subscriber = Registry::default().with(stdout_layer);
+    let global_subscriber = Registry::default()
+        .with(stdout_layer)
+        .with(InstanceLogLayer.with_filter(filter_fn(instance_log_enabled)));
     tracing::subscriber::set_global_default(global_subscriber)
         .expect("Failed to set global subscriber");
     #[cfg(not(debug_assertions))]
     {
-        check_env_permissions().unwrap();
+        execution::permissions::check_env_permissions().unwrap();
     }
 
     dotenv_override().expect("Failed to read ENV file");
-    info!("Starting {APPLICATION_NAME}");
+    load_config_file().expect("Failed to read config file");
     CryptoProvider::install_default(default_provider()).unwrap();
 
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(run())
+}
+
+async fn run() -> GenResult<()> {
+    let cli = Cli::parse();
+    if let Some(command) = cli.command {
+        return run_command(command, cli.insecure).await;
+    }
+
+    info!("Starting {APPLICATION_NAME}");
+    validate_startup().await?;
+
     let db = get_database_connection().await;
 
     // Apply all pending migrations
     Migrator::up(&db, None)
         .await
         .expect("Failed to apply Database changes");
+    audit_user_directories(&db)
+        .await
+        .warn("Auditing user directory permissions");
+    ApplicationStatus::load_last_webcom_outage(&db)
+        .await
+        .warn("Loading last webcom outage");
 
-    let (watchdog_tx, mut watchdog_rx) = channel(1);
+    let (watchdog_tx, mut watchdog_rx) = channel(WATCHDOG_QUEUE_SIZE);
     _ = watchdog_tx.try_send(WatchdogRequest::FirstTime);
 
     let instances: Arc<RwLock<InstanceMap>> = Arc::new(RwLock::new(HashMap::new()));
 
-    tokio::spawn(execution_timer(instances.clone()));
-    tokio::spawn(api(instances.clone(), watchdog_tx));
+    tokio::spawn(execution_timer(instances.clone(), SystemClock));
+    tokio::spawn(kuma::kuma_reconciliation(instances.clone()));
+    tokio::spawn(kuma::kuma_pending_queue(instances.clone()));
+    tokio::spawn(listen_for_changes(watchdog_tx.clone()));
+    api(instances.clone(), watchdog_tx).await?;
+    tokio::spawn(handle_sighup());
 
     watchdog(instances.clone(), &db, &mut watchdog_rx)
         .await
@@ -0,0 +1,23 @@
+use crate::config::var;
+use serde_json::json;
+
+use crate::GenResult;
+
+// Posts a message to ADMIN_WEBHOOK_URL, if it is set
+// The message is put in both content and text, so it works with Discord as well as Slack style webhooks
+pub async fn send_admin_webhook(message: &str) -> GenResult<()> {
+    let Ok(webhook_url) = var("ADMIN_WEBHOOK_URL") else {
+        return Ok(());
+    };
+    if webhook_url.is_empty() {
+        return Ok(());
+    }
+    reqwest::Client::new()
+        .post(webhook_url)
+        .header("Content-Type", "application/json")
+        .body(json!({ "content": message, "text": message }).to_string())
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,182 @@
+use std::{cell::RefCell, sync::Arc};
+
+use entity::user_data;
+use sea_orm::{ActiveValue::Set, EntityTrait, IntoActiveModel, QuerySelect};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use tracing::*;
+
+use crate::{
+    GENERAL_PROPERTIES, GenResult, NAME, USER_PROPERTIES,
+    database::{
+        secret::Secret,
+        variables::{GeneralProperties, ThreadShare, UserData, default_properties_id},
+    },
+    errors::{OptionResult, ResultLog},
+    get_database_connection,
+    webcom::{admin_webhook::send_admin_webhook, email::send_mail_paused_mail},
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BounceKind {
+    HardBounce,
+    SoftBounce,
+    Complaint,
+}
+
+/*
+A bounce or spam complaint, posted by the mail provider to the bounce webhook
+Most providers can be configured to post this format, or a small script can translate their format
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BounceReport {
+    pub email: String,
+    pub kind: BounceKind,
+    pub reason: Option<String>,
+}
+
+impl BounceReport {
+    // A soft bounce (full mailbox, greylisting) usually solves itself, so mails keep being sent
+    pub fn pauses_mail(&self) -> bool {
+        self.kind != BounceKind::SoftBounce
+    }
+
+    fn description(&self) -> String {
+        let kind = match self.kind {
+            BounceKind::HardBounce => "Hard bounce",
+            BounceKind::SoftBounce => "Soft bounce",
+            BounceKind::Complaint => "Spam melding",
+        };
+        match &self.reason {
+            Some(reason) => format!("{kind}: {reason}"),
+            None => kind.to_owned(),
+        }
+    }
+}
+
+/*
+Pauses all mails to the user, and lets the admin know the address needs to be looked at
+Mails to the support address are still sent, only the address of the user is paused
+*/
+pub async fn pause_user_mail(
+    report: &BounceReport,
+    user_data: ThreadShare<UserData>,
+) -> GenResult<()> {
+    let reason = report.description();
+    let now = chrono::offset::Utc::now().naive_utc();
+    let user_id = user_data.read().await.id;
+    set_mail_paused(user_id, Some(now), Some(reason.clone())).await?;
+    let user_name = {
+        let mut user = user_data.write().await;
+        user.mail_paused_date = Some(now);
+        user.mail_paused_reason = Some(reason.clone());
+        user.user_name.clone()
+    };
+    warn!("Mails to {user_name} are paused. {reason}");
+    // Sending over SMTP blocks, the other tasks on this thread are moved away in the meantime
+    tokio::task::block_in_place(|| send_mail_paused_mail(&reason)).warn("Sending mail paused mail");
+    send_admin_webhook(&format!("Mails naar {user_name} zijn gepauzeerd. {reason}"))
+        .await
+        .warn("Sending mail paused webhook");
+    Ok(())
+}
+
+// The id of the user with this address, also users without a running instance like ones that are not activated yet
+pub async fn find_user_by_email(email: &str) -> GenResult<Option<i32>> {
+    let db = get_database_connection().await;
+    let users: Vec<(i32, Secret)> = user_data::Entity::find()
+        .select_only()
+        .column(user_data::Column::UserDataId)
+        .column(user_data::Column::Email)
+        .into_tuple()
+        .all(&db)
+        .await?;
+    Ok(users
+        .into_iter()
+        .find(|(_, address)| address.0.expose_secret().eq_ignore_ascii_case(email))
+        .map(|(id, _)| id))
+}
+
+// Pauses the mails of a user without a running instance, with the user and their properties loaded for the mail to the admin
+pub async fn pause_stored_user_mail(report: &BounceReport, user_id: i32) -> GenResult<()> {
+    let db = get_database_connection().await;
+    let user = UserData::get_id(&db, user_id)
+        .await?
+        .result_reason("UserData not found")?;
+    let properties_id = user
+        .custom_general_properties
+        .unwrap_or(default_properties_id());
+    let properties = GeneralProperties::get(&db, properties_id)
+        .await?
+        .result_reason("Properties not found")?;
+    let user_data = Arc::new(RwLock::new(user.clone()));
+    USER_PROPERTIES
+        .scope(
+            RefCell::new(Some(Arc::new(user))),
+            GENERAL_PROPERTIES.scope(
+                RefCell::new(Some(Arc::new(properties))),
+                NAME.scope(RefCell::new(None), pause_user_mail(report, user_data)),
+            ),
+        )
+        .await
+}
+
+// Called once the address of the user is fixed
+pub async fn resume_user_mail(user_data: ThreadShare<UserData>) -> GenResult<()> {
+    let user_id = user_data.read().await.id;
+    set_mail_paused(user_id, None, None).await?;
+    let mut user = user_data.write().await;
+    user.mail_paused_date = None;
+    user.mail_paused_reason = None;
+    info!("Mails to {} are resumed", user.user_name);
+    Ok(())
+}
+
+async fn set_mail_paused(
+    user_id: i32,
+    date: Option<chrono::NaiveDateTime>,
+    reason: Option<String>,
+) -> GenResult<()> {
+    let db = get_database_connection().await;
+    let mut active_user = user_data::Entity::find_by_id(user_id)
+        .one(&db)
+        .await?
+        .result_reason("UserData not found")?
+        .into_active_model();
+    active_user.mail_paused_date = Set(date);
+    active_user.mail_paused_reason = Set(reason);
+    user_data::Entity::update(active_user)
+        .validate()?
+        .exec(&db)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_soft_bounces_keep_mail_going() {
+        let report = |kind| BounceReport {
+            email: "user@example.com".to_owned(),
+            kind,
+            reason: None,
+        };
+        assert!(report(BounceKind::HardBounce).pauses_mail());
+        assert!(report(BounceKind::Complaint).pauses_mail());
+        assert!(!report(BounceKind::SoftBounce).pauses_mail());
+    }
+
+    #[test]
+    fn bounce_report_is_parsed() {
+        let report: BounceReport = serde_json::from_str(
+            r#"{"email": "user@example.com", "kind": "hard_bounce", "reason": "550 User unknown"}"#,
+        )
+        .unwrap();
+        assert_eq!(report.kind, BounceKind::HardBounce);
+        assert_eq!(report.description(), "Hard bounce: 550 User unknown");
+    }
+}
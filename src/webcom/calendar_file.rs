@@ -0,0 +1,196 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::config::var;
+use icalendar::parser::{read_calendar, unfold};
+use tracing::*;
+
+use crate::{
+    GenResult,
+    errors::{OptionResult, ResultLog},
+};
+
+const DEFAULT_BACKUP_COUNT: usize = 3;
+// Kept in the directory of the user, the calendar directory is served to everyone
+const BACKUP_DIR: &str = "calendar_backups";
+
+fn backup_count() -> usize {
+    var("CALENDAR_BACKUPS")
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(DEFAULT_BACKUP_COUNT)
+}
+
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name: OsString = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// The newest backup is 1, so calendar_backups/calendar.1.bak in the directory of the user
+fn backup_path(user_dir: &Path, number: usize) -> PathBuf {
+    user_dir
+        .join(BACKUP_DIR)
+        .join(format!("calendar.{number}.bak"))
+}
+
+// Older versions kept the backups next to the calendar, where they could be downloaded
+pub fn remove_legacy_backups(path: &Path) {
+    for number in 1..=backup_count().max(DEFAULT_BACKUP_COUNT) {
+        let backup = with_suffix(path, &format!(".{number}.bak"));
+        if backup.exists() {
+            fs::remove_file(&backup).warn("Removing legacy calendar backup");
+        }
+    }
+}
+
+fn write_synced(path: &Path, contents: &[u8]) -> GenResult<()> {
+    let mut file = File::create(path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/*
+Writes the file in the directory of the user first and then renames it, a rename is atomic
+So an interrupted write never leaves a truncated calendar which clients would cache
+A rename only works within one filesystem, if the calendar directory is on another one the temp file is written next to the calendar
+*/
+pub fn write_atomic(path: &Path, user_dir: &Path, contents: &[u8]) -> GenResult<()> {
+    let file_name = path
+        .file_name()
+        .result_reason("Calendar path has no file name")?;
+    let temp_path = with_suffix(&user_dir.join(file_name), ".tmp");
+    write_synced(&temp_path, contents)?;
+    match fs::rename(&temp_path, path) {
+        Err(err) if err.kind() == ErrorKind::CrossesDevices => {
+            fs::remove_file(&temp_path).warn("Removing calendar temp file");
+            warn!("Calendar directory is on another filesystem than the user directory");
+            let temp_path = with_suffix(path, ".tmp");
+            write_synced(&temp_path, contents)?;
+            fs::rename(&temp_path, path)?;
+        }
+        result => result?,
+    }
+    Ok(())
+}
+
+// Keeps the previous versions of the calendar as .bak files before writing the new one
+pub fn write_calendar(path: &Path, user_dir: &Path, contents: &[u8]) -> GenResult<()> {
+    rotate_backups(path, user_dir, backup_count()).warn("Rotating calendar backups");
+    remove_legacy_backups(path);
+    write_atomic(path, user_dir, contents)
+}
+
+fn rotate_backups(path: &Path, user_dir: &Path, count: usize) -> GenResult<()> {
+    if count == 0 || !path.exists() {
+        return Ok(());
+    }
+    fs::create_dir_all(user_dir.join(BACKUP_DIR))?;
+    for number in (1..count).rev() {
+        let backup = backup_path(user_dir, number);
+        if backup.exists() {
+            fs::rename(&backup, backup_path(user_dir, number + 1))?;
+        }
+    }
+    fs::copy(path, backup_path(user_dir, 1))?;
+    Ok(())
+}
+
+fn is_valid_calendar(path: &Path) -> bool {
+    fs::read_to_string(path).is_ok_and(|text| {
+        text.trim_end().ends_with("END:VCALENDAR") && read_calendar(&unfold(&text)).is_ok()
+    })
+}
+
+/*
+Restores the newest backup which is a complete calendar
+The restored backup and every newer one are removed, so rolling back again goes further back
+Returns which backup was restored
+*/
+pub fn rollback_calendar(path: &Path, user_dir: &Path) -> GenResult<usize> {
+    let count = backup_count();
+    let restored = (1..=count)
+        .find(|number| is_valid_calendar(&backup_path(user_dir, *number)))
+        .result_reason("No valid calendar backup found")?;
+    info!("Rolling back calendar to backup {restored}");
+    write_atomic(path, user_dir, &fs::read(backup_path(user_dir, restored))?)?;
+    for number in 1..=restored {
+        fs::remove_file(backup_path(user_dir, number)).warn("Removing calendar backup");
+    }
+    for number in restored + 1..=count {
+        let backup = backup_path(user_dir, number);
+        if backup.exists() {
+            fs::rename(&backup, backup_path(user_dir, number - restored))?;
+        }
+    }
+    Ok(restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calendar(summary: &str) -> String {
+        format!(
+            "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:test\r\nX-WR-CALNAME:{summary}\r\nEND:VCALENDAR\r\n"
+        )
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn previous_versions_are_kept() {
+        let dir = test_dir("mijn_bussie_calendar_backup_test");
+        let user_dir = dir.join("user");
+        let calendar_dir = dir.join("calendars");
+        fs::create_dir_all(&user_dir).unwrap();
+        fs::create_dir_all(&calendar_dir).unwrap();
+        let path = calendar_dir.join("calendar.ics");
+        for version in ["a", "b", "c", "d", "e"] {
+            rotate_backups(&path, &user_dir, 3).unwrap();
+            write_atomic(&path, &user_dir, calendar(version).as_bytes()).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), calendar("e"));
+        assert_eq!(
+            fs::read_to_string(backup_path(&user_dir, 1)).unwrap(),
+            calendar("d")
+        );
+        assert_eq!(
+            fs::read_to_string(backup_path(&user_dir, 3)).unwrap(),
+            calendar("b")
+        );
+        assert!(!backup_path(&user_dir, 4).exists());
+        // Only the calendar itself is in the served directory
+        assert_eq!(fs::read_dir(&calendar_dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rollback_skips_truncated_backups() {
+        let dir = test_dir("mijn_bussie_calendar_rollback_test");
+        let path = dir.join("calendar.ics");
+        fs::create_dir_all(dir.join(BACKUP_DIR)).unwrap();
+        fs::write(&path, "BEGIN:VCALENDAR\r\nVERS").unwrap();
+        fs::write(backup_path(&dir, 1), "BEGIN:VCAL").unwrap();
+        fs::write(backup_path(&dir, 2), calendar("good")).unwrap();
+        fs::write(backup_path(&dir, 3), calendar("older")).unwrap();
+        assert_eq!(rollback_calendar(&path, &dir).unwrap(), 2);
+        assert_eq!(fs::read_to_string(&path).unwrap(), calendar("good"));
+        assert_eq!(
+            fs::read_to_string(backup_path(&dir, 1)).unwrap(),
+            calendar("older")
+        );
+        assert!(!backup_path(&dir, 2).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use chrono::Duration;
+use chrono::{Duration, NaiveDateTime};
 use entity::{user_data, user_properties};
 use sea_orm::{ActiveValue::Set, EntityTrait, IntoActiveModel};
 use serde::Serialize;
@@ -8,14 +8,27 @@ use tokio::sync::RwLock;
 use tracing::*;
 
 const AUTO_DELETE_DURATION: Duration = Duration::days(31);
-const FRESH_DELETE_DURATION: Duration = Duration::days(1);
+pub const FRESH_DELETE_DURATION: Duration = Duration::days(1);
+// How long before the deletion the user is warned
+const WARNING_DURATION: Duration = Duration::days(7);
 
 use crate::{
-    GenResult, create_path,
-    database::variables::UserData,
-    errors::{FailureType, OptionResult, ResultLog, SignInFailure},
+    GenResult, create_path, create_path_local,
+    database::{
+        calendar_fetch::CalendarFetch,
+        invite_code::{DELETED_USER_INVITE_DURATION, create_invite_code},
+        variables::{GeneralProperties, UserData},
+    },
+    errors::{
+        FailureType, IncorrectCredentialsCount, OptionResult, ParkState, ResultLog, SignInFailure,
+    },
+    execution::permissions::write_private_file,
     get_data, get_database_connection,
+    health::last_calendar_write,
+    kuma::remove_user_monitor,
+    webcom::calendar_file::remove_legacy_backups,
     webcom::email::{DeletedReason, send_account_deleted_mail, send_deletion_warning_mail},
+    webcom::ical::get_ical_path,
 };
 
 // The current system is really messy if you want to update user values from the database,
@@ -39,6 +52,12 @@ pub async fn update_instance_timestamps(
             active_user.last_succesfull_sign_in_date = Set(Some(timestamp.clone()));
             instance_data.last_succesfull_sign_in_date = Some(timestamp.clone());
         }
+        // The calendar file itself is the truth, a dry run or failed run does not write it
+        if let Some(write_time) = last_calendar_write() {
+            let write_date = chrono::DateTime::<chrono::Utc>::from(write_time).naive_utc();
+            active_user.last_calendar_write_date = Set(Some(write_date));
+            instance_data.last_calendar_write_date = Some(write_date);
+        }
         if execution_by_system {
             active_user.last_system_execution_date = Set(Some(timestamp.clone()));
             instance_data.last_system_execution_date = Some(timestamp.clone());
@@ -51,8 +70,9 @@ pub async fn update_instance_timestamps(
     Ok(())
 }
 
-#[derive(Debug, Serialize, Clone)]
-enum InstanceStanding {
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum InstanceStanding {
     Safe,
     Fresh,
     InDanger,
@@ -63,41 +83,123 @@ enum InstanceStanding {
 
 #[derive(Debug, Serialize, Clone)]
 pub struct StandingInformation {
-    standing: InstanceStanding,
-    failed_days: Option<i64>,
-    deletion_threshold: i64,
-    warning_sent: bool,
+    pub standing: InstanceStanding,
+    pub failed_days: Option<i64>,
+    // None if the account is not going to be deleted
+    pub days_until_deletion: Option<i64>,
+    pub last_successful_sign_in: Option<NaiveDateTime>,
+    pub deletion_threshold: i64,
+    pub warning_sent: bool,
+    // Set if runs are skipped because of failed sign ins
+    pub park: Option<ParkState>,
+    // Only known if the calendar is served by the API
+    pub last_calendar_fetch: Option<CalendarFetch>,
 }
 
 impl StandingInformation {
     pub fn get() -> Self {
         let (user, _properties) = get_data();
         let current_time = chrono::offset::Utc::now().naive_utc();
-        let standing = InstanceStanding::get_standing();
+        let standing = InstanceStanding::get_standing_at(&user, current_time);
+        let days_until_deletion = days_until_deletion(&user, standing, current_time);
         let failed_days = user
             .last_succesfull_sign_in_date
             .clone()
             .and_then(|date| Some(current_time.signed_duration_since(date).num_days()));
         let deletion_threshold = AUTO_DELETE_DURATION.num_days();
         let warning_sent = create_path("warning_sent").exists();
+        let park = IncorrectCredentialsCount::load().park_state();
         Self {
             standing,
             failed_days,
+            days_until_deletion,
+            last_successful_sign_in: user.last_succesfull_sign_in_date,
             deletion_threshold,
             warning_sent,
+            park,
+            last_calendar_fetch: None,
+        }
+    }
+}
+
+// Fresh accounts are deleted a day after creation, others a month after the last successful sign in
+fn deletion_date(user: &UserData, standing: InstanceStanding) -> Option<NaiveDateTime> {
+    match standing {
+        InstanceStanding::Safe => None,
+        InstanceStanding::Fresh | InstanceStanding::MustDeleteFresh => {
+            Some(user.creation_date + FRESH_DELETE_DURATION)
+        }
+        _ => Some(user.last_succesfull_sign_in_date? + AUTO_DELETE_DURATION),
+    }
+}
+
+// Fresh accounts do not get a warning, they are deleted too quickly for that
+fn warning_date(user: &UserData, standing: InstanceStanding) -> Option<NaiveDateTime> {
+    match standing {
+        InstanceStanding::InDanger
+        | InstanceStanding::AlmostDeleted
+        | InstanceStanding::MustDelete => {
+            Some(user.last_succesfull_sign_in_date? + AUTO_DELETE_DURATION - WARNING_DURATION)
         }
+        _ => None,
+    }
+}
+
+fn days_until_deletion(
+    user: &UserData,
+    standing: InstanceStanding,
+    current_time: NaiveDateTime,
+) -> Option<i64> {
+    Some(
+        deletion_date(user, standing)?
+            .signed_duration_since(current_time)
+            .num_days()
+            .max(0),
+    )
+}
+
+/*
+What would happen to an account under the current rules, without actually doing it
+The warning and deletion happen after the first run past these dates
+*/
+#[derive(Debug, Serialize, Clone)]
+pub struct DeletionPreview {
+    pub user_name: String,
+    pub standing: InstanceStanding,
+    pub warning_date: Option<NaiveDateTime>,
+    pub deletion_date: Option<NaiveDateTime>,
+    pub warning_sent: bool,
+}
+
+impl DeletionPreview {
+    // None if the account is not going to be deleted
+    pub fn get_local(user: &UserData, properties: &GeneralProperties) -> Option<Self> {
+        let current_time = chrono::offset::Utc::now().naive_utc();
+        let standing = InstanceStanding::get_standing_at(user, current_time);
+        if standing == InstanceStanding::Safe {
+            return None;
+        }
+        Some(Self {
+            user_name: user.user_name.clone(),
+            standing,
+            warning_date: warning_date(user, standing),
+            deletion_date: deletion_date(user, standing),
+            warning_sent: create_path_local(user, properties, "warning_sent").exists(),
+        })
     }
 }
 
 impl InstanceStanding {
     fn get_standing() -> InstanceStanding {
         let (user, _properties) = get_data();
+        Self::get_standing_at(&user, chrono::offset::Utc::now().naive_utc())
+    }
 
+    fn get_standing_at(user: &UserData, current_time: NaiveDateTime) -> InstanceStanding {
         if !user.user_properties.auto_delete_account {
             return InstanceStanding::Safe;
         }
 
-        let current_time = chrono::offset::Utc::now().naive_utc();
         match user.last_succesfull_sign_in_date.clone() {
             Some(sign_in_date)
                 if sign_in_date.eq(&user.last_execution_date.unwrap_or_default()) =>
@@ -111,7 +213,7 @@ impl InstanceStanding {
             }
             Some(sign_in_date)
                 if current_time.signed_duration_since(sign_in_date)
-                    >= AUTO_DELETE_DURATION - Duration::days(7) =>
+                    >= AUTO_DELETE_DURATION - WARNING_DURATION =>
             {
                 Self::AlmostDeleted
             }
@@ -137,7 +239,7 @@ pub async fn check_instance_standing() -> bool {
         }
         InstanceStanding::AlmostDeleted => {
             send_deletion_warning_mail().warn("sending deletion warning");
-            std::fs::write(warning_sent_path, []).warn("writing deletion sent warning");
+            write_private_file(warning_sent_path, []).warn("writing deletion sent warning");
         }
         InstanceStanding::MustDelete => {
             delete_account(user.id, DeletedReason::OldAge)
@@ -161,9 +263,15 @@ pub async fn delete_account(user_id: i32, reason: DeletedReason) -> GenResult<()
     let path = create_path("");
     warn!("Deleting user");
     info!("{path:?}");
+    // The calendar backups are kept in the user dir, only backups of older versions are next to the calendar
     std::fs::remove_dir_all(path).warn("Deleting user dir");
+    remove_legacy_backups(&get_ical_path());
     let user_data = UserData::get_id(&db, user_id).await?.result()?;
     let properties_id = user_data.user_properties.user_properties_id;
+    let (_user, properties) = get_data();
+    remove_user_monitor(&user_data.user_name, &properties.kuma_properties)
+        .await
+        .warn("Removing kuma monitor");
     user_data::Entity::delete_by_id(user_id)
         .exec(&db)
         .await
@@ -172,6 +280,80 @@ pub async fn delete_account(user_id: i32, reason: DeletedReason) -> GenResult<()
         .exec(&db)
         .await
         .warn("Removing user properties");
-    send_account_deleted_mail(reason).warn("Sending deletion mail");
+    let invite = create_invite_code(
+        &db,
+        properties.general_properties_id,
+        DELETED_USER_INVITE_DURATION,
+    )
+    .await
+    .warn_owned("Creating invite for deleted user")
+    .ok();
+    send_account_deleted_mail(reason, invite.as_ref().map(|invite| invite.code.as_str()))
+        .warn("Sending deletion mail");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_signed_in_days_ago(days: i64, now: NaiveDateTime) -> UserData {
+        let mut user = UserData::example();
+        user.creation_date = now - Duration::days(100);
+        user.last_succesfull_sign_in_date = Some(now - Duration::days(days));
+        user.last_execution_date = Some(now);
+        user
+    }
+
+    #[test]
+    fn standing_follows_failed_days() {
+        let now = NaiveDateTime::default() + Duration::days(365);
+        let user = user_signed_in_days_ago(0, now);
+        assert_eq!(
+            InstanceStanding::get_standing_at(&user, now),
+            InstanceStanding::Safe
+        );
+        assert_eq!(
+            days_until_deletion(&user, InstanceStanding::Safe, now),
+            None
+        );
+
+        let user = user_signed_in_days_ago(10, now);
+        let standing = InstanceStanding::get_standing_at(&user, now);
+        assert_eq!(standing, InstanceStanding::InDanger);
+        assert_eq!(days_until_deletion(&user, standing, now), Some(21));
+        assert_eq!(
+            warning_date(&user, standing),
+            Some(now - Duration::days(10) + Duration::days(24))
+        );
+
+        let user = user_signed_in_days_ago(26, now);
+        assert_eq!(
+            InstanceStanding::get_standing_at(&user, now),
+            InstanceStanding::AlmostDeleted
+        );
+
+        let user = user_signed_in_days_ago(40, now);
+        let standing = InstanceStanding::get_standing_at(&user, now);
+        assert_eq!(standing, InstanceStanding::MustDelete);
+        assert_eq!(days_until_deletion(&user, standing, now), Some(0));
+    }
+
+    #[test]
+    fn fresh_account_counts_from_creation() {
+        let now = NaiveDateTime::default() + Duration::days(365);
+        let mut user = UserData::example();
+        user.creation_date = now - Duration::hours(2);
+        let standing = InstanceStanding::get_standing_at(&user, now);
+        assert_eq!(standing, InstanceStanding::Fresh);
+        assert_eq!(days_until_deletion(&user, standing, now), Some(0));
+    }
+
+    #[test]
+    fn standing_is_serialized_in_snake_case() {
+        assert_eq!(
+            serde_json::to_string(&InstanceStanding::AlmostDeleted).unwrap(),
+            "\"almost_deleted\""
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use chrono::{Months, NaiveDateTime};
+use entity::user_data;
+use sea_orm::{ActiveValue::Set, EntityTrait, IntoActiveModel};
+use tokio::sync::RwLock;
+use tracing::*;
+
+use crate::{
+    GenResult,
+    database::variables::UserData,
+    errors::{FailureType, ResultLog},
+    get_data, get_database_connection,
+    webcom::email::send_donation_reminder_mail,
+};
+
+/*
+Whether it is time to remind the user of the donation options again
+Users which never got a reminder count from the moment they signed up, they already saw the donation text in the welcome mail
+An interval of 0 months disables the reminder
+*/
+fn donation_reminder_due(
+    creation_date: NaiveDateTime,
+    last_reminder: Option<NaiveDateTime>,
+    now: NaiveDateTime,
+    interval_months: i32,
+) -> bool {
+    if interval_months <= 0 {
+        return false;
+    }
+    last_reminder
+        .unwrap_or(creation_date)
+        .checked_add_months(Months::new(interval_months as u32))
+        .is_some_and(|due_date| now >= due_date)
+}
+
+// Only done after a successful run, no use asking for money if the calendar is not even working
+pub async fn check_donation_reminder(
+    exit_code: &FailureType,
+    instance_data: Arc<RwLock<UserData>>,
+) -> GenResult<()> {
+    let (_user, properties) = get_data();
+    let user = instance_data.read().await.clone();
+    let donation_text = &properties.donation_text;
+    if exit_code != &FailureType::OK
+        || !user.user_properties.send_donation_reminder
+        || (donation_text.donate_link.is_empty() && donation_text.iban.is_empty())
+    {
+        return Ok(());
+    }
+    let now = chrono::offset::Utc::now().naive_utc();
+    if !donation_reminder_due(
+        user.creation_date,
+        user.last_donation_reminder_date,
+        now,
+        properties.donation_reminder_months,
+    ) {
+        return Ok(());
+    }
+    info!("Sending donation reminder");
+    send_donation_reminder_mail()?;
+
+    // Stored even if the database update fails, so a broken database does not cause a reminder every run
+    instance_data.write().await.last_donation_reminder_date = Some(now);
+    let db = get_database_connection().await;
+    if let Some(user) = user_data::Entity::find_by_id(user.id).one(&db).await? {
+        let mut active_user = user.into_active_model();
+        active_user.last_donation_reminder_date = Set(Some(now));
+        user_data::Entity::update(active_user)
+            .validate()?
+            .exec(&db)
+            .await
+            .warn("Storing donation reminder date");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(year, month, day)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn first_reminder_counts_from_creation() {
+        let created = date(2026, 1, 15);
+        assert!(!donation_reminder_due(created, None, date(2026, 7, 14), 6));
+        assert!(donation_reminder_due(created, None, date(2026, 7, 15), 6));
+    }
+
+    #[test]
+    fn reminders_are_rate_limited() {
+        let created = date(2025, 1, 1);
+        let last = Some(date(2026, 3, 1));
+        assert!(!donation_reminder_due(created, last, date(2026, 8, 31), 6));
+        assert!(donation_reminder_due(created, last, date(2026, 9, 1), 6));
+    }
+
+    #[test]
+    fn zero_interval_disables_reminder() {
+        assert!(!donation_reminder_due(
+            date(2020, 1, 1),
+            None,
+            date(2026, 1, 1),
+            0
+        ));
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use time::{Duration, PrimitiveDateTime, Weekday};
+
+use crate::{
+    database::variables::{GeneralProperties, UserData},
+    webcom::{holidays::holiday_minutes_between, shift::Shift},
+};
+
+// Evening surcharge runs from the configured hour until this hour the next morning
+const EVENING_SURCHARGE_END_HOUR: u8 = 6;
+
+// The pay rules of a user, the wage is set per user and the surcharges per properties group
+#[derive(Debug, Clone)]
+pub struct PayRules {
+    pub hourly_wage_cents: i32,
+    pub evening_surcharge_start_hour: i32,
+    pub evening_surcharge_percent: i32,
+    pub weekend_surcharge_percent: i32,
+}
+
+impl PayRules {
+    pub fn new(user: &UserData, properties: &GeneralProperties) -> Self {
+        Self {
+            hourly_wage_cents: user.user_properties.hourly_wage_cents,
+            evening_surcharge_start_hour: properties.evening_surcharge_start_hour,
+            evening_surcharge_percent: properties.evening_surcharge_percent,
+            weekend_surcharge_percent: properties.weekend_surcharge_percent,
+        }
+    }
+
+    // The surcharge percentage for a single minute, only the highest surcharge counts
+    fn surcharge_percent(&self, minute: PrimitiveDateTime) -> i32 {
+        let weekend = matches!(minute.weekday(), Weekday::Saturday | Weekday::Sunday);
+        let hour = minute.hour();
+        let evening =
+            hour as i32 >= self.evening_surcharge_start_hour || hour < EVENING_SURCHARGE_END_HOUR;
+        let weekend_percent = if weekend {
+            self.weekend_surcharge_percent
+        } else {
+            0
+        };
+        let evening_percent = if evening {
+            self.evening_surcharge_percent
+        } else {
+            0
+        };
+        weekend_percent.max(evening_percent)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MonthEarnings {
+    pub year: i32,
+    pub month: u8,
+    pub shifts: usize,
+    pub working_minutes: i64,
+    // Minutes worked on public holidays, counted per calendar day so a night shift into a holiday counts partly
+    pub holiday_minutes: i64,
+    pub base_cents: i64,
+    pub surcharge_cents: i64,
+    pub total_cents: i64,
+}
+
+/*
+Estimates the pay of a single shift in cents, returned as (base, surcharge)
+The base is the paid hours times the wage, the surcharges are calculated over the actual time of the shift
+*/
+pub fn shift_earnings(shift: &Shift, rules: &PayRules) -> (i64, i64) {
+    let wage = rules.hourly_wage_cents as i64;
+    let base = shift.working_hours().whole_minutes() * wage / 60;
+    let start = PrimitiveDateTime::new(shift.date, shift.start);
+    let end = PrimitiveDateTime::new(shift.end_date, shift.end);
+    let mut surcharge_percent_minutes = 0;
+    let mut minute = start;
+    while minute < end {
+        surcharge_percent_minutes += rules.surcharge_percent(minute) as i64;
+        minute += Duration::MINUTE;
+    }
+    let surcharge = surcharge_percent_minutes * wage / 60 / 100;
+    (base, surcharge)
+}
+
+// Minutes of the shift which fall on a public holiday
+pub fn holiday_minutes(shift: &Shift) -> i64 {
+    holiday_minutes_between(
+        PrimitiveDateTime::new(shift.date, shift.start),
+        PrimitiveDateTime::new(shift.end_date, shift.end),
+    )
+}
+
+// Estimated earnings per month, sorted from old to new
+pub fn monthly_earnings<'a>(
+    shifts: impl Iterator<Item = &'a Shift>,
+    rules: &PayRules,
+) -> Vec<MonthEarnings> {
+    let mut months: BTreeMap<(i32, u8), MonthEarnings> = BTreeMap::new();
+    for shift in shifts {
+        let (year, month) = (shift.date.year(), shift.date.month() as u8);
+        let earnings = months.entry((year, month)).or_insert(MonthEarnings {
+            year,
+            month,
+            ..Default::default()
+        });
+        let (base, surcharge) = shift_earnings(shift, rules);
+        earnings.shifts += 1;
+        earnings.working_minutes += shift.working_hours().whole_minutes();
+        earnings.holiday_minutes += holiday_minutes(shift);
+        earnings.base_cents += base;
+        earnings.surcharge_cents += surcharge;
+        earnings.total_cents += base + surcharge;
+    }
+    months.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webcom::shift_diff::tests::shift;
+
+    fn rules() -> PayRules {
+        PayRules {
+            hourly_wage_cents: 1200,
+            evening_surcharge_start_hour: 19,
+            evening_surcharge_percent: 25,
+            weekend_surcharge_percent: 50,
+        }
+    }
+
+    #[test]
+    fn saved_shift_without_working_hours_is_paid_fully() {
+        let shift = shift("V2309", 2, "06:00", "13:40");
+        let mut saved = serde_json::to_value(&shift).unwrap();
+        saved.as_object_mut().unwrap().remove("working_hours");
+        let saved: Shift = serde_json::from_value(saved).unwrap();
+        assert_eq!(saved.working_hours(), saved.duration);
+        assert_eq!(
+            shift_earnings(&saved, &rules()),
+            shift_earnings(&shift, &rules())
+        );
+    }
+
+    #[test]
+    fn day_shift_has_no_surcharge() {
+        // 2 june 2025 is a monday
+        let shift = shift("V2309", 2, "06:00", "13:40");
+        assert_eq!(shift_earnings(&shift, &rules()), (460 * 1200 / 60, 0));
+    }
+
+    #[test]
+    fn evening_hours_get_surcharge() {
+        let shift = shift("L1201", 2, "15:00", "21:00");
+        // two hours after 19:00, at 25% of 12 euro
+        assert_eq!(shift_earnings(&shift, &rules()).1, 2 * 300);
+    }
+
+    #[test]
+    fn weekend_surcharge_wins_over_evening() {
+        // 7 june 2025 is a saturday
+        let shift = shift("L1201", 7, "15:00", "21:00");
+        assert_eq!(shift_earnings(&shift, &rules()).1, 6 * 600);
+    }
+
+    #[test]
+    fn holiday_minutes_are_counted() {
+        // 9 june 2025 is second pentecost
+        assert_eq!(holiday_minutes(&shift("V2309", 9, "06:00", "13:40")), 460);
+        assert_eq!(holiday_minutes(&shift("V2309", 10, "06:00", "13:40")), 0);
+        let shifts = [shift("V2309", 9, "06:00", "13:40")];
+        assert_eq!(
+            monthly_earnings(shifts.iter(), &rules())[0].holiday_minutes,
+            460
+        );
+    }
+
+    #[test]
+    fn earnings_are_grouped_per_month() {
+        let shifts = [
+            shift("V2309", 2, "06:00", "13:40"),
+            shift("V2309", 3, "06:00", "13:40"),
+        ];
+        let months = monthly_earnings(shifts.iter(), &rules());
+        assert_eq!(months.len(), 1);
+        assert_eq!(months[0].month, 6);
+        assert_eq!(months[0].shifts, 2);
+        assert_eq!(months[0].working_minutes, 2 * 460);
+        assert_eq!(months[0].total_cents, 2 * 460 * 20);
+    }
+}
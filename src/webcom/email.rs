@@ -1,22 +1,50 @@
+use crate::config::{public_api_url, template_path};
+use crate::database::onboarding_variants::DEFAULT_ONBOARDING_TEMPLATE;
 use crate::database::secret::Secret;
-use crate::errors::IncorrectCredentialsCount;
-use crate::{APPLICATION_NAME, GenError, GenResult, get_data, webcom::shift::ShiftState};
+use crate::database::sent_notifications::{ChangeType, mark_sent, unsent_shifts};
+use crate::database::short_link::{shorten_link, shorten_links, shorten_shared_link};
+use crate::database::variables::{GeneralProperties, UserData};
+use crate::errors::{AppResult, ErrorCategory, IncorrectCredentialsCount};
+use crate::health::{ApplicationState, Phase};
+use crate::kuma::public_kuma_url;
+use crate::webcom::error_digest::queue_errors;
+use crate::webcom::holidays::{HOLIDAY_LABEL, holiday_name};
+use crate::webcom::ical::load_known_shifts;
+use crate::webcom::mail_transport::{MailTransport, MemoryMailTransport, load_mailer};
+use crate::webcom::overtime::{WeekHours, newly_exceeded_weeks, weekly_hours};
+use crate::webcom::rest_period::{RestViolation, rest_violations};
+use crate::webcom::shift_diff::{ShiftDiff, diff_shifts, upcoming};
+use crate::webcom::week_strip::render_week_strip;
+use crate::{APPLICATION_NAME, get_data, webcom::shift::ShiftState};
 use crate::{
-    SignInFailure, create_ical_filename, create_shift_link, get_set_name, webcom::shift::Shift,
+    SignInFailure, create_ical_filename, create_ical_filename_local, create_shift_link,
+    get_set_name, webcom::shift::Shift,
 };
 use lettre::{
-    Message, SmtpTransport, Transport, message::header::ContentType,
-    transport::smtp::authentication::Credentials,
+    Message,
+    message::{
+        MessageBuilder,
+        header::{ContentType, Header, HeaderName, HeaderValue},
+    },
+};
+use secrecy::{ExposeSecret, SecretString};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    time::{Duration, Instant},
 };
-use secrecy::ExposeSecret;
-use std::{collections::HashMap, fs};
 use strfmt::strfmt;
+use time::PrimitiveDateTime;
 use time::macros::format_description;
 use tracing::*;
 use url::Url;
 
 const ERROR_VALUE: &str = "HIER HOORT WAT ANDERS DAN DEZE TEKST TE STAAN, CONFIGURATIE INCORRECT";
 const SENDER_NAME: &str = "Peter";
+// Shift used in the onboarding preview when the user has no upcoming shifts
+const PREVIEW_SHIFT_TEXT: &str = "Dienst: V2309 •  • Geldig vanaf: 29.06.2025 •  • Tijd: 06:14 - 13:54 •  • Dienstduur: 07:40 Uren •  • Loonuren: 07:40 Uren •  • Dagsoort:  • Donderdag •  • Dienstsoort:  • Rijdienst •  • Startplaats:  • ehvgas, Einhoven garage streek •  • Omschrijving:  • V";
 pub const TIME_DESCRIPTION: &[time::format_description::BorrowedFormatItem<'_>] =
     format_description!("[hour]:[minute]");
 pub const DATE_DESCRIPTION: &[time::format_description::BorrowedFormatItem<'_>] =
@@ -26,6 +54,62 @@ pub const COLOR_BASE: &str = "#5F5AD3";
 pub const COLOR_RED: &str = "#a51d2d";
 pub const COLOR_GREEN: &str = "#26a269";
 
+// How far ahead a reserve duty turning into a real duty is seen as a call-out
+const CALL_OUT_WINDOW: time::Duration = time::Duration::hours(48);
+
+// Headers marking a mail as high priority, both are used as clients differ in which one they read
+#[derive(Clone)]
+struct XPriority;
+
+impl Header for XPriority {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("X-Priority")
+    }
+
+    fn parse(_: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self)
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), "1 (Highest)".to_owned())
+    }
+}
+
+// The user a mail was sent to, so a reply reaching the support address can be traced back to the user
+#[derive(Clone)]
+struct MailUser(String);
+
+impl Header for MailUser {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("X-Mijn-Bussie-User")
+    }
+
+    fn parse(value: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self(value.to_owned()))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), self.0.clone())
+    }
+}
+
+#[derive(Clone)]
+struct Importance;
+
+impl Header for Importance {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("Importance")
+    }
+
+    fn parse(_: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self)
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), "high".to_owned())
+    }
+}
+
 trait StrikethroughString {
     fn strikethrough(&self) -> String;
 }
@@ -42,6 +126,8 @@ pub struct EnvMailVariables {
     pub smtp_server: String,
     pub smtp_username: String,
     pub smtp_password: String,
+    pub smtp_pool_max_size: u32,
+    pub smtp_pool_idle_timeout: Duration,
     pub mail_from: String,
     pub mail_to: Secret,
     mail_error_to: String,
@@ -51,6 +137,9 @@ pub struct EnvMailVariables {
     send_failed_signin_mail: bool,
     send_error_mail: bool,
     send_removed_shift: bool,
+    send_overtime_mail: bool,
+    // Set if a mail to the user bounced, mails to mail_to are dropped
+    pub mail_paused: bool,
 }
 
 /*
@@ -65,6 +154,9 @@ impl EnvMailVariables {
         let smtp_server = email_properties.smtp_server;
         let smtp_username = email_properties.smtp_username;
         let smtp_password = email_properties.smtp_password;
+        let smtp_pool_max_size = email_properties.smtp_pool_max_size.max(1) as u32;
+        let smtp_pool_idle_timeout =
+            Duration::from_secs(email_properties.smtp_pool_idle_timeout_seconds.max(1) as u64);
         let mail_from = email_properties.mail_from;
         let mail_to = user.email.clone();
         let mail_error_to = properties.support_mail.clone();
@@ -74,10 +166,14 @@ impl EnvMailVariables {
         let send_welcome_mail = user.user_properties.send_welcome_mail;
         let send_removed_shift = user.user_properties.send_mail_removed_shift;
         let send_failed_signin_mail = user.user_properties.send_failed_signin_mail;
+        let send_overtime_mail = user.user_properties.send_overtime_mail;
+        let mail_paused = user.mail_paused_date.is_some();
         Self {
             smtp_server,
             smtp_username,
             smtp_password,
+            smtp_pool_max_size,
+            smtp_pool_idle_timeout,
             mail_from,
             mail_to,
             mail_error_to,
@@ -87,10 +183,32 @@ impl EnvMailVariables {
             send_welcome_mail,
             send_failed_signin_mail,
             send_removed_shift,
+            send_overtime_mail,
+            mail_paused,
         }
     }
 }
 
+/*
+Every mail to the user is sent from the relay account, so replies are routed to the support address instead
+The user name is added as a header, so the operator knows who replied
+*/
+fn user_mail_builder(
+    from: String,
+    name: &str,
+    env: &EnvMailVariables,
+) -> AppResult<MessageBuilder> {
+    let (user, _properties) = get_data();
+    let mut builder = Message::builder()
+        .from(from.parse()?)
+        .to(format!("{} <{}>", name, env.mail_to.0.expose_secret()).parse()?)
+        .header(MailUser(user.user_name.clone()));
+    if !env.mail_error_to.is_empty() {
+        builder = builder.reply_to(format!("{APPLICATION_NAME} <{}>", env.mail_error_to).parse()?);
+    }
+    Ok(builder)
+}
+
 /*
 Main function for sending mails, it will always be called and will individually check if that function needs to be called
 If loading previous shifts fails for whatever it will not error but just do an early return.
@@ -101,12 +219,14 @@ pub fn send_emails(
     current_shifts: Vec<Shift>,
     previous_shifts: Vec<Shift>,
     replace_old: bool,
-) -> GenResult<Vec<Shift>> {
+    state: &mut ApplicationState,
+) -> AppResult<Vec<Shift>> {
     let env = EnvMailVariables::new();
     let mailer = load_mailer(&env)?;
     if previous_shifts.is_empty() {
         // if the previous were empty, just return the list of current shifts as all new
         error!("!!! PREVIOUS SHIFTS WAS EMPTY. SKIPPING !!!");
+        state.changed_shifts = current_shifts.len() as u64;
         return Ok(current_shifts
             .into_iter()
             .map(|mut shift| {
@@ -116,143 +236,169 @@ pub fn send_emails(
             .collect());
     }
     Ok(attach_shift_status(
-        &mailer,
+        mailer.as_ref(),
         previous_shifts,
         current_shifts,
         replace_old,
         &env,
+        state,
     )?)
 }
 
-// Creates SMTPtransport from username, password and server found in env
-fn load_mailer(env: &EnvMailVariables) -> GenResult<SmtpTransport> {
-    let creds = Credentials::new(env.smtp_username.clone(), env.smtp_password.clone());
-    let mailer = SmtpTransport::relay(&env.smtp_server)?
-        .credentials(creds)
-        .build();
-    Ok(mailer)
-}
-
 /*
-Will search for new shifts given previous shifts.
-Will be ran twice, If provided new shifts, it will look for updated shifts instead
-Will send an email is send_mail is true
-It doesn't make a lot of sense that this function is in Email
+Diffs the previous shifts against the new shifts and sends mails about the differences if required
+Returns the shifts which are still valid
 */
 fn attach_shift_status(
-    mailer: &SmtpTransport,
+    mailer: &dyn MailTransport,
     previous_shifts: Vec<Shift>,
     new_shifts: Vec<Shift>,
     replace_old: bool,
     env: &EnvMailVariables,
-) -> GenResult<Vec<Shift>> {
-    let current_date = time::OffsetDateTime::now_local()?.date();
-    let mut previous_shifts_map = previous_shifts
-        .into_iter()
-        .map(|shift| (shift.magic_number, shift))
-        .collect::<HashMap<i64, Shift>>();
-    // Iterate through the current shifts to check for updates or new shifts
-    // We start with a list of previously valid shifts. All marked as deleted
-    // we will then loop over a list of newly loaded shifts from the website
-    for mut new_shift in new_shifts {
-        // If the hash of this current shift is found in the previously valid shift list,
-        // we know this shift has remained unchanged. So mark it as such
-        if let Some(previous_shift) = previous_shifts_map.get_mut(&new_shift.magic_number) {
-            if !replace_old {
-                previous_shift.state = ShiftState::Unchanged;
-            } else {
-                new_shift.state = ShiftState::Unchanged;
-                *previous_shift = new_shift
-            }
-        } else {
-            // if it is not found, we loop over the list of previously known shifts
-            for previous_shift in previous_shifts_map.clone() {
-                // if during the loop, we find a previously valid shift with the same starting date as the current shift
-                // whereby we assume only 1 shift can be active per day
-                // we know it must have changed, as if it hadn't it would have been found from its hash
-                // so it can be marked as changed
-                // We must first remove the old shift, then add the new shift
-                if previous_shift.1.date == new_shift.date {
-                    match previous_shifts_map.remove(&previous_shift.0) {
-                        Some(_) => (),
-                        None => warn!(
-                            "Tried to remove shift {} as it has been updated, but that failed",
-                            previous_shift.1.number
-                        ),
-                    };
-                    new_shift.state = ShiftState::Changed;
-                    previous_shifts_map.insert(new_shift.magic_number, new_shift.clone());
-                    break;
-                }
-            }
-
-            // If after that loop, no previously known shift with the same start date as the new shift was found
-            // we know it is a new shift, so we mark it as such and add it to the list of known shifts
-            if new_shift.state != ShiftState::Changed {
-                new_shift.state = ShiftState::New;
-                previous_shifts_map.insert(new_shift.magic_number, new_shift);
-            }
-            // Because we only loop over new shifts, all old and deleted shifts do not even get looked at. And since they start as deleted
-            // They will be deleted
+    state: &mut ApplicationState,
+) -> AppResult<Vec<Shift>> {
+    let now = time::OffsetDateTime::now_local()?;
+    let now = PrimitiveDateTime::new(now.date(), now.time());
+    let current_date = now.date();
+    let previous_weeks = weekly_hours(previous_shifts.iter());
+    let started = Instant::now();
+    let diff = diff_shifts(previous_shifts, new_shifts, replace_old);
+    state.phase_timings.record(Phase::Diff, None, started);
+    state.changed_shifts = (diff.new_shifts().count()
+        + diff.changed_shifts().count()
+        + diff.removed_shifts().count()) as u64;
+    debug!(
+        "Shift diff: {} unchanged, {} removed",
+        diff.unchanged_shifts().count(),
+        diff.removed_shifts().count()
+    );
+    let started = Instant::now();
+    send_shift_diff_mails(mailer, &diff, now, env)?;
+    if env.send_overtime_mail {
+        let max_weekly_hours = get_data().0.user_properties.max_weekly_hours;
+        let exceeded_weeks =
+            newly_exceeded_weeks(&previous_weeks, &diff, max_weekly_hours, current_date);
+        if !exceeded_weeks.is_empty() {
+            info!("{} weeks went over the maximum hours", exceeded_weeks.len());
+            send_overtime_mail(mailer, env, &exceeded_weeks, max_weekly_hours)?;
         }
     }
-    let current_shift_vec: Vec<Shift> = previous_shifts_map.into_values().collect();
-    let mut new_shifts: Vec<&Shift> = current_shift_vec
-        .iter()
-        .filter(|item| item.state == ShiftState::New)
-        .collect();
-    let mut updated_shifts: Vec<&Shift> = current_shift_vec
-        .iter()
-        .filter(|item| item.state == ShiftState::Changed)
-        .collect();
-    let mut removed_shifts: Vec<&Shift> = current_shift_vec
-        .iter()
-        .filter(|item| item.state == ShiftState::Deleted)
-        .collect();
-    // debug!("shift vec : {:#?}",current_shift_vec);
-    debug!("Removed shift vec size: {}", removed_shifts.len());
-    new_shifts.retain(|shift| shift.date >= current_date);
-    if !new_shifts.is_empty() && env.send_email_new_shift {
+    state.phase_timings.record(Phase::Email, None, started);
+    Ok(diff.into_current())
+}
+
+// Sends the mails for new, updated and removed shifts in the diff, if the user wants them
+fn send_shift_diff_mails(
+    mailer: &dyn MailTransport,
+    diff: &ShiftDiff,
+    now: PrimitiveDateTime,
+    env: &EnvMailVariables,
+) -> AppResult<()> {
+    let current_date = now.date();
+    let min_rest_hours = get_data().0.user_properties.min_rest_hours;
+    let violations = rest_violations(diff.current_shifts(), min_rest_hours);
+    let current_shifts: Vec<&Shift> = diff.current_shifts().collect();
+    // Only the shifts which were not mailed about yet are mailed, they are recorded once the mail is sent
+    let new_shifts = match env.send_email_new_shift {
+        true => unsent_shifts(
+            upcoming(diff.new_shifts(), current_date),
+            ChangeType::New,
+            current_date,
+        ),
+        false => vec![],
+    };
+    if !new_shifts.is_empty() {
         info!("Found {} new shifts, sending email", new_shifts.len());
-        create_send_new_email(mailer, new_shifts, env, false)?;
+        create_send_new_email(
+            mailer,
+            new_shifts.clone(),
+            &current_shifts,
+            &violations,
+            env,
+            false,
+        )?;
+        mark_sent(&new_shifts, ChangeType::New, current_date);
+    }
+    // Call-outs are always mailed, even if the user does not want mails about updated shifts
+    let call_outs: Vec<(&Shift, &Shift)> = diff
+        .reserve_call_outs(now + CALL_OUT_WINDOW)
+        .into_iter()
+        .filter(|(_, shift)| PrimitiveDateTime::new(shift.date, shift.start) >= now)
+        .collect();
+    let unsent_call_outs = unsent_shifts(
+        call_outs.iter().map(|(_, shift)| *shift).collect(),
+        ChangeType::CallOut,
+        current_date,
+    );
+    for (reserve_shift, shift) in &call_outs {
+        if !unsent_call_outs
+            .iter()
+            .any(|unsent| unsent.magic_number == shift.magic_number)
+        {
+            continue;
+        }
+        info!("Reserve duty {} was called out", reserve_shift.number);
+        send_reserve_call_out_mail(mailer, env, reserve_shift, shift, &violations)?;
+        mark_sent(&[*shift], ChangeType::CallOut, current_date);
     }
-    updated_shifts.retain(|shift| shift.date >= current_date);
-    if !updated_shifts.is_empty() && env.send_mail_updated_shift {
+    let mut updated_shifts = upcoming(diff.changed_shifts(), current_date);
+    updated_shifts.retain(|shift| {
+        !call_outs
+            .iter()
+            .any(|(_, call_out)| call_out.magic_number == shift.magic_number)
+    });
+    let updated_shifts = match env.send_mail_updated_shift {
+        true => unsent_shifts(updated_shifts, ChangeType::Updated, current_date),
+        false => vec![],
+    };
+    if !updated_shifts.is_empty() {
         info!(
             "Found {} updated shifts, sending email",
             updated_shifts.len()
         );
-        create_send_new_email(mailer, updated_shifts, env, true)?;
+        create_send_new_email(
+            mailer,
+            updated_shifts.clone(),
+            &current_shifts,
+            &violations,
+            env,
+            true,
+        )?;
+        mark_sent(&updated_shifts, ChangeType::Updated, current_date);
     }
-    if !removed_shifts.is_empty() && env.send_removed_shift {
+    let removed_shifts = match env.send_removed_shift {
+        true => unsent_shifts(
+            upcoming(diff.removed_shifts(), current_date),
+            ChangeType::Removed,
+            current_date,
+        ),
+        false => vec![],
+    };
+    if !removed_shifts.is_empty() {
         info!("Removing {} shifts", removed_shifts.len());
-        removed_shifts.retain(|shift| shift.date >= current_date);
-        if !removed_shifts.is_empty() {
-            send_removed_shifts_mail(mailer, env, removed_shifts)?;
-        }
+        send_removed_shifts_mail(mailer, env, removed_shifts.clone())?;
+        mark_sent(&removed_shifts, ChangeType::Removed, current_date);
     }
-    // At last remove all shifts marked as removed from the vec
-    let current_shift_vec = current_shift_vec
-        .into_iter()
-        .filter(|shift| shift.state != ShiftState::Deleted)
-        .collect();
-    Ok(current_shift_vec)
+    Ok(())
 }
 
 /*
 Composes and sends mail with either new shifts or updated shifts if required. in plaintext
 Depending on if update is true or false
 Will always send under the name of Peter
+A week strip of the days around the first shift is attached inline, clients which can not show it still have the table
 */
 fn create_send_new_email(
-    mailer: &SmtpTransport,
+    mailer: &dyn MailTransport,
     new_shifts: Vec<&Shift>,
+    current_shifts: &[&Shift],
+    rest_violations: &HashMap<i64, RestViolation>,
     env: &EnvMailVariables,
     update: bool,
-) -> GenResult<()> {
-    let base_html = fs::read_to_string("./templates/email_base.html").unwrap();
-    let mut changed_mail_html = fs::read_to_string("./templates/changed_shift.html").unwrap();
-    let shift_table = fs::read_to_string("./templates/shift_table.html").unwrap();
+) -> AppResult<()> {
+    let base_html = fs::read_to_string(template_path("email_base.html")).unwrap();
+    let mut changed_mail_html = fs::read_to_string(template_path("changed_shift.html")).unwrap();
+    let shift_table = fs::read_to_string(template_path("shift_table.html")).unwrap();
     let enkel_meervoud = if new_shifts.len() != 1 { "en" } else { "" };
     let name = get_set_name(None);
     let new_update_text = match update {
@@ -260,27 +406,24 @@ fn create_send_new_email(
         false => "nieuwe",
     };
 
+    let pdf_links = shorten_pdf_links(&new_shifts);
     let mut shift_tables = String::new();
     for shift in &new_shifts {
-        let shift_table_clone = strfmt!(&shift_table,
-            shift_number => shift.number.clone(),
-            shift_date => shift.date.format(DATE_DESCRIPTION)?.to_string(),
-            shift_start => shift.start.format(TIME_DESCRIPTION)?.to_string(),
-            shift_end => shift.end.format(TIME_DESCRIPTION)?.to_string(),
-            shift_duration_hour => shift.duration.whole_hours().to_string(),
-            shift_duration_minute => (shift.duration.whole_minutes() % 60).to_string(),
-            shift_link => create_shift_link(shift, false).unwrap_or_default(),
-            bussie_login => if let Ok(url) = create_calendar_link() {format!("/loginlink/{url}")} else {String::new()},
-            shift_link_pdf => create_shift_link(shift, true).unwrap_or_default()
-        )?;
-        shift_tables.push_str(&shift_table_clone);
+        shift_tables.push_str(&create_shift_table(
+            &shift_table,
+            shift,
+            rest_violations,
+            &pdf_links,
+        )?);
     }
+    let week_strip_html = render_week_strip(current_shifts, &new_shifts).unwrap_or_default();
     changed_mail_html = strfmt!(
         &changed_mail_html,
         name => name.clone(),
         shift_changed_ammount => new_shifts.len().to_string(),
         new_update => new_update_text.to_string(),
         single_plural => enkel_meervoud.to_string(),
+        week_strip => week_strip_html,
         shift_tables => shift_tables.to_string()
     )?;
     let email_body_html = strfmt!(&base_html,
@@ -289,22 +432,118 @@ fn create_send_new_email(
         footer => create_footer().unwrap_or(ERROR_VALUE.to_owned())
     )?;
 
-    let email = Message::builder()
-        .from(format!("Peter <{}>", &env.mail_from).parse()?)
-        .to(format!("{} <{}>", &name, &env.mail_to.0.expose_secret()).parse()?)
-        .subject(format!(
+    let email =
+        user_mail_builder(format!("Peter <{}>", &env.mail_from), &name, env)?.subject(format!(
             "Je hebt {} {} dienst{}",
             &new_shifts.len(),
             new_update_text,
             enkel_meervoud
+        ));
+    let email = email.header(ContentType::TEXT_HTML).body(email_body_html)?;
+    mailer.send_message(&email)?;
+    Ok(())
+}
+
+// The PDF links of all shifts in a mail are shortened at once
+fn shorten_pdf_links(shifts: &[&Shift]) -> HashMap<String, String> {
+    let links: Vec<String> = shifts
+        .iter()
+        .filter_map(|shift| create_shift_link(shift, true).ok())
+        .collect();
+    shorten_links(&links)
+}
+
+fn pdf_link(shift: &Shift, pdf_links: &HashMap<String, String>) -> String {
+    let link = create_shift_link(shift, true).unwrap_or_default();
+    pdf_links.get(&link).cloned().unwrap_or(link)
+}
+
+fn create_shift_table(
+    shift_table: &str,
+    shift: &Shift,
+    rest_violations: &HashMap<i64, RestViolation>,
+    pdf_links: &HashMap<String, String>,
+) -> AppResult<String> {
+    Ok(strfmt!(shift_table,
+        shift_number => shift.number.clone(),
+        shift_date => shift.date.format(DATE_DESCRIPTION)?.to_string(),
+        shift_start => shift.start.format(TIME_DESCRIPTION)?.to_string(),
+        shift_end => shift.end.format(TIME_DESCRIPTION)?.to_string(),
+        shift_duration_hour => shift.duration.whole_hours().to_string(),
+        shift_duration_minute => (shift.duration.whole_minutes() % 60).to_string(),
+        shift_link => create_shift_link(shift, false).unwrap_or_default(),
+        bussie_login => if let Ok(url) = create_calendar_link() {format!("/loginlink/{url}")} else {String::new()},
+        shift_link_pdf => pdf_link(shift, pdf_links),
+        holiday_notice => create_holiday_notice(shift),
+        rest_warning => rest_violations.get(&shift.magic_number).map(create_rest_warning).unwrap_or_default()
+    )?)
+}
+
+/*
+A reserve duty turned into a real duty at short notice, this is always sent with a high priority
+regardless of the mail settings of the user
+*/
+fn send_reserve_call_out_mail(
+    mailer: &dyn MailTransport,
+    env: &EnvMailVariables,
+    reserve_shift: &Shift,
+    shift: &Shift,
+    rest_violations: &HashMap<i64, RestViolation>,
+) -> AppResult<()> {
+    let base_html = fs::read_to_string(template_path("email_base.html")).unwrap();
+    let call_out_html = fs::read_to_string(template_path("reserve_call_out.html")).unwrap();
+    let shift_table = fs::read_to_string(template_path("shift_table.html")).unwrap();
+    let name = get_set_name(None);
+    let call_out_html = strfmt!(&call_out_html,
+        name => name.clone(),
+        reserve_number => reserve_shift.number.clone(),
+        shift_tables => create_shift_table(&shift_table, shift, rest_violations, &shorten_pdf_links(&[shift]))?
+    )?;
+    let email_body_html = strfmt!(&base_html,
+        content => call_out_html,
+        banner_color => COLOR_RED,
+        footer => create_footer().unwrap_or_default()
+    )?;
+    let email = user_mail_builder(format!("{} <{}>", SENDER_NAME, &env.mail_from), &name, env)?
+        .subject(format!(
+            "Reservedienst omgezet: {} op {} om {}",
+            shift.number,
+            shift.date.format(DATE_DESCRIPTION)?,
+            shift.start.format(TIME_DESCRIPTION)?
         ))
+        .header(XPriority)
+        .header(Importance)
         .header(ContentType::TEXT_HTML)
         .body(email_body_html)?;
-    mailer.send(&email)?;
+    mailer.send_message(&email)?;
     Ok(())
 }
 
-fn create_footer() -> GenResult<String> {
+// Extra row for the shift table, highlighting that there is not enough rest around the shift
+fn create_rest_warning(violation: &RestViolation) -> String {
+    format!(
+        r#"<tr>
+                <td style="background-color:{COLOR_RED}; color:#ffffff;"><strong>Rusttijd</strong></td>
+                <td style="background-color:{COLOR_RED}; color:#ffffff;">{}</td>
+            </tr>"#,
+        violation.description()
+    )
+}
+
+// Extra row for the shift table if the shift starts on a public holiday
+fn create_holiday_notice(shift: &Shift) -> String {
+    match holiday_name(shift.date) {
+        Some(holiday) => format!(
+            r#"<tr>
+                <td style="background-color:{COLOR_BASE}; color:#ffffff;"><strong>Feestdag</strong></td>
+                <td style="background-color:{COLOR_BASE}; color:#ffffff;">{holiday} • {HOLIDAY_LABEL}</td>
+            </tr>"#
+        ),
+        None => String::new(),
+    }
+}
+
+fn create_footer() -> AppResult<String> {
     let (_user, properties) = get_data();
     let footer_text = r#"<tr>
       <td style="background-color:#FFFFFF; text-align:center; padding-top:0px;font-size:12px;">
@@ -327,21 +566,45 @@ fn create_footer() -> GenResult<String> {
         .unwrap_or_default())
 }
 
-pub fn create_calendar_link() -> GenResult<Url> {
-    let (_user, properties) = get_data();
+// If the calendar is served by the API the link points there, so fetches by calendar clients are recorded
+pub fn create_calendar_link() -> AppResult<Url> {
+    let (user, properties) = get_data();
+    create_calendar_link_local(&user, &properties)
+}
+
+pub fn create_calendar_link_local(
+    user: &UserData,
+    properties: &GeneralProperties,
+) -> AppResult<Url> {
+    if let Some(api_url) = public_api_url() {
+        return Ok(Url::parse(&format!(
+            "{api_url}/api/calendar/{}.ics",
+            user.calendar_token
+        ))?);
+    }
     let domain = &properties.ical_domain;
     let url = Url::parse(domain)?;
-    Ok(url.join(&create_ical_filename())?)
+    Ok(url.join(&create_ical_filename_local(user))?)
+}
+
+// The same link as the calendar link, but opened by the calendar app instead of downloaded by the browser
+pub fn create_webcal_link_local(
+    user: &UserData,
+    properties: &GeneralProperties,
+) -> AppResult<String> {
+    let url = create_calendar_link_local(user, properties)?;
+    let without_scheme = url.as_str().split_once("://").map(|(_, rest)| rest);
+    Ok(format!("webcal://{}", without_scheme.unwrap_or_default()))
 }
 
 fn send_removed_shifts_mail(
-    mailer: &SmtpTransport,
+    mailer: &dyn MailTransport,
     env: &EnvMailVariables,
     removed_shifts: Vec<&Shift>,
-) -> GenResult<()> {
-    let base_html = fs::read_to_string("./templates/email_base.html").unwrap();
-    let removed_shift_html = fs::read_to_string("./templates/removed_shift_base.html").unwrap();
-    let shift_table = fs::read_to_string("./templates/shift_table.html").unwrap();
+) -> AppResult<()> {
+    let base_html = fs::read_to_string(template_path("email_base.html")).unwrap();
+    let removed_shift_html = fs::read_to_string(template_path("removed_shift_base.html")).unwrap();
+    let shift_table = fs::read_to_string(template_path("shift_table.html")).unwrap();
     info!("Sending removed shifts mail");
     let enkelvoud_meervoud = if removed_shifts.len() == 1 {
         "is"
@@ -350,6 +613,7 @@ fn send_removed_shifts_mail(
     };
     let email_shift_s = if removed_shifts.len() == 1 { "" } else { "en" };
     let name = get_set_name(None);
+    let pdf_links = shorten_pdf_links(&removed_shifts);
     let mut shift_tables = String::new();
     for shift in &removed_shifts {
         let shift_table_clone = strfmt!(&shift_table,
@@ -361,7 +625,9 @@ fn send_removed_shifts_mail(
             shift_duration_minute => (shift.duration.whole_minutes() % 60).to_string().strikethrough(),
             shift_link => create_shift_link(shift, false).unwrap_or_default(),
             bussie_login => if let Ok(url) = create_calendar_link() {format!("/loginlink/{url}")} else {String::new()},
-            shift_link_pdf => create_shift_link(shift, true).unwrap_or_default()
+            shift_link_pdf => pdf_link(shift, &pdf_links),
+            holiday_notice => String::new(),
+            rest_warning => String::new()
         )?;
         shift_tables.push_str(&shift_table_clone);
     }
@@ -377,9 +643,7 @@ fn send_removed_shifts_mail(
         banner_color => COLOR_BASE,
         footer => create_footer().unwrap_or_default()
     )?;
-    let email = Message::builder()
-        .from(format!("{} <{}>", SENDER_NAME, &env.mail_from).parse()?)
-        .to(format!("{} <{}>", &name, &env.mail_to.0.expose_secret()).parse()?)
+    let email = user_mail_builder(format!("{} <{}>", SENDER_NAME, &env.mail_from), &name, env)?
         .subject(&format!(
             "{} dienst{} {} verwijderd",
             removed_shifts.len(),
@@ -388,7 +652,48 @@ fn send_removed_shifts_mail(
         ))
         .header(ContentType::TEXT_HTML)
         .body(email_body_html)?;
-    mailer.send(&email)?;
+    mailer.send_message(&email)?;
+    Ok(())
+}
+
+// Warns the user that new or changed shifts made them work more than their maximum amount of hours in a week
+fn send_overtime_mail(
+    mailer: &dyn MailTransport,
+    env: &EnvMailVariables,
+    weeks: &[WeekHours],
+    max_weekly_hours: i32,
+) -> AppResult<()> {
+    let base_html = fs::read_to_string(template_path("email_base.html")).unwrap();
+    let overtime_html = fs::read_to_string(template_path("overtime.html")).unwrap();
+    let name = get_set_name(None);
+    let week_single_plural = if weeks.len() == 1 {
+        "een week".to_owned()
+    } else {
+        format!("{} weken", weeks.len())
+    };
+    let overtime_warnings = weeks
+        .iter()
+        .map(|week| week.warning(max_weekly_hours))
+        .collect::<Vec<String>>()
+        .join("<br>");
+    let overtime_html = strfmt!(&overtime_html,
+        name => name.clone(),
+        week_single_plural => week_single_plural.clone(),
+        max_weekly_hours => max_weekly_hours.to_string(),
+        overtime_warnings
+    )?;
+    let email_body_html = strfmt!(&base_html,
+        content => overtime_html,
+        banner_color => COLOR_RED,
+        footer => create_footer().unwrap_or_default()
+    )?;
+    let email = user_mail_builder(format!("{} <{}>", SENDER_NAME, &env.mail_from), &name, env)?
+        .subject(format!(
+            "Je staat in {week_single_plural} te veel uren ingeroosterd"
+        ))
+        .header(ContentType::TEXT_HTML)
+        .body(email_body_html)?;
+    mailer.send_message(&email)?;
     Ok(())
 }
 
@@ -396,7 +701,7 @@ fn send_removed_shifts_mail(
 Composes and sends email of found errors, in plaintext
 List of errors can be as long as possible, but for now is always 3
 */
-pub fn send_errors(errors: &Vec<GenError>, name: &str) -> GenResult<()> {
+pub fn send_errors<E: Display>(errors: &[E], name: &str, category: ErrorCategory) -> AppResult<()> {
     let env = EnvMailVariables::new();
     if !env.send_error_mail {
         info!("tried to send error mail, but is disabled");
@@ -406,50 +711,64 @@ pub fn send_errors(errors: &Vec<GenError>, name: &str) -> GenResult<()> {
         "Er zijn fouten opgetreden, mailtje met fouten wordt gestuurd naar {}",
         &env.mail_error_to
     );
+    Ok(queue_errors(errors, name, category)?)
+}
+
+// The combined errors of one or more users, see error_digest
+pub fn send_error_digest(subject: &str, body: &str) -> AppResult<()> {
+    let env = EnvMailVariables::new();
     let mailer = load_mailer(&env)?;
-    let mut email_errors = "Er zijn fouten opgetreden tijdens het laden van shifts\n".to_string();
-    for error in errors {
-        email_errors.push_str(&format!("Error: \n{}\n\n", error.to_string()));
-    }
     let email = Message::builder()
         .from(format!("Foutje Berichtmans <{}>", &env.mail_from).parse()?)
-        .to(format!("{} <{}>", &name, &env.mail_error_to).parse()?)
-        .subject(&format!("Fout bij laden shifts van: {}", name))
+        .to(format!("{APPLICATION_NAME} <{}>", &env.mail_error_to).parse()?)
+        .subject(subject)
         .header(ContentType::TEXT_PLAIN)
-        .body(email_errors)?;
-    mailer.send(&email)?;
+        .body(body.to_owned())?;
+    mailer.send_message(&email)?;
     Ok(())
 }
 
-pub fn send_welcome_mail(force: bool) -> GenResult<()> {
-    let env = EnvMailVariables::new();
-
-    if !env.send_welcome_mail && !force {
+// Forced welcome mails, like the ones requested through the API, are sent even if the user disabled them
+pub fn welcome_mail_enabled(force: bool) -> bool {
+    let enabled = force || EnvMailVariables::new().send_welcome_mail;
+    if !enabled {
         info!("Wanted to send welcome mail. But it is disabled");
-        return Ok(());
     }
+    enabled
+}
 
+// The template is picked by the caller with load_welcome_template, that needs the database
+pub fn send_welcome_mail(template: &str) -> AppResult<()> {
+    let env = EnvMailVariables::new();
     let mailer = load_mailer(&env)?;
-    let (_user, properties) = get_data();
+    create_send_welcome_mail(mailer.as_ref(), &env, template)
+}
+
+fn create_send_welcome_mail(
+    mailer: &dyn MailTransport,
+    env: &EnvMailVariables,
+    template: &str,
+) -> AppResult<()> {
+    let (user, properties) = get_data();
 
-    let base_html = fs::read_to_string("./templates/email_base.html").unwrap();
-    let onboarding_html = fs::read_to_string("./templates/onboarding_base.html").unwrap();
+    let base_html = fs::read_to_string(template_path("email_base.html")).unwrap();
+    let onboarding_html = fs::read_to_string(template_path(template))?;
 
     let name = get_set_name(None);
 
     let agenda_url = create_calendar_link()?.to_string();
-    let agenda_url_webcal = agenda_url.clone().replace("https", "webcal");
+    let agenda_url_webcal = create_webcal_link_local(&user, &properties)?;
     // A lot of email clients don't want to open webcal links. So by pointing to a website which returns a 302 to a webcal link it tricks the email client
+    // The API does this itself, the webcal domain is only needed without it
     let rewrite_url = &properties.webcal_domain;
-    let webcal_rewrite_url = format!(
-        "{rewrite_url}{}",
-        if !rewrite_url.is_empty() {
-            create_ical_filename()
-        } else {
-            agenda_url_webcal.clone()
-        }
-    );
-    let kuma_url = &properties.kuma_properties.domain;
+    let webcal_rewrite_url = shorten_link(&if let Some(api_url) = public_api_url() {
+        format!("{api_url}/api/webcal/{}", user.calendar_token)
+    } else if !rewrite_url.is_empty() {
+        format!("{rewrite_url}{}", create_ical_filename())
+    } else {
+        agenda_url_webcal.clone()
+    });
+    let kuma_url = public_kuma_url(&properties);
     let kuma_info = if !kuma_url.is_empty() {
         let extracted_kuma_mail = &properties
             .kuma_properties
@@ -472,7 +791,7 @@ pub fn send_welcome_mail(force: bool) -> GenResult<()> {
     let donation_link = donation_properties.donate_link;
     let iban = donation_properties.iban;
     let iban_name = donation_properties.iban_name;
-    let admin_email = env.mail_error_to;
+    let admin_email = env.mail_error_to.clone();
     let onboarding_html = strfmt!(&onboarding_html,
         name => name.clone(),
         agenda_url,
@@ -492,21 +811,80 @@ pub fn send_welcome_mail(force: bool) -> GenResult<()> {
         footer => "".to_owned()
     )?;
     warn!("welkom mail sturen");
-    let email = Message::builder()
-        .from(format!("{} <{}>", SENDER_NAME, &env.mail_from).parse()?)
-        .to(format!("{} <{}>", name, &env.mail_to.0.expose_secret()).parse()?)
+    let email = user_mail_builder(format!("{} <{}>", SENDER_NAME, &env.mail_from), &name, env)?
         .subject(format!("Welkom bij {APPLICATION_NAME} {}!", &name))
         .header(ContentType::TEXT_HTML)
         .body(email_body_html)?;
-    mailer.send(&email)?;
+    mailer.send_message(&email)?;
     Ok(())
 }
 
-pub fn send_deletion_warning_mail() -> GenResult<()> {
+// What a new user would get when signing up
+#[derive(Debug, Clone, Serialize)]
+pub struct OnboardingPreview {
+    pub recipient: String,
+    pub calendar_link: String,
+    // The complete mails, as they would be sent
+    pub mails: Vec<String>,
+    pub sent: bool,
+}
+
+/*
+Renders the onboarding of the current user, the welcome mail and a mail about a new shift
+The mails are addressed to the support address instead of the user, and only sent there if send is true
+A known upcoming shift of the user is used, or PREVIEW_SHIFT_TEXT if there is none
+*/
+pub fn preview_onboarding(send: bool) -> AppResult<OnboardingPreview> {
+    let mut env = EnvMailVariables::new();
+    env.mail_to = Secret(SecretString::new(env.mail_error_to.clone().into()));
+    let preview_mailer = MemoryMailTransport::default();
+    create_send_welcome_mail(&preview_mailer, &env, DEFAULT_ONBOARDING_TEMPLATE)?;
+
+    let today = time::OffsetDateTime::now_utc().date();
+    let sample_shift = match load_known_shifts()
+        .ok()
+        .and_then(|shifts| shifts.into_iter().find(|shift| shift.date >= today))
+    {
+        Some(shift) => shift,
+        None => Shift::new(PREVIEW_SHIFT_TEXT.to_owned(), today)?,
+    };
+    create_send_new_email(
+        &preview_mailer,
+        vec![&sample_shift],
+        &[&sample_shift],
+        &HashMap::new(),
+        &env,
+        false,
+    )?;
+
+    let mails = preview_mailer
+        .sent
+        .lock()
+        .map_err(|_| "Mail store poisoned")?
+        .clone();
+    if send {
+        let mailer = load_mailer(&env)?;
+        for mail in &mails {
+            mailer.send_message(mail)?;
+        }
+    }
+    Ok(OnboardingPreview {
+        recipient: env.mail_error_to.clone(),
+        calendar_link: create_calendar_link()?.to_string(),
+        mails: mails
+            .iter()
+            .map(|mail| String::from_utf8_lossy(&mail.formatted()).into_owned())
+            .collect(),
+        sent: send,
+    })
+}
+
+pub fn send_deletion_warning_mail() -> AppResult<()> {
     let env = EnvMailVariables::new();
 
-    let base_html = fs::read_to_string("./templates/email_base.html").unwrap();
-    let warning_html = fs::read_to_string("./templates/potential_account_deletion.html").unwrap();
+    let base_html = fs::read_to_string(template_path("email_base.html")).unwrap();
+    let warning_html =
+        fs::read_to_string(template_path("potential_account_deletion.html")).unwrap();
     let (_user, properties) = get_data();
     let mailer = load_mailer(&env)?;
     let name = get_set_name(None);
@@ -524,13 +902,217 @@ pub fn send_deletion_warning_mail() -> GenResult<()> {
         footer => String::new()
     )?;
 
+    let email = user_mail_builder(
+        format!("{APPLICATION_NAME} <{}>", &env.mail_from),
+        &name,
+        &env,
+    )?
+    .subject("Je Mijn Bussie account wordt over 7 dagen verwijderd")
+    .header(ContentType::TEXT_HTML)
+    .body(email_body_html)?;
+    mailer.send_message(&email)?;
+    Ok(())
+}
+
+// The calendar has not been written for longer than the stale threshold
+pub fn send_stale_calendar_mail(hours: u64) -> AppResult<()> {
+    let env = EnvMailVariables::new();
+
+    let base_html = fs::read_to_string(template_path("email_base.html")).unwrap();
+    let stale_html = fs::read_to_string(template_path("stale_calendar.html")).unwrap();
+    let mailer = load_mailer(&env)?;
+    let name = get_set_name(None);
+
+    let stale_html = strfmt!(&stale_html,
+        name => name.clone(),
+        hours => hours.to_string(),
+        admin_email => env.mail_error_to.clone()
+    )?;
+    let email_body_html = strfmt!(&base_html,
+        content => stale_html,
+        banner_color => COLOR_RED,
+        footer => String::new()
+    )?;
+
+    let email = user_mail_builder(
+        format!("{APPLICATION_NAME} <{}>", &env.mail_from),
+        &name,
+        &env,
+    )?
+    .subject("Je agenda wordt niet meer bijgewerkt")
+    .header(ContentType::TEXT_HTML)
+    .body(email_body_html)?;
+    mailer.send_message(&email)?;
+    Ok(())
+}
+
+// Reminds the user of the donation options, the same ones as in the welcome mail
+pub fn send_donation_reminder_mail() -> AppResult<()> {
+    let env = EnvMailVariables::new();
+    let (_user, properties) = get_data();
+
+    let base_html = fs::read_to_string(template_path("email_base.html")).unwrap();
+    let reminder_html = fs::read_to_string(template_path("donation_reminder.html")).unwrap();
+    let mailer = load_mailer(&env)?;
+    let name = get_set_name(None);
+
+    let donation_properties = properties.donation_text.clone();
+    let reminder_html = strfmt!(&reminder_html,
+        name => name.clone(),
+        donation_service => donation_properties.donate_service_name,
+        donation_text => donation_properties.donate_text,
+        donation_link => donation_properties.donate_link,
+        iban => donation_properties.iban,
+        iban_name => donation_properties.iban_name,
+        admin_email => env.mail_error_to.clone()
+    )?;
+    let email_body_html = strfmt!(&base_html,
+        content => reminder_html,
+        banner_color => COLOR_BASE,
+        footer => String::new()
+    )?;
+
+    let email = user_mail_builder(format!("{SENDER_NAME} <{}>", &env.mail_from), &name, &env)?
+        .subject(format!("Steun {APPLICATION_NAME}"))
+        .header(ContentType::TEXT_HTML)
+        .body(email_body_html)?;
+    mailer.send_message(&email)?;
+    Ok(())
+}
+
+// Tells the user their roster was checked and nothing changed, for users who worry silence means it is broken
+pub fn send_weekly_summary_mail(checks: usize, next_shift: Option<&Shift>) -> AppResult<()> {
+    let env = EnvMailVariables::new();
+
+    let base_html = fs::read_to_string(template_path("email_base.html")).unwrap();
+    let summary_html = fs::read_to_string(template_path("weekly_summary.html")).unwrap();
+    let mailer = load_mailer(&env)?;
+    let name = get_set_name(None);
+
+    let next_shift = match next_shift {
+        Some(shift) => format!(
+            "Je volgende dienst is <b>{}</b> op {} om {}",
+            shift.number,
+            shift.date.format(DATE_DESCRIPTION)?,
+            shift.start.format(TIME_DESCRIPTION)?
+        ),
+        None => "Er staan geen diensten meer in je rooster".to_owned(),
+    };
+    let summary_html = strfmt!(&summary_html,
+        name => name.clone(),
+        checks => checks.to_string(),
+        next_shift,
+        admin_email => env.mail_error_to.clone()
+    )?;
+    let email_body_html = strfmt!(&base_html,
+        content => summary_html,
+        banner_color => COLOR_BASE,
+        footer => String::new()
+    )?;
+
+    let email = user_mail_builder(format!("{SENDER_NAME} <{}>", &env.mail_from), &name, &env)?
+        .subject("Geen wijzigingen in je rooster deze week")
+        .header(ContentType::TEXT_HTML)
+        .body(email_body_html)?;
+    mailer.send_message(&email)?;
+    Ok(())
+}
+
+// Lets the support address know a new user finished their first run
+pub fn send_new_user_mail(sign_in_succeeded: bool, exit_code: &str) -> AppResult<()> {
+    let env = EnvMailVariables::new();
+    let (user, _properties) = get_data();
+
+    let base_html = fs::read_to_string(template_path("email_base.html")).unwrap();
+    let new_user_html = fs::read_to_string(template_path("new_user.html")).unwrap();
+    let mailer = load_mailer(&env)?;
+
+    let new_user_html = strfmt!(&new_user_html,
+        user_name => user.user_name.clone(),
+        name => get_set_name(None),
+        sign_in_result => match sign_in_succeeded {
+            true => "gelukt",
+            false => "mislukt",
+        }.to_owned(),
+        exit_code => exit_code.to_owned()
+    )?;
+    let email_body_html = strfmt!(&base_html,
+        content => new_user_html,
+        banner_color => match sign_in_succeeded {
+            true => COLOR_GREEN,
+            false => COLOR_RED,
+        },
+        footer => String::new()
+    )?;
+
     let email = Message::builder()
         .from(format!("{APPLICATION_NAME} <{}>", &env.mail_from).parse()?)
-        .to(format!("{} <{}>", &name, &env.mail_to.0.expose_secret()).parse()?)
-        .subject("Je Mijn Bussie account wordt over 7 dagen verwijderd")
+        .to(format!("{APPLICATION_NAME} <{}>", &env.mail_error_to).parse()?)
+        .subject(format!("Nieuwe gebruiker {}", user.user_name))
         .header(ContentType::TEXT_HTML)
         .body(email_body_html)?;
-    mailer.send(&email)?;
+    mailer.send_message(&email)?;
+    Ok(())
+}
+
+// Lets the support address know mails to the user are paused, because they bounced
+pub fn send_mail_paused_mail(reason: &str) -> AppResult<()> {
+    let env = EnvMailVariables::new();
+    let (user, _properties) = get_data();
+
+    let base_html = fs::read_to_string(template_path("email_base.html")).unwrap();
+    let mail_paused_html = fs::read_to_string(template_path("mail_paused.html")).unwrap();
+    let mailer = load_mailer(&env)?;
+
+    let mail_paused_html = strfmt!(&mail_paused_html,
+        user_name => user.user_name.clone(),
+        name => get_set_name(None),
+        reason => reason.to_owned()
+    )?;
+    let email_body_html = strfmt!(&base_html,
+        content => mail_paused_html,
+        banner_color => COLOR_RED,
+        footer => String::new()
+    )?;
+
+    let email = Message::builder()
+        .from(format!("{APPLICATION_NAME} <{}>", &env.mail_from).parse()?)
+        .to(format!("{APPLICATION_NAME} <{}>", &env.mail_error_to).parse()?)
+        .subject(format!("Mails naar {} gepauzeerd", user.user_name))
+        .header(ContentType::TEXT_HTML)
+        .body(email_body_html)?;
+    mailer.send_message(&email)?;
+    Ok(())
+}
+
+// An alert about the application itself for the support address, the same text is posted to ADMIN_WEBHOOK_URL
+pub fn send_admin_alert_mail(title: &str, message: &str, healthy: bool) -> AppResult<()> {
+    let env = EnvMailVariables::new();
+
+    let base_html = fs::read_to_string(template_path("email_base.html")).unwrap();
+    let alert_html = fs::read_to_string(template_path("admin_alert.html")).unwrap();
+    let mailer = load_mailer(&env)?;
+
+    let alert_html = strfmt!(&alert_html,
+        title => title.to_owned(),
+        message => message.to_owned()
+    )?;
+    let email_body_html = strfmt!(&base_html,
+        content => alert_html,
+        banner_color => match healthy {
+            true => COLOR_GREEN,
+            false => COLOR_RED,
+        },
+        footer => String::new()
+    )?;
+
+    let email = Message::builder()
+        .from(format!("{APPLICATION_NAME} <{}>", &env.mail_from).parse()?)
+        .to(format!("{APPLICATION_NAME} <{}>", &env.mail_error_to).parse()?)
+        .subject(title)
+        .header(ContentType::TEXT_HTML)
+        .body(email_body_html)?;
+    mailer.send_message(&email)?;
     Ok(())
 }
 
@@ -554,11 +1136,15 @@ impl DeletedReason {
     }
 }
 
-pub fn send_account_deleted_mail(reason: DeletedReason) -> GenResult<()> {
+// The sign up link contains the invite code if there is one, so the user can come back without asking for a code
+pub fn send_account_deleted_mail(
+    reason: DeletedReason,
+    invite_code: Option<&str>,
+) -> AppResult<()> {
     let env = EnvMailVariables::new();
 
-    let base_html = fs::read_to_string("./templates/email_base.html").unwrap();
-    let deletion_html = fs::read_to_string("./templates/inform_account_deletion.html").unwrap();
+    let base_html = fs::read_to_string(template_path("email_base.html")).unwrap();
+    let deletion_html = fs::read_to_string(template_path("inform_account_deletion.html")).unwrap();
     let (_user, properties) = get_data();
     let mailer = load_mailer(&env)?;
     let name = get_set_name(None);
@@ -570,7 +1156,7 @@ pub fn send_account_deleted_mail(reason: DeletedReason) -> GenResult<()> {
             DeletedReason::NewDead => "hidden",
             _ => "unset"
         }.to_owned(),
-        sign_up_link => properties.sign_up_url.clone(),
+        sign_up_link => shorten_shared_link(&sign_up_url(&properties.sign_up_url, invite_code)),
         admin_email => env.mail_error_to.clone()
     )?;
     let email_body_html = strfmt!(&base_html,
@@ -579,25 +1165,37 @@ pub fn send_account_deleted_mail(reason: DeletedReason) -> GenResult<()> {
         footer => String::new()
     )?;
 
-    let email = Message::builder()
-        .from(format!("{APPLICATION_NAME} <{}>", &env.mail_from).parse()?)
-        .to(format!("{} <{}>", &name, &env.mail_to.0.expose_secret()).parse()?)
-        .subject("Je Mijn Bussie is verwijderd")
-        .header(ContentType::TEXT_HTML)
-        .body(email_body_html)?;
-    mailer.send(&email)?;
+    let email = user_mail_builder(
+        format!("{APPLICATION_NAME} <{}>", &env.mail_from),
+        &name,
+        &env,
+    )?
+    .subject("Je Mijn Bussie is verwijderd")
+    .header(ContentType::TEXT_HTML)
+    .body(email_body_html)?;
+    mailer.send_message(&email)?;
     Ok(())
 }
 
-pub fn send_incorrect_new_password_mail() -> GenResult<()> {
+fn sign_up_url(sign_up_url: &str, invite_code: Option<&str>) -> String {
+    match (Url::parse(sign_up_url), invite_code) {
+        (Ok(mut url), Some(invite_code)) => {
+            url.query_pairs_mut().append_pair("invite", invite_code);
+            url.to_string()
+        }
+        _ => sign_up_url.to_owned(),
+    }
+}
+
+pub fn send_incorrect_new_password_mail() -> AppResult<()> {
     let env = EnvMailVariables::new();
     if !env.send_failed_signin_mail {
         return Ok(());
     }
 
-    let base_html = fs::read_to_string("./templates/email_base.html").unwrap();
+    let base_html = fs::read_to_string(template_path("email_base.html")).unwrap();
     let new_password_fail_html =
-        fs::read_to_string("./templates/new_password_failed.html").unwrap();
+        fs::read_to_string(template_path("new_password_failed.html")).unwrap();
     let (_user, properties) = get_data();
     let mailer = load_mailer(&env)?;
     let name = get_set_name(None);
@@ -615,27 +1213,29 @@ pub fn send_incorrect_new_password_mail() -> GenResult<()> {
         footer => create_footer().unwrap_or_default()
     )?;
 
-    let email = Message::builder()
-        .from(format!("{APPLICATION_NAME} <{}>", &env.mail_from).parse()?)
-        .to(format!("{} <{}>", &name, &env.mail_to.0.expose_secret()).parse()?)
-        .subject("Opgegeven Webcomm wachtwoord incorrect")
-        .header(ContentType::TEXT_HTML)
-        .body(email_body_html)?;
-    mailer.send(&email)?;
+    let email = user_mail_builder(
+        format!("{APPLICATION_NAME} <{}>", &env.mail_from),
+        &name,
+        &env,
+    )?
+    .subject("Opgegeven Webcomm wachtwoord incorrect")
+    .header(ContentType::TEXT_HTML)
+    .body(email_body_html)?;
+    mailer.send_message(&email)?;
     Ok(())
 }
 
 pub fn send_failed_signin_mail(
     error: &IncorrectCredentialsCount,
     first_time: bool,
-) -> GenResult<()> {
+) -> AppResult<()> {
     let env = EnvMailVariables::new();
     if !env.send_failed_signin_mail {
         return Ok(());
     }
 
-    let base_html = fs::read_to_string("./templates/email_base.html").unwrap();
-    let login_failure_html = fs::read_to_string("./templates/failed_signin.html").unwrap();
+    let base_html = fs::read_to_string(template_path("email_base.html")).unwrap();
+    let login_failure_html = fs::read_to_string(template_path("failed_signin.html")).unwrap();
     let (_user, properties) = get_data();
     info!("Sending failed sign in mail");
     let mailer = load_mailer(&env)?;
@@ -668,13 +1268,15 @@ pub fn send_failed_signin_mail(
         footer => create_footer().unwrap_or_default()
     )?;
 
-    let email = Message::builder()
-        .from(format!("{APPLICATION_NAME} <{}>", &env.mail_from).parse()?)
-        .to(format!("{} <{}>", &name, &env.mail_to.0.expose_secret()).parse()?)
-        .subject("INLOGGEN WEBCOM NIET GELUKT!")
-        .header(ContentType::TEXT_HTML)
-        .body(email_body_html)?;
-    mailer.send(&email)?;
+    let email = user_mail_builder(
+        format!("{APPLICATION_NAME} <{}>", &env.mail_from),
+        &name,
+        &env,
+    )?
+    .subject("INLOGGEN WEBCOM NIET GELUKT!")
+    .header(ContentType::TEXT_HTML)
+    .body(email_body_html)?;
+    mailer.send_message(&email)?;
     Ok(())
 }
 
@@ -688,15 +1290,15 @@ fn create_new_password_form_html(password_reset_link: &str) -> String {
 </tr>")
 }
 
-pub fn send_sign_in_succesful() -> GenResult<()> {
+pub fn send_sign_in_succesful() -> AppResult<()> {
     let env = EnvMailVariables::new();
 
     if !env.send_failed_signin_mail {
         return Ok(());
     }
 
-    let base_html = fs::read_to_string("./templates/email_base.html").unwrap();
-    let login_success_html = fs::read_to_string("./templates/signin_succesful.html").unwrap();
+    let base_html = fs::read_to_string(template_path("email_base.html")).unwrap();
+    let login_success_html = fs::read_to_string(template_path("signin_succesful.html")).unwrap();
     let name = get_set_name(None);
     info!("Sending succesful sign in mail");
 
@@ -710,50 +1312,175 @@ pub fn send_sign_in_succesful() -> GenResult<()> {
         footer => create_footer().unwrap_or_default()
     )?;
 
-    let email = Message::builder()
-        .from(format!("{APPLICATION_NAME} <{}>", &env.mail_from).parse()?)
-        .to(format!("{} <{}>", name, &env.mail_to.0.expose_secret()).parse()?)
-        .subject(format!("{APPLICATION_NAME} kan weer inloggen!"))
-        .header(ContentType::TEXT_HTML)
-        .body(email_body_html)?;
-    mailer.send(&email)?;
+    let email = user_mail_builder(
+        format!("{APPLICATION_NAME} <{}>", &env.mail_from),
+        &name,
+        &env,
+    )?
+    .subject(format!("{APPLICATION_NAME} kan weer inloggen!"))
+    .header(ContentType::TEXT_HTML)
+    .body(email_body_html)?;
+    mailer.send_message(&email)?;
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{cell::RefCell, sync::Arc};
+
     use time::Date;
 
     use super::*;
+    use crate::{GENERAL_PROPERTIES, GenResult, NAME, USER_PROPERTIES};
+
     #[test]
     fn send_new_shift_mail() -> GenResult<()> {
-        let shift = create_example_shift();
-        let (env, mailer) = get_mailer()?;
-        create_send_new_email(&mailer, vec![&shift, &shift], &env, false)
+        with_example_data(|| {
+            let shift = create_example_shift();
+            let (env, mailer) = get_mailer();
+            create_send_new_email(
+                &mailer,
+                vec![&shift, &shift],
+                &[&shift],
+                &HashMap::new(),
+                &env,
+                false,
+            )?;
+            let sent = mailer.sent.lock().unwrap();
+            assert_eq!(sent.len(), 1);
+            let mail = String::from_utf8(sent[0].formatted())?;
+            assert!(mail.contains("Je hebt 2 nieuwe diensten"));
+            assert!(mail.contains("test@example.com"));
+            assert!(mail.contains("background-color:"));
+            assert!(
+                mail.lines()
+                    .any(|line| line.starts_with("Reply-To:")
+                        && line.contains("support@example.com"))
+            );
+            assert!(mail.contains("X-Mijn-Bussie-User: test_user"));
+            Ok(())
+        })
     }
 
     #[test]
     fn send_updated_shift_mail() -> GenResult<()> {
-        let shift = create_example_shift();
-        let (env, mailer) = get_mailer()?;
-        create_send_new_email(&mailer, vec![&shift, &shift], &env, true)
+        with_example_data(|| {
+            let shift = create_example_shift();
+            let (env, mailer) = get_mailer();
+            let violation = RestViolation {
+                before: Some(time::Duration::hours(8)),
+                after: None,
+            };
+            let violations = HashMap::from([(shift.magic_number, violation)]);
+            create_send_new_email(&mailer, vec![&shift], &[&shift], &violations, &env, true)?;
+            let sent = mailer.sent.lock().unwrap();
+            assert_eq!(sent.len(), 1);
+            assert!(String::from_utf8(sent[0].formatted())?.contains("Je hebt 1 geupdate dienst"));
+            Ok(())
+        })
     }
 
     #[test]
     fn send_deleted_shift_mail() -> GenResult<()> {
-        let shift = create_example_shift();
-        let (env, mailer) = get_mailer()?;
-        send_removed_shifts_mail(&mailer, &env, vec![&shift, &shift])
+        with_example_data(|| {
+            let shift = create_example_shift();
+            let (env, mailer) = get_mailer();
+            send_removed_shifts_mail(&mailer, &env, vec![&shift, &shift])?;
+            assert_eq!(mailer.sent.lock().unwrap().len(), 1);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn send_overtime_mail_test() -> GenResult<()> {
+        with_example_data(|| {
+            let (env, mailer) = get_mailer();
+            let week = WeekHours {
+                year: 2025,
+                week: 26,
+                duration: time::Duration::hours(50),
+            };
+            send_overtime_mail(&mailer, &env, &[week], 48)?;
+            let sent = mailer.sent.lock().unwrap();
+            assert_eq!(sent.len(), 1);
+            assert!(String::from_utf8(sent[0].formatted())?.contains("een week te veel uren"));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn send_reserve_call_out_mail_test() -> GenResult<()> {
+        with_example_data(|| {
+            let shift = create_example_shift();
+            let mut reserve_shift = shift.clone();
+            reserve_shift.number = "R0001".to_owned();
+            let (env, mailer) = get_mailer();
+            send_reserve_call_out_mail(&mailer, &env, &reserve_shift, &shift, &HashMap::new())?;
+            let sent = mailer.sent.lock().unwrap();
+            assert_eq!(sent.len(), 1);
+            let mail = String::from_utf8(sent[0].formatted())?;
+            assert!(mail.contains("Reservedienst omgezet: V2309"));
+            assert!(mail.contains("X-Priority: 1 (Highest)"));
+            Ok(())
+        })
     }
 
     #[test]
     fn send_welcome_mail_test() -> GenResult<()> {
-        send_welcome_mail(true)
+        Ok(with_example_data(|| {
+            send_welcome_mail(DEFAULT_ONBOARDING_TEMPLATE)
+        })?)
+    }
+
+    #[test]
+    fn preview_onboarding_goes_to_support() -> GenResult<()> {
+        with_example_data(|| {
+            let preview = preview_onboarding(false)?;
+            assert_eq!(preview.mails.len(), 2);
+            assert!(!preview.sent);
+            assert!(
+                preview
+                    .mails
+                    .iter()
+                    .all(|mail| mail.contains(&format!("<{}>", preview.recipient)))
+            );
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn send_stale_calendar_mail_test() -> GenResult<()> {
+        Ok(with_example_data(|| send_stale_calendar_mail(50))?)
+    }
+
+    #[test]
+    fn send_donation_reminder_mail_test() -> GenResult<()> {
+        Ok(with_example_data(send_donation_reminder_mail)?)
+    }
+
+    #[test]
+    fn send_weekly_summary_mail_test() -> GenResult<()> {
+        with_example_data(|| send_weekly_summary_mail(42, Some(&create_example_shift())))?;
+        Ok(with_example_data(|| send_weekly_summary_mail(0, None))?)
+    }
+
+    #[test]
+    fn send_new_user_mail_test() -> GenResult<()> {
+        Ok(with_example_data(|| {
+            send_new_user_mail(false, "SignInFailed")
+        })?)
+    }
+
+    #[test]
+    fn send_mail_paused_mail_test() -> GenResult<()> {
+        Ok(with_example_data(|| {
+            send_mail_paused_mail("Hard bounce: 550 User unknown")
+        })?)
     }
 
     #[test]
     fn send_new_password_incorrect_mail() -> GenResult<()> {
-        send_incorrect_new_password_mail()
+        Ok(with_example_data(send_incorrect_new_password_mail)?)
     }
 
     #[test]
@@ -763,21 +1490,44 @@ mod tests {
             error: Some(SignInFailure::IncorrectCredentials),
             previous_password_hash: None,
         };
-        send_failed_signin_mail(&credential_error, false)
+        Ok(with_example_data(|| {
+            send_failed_signin_mail(&credential_error, false)
+        })?)
     }
 
     #[test]
     fn send_succesful_sign_in() -> GenResult<()> {
-        send_sign_in_succesful()
+        Ok(with_example_data(send_sign_in_succesful)?)
+    }
+
+    #[test]
+    fn sign_up_url_contains_invite() {
+        assert_eq!(
+            sign_up_url("https://example.com/signup", Some("abc123")),
+            "https://example.com/signup?invite=abc123"
+        );
+        assert_eq!(
+            sign_up_url("https://example.com/signup", None),
+            "https://example.com/signup"
+        );
+        assert_eq!(sign_up_url("", Some("abc123")), "");
+    }
+
+    // Run the function as if it was running inside of an instance
+    fn with_example_data<T>(function: impl FnOnce() -> T) -> T {
+        USER_PROPERTIES.sync_scope(RefCell::new(Some(Arc::new(UserData::example()))), || {
+            GENERAL_PROPERTIES.sync_scope(
+                RefCell::new(Some(Arc::new(GeneralProperties::example()))),
+                || NAME.sync_scope(RefCell::new(None), function),
+            )
+        })
     }
 
     fn create_example_shift() -> Shift {
         Shift::new("Dienst: V2309 •  • Geldig vanaf: 29.06.2025 •  • Tijd: 06:14 - 13:54 •  • Dienstduur: 07:40 Uren •  • Loonuren: 07:40 Uren •  • Dagsoort:  • Donderdag •  • Dienstsoort:  • Rijdienst •  • Startplaats:  • ehvgas, Einhoven garage streek •  • Omschrijving:  • V".to_owned(),Date::from_calendar_date(2025, time::Month::June, 29).unwrap()).unwrap()
     }
 
-    fn get_mailer() -> GenResult<(EnvMailVariables, SmtpTransport)> {
-        let env = EnvMailVariables::new();
-        let mailer = load_mailer(&env)?;
-        Ok((env, mailer))
+    fn get_mailer() -> (EnvMailVariables, MemoryMailTransport) {
+        (EnvMailVariables::new(), MemoryMailTransport::default())
     }
 }
@@ -0,0 +1,172 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use tracing::*;
+
+use crate::{GenResult, errors::ErrorCategory, get_data, webcom::email::send_error_digest};
+
+// Errors waiting to be mailed, per properties group. Every group has its own support address and mail settings
+static ERROR_DIGEST: LazyLock<Mutex<HashMap<i32, ErrorDigest>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BufferedError {
+    pub user_name: String,
+    pub category: ErrorCategory,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+struct ErrorDigest {
+    pending: Vec<BufferedError>,
+    last_sent: Option<Instant>,
+}
+
+impl ErrorDigest {
+    // Returns the errors to mail if the window since the last mail has passed
+    fn take_due(&mut self, now: Instant, window: Duration) -> Option<Vec<BufferedError>> {
+        if self.pending.is_empty()
+            || self
+                .last_sent
+                .is_some_and(|last_sent| now < last_sent + window)
+        {
+            return None;
+        }
+        self.last_sent = Some(now);
+        Some(std::mem::take(&mut self.pending))
+    }
+}
+
+/*
+A Webcomm outage makes every user fail at the same time, which used to mean a mail per user to the support address
+Errors are buffered instead, the first one is mailed right away and everything after that is combined
+into at most one mail per window, grouped by the category of the error
+Errors only end up in the mail of the properties group of the user, other groups have their own digest
+*/
+pub fn queue_errors<E: Display>(
+    errors: &[E],
+    name: &str,
+    category: ErrorCategory,
+) -> GenResult<()> {
+    let (_user, properties) = get_data();
+    let buffered = BufferedError {
+        user_name: name.to_owned(),
+        category,
+        errors: errors.iter().map(|error| error.to_string()).collect(),
+    };
+    ERROR_DIGEST
+        .lock()
+        .map_err(|_| "Error digest lock poisoned")?
+        .entry(properties.general_properties_id)
+        .or_default()
+        .pending
+        .push(buffered);
+    flush_error_digest()
+}
+
+// Called after every run of every instance, so buffered errors are sent once the window passed
+pub fn flush_error_digest() -> GenResult<()> {
+    let (_user, properties) = get_data();
+    let group = properties.general_properties_id;
+    let window = Duration::from_secs(properties.error_mail_window_minutes.max(0) as u64 * 60);
+    let Some(entries) = ERROR_DIGEST
+        .lock()
+        .map_err(|_| "Error digest lock poisoned")?
+        .get_mut(&group)
+        .and_then(|digest| digest.take_due(Instant::now(), window))
+    else {
+        return Ok(());
+    };
+    let (subject, body) = render_digest(&entries);
+    if let Err(err) = send_error_digest(&subject, &body) {
+        // Put them back, so they are tried again in the next window
+        if let Ok(mut digests) = ERROR_DIGEST.lock() {
+            let digest = digests.entry(group).or_default();
+            let mut entries = entries;
+            entries.append(&mut digest.pending);
+            digest.pending = entries;
+        }
+        return Err(err.into());
+    }
+    info!("Sent error mail with {} errors", entries.len());
+    Ok(())
+}
+
+fn render_digest(entries: &[BufferedError]) -> (String, String) {
+    let mut grouped: BTreeMap<String, Vec<&BufferedError>> = BTreeMap::new();
+    for entry in entries {
+        grouped
+            .entry(format!("{:?}", entry.category))
+            .or_default()
+            .push(entry);
+    }
+    let mut user_names: Vec<&str> = entries
+        .iter()
+        .map(|entry| entry.user_name.as_str())
+        .collect();
+    user_names.sort();
+    user_names.dedup();
+    let subject = match user_names.as_slice() {
+        [name] => format!("Fout bij laden shifts van: {name}"),
+        names => format!("Fouten bij laden shifts van {} gebruikers", names.len()),
+    };
+    let mut body = "Er zijn fouten opgetreden tijdens het laden van shifts\n".to_string();
+    for (failure, entries) in grouped {
+        body.push_str(&format!("\n=== {failure} ({}x) ===\n", entries.len()));
+        for entry in entries {
+            body.push_str(&format!("\n{}:\n", entry.user_name));
+            for error in &entry.errors {
+                body.push_str(&format!("Error: \n{error}\n\n"));
+            }
+        }
+    }
+    (subject, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffered(user_name: &str, category: ErrorCategory) -> BufferedError {
+        BufferedError {
+            user_name: user_name.to_owned(),
+            category,
+            errors: vec!["timeout".to_owned()],
+        }
+    }
+
+    #[test]
+    fn at_most_one_mail_per_window() {
+        let window = Duration::from_secs(3600);
+        let start = Instant::now();
+        let mut digest = ErrorDigest::default();
+        assert_eq!(digest.take_due(start, window), None);
+        digest.pending.push(buffered("Piet", ErrorCategory::Other));
+        assert_eq!(digest.take_due(start, window).unwrap().len(), 1);
+        digest.pending.push(buffered("Klaas", ErrorCategory::Other));
+        digest.pending.push(buffered("Henk", ErrorCategory::Other));
+        assert_eq!(
+            digest.take_due(start + Duration::from_secs(60), window),
+            None
+        );
+        assert_eq!(digest.take_due(start + window, window).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn digest_is_grouped_by_failure() {
+        let (subject, body) = render_digest(&[
+            buffered("Piet", ErrorCategory::Webcom),
+            buffered("Klaas", ErrorCategory::Browser),
+            buffered("Henk", ErrorCategory::Webcom),
+        ]);
+        assert_eq!(subject, "Fouten bij laden shifts van 3 gebruikers");
+        assert!(body.contains("=== Webcom (2x) ==="));
+        assert!(body.find("=== Browser").unwrap() < body.find("=== Webcom").unwrap());
+        let (subject, _body) = render_digest(&[buffered("Piet", ErrorCategory::Browser)]);
+        assert_eq!(subject, "Fout bij laden shifts van: Piet");
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+use icalendar::{Component, Event};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    GenResult, create_path,
+    database::state_file::{read_state_file, write_state_file},
+    errors::OptionResult,
+    webcom::ical::CALENDAR_VERSION,
+};
+
+const EVENT_STORE_PATH: &str = "event_store.json";
+const CALENDAR_END: &str = "END:VCALENDAR\r\n";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct StoredEvent {
+    // Hash of everything the event is made from
+    fingerprint: u64,
+    text: String,
+}
+
+/*
+Every event of the last created calendar, already serialized and keyed by uid
+Serializing the events is most of the work of creating a calendar, so events made from the same input are reused as is
+The calendar version is stored as well, a new version means the events look different so all of them are thrown away
+*/
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct EventStore {
+    calendar_version: String,
+    events: HashMap<String, StoredEvent>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct EventChanges {
+    pub added: usize,
+    pub changed: usize,
+    pub removed: usize,
+    pub reused: usize,
+}
+
+impl EventStore {
+    pub fn load() -> Self {
+        read_state_file(create_path(EVENT_STORE_PATH))
+            .ok()
+            .and_then(|text| serde_json::from_str::<Self>(&text).ok())
+            .filter(|store| store.calendar_version == CALENDAR_VERSION)
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> GenResult<()> {
+        write_state_file(create_path(EVENT_STORE_PATH), serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn render(&mut self) -> EventRenderer<'_> {
+        EventRenderer {
+            store: self,
+            events: HashMap::new(),
+            output: String::new(),
+            changes: EventChanges::default(),
+        }
+    }
+}
+
+// Collects the events of a single calendar, in the order they are pushed
+pub struct EventRenderer<'a> {
+    store: &'a mut EventStore,
+    events: HashMap<String, StoredEvent>,
+    output: String,
+    changes: EventChanges,
+}
+
+impl EventRenderer<'_> {
+    // create is only called if the event is new or its fingerprint changed
+    pub fn push(&mut self, uid: String, fingerprint: u64, create: impl FnOnce() -> Event) {
+        let event = match self.store.events.remove(&uid) {
+            Some(stored) if stored.fingerprint == fingerprint => {
+                self.changes.reused += 1;
+                stored
+            }
+            stored => {
+                match stored {
+                    Some(_) => self.changes.changed += 1,
+                    None => self.changes.added += 1,
+                }
+                StoredEvent {
+                    fingerprint,
+                    text: create().to_string(),
+                }
+            }
+        };
+        self.output.push_str(&event.text);
+        self.events.insert(uid, event);
+    }
+
+    // Replaces the store with the pushed events, everything which was not pushed again is removed
+    pub fn finish(self) -> (String, EventChanges) {
+        let mut changes = self.changes;
+        changes.removed = self.store.events.len();
+        self.store.events = self.events;
+        self.store.calendar_version = CALENDAR_VERSION.to_owned();
+        (self.output, changes)
+    }
+}
+
+pub fn fingerprint(input: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    input.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Puts the serialized events in a calendar which does not contain any events yet
+pub fn insert_events(calendar: &str, events: &str) -> GenResult<String> {
+    let header = calendar
+        .strip_suffix(CALENDAR_END)
+        .result_reason("Calendar does not end with END:VCALENDAR")?;
+    Ok(format!("{header}{events}{CALENDAR_END}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn render(store: &mut EventStore, events: &[(&str, u64)], created: &Cell<usize>) -> String {
+        let mut renderer = store.render();
+        for (uid, fingerprint) in events {
+            renderer.push(uid.to_string(), *fingerprint, || {
+                created.set(created.get() + 1);
+                Event::new()
+                    .uid(uid)
+                    .summary(&fingerprint.to_string())
+                    .done()
+            });
+        }
+        renderer.finish().0
+    }
+
+    #[test]
+    fn unchanged_events_are_reused() {
+        let mut store = EventStore::default();
+        let created = Cell::new(0);
+        let first = render(&mut store, &[("a", 1), ("b", 2)], &created);
+        assert_eq!(created.get(), 2);
+        let second = render(&mut store, &[("a", 1), ("b", 2)], &created);
+        assert_eq!(created.get(), 2);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn changes_are_counted() {
+        let mut store = EventStore::default();
+        let created = Cell::new(0);
+        render(&mut store, &[("a", 1), ("b", 2), ("c", 3)], &created);
+        let mut renderer = store.render();
+        renderer.push("a".to_owned(), 1, || Event::new().done());
+        renderer.push("b".to_owned(), 5, || Event::new().done());
+        renderer.push("d".to_owned(), 4, || Event::new().done());
+        let (_output, changes) = renderer.finish();
+        assert_eq!(
+            changes,
+            EventChanges {
+                added: 1,
+                changed: 1,
+                removed: 1,
+                reused: 1
+            }
+        );
+        assert!(!store.events.contains_key("c"));
+        assert_eq!(store.events.len(), 3);
+    }
+
+    #[test]
+    fn events_are_placed_before_the_end() {
+        let calendar = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nEND:VCALENDAR\r\n";
+        assert_eq!(
+            insert_events(calendar, "BEGIN:VEVENT\r\nEND:VEVENT\r\n").unwrap(),
+            "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n"
+        );
+        assert!(insert_events("", "").is_err());
+    }
+}
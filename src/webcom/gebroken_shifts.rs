@@ -1,7 +1,6 @@
 use crate::{
     GenResult,
     errors::ResultLog,
-    get_data,
     webcom::email::{DATE_DESCRIPTION, TIME_DESCRIPTION},
     webcom::shift::Shift,
     webcom::shift::ShiftState,
@@ -67,20 +66,24 @@ A small function to combine the three functions needed for creating a broken shi
 */
 async fn get_broken_shift_time(driver: &WebDriver, shift: &mut Shift) -> GenResult<()> {
     let broken_diensten = load_broken_dienst_page(driver, &shift).await?;
-    let between_times = find_broken_start_stop_time(broken_diensten).await?;
+    let (between_times, pause_locations) = find_broken_start_stop_time(broken_diensten).await?;
     shift.broken_period = Some(between_times);
+    shift.pause_locations = pause_locations;
     Ok(())
 }
 
 /*
 Looks for a time difference between one event in the shift info and the next
 If something boes wrong, skip it.
+Also returns where every break is spent, which is the end location of the activity before it
 */
 pub async fn find_broken_start_stop_time(
     shift_rows: Vec<WebElement>,
-) -> GenResult<Vec<(Time, Time)>> {
+) -> GenResult<(Vec<(Time, Time)>, Vec<String>)> {
     let mut broken_periods: Vec<(Time, Time)> = vec![];
+    let mut pause_locations: Vec<String> = vec![];
     let mut previous_element_end_time = None;
+    let mut previous_element_end_location = String::new();
     for activity in shift_rows {
         let activity_columns = activity.query(By::Tag("td")).all_from_selector().await?;
         let (activity_start_time, activity_end_time) = match async || -> GenResult<(Time, Time)> {
@@ -104,58 +107,18 @@ pub async fn find_broken_start_stop_time(
             let time_difference = activity_start_time - previous_time;
             if time_difference > Duration::minutes(10) {
                 broken_periods.push((previous_time, activity_start_time));
+                pause_locations.push(previous_element_end_location.clone());
             }
         }
         previous_element_end_time = Some(activity_end_time);
+        // The location is only used in the calendar, so an empty one is fine
+        previous_element_end_location = match activity_columns.get(4) {
+            Some(column) => column.text().await.unwrap_or_default().trim().to_owned(),
+            None => String::new(),
+        };
     }
-    debug!("Broken periods found: {broken_periods:?}");
-    Ok(broken_periods)
-}
-
-/*
-A function created to overcome a limitation of gnome calendar
-https://gitlab.gnome.org/GNOME/gnome-calendar/-/issues/944
-Not needed for most people
-*/
-pub fn split_night_shift(shifts: &Vec<Shift>) -> GenResult<Vec<Shift>> {
-    let (user, _properties) = get_data();
-    let split = user.user_properties.split_night_shift;
-    let mut temp_shift: Vec<Shift> = vec![];
-    if !split {
-        temp_shift = shifts.clone();
-        return Ok(temp_shift);
-    }
-    for shift in shifts {
-        if shift.end_date != shift.date {
-            let split_shift = Shift::new_from_existing(
-                (
-                    Time::from_hms(0, 0, 0).unwrap(),
-                    Time::from_hms(0, 0, 0).unwrap(),
-                ),
-                shift,
-                true,
-            );
-            temp_shift.extend(split_shift);
-        } else {
-            temp_shift.push(shift.clone());
-        }
-    }
-    Ok(temp_shift)
-}
-
-// Function to stop shifts at midnight. This is a request from Jerry
-pub fn stop_shift_at_midnight(shifts: &Vec<Shift>) -> Vec<Shift> {
-    let mut temp_shifts: Vec<Shift> = vec![];
-    for shift in shifts {
-        let mut shift_clone = shift.clone();
-        if shift.end_date != shift.date {
-            shift_clone.original_end_time = Some(shift_clone.end);
-            shift_clone.end = Time::from_hms(23, 59, 0).unwrap();
-            shift_clone.end_date = shift_clone.date;
-        }
-        temp_shifts.push(shift_clone);
-    }
-    temp_shifts
+    debug!("Broken periods found: {broken_periods:?} at {pause_locations:?}");
+    Ok((broken_periods, pause_locations))
 }
 
 /*
@@ -189,28 +152,6 @@ pub async fn navigate_to_subdirectory(
     Ok(())
 }
 
-// This function clones a vec of shifts and splits broken shifts, if that value is set
-pub fn split_broken_shifts(shifts: &Vec<Shift>) -> Vec<Shift> {
-    let mut shifts_clone: Vec<Shift> = shifts.iter().cloned().collect();
-    let mut shifts_to_append = vec![];
-    let vec_len = shifts_clone.len() - 1;
-    for shift in shifts.iter().rev().enumerate() {
-        let position = vec_len - shift.0;
-        if shift.1.broken_period.is_some() {
-            if let Some(mut shifts_split) = shift.1.split_broken() {
-                debug!(
-                    "Broken shift {} has broken shift times of {:?}",
-                    shift.1.number, shift.1.broken_period
-                );
-                shifts_clone.remove(position);
-                shifts_to_append.append(&mut shifts_split);
-            }
-        }
-    }
-    shifts_clone.append(&mut shifts_to_append);
-    shifts_clone
-}
-
 /*
 A simple function to wait until a page is truly fully loaded
 You need to provide a element on the page to wait for
@@ -0,0 +1,147 @@
+use time::{Date, Duration, Month, PrimitiveDateTime, Time, Weekday};
+
+// Shown next to shifts on a public holiday, in the mails and in the calendar
+pub const HOLIDAY_LABEL: &str = "🎉 feestdagtoeslag";
+
+// Liberation day is only a day off in lustrum years
+const LIBERATION_DAY_INTERVAL: i32 = 5;
+
+/*
+The Dutch public holidays of the CAO, calculated instead of loaded from a list so it never runs out of years
+*/
+pub fn holiday_name(date: Date) -> Option<&'static str> {
+    let year = date.year();
+    let fixed = match (date.month(), date.day()) {
+        (Month::January, 1) => Some("Nieuwjaarsdag"),
+        (Month::May, 5) if year % LIBERATION_DAY_INTERVAL == 0 => Some("Bevrijdingsdag"),
+        (Month::December, 25) => Some("Eerste Kerstdag"),
+        (Month::December, 26) => Some("Tweede Kerstdag"),
+        _ => None,
+    };
+    if fixed.is_some() {
+        return fixed;
+    }
+    if date == kings_day(year)? {
+        return Some("Koningsdag");
+    }
+    let easter = easter_sunday(year)?;
+    match (date - easter).whole_days() {
+        -2 => Some("Goede Vrijdag"),
+        0 => Some("Eerste Paasdag"),
+        1 => Some("Tweede Paasdag"),
+        39 => Some("Hemelvaartsdag"),
+        49 => Some("Eerste Pinksterdag"),
+        50 => Some("Tweede Pinksterdag"),
+        _ => None,
+    }
+}
+
+// Minutes between start and end which fall on a public holiday, every calendar day is only checked once
+pub fn holiday_minutes_between(start: PrimitiveDateTime, end: PrimitiveDateTime) -> i64 {
+    let mut minutes = 0;
+    let mut day_start = start;
+    while day_start < end {
+        let Some(next_day) = day_start.date().next_day() else {
+            break;
+        };
+        let day_end = PrimitiveDateTime::new(next_day, Time::MIDNIGHT).min(end);
+        if holiday_name(day_start.date()).is_some() {
+            minutes += (day_end - day_start).whole_minutes();
+        }
+        day_start = day_end;
+    }
+    minutes
+}
+
+// Kings day moves to the saturday before if the 27th of april is a sunday
+fn kings_day(year: i32) -> Option<Date> {
+    let date = Date::from_calendar_date(year, Month::April, 27).ok()?;
+    Some(if date.weekday() == Weekday::Sunday {
+        date - Duration::DAY
+    } else {
+        date
+    })
+}
+
+// Anonymous gregorian algorithm (Meeus/Jones/Butcher)
+fn easter_sunday(year: i32) -> Option<Date> {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    Date::from_calendar_date(year, Month::try_from(month as u8).ok()?, day as u8).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::date;
+
+    #[test]
+    fn easter_is_calculated() {
+        assert_eq!(easter_sunday(2024), Some(date!(2024 - 03 - 31)));
+        assert_eq!(easter_sunday(2025), Some(date!(2025 - 04 - 20)));
+        assert_eq!(easter_sunday(2026), Some(date!(2026 - 04 - 05)));
+    }
+
+    #[test]
+    fn moving_holidays_follow_easter() {
+        assert_eq!(holiday_name(date!(2025 - 04 - 18)), Some("Goede Vrijdag"));
+        assert_eq!(holiday_name(date!(2025 - 04 - 21)), Some("Tweede Paasdag"));
+        assert_eq!(holiday_name(date!(2025 - 05 - 29)), Some("Hemelvaartsdag"));
+        assert_eq!(
+            holiday_name(date!(2025 - 06 - 09)),
+            Some("Tweede Pinksterdag")
+        );
+    }
+
+    #[test]
+    fn kings_day_moves_away_from_sunday() {
+        assert_eq!(holiday_name(date!(2025 - 04 - 26)), Some("Koningsdag"));
+        assert_eq!(holiday_name(date!(2025 - 04 - 27)), None);
+        assert_eq!(holiday_name(date!(2026 - 04 - 27)), Some("Koningsdag"));
+    }
+
+    #[test]
+    fn normal_days_are_not_holidays() {
+        assert_eq!(holiday_name(date!(2025 - 06 - 02)), None);
+        assert_eq!(holiday_name(date!(2026 - 05 - 05)), None);
+    }
+
+    #[test]
+    fn fixed_holidays_are_found() {
+        assert_eq!(holiday_name(date!(2025 - 12 - 25)), Some("Eerste Kerstdag"));
+        assert_eq!(holiday_name(date!(2025 - 05 - 05)), Some("Bevrijdingsdag"));
+        assert_eq!(holiday_name(date!(2030 - 05 - 05)), Some("Bevrijdingsdag"));
+    }
+
+    #[test]
+    fn holiday_minutes_are_split_per_day() {
+        use time::macros::datetime;
+        // A night shift from christmas eve into christmas day
+        assert_eq!(
+            holiday_minutes_between(
+                datetime!(2025 - 12 - 24 22:00),
+                datetime!(2025 - 12 - 25 02:30)
+            ),
+            150
+        );
+        assert_eq!(
+            holiday_minutes_between(
+                datetime!(2025 - 12 - 25 22:00),
+                datetime!(2025 - 12 - 27 01:00)
+            ),
+            26 * 60
+        );
+    }
+}
@@ -1,5 +1,14 @@
+use crate::database::calendar_share::BuddyShift;
+use crate::database::shift_history::ShiftHistoryEntry;
+use crate::database::shift_notes::ShiftNote;
+use crate::database::state_file::{read_state_file, write_state_file};
+use crate::database::variables::{GeneralProperties, UserData};
+use crate::execution::permissions::write_private_file;
+use crate::webcom::event_store::{EventStore, fingerprint, insert_events};
+use crate::webcom::holidays::{HOLIDAY_LABEL, holiday_name};
+use crate::webcom::rest_period::{RestViolation, rest_violations};
 use crate::{
-    FailureType, GenResult, create_ical_filename, create_path, create_shift_link, get_data,
+    FailureType, GenResult, create_ical_filename_local, create_path, create_shift_link, get_data,
     get_set_name, webcom::shift::Shift, webcom::shift::ShiftState,
 };
 use crate::{errors::ResultLog, webcom::email::TIME_DESCRIPTION};
@@ -11,7 +20,7 @@ use icalendar::{
 use serde_json::from_str;
 use std::{
     collections::HashMap,
-    fs::{self, read_to_string, write},
+    fs::{self, read_to_string},
     path::{Path, PathBuf},
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
@@ -33,11 +42,13 @@ impl ToNaive for time::Date {
 // Add B if it modifies of removes an already existing value
 // Add W if it is wanted to resend the welcome mail
 // Add F if you want to force replace relevant shifts
-pub const CALENDAR_VERSION: &str = "6F";
+pub const CALENDAR_VERSION: &str = "7F";
 
 const PREVIOUS_EXECUTION_DATE_PATH: &str = "previous_execution_date.json";
 pub const NON_RELEVANT_EVENTS_PATH: &str = "non_relevant_events.json";
 pub const RELEVANT_EVENTS_PATH: &str = "relevant_events.json";
+// Event uids look like <user id>-<shift uid>-<part>@EVENT_UID_DOMAIN, so calendar clients can update events in place
+const EVENT_UID_DOMAIN: &str = "bussie.app";
 
 #[derive(Debug, Error, Clone, Copy, PartialEq)]
 pub enum CalendarVersionError {
@@ -158,7 +169,7 @@ fn is_partial_calendar_regeneration_needed(
                 "Getting previous execution date went wrong. Err: {}",
                 err.to_string()
             );
-            _ = write(
+            _ = write_private_file(
                 create_path(PREVIOUS_EXECUTION_DATE_PATH),
                 serde_json::to_string(&current_date)?.as_bytes(),
             );
@@ -166,7 +177,7 @@ fn is_partial_calendar_regeneration_needed(
         }
     };
     debug!("Current date: {current_date}");
-    _ = write(
+    _ = write_private_file(
         create_path(PREVIOUS_EXECUTION_DATE_PATH),
         serde_json::to_string(&current_date)?.as_bytes(),
     );
@@ -202,12 +213,12 @@ fn event_to_shift(events: Vec<Event>) -> Vec<Shift> {
 // Save relevant shifts to disk
 pub fn save_partial_shift_files(shifts: &Vec<Shift>) -> GenResult<()> {
     let (relevant_shifts, non_relevant_shifts) = split_relevant_shifts(shifts.clone());
-    write(
+    write_state_file(
         create_path(RELEVANT_EVENTS_PATH),
         serde_json::to_string_pretty(&relevant_shifts)?,
     )
     .warn("Saving relevant shifts");
-    write(
+    write_state_file(
         create_path(NON_RELEVANT_EVENTS_PATH),
         serde_json::to_string_pretty(&non_relevant_shifts)?,
     )
@@ -215,6 +226,15 @@ pub fn save_partial_shift_files(shifts: &Vec<Shift>) -> GenResult<()> {
     Ok(())
 }
 
+// All shifts known after the last execution, relevant and non-relevant
+pub fn load_known_shifts() -> GenResult<Vec<Shift>> {
+    let mut shifts: Vec<Shift> = from_str(&read_state_file(create_path(RELEVANT_EVENTS_PATH))?)?;
+    let mut non_relevant_shifts: Vec<Shift> =
+        from_str(&read_state_file(create_path(NON_RELEVANT_EVENTS_PATH))?)?;
+    shifts.append(&mut non_relevant_shifts);
+    Ok(shifts)
+}
+
 #[derive(Debug, Default)]
 pub struct PreviousShifts {
     pub relevant_shifts: Vec<Shift>,
@@ -222,10 +242,14 @@ pub struct PreviousShifts {
 }
 
 pub fn get_ical_path() -> PathBuf {
-    let (_user, properties) = get_data();
+    let (user, properties) = get_data();
+    get_ical_path_local(&user, &properties)
+}
+
+pub fn get_ical_path_local(user: &UserData, properties: &GeneralProperties) -> PathBuf {
     let mut ical_path = PathBuf::new();
     ical_path.push(&properties.calendar_target);
-    ical_path.push(create_ical_filename());
+    ical_path.push(create_ical_filename_local(user));
     ical_path
 }
 
@@ -278,8 +302,8 @@ pub fn get_previous_shifts() -> GenResult<Result<PreviousShifts, CalendarVersion
         }))
     } else {
         info!("Calendar regeneration NOT needed");
-        let relevant_shift_str = read_to_string(create_path(RELEVANT_EVENTS_PATH))?;
-        let non_relevant_shifts_str = read_to_string(create_path(NON_RELEVANT_EVENTS_PATH))?;
+        let relevant_shift_str = read_state_file(create_path(RELEVANT_EVENTS_PATH))?;
+        let non_relevant_shifts_str = read_state_file(create_path(NON_RELEVANT_EVENTS_PATH))?;
         let previous_relevant_shifts: Vec<Shift> = serde_json::from_str(&relevant_shift_str)?;
         // All relevant shifts MUST FIRST BE MARKED AS DELETED for deleted shift detection to work
         let previous_relevant_shifts = previous_relevant_shifts
@@ -298,7 +322,13 @@ pub fn get_previous_shifts() -> GenResult<Result<PreviousShifts, CalendarVersion
     }
 }
 
-fn create_event(shift: &Shift, metadata: Option<&&Shift>) -> Event {
+fn create_event(
+    shift: &Shift,
+    uid: &str,
+    metadata: Option<&&Shift>,
+    rest_violation: Option<&RestViolation>,
+    note: Option<&ShiftNote>,
+) -> Event {
     let shift_link = create_shift_link(shift, true).unwrap_or("ERROR".to_owned());
     let cut_off_end_time = if let Some(end_time) = shift.original_end_time {
         format!(
@@ -308,18 +338,30 @@ fn create_event(shift: &Shift, metadata: Option<&&Shift>) -> Event {
     } else {
         String::new()
     };
+    let holiday = if let Some(holiday) = holiday_name(shift.date) {
+        format!("\n{HOLIDAY_LABEL} • {holiday}")
+    } else {
+        String::new()
+    };
+    let rest_warning = if let Some(violation) = rest_violation {
+        format!("\n⚠ {}", violation.description())
+    } else {
+        String::new()
+    };
     Event::new()
+        .uid(uid)
         .summary(&format!("{}{cut_off_end_time}", shift.number))
         .description(&format!(
             "Dienstsoort • {}
 Duur • {} uur {} minuten
 Omschrijving • {}
-Shift sheet • {}",
+Shift sheet • {}{holiday}{rest_warning}{}",
             shift.kind,
             shift.duration.whole_hours(),
             shift.duration.whole_minutes() % 60,
             shift.description,
-            shift_link
+            shift_link,
+            note.map(ShiftNote::description).unwrap_or_default()
         ))
         .location(&shift.location)
         .append_property(icalendar::Property::new(
@@ -331,28 +373,59 @@ Shift sheet • {}",
         .done()
 }
 
+/*
+An unpaid break of a broken shift as its own event
+It is marked as free time, so it does not block the agenda like the shift itself does
+*/
+fn create_pause_event(shift: &Shift, uid: &str, index: usize, start: Time, end: Time) -> Event {
+    let location = shift
+        .pause_locations
+        .get(index)
+        .filter(|location| !location.is_empty())
+        .map(|location| format!(" • {location}"))
+        .unwrap_or_default();
+    let end_date = if end < start {
+        shift.end_date
+    } else {
+        shift.date
+    };
+    Event::new()
+        .uid(uid)
+        .summary(&format!("Pauze {}{location}", shift.number))
+        .append_property(("TRANSP", "TRANSPARENT"))
+        .starts(create_dateperhapstime(shift.date, start))
+        .ends(create_dateperhapstime(end_date, end))
+        .done()
+}
+
 /*
 Creates the ICAL file to add to the calendar
 Needs previous exit code so it can add it to the calendar
 Will later be replaced with current exit code if its different
+Only events which are new or changed are serialized, the rest is taken from the event store
 */
 pub fn create_calendar_file(
     shifts: &Vec<Shift>,
     metadata: &Vec<Shift>,
     previous_exit_code: &FailureType,
+    notes: &HashMap<i64, ShiftNote>,
+    event_store: &mut EventStore,
 ) -> GenResult<String> {
     let (user, properties) = get_data();
     let metadata_shifts_hashmap: HashMap<i64, &Shift> =
         metadata.into_iter().map(|x| (x.magic_number, x)).collect();
+    // Checked on the original shifts, before they are split up
+    let rest_violations = rest_violations(metadata.iter(), user.user_properties.min_rest_hours);
     let name = get_set_name(None);
     // get the current systemtime as a unix timestamp
     let current_timestamp = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or(Duration::from_secs(0));
-    let heartbeat_interval: i32 = properties.expected_execution_time_seconds
+    let heartbeat_interval: i32 = user.expected_execution_time_seconds(&properties)
         + (user.user_properties.execution_interval_minutes * 60);
+    let refresh_interval = format!("PT{}M", user.user_properties.execution_interval_minutes);
     info!("Creating calendar file...");
-    let mut calendar = Calendar::new()
+    let calendar = Calendar::new()
         .name(&format!("Hermes rooster - {}", name))
         .append_property(("X-USER-NAME", name.as_str()))
         .append_property((
@@ -364,6 +437,13 @@ pub fn create_calendar_file(
             heartbeat_interval.to_string().as_str(),
         ))
         .append_property(("X-CAL-VERSION", CALENDAR_VERSION.to_string().as_str()))
+        // Tells calendar clients how often it is useful to fetch the calendar again
+        .append_property(("X-PUBLISHED-TTL", refresh_interval.as_str()))
+        .append_property(
+            icalendar::Property::new("REFRESH-INTERVAL", &refresh_interval)
+                .add_parameter("VALUE", "DURATION")
+                .done(),
+        )
         .append_property((
             "X-EXIT-CODE",
             serde_json::to_string(&previous_exit_code)
@@ -373,11 +453,127 @@ pub fn create_calendar_file(
         .append_property(("METHOD", "PUBLISH"))
         .timezone("Europe/Amsterdam")
         .done();
+    let mut events = event_store.render();
+    if user.user_properties.show_broken_pause {
+        for shift in metadata {
+            let Some(broken_periods) = &shift.broken_period else {
+                continue;
+            };
+            let event_fingerprint = fingerprint(shift);
+            for (index, (start, end)) in broken_periods.iter().enumerate() {
+                let uid = format!(
+                    "{}-{}-pauze-{index}@{EVENT_UID_DOMAIN}",
+                    user.id,
+                    shift.uid()
+                );
+                events.push(uid.clone(), event_fingerprint, || {
+                    create_pause_event(shift, &uid, index, *start, *end)
+                });
+            }
+        }
+    }
+    // Parts of the same shift get their own uid, numbered in the order they are in
+    let mut part_numbers: HashMap<String, usize> = HashMap::new();
     for shift in shifts {
         let metadata_shift = metadata_shifts_hashmap.get(&shift.magic_number);
-        calendar.push(create_event(&shift, metadata_shift));
+        // The parts of a night shift have different dates, so the uid of the original shift is used
+        let shift_uid = metadata_shift.map_or(shift.uid(), |original| original.uid());
+        let part_number = part_numbers.entry(shift_uid.clone()).or_insert(0);
+        let uid = format!("{}-{shift_uid}-{part_number}@{EVENT_UID_DOMAIN}", user.id);
+        *part_number += 1;
+        let rest_violation = rest_violations.get(&shift.magic_number);
+        let note = notes.get(&shift.magic_number);
+        // Everything the event is made from, the shift link depends on the domain
+        let event_fingerprint = fingerprint(&(
+            shift,
+            metadata_shift,
+            rest_violation.map(RestViolation::description),
+            note.map(ShiftNote::description),
+            &properties.pdf_shift_domain,
+        ));
+        events.push(uid.clone(), event_fingerprint, || {
+            create_event(&shift, &uid, metadata_shift, rest_violation, note)
+        });
+    }
+    let (events, changes) = events.finish();
+    info!(
+        "Calendar has {} new, {} changed and {} removed events",
+        changes.added, changes.changed, changes.removed
+    );
+    insert_events(&calendar.to_string(), &events)
+}
+
+/*
+Calendar with the shifts colleagues shared with this user
+Only contains the number and times, the shift sheets of others must not be reachable through it
+*/
+pub fn create_buddy_calendar(viewer_name: &str, shifts: &[BuddyShift]) -> String {
+    let mut calendar = Calendar::new()
+        .name(&format!("Hermes rooster - collega's van {viewer_name}"))
+        .append_property(("X-CAL-VERSION", CALENDAR_VERSION.to_string().as_str()))
+        .append_property(("METHOD", "PUBLISH"))
+        .timezone("Europe/Amsterdam")
+        .done();
+    for shift in shifts {
+        calendar.push(
+            Event::new()
+                .uid(&format!("{}@{EVENT_UID_DOMAIN}", shift.uid))
+                .summary(&format!("{} • {}", shift.owner, shift.number))
+                .starts(CalendarDateTime::WithTimezone {
+                    date_time: NaiveDateTime::new(shift.date, shift.start),
+                    tzid: "Europe/Amsterdam".to_string(),
+                })
+                .ends(CalendarDateTime::WithTimezone {
+                    date_time: NaiveDateTime::new(shift.end_date, shift.end),
+                    tzid: "Europe/Amsterdam".to_string(),
+                })
+                .done(),
+        );
+    }
+    calendar.to_string()
+}
+
+/*
+Static calendar of one year from the shift history, for the tax return or administration
+Deleted shifts are left out, it is never refreshed so it has no refresh interval or exit code
+*/
+pub fn create_yearly_calendar(user_name: &str, year: i32, shifts: &[ShiftHistoryEntry]) -> String {
+    let mut calendar = Calendar::new()
+        .name(&format!("Hermes rooster {year} - {user_name}"))
+        .append_property(("X-CAL-VERSION", CALENDAR_VERSION.to_string().as_str()))
+        .append_property(("METHOD", "PUBLISH"))
+        .timezone("Europe/Amsterdam")
+        .done();
+    for shift in shifts
+        .iter()
+        .filter(|shift| shift.state != ShiftState::Deleted)
+    {
+        calendar.push(
+            Event::new()
+                .uid(&format!("{year}-{}@{EVENT_UID_DOMAIN}", shift.uid))
+                .summary(&shift.number)
+                .description(&format!(
+                    "Dienstsoort • {}
+Duur • {} uur {} minuten
+Betaald • {} uur {} minuten",
+                    shift.kind,
+                    shift.duration_minutes / 60,
+                    shift.duration_minutes % 60,
+                    shift.working_minutes / 60,
+                    shift.working_minutes % 60
+                ))
+                .starts(CalendarDateTime::WithTimezone {
+                    date_time: NaiveDateTime::new(shift.date, shift.start),
+                    tzid: "Europe/Amsterdam".to_string(),
+                })
+                .ends(CalendarDateTime::WithTimezone {
+                    date_time: NaiveDateTime::new(shift.end_date, shift.end),
+                    tzid: "Europe/Amsterdam".to_string(),
+                })
+                .done(),
+        );
     }
-    Ok(String::from(calendar.to_string()))
+    calendar.to_string()
 }
 
 /*
@@ -0,0 +1,208 @@
+use std::{
+    cell::RefCell,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use crate::config::var;
+use lettre::{
+    Message, SmtpTransport, Transport,
+    transport::smtp::{PoolConfig, SUBMISSIONS_PORT, authentication::Credentials},
+};
+use secrecy::ExposeSecret;
+use tokio::task_local;
+use tracing::*;
+
+use crate::errors::AppResult;
+use crate::execution::permissions::write_private_file;
+use crate::{GenResult, create_path, is_dry_run, webcom::email::EnvMailVariables};
+
+// Tests should never send real mails
+const DEFAULT_MAIL_TRANSPORT: &str = if cfg!(test) { "memory" } else { "smtp" };
+// The memory transport only keeps the mails for tests to look at, outside of tests they would silently disappear
+const MAIL_TRANSPORTS: &[&str] = if cfg!(test) {
+    &["smtp", "file", "memory"]
+} else {
+    &["smtp", "file"]
+};
+
+// Checked at startup, so a typo is not only noticed once the first mail fails
+pub fn validate_mail_transport() -> Option<String> {
+    let transport = var("MAIL_TRANSPORT").unwrap_or(DEFAULT_MAIL_TRANSPORT.to_owned());
+    (!MAIL_TRANSPORTS.contains(&transport.as_str())).then(|| {
+        format!(
+            "Unknown MAIL_TRANSPORT {transport}, use one of {}",
+            MAIL_TRANSPORTS.join(", ")
+        )
+    })
+}
+
+// Everything that is able to deliver a mail
+pub trait MailTransport: Send + Sync {
+    fn send_message(&self, email: &Message) -> AppResult<()>;
+}
+
+pub struct SmtpMailTransport(SmtpTransport);
+
+impl MailTransport for SmtpMailTransport {
+    fn send_message(&self, email: &Message) -> AppResult<()> {
+        self.0.send(email)?;
+        Ok(())
+    }
+}
+
+// Writes every mail as an .eml file to a directory, used for dry runs
+pub struct FileMailTransport {
+    directory: PathBuf,
+}
+
+impl MailTransport for FileMailTransport {
+    fn send_message(&self, email: &Message) -> AppResult<()> {
+        fs::create_dir_all(&self.directory)?;
+        let mail_path = self.directory.join(format!(
+            "{}.eml",
+            chrono::offset::Utc::now().format("%Y%m%d_%H%M%S_%f")
+        ));
+        write_private_file(&mail_path, email.formatted())?;
+        info!("Mail saved to {mail_path:?} instead of sent");
+        Ok(())
+    }
+}
+
+// Keeps all mails in memory, so tests can check what would have been sent
+#[derive(Clone, Default)]
+pub struct MemoryMailTransport {
+    pub sent: Arc<Mutex<Vec<Message>>>,
+}
+
+impl MailTransport for MemoryMailTransport {
+    fn send_message(&self, email: &Message) -> AppResult<()> {
+        self.sent
+            .lock()
+            .map_err(|_| "Mail store poisoned")?
+            .push(email.clone());
+        Ok(())
+    }
+}
+
+// Drops every mail to an address which bounced, mails to other addresses go through
+pub struct PausedRecipientTransport {
+    inner: Box<dyn MailTransport>,
+    paused_address: String,
+}
+
+impl MailTransport for PausedRecipientTransport {
+    fn send_message(&self, email: &Message) -> AppResult<()> {
+        let to_paused = email.envelope().to().iter().any(|address| {
+            AsRef::<str>::as_ref(address).eq_ignore_ascii_case(&self.paused_address)
+        });
+        if to_paused {
+            warn!("Not sending mail, mails to this user are paused");
+            return Ok(());
+        }
+        self.inner.send_message(email)
+    }
+}
+
+// SMTP_PORT can be set for servers that don't use SMTP over TLS on 465
+pub fn smtp_port() -> u16 {
+    var("SMTP_PORT")
+        .ok()
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(SUBMISSIONS_PORT)
+}
+
+task_local! {
+    /*
+    A run can send a few mails right after each other, which would all open their own TLS session
+    The transport keeps a pool of connections, so it is kept for the rest of the run and dropped with it
+    Mails sent outside of a run get a transport of their own
+    */
+    pub static RUN_SMTP_TRANSPORT: RefCell<Option<SmtpTransport>>;
+}
+
+fn smtp_transport(env: &EnvMailVariables) -> GenResult<SmtpTransport> {
+    if let Some(transport) = RUN_SMTP_TRANSPORT
+        .try_with(|transport| transport.borrow().clone())
+        .ok()
+        .flatten()
+    {
+        return Ok(transport);
+    }
+    let creds = Credentials::new(env.smtp_username.clone(), env.smtp_password.clone());
+    let transport = SmtpTransport::relay(&env.smtp_server)?
+        .port(smtp_port())
+        .credentials(creds)
+        .pool_config(
+            PoolConfig::new()
+                .max_size(env.smtp_pool_max_size)
+                .idle_timeout(env.smtp_pool_idle_timeout),
+        )
+        .build();
+    _ = RUN_SMTP_TRANSPORT.try_with(|cached| cached.replace(Some(transport.clone())));
+    Ok(transport)
+}
+
+/*
+Select the transport using MAIL_TRANSPORT, which can be smtp or file. Tests use the memory transport
+A dry run always uses the file transport, so no mail can accidentally be sent
+*/
+pub fn load_mailer(env: &EnvMailVariables) -> GenResult<Box<dyn MailTransport>> {
+    let mailer = load_transport(env)?;
+    if !env.mail_paused {
+        return Ok(mailer);
+    }
+    Ok(Box::new(PausedRecipientTransport {
+        inner: mailer,
+        paused_address: env.mail_to.0.expose_secret().to_owned(),
+    }))
+}
+
+fn load_transport(env: &EnvMailVariables) -> GenResult<Box<dyn MailTransport>> {
+    let transport = var("MAIL_TRANSPORT").unwrap_or(DEFAULT_MAIL_TRANSPORT.to_owned());
+    if is_dry_run() {
+        return Ok(Box::new(FileMailTransport {
+            directory: create_path("dry_run_mails"),
+        }));
+    }
+    Ok(match transport.as_str() {
+        "file" => Box::new(FileMailTransport {
+            directory: create_path("mails"),
+        }),
+        "memory" if cfg!(test) => Box::new(MemoryMailTransport::default()),
+        "smtp" => Box::new(SmtpMailTransport(smtp_transport(env)?)),
+        unknown => return Err(format!("Unknown MAIL_TRANSPORT {unknown}").into()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mail_to(address: &str) -> Message {
+        Message::builder()
+            .from("bussie@example.com".parse().unwrap())
+            .to(address.parse().unwrap())
+            .body(String::new())
+            .unwrap()
+    }
+
+    #[test]
+    fn mails_to_paused_address_are_dropped() -> GenResult<()> {
+        let memory = MemoryMailTransport::default();
+        let mailer = PausedRecipientTransport {
+            inner: Box::new(memory.clone()),
+            paused_address: "User@example.com".to_owned(),
+        };
+        mailer.send_message(&mail_to("user@example.com"))?;
+        mailer.send_message(&mail_to("support@example.com"))?;
+        let sent = memory.sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(
+            AsRef::<str>::as_ref(&sent[0].envelope().to()[0]),
+            "support@example.com"
+        );
+        Ok(())
+    }
+}
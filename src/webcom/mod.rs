@@ -1,8 +1,27 @@
+pub mod admin_webhook;
+pub mod bounce;
+pub mod calendar_file;
 pub mod deletion;
+pub mod donation;
+pub mod earnings;
 pub mod email;
+pub mod error_digest;
+pub mod event_store;
 pub mod gebroken_shifts;
+pub mod holidays;
 pub mod ical;
+pub mod mail_transport;
+pub mod new_user;
+pub mod occurrence;
+pub mod overtime;
 pub mod parsing;
+pub mod rest_period;
 pub mod shift;
+pub mod shift_diff;
+pub mod sign_in_backfill;
+pub mod signed_off;
+pub mod time_sheet;
 pub mod webcom;
 pub mod webdriver;
+pub mod week_strip;
+pub mod weekly_summary;
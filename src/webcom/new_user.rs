@@ -0,0 +1,60 @@
+use tracing::*;
+
+use crate::{
+    GenResult,
+    database::variables::UserData,
+    errors::{FailureType, ResultLog},
+    webcom::{admin_webhook::send_admin_webhook, email::send_new_user_mail},
+};
+
+// Only incorrect credentials mean the sign in itself failed, every other failure happens after signing in
+fn first_sign_in_succeeded(exit_code: &FailureType) -> bool {
+    !matches!(exit_code, FailureType::SignInFailed(_))
+}
+
+/*
+Lets the admin know a new user finished their first run, so a broken signup is noticed right away
+Users are new if they never ran before, so it does not matter if they came in through the signup endpoint or the watchdog
+The mail goes to the support address, ADMIN_WEBHOOK_URL is optionally posted to as well
+*/
+pub async fn notify_new_user(exit_code: &FailureType, user: &UserData) {
+    if user.last_execution_date.is_some() {
+        return;
+    }
+    let sign_in_succeeded = first_sign_in_succeeded(exit_code);
+    info!("New user {} finished their first run", user.user_name);
+    send_new_user_mail(sign_in_succeeded, &exit_code.to_string()).warn("Sending new user mail");
+    send_new_user_webhook(&user.user_name, sign_in_succeeded, exit_code)
+        .await
+        .warn("Sending new user webhook");
+}
+
+async fn send_new_user_webhook(
+    user_name: &str,
+    sign_in_succeeded: bool,
+    exit_code: &FailureType,
+) -> GenResult<()> {
+    let message = format!(
+        "Nieuwe gebruiker {user_name}, eerste keer inloggen {}. Exit code: {exit_code}",
+        match sign_in_succeeded {
+            true => "gelukt",
+            false => "mislukt",
+        }
+    );
+    send_admin_webhook(&message).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::SignInFailure;
+
+    #[test]
+    fn only_sign_in_failures_count_as_failed() {
+        assert!(first_sign_in_succeeded(&FailureType::OK));
+        assert!(first_sign_in_succeeded(&FailureType::TriesExceeded));
+        assert!(!first_sign_in_succeeded(&FailureType::SignInFailed(
+            SignInFailure::IncorrectCredentials
+        )));
+    }
+}
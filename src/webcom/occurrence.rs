@@ -0,0 +1,358 @@
+use time::{Duration, PrimitiveDateTime, Time};
+
+use crate::webcom::shift::Shift;
+
+// How shifts should be cut up before they are put in the calendar
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SplitOptions {
+    // Leave out the breaks of broken shifts
+    pub split_broken: bool,
+    // End shifts at 23:59 and mention the real end time in the summary
+    pub stop_at_midnight: bool,
+    // Split shifts into one part per day, does nothing if they are already stopped at midnight
+    pub split_at_midnight: bool,
+}
+
+/*
+A single block of time in the calendar belonging to a shift
+A shift has one occurrence, unless it is broken or crosses midnight and the user wants those split up
+All times are local wall clock times, the calendar adds the timezone. So DST does not change any of the times
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShiftOccurrence<'a> {
+    pub shift: &'a Shift,
+    pub start: PrimitiveDateTime,
+    pub end: PrimitiveDateTime,
+    // The real end time, if this occurrence was stopped at midnight
+    pub cut_off_end: Option<Time>,
+}
+
+impl ShiftOccurrence<'_> {
+    // The calendar works with shifts, so this creates a copy of the shift with the times of this occurrence
+    pub fn to_shift(&self) -> Shift {
+        let mut shift = self.shift.clone();
+        shift.date = self.start.date();
+        shift.start = self.start.time();
+        shift.end_date = self.end.date();
+        shift.end = self.end.time();
+        shift.original_end_time = self.cut_off_end;
+        shift
+    }
+}
+
+// Creates every occurrence of the given shifts, a shift that is given twice only ends up once
+pub fn shift_occurrences(shifts: &[Shift], options: SplitOptions) -> Vec<ShiftOccurrence<'_>> {
+    let mut occurrences: Vec<ShiftOccurrence> = vec![];
+    for shift in shifts {
+        for occurrence in single_shift_occurrences(shift, options) {
+            let duplicate = occurrences.iter().any(|existing| {
+                existing.shift.magic_number == occurrence.shift.magic_number
+                    && existing.start == occurrence.start
+                    && existing.end == occurrence.end
+            });
+            if !duplicate {
+                occurrences.push(occurrence);
+            }
+        }
+    }
+    occurrences.sort_by_key(|occurrence| (occurrence.start, occurrence.shift.magic_number));
+    occurrences
+}
+
+fn single_shift_occurrences(shift: &Shift, options: SplitOptions) -> Vec<ShiftOccurrence<'_>> {
+    let start = PrimitiveDateTime::new(shift.date, shift.start);
+    let end = PrimitiveDateTime::new(shift.end_date, shift.end);
+    let pieces = if options.split_broken {
+        broken_pieces(shift, start, end)
+    } else {
+        vec![(start, end)]
+    };
+    let mut occurrences = vec![];
+    for (piece_start, piece_end) in pieces {
+        let midnight = next_midnight(piece_start);
+        if piece_end <= midnight {
+            occurrences.push(ShiftOccurrence {
+                shift,
+                start: piece_start,
+                end: piece_end,
+                cut_off_end: None,
+            });
+        } else if options.stop_at_midnight {
+            occurrences.push(ShiftOccurrence {
+                shift,
+                start: piece_start,
+                end: PrimitiveDateTime::new(piece_start.date(), Time::from_hms(23, 59, 0).unwrap()),
+                cut_off_end: Some(piece_end.time()),
+            });
+        } else if options.split_at_midnight {
+            let mut part_start = piece_start;
+            while piece_end > next_midnight(part_start) {
+                let part_end = next_midnight(part_start);
+                occurrences.push(ShiftOccurrence {
+                    shift,
+                    start: part_start,
+                    end: part_end,
+                    cut_off_end: None,
+                });
+                part_start = part_end;
+            }
+            occurrences.push(ShiftOccurrence {
+                shift,
+                start: part_start,
+                end: piece_end,
+                cut_off_end: None,
+            });
+        } else {
+            occurrences.push(ShiftOccurrence {
+                shift,
+                start: piece_start,
+                end: piece_end,
+                cut_off_end: None,
+            });
+        }
+    }
+    occurrences
+}
+
+/*
+The working parts of a broken shift, between its breaks
+Break times only contain a time, so each one is placed at the first moment after the previous one
+A break that does not fit within the shift is ignored, together with every break after it
+*/
+fn broken_pieces(
+    shift: &Shift,
+    start: PrimitiveDateTime,
+    end: PrimitiveDateTime,
+) -> Vec<(PrimitiveDateTime, PrimitiveDateTime)> {
+    let Some(broken_periods) = shift.broken_period.as_deref() else {
+        return vec![(start, end)];
+    };
+    let mut pieces = vec![];
+    let mut cursor = start;
+    for (pause_start, pause_end) in broken_periods {
+        let pause_start = at_or_after(cursor, *pause_start);
+        let pause_end = at_or_after(pause_start, *pause_end);
+        if pause_end >= end {
+            break;
+        }
+        if pause_start > cursor {
+            pieces.push((cursor, pause_start));
+        }
+        cursor = pause_end;
+    }
+    pieces.push((cursor, end));
+    pieces
+}
+
+fn at_or_after(moment: PrimitiveDateTime, time: Time) -> PrimitiveDateTime {
+    let candidate = PrimitiveDateTime::new(moment.date(), time);
+    if candidate < moment {
+        candidate + Duration::DAY
+    } else {
+        candidate
+    }
+}
+
+fn next_midnight(moment: PrimitiveDateTime) -> PrimitiveDateTime {
+    PrimitiveDateTime::new(moment.date() + Duration::DAY, Time::MIDNIGHT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webcom::shift_diff::tests::shift;
+    use time::macros::{date, datetime, time};
+
+    const SPLIT: SplitOptions = SplitOptions {
+        split_broken: true,
+        stop_at_midnight: false,
+        split_at_midnight: true,
+    };
+    const STOP: SplitOptions = SplitOptions {
+        split_broken: true,
+        stop_at_midnight: true,
+        split_at_midnight: false,
+    };
+    const BROKEN_ONLY: SplitOptions = SplitOptions {
+        split_broken: true,
+        stop_at_midnight: false,
+        split_at_midnight: false,
+    };
+    const NOTHING: SplitOptions = SplitOptions {
+        split_broken: false,
+        stop_at_midnight: false,
+        split_at_midnight: false,
+    };
+
+    fn times(occurrences: &[ShiftOccurrence]) -> Vec<(PrimitiveDateTime, PrimitiveDateTime)> {
+        occurrences
+            .iter()
+            .map(|occurrence| (occurrence.start, occurrence.end))
+            .collect()
+    }
+
+    fn broken_shift(periods: Vec<(Time, Time)>) -> Shift {
+        let mut shift = shift("G1001", 2, "06:00", "19:00");
+        shift.is_broken = true;
+        shift.broken_period = Some(periods);
+        shift
+    }
+
+    #[test]
+    fn day_shift_is_untouched() {
+        let shifts = vec![shift("V2309", 2, "06:00", "13:40")];
+        for options in [SPLIT, STOP, NOTHING] {
+            assert_eq!(
+                times(&shift_occurrences(&shifts, options)),
+                vec![(datetime!(2025-06-02 06:00), datetime!(2025-06-02 13:40))]
+            );
+        }
+    }
+
+    #[test]
+    fn night_shift_is_split_at_midnight() {
+        let shifts = vec![shift("N0001", 2, "22:00", "05:40")];
+        assert_eq!(
+            times(&shift_occurrences(&shifts, SPLIT)),
+            vec![
+                (datetime!(2025-06-02 22:00), datetime!(2025-06-03 00:00)),
+                (datetime!(2025-06-03 00:00), datetime!(2025-06-03 05:40)),
+            ]
+        );
+        assert_eq!(
+            times(&shift_occurrences(&shifts, NOTHING)),
+            vec![(datetime!(2025-06-02 22:00), datetime!(2025-06-03 05:40))]
+        );
+    }
+
+    #[test]
+    fn night_shift_is_stopped_at_midnight() {
+        let shifts = vec![shift("N0001", 2, "22:00", "05:40")];
+        let occurrences = shift_occurrences(&shifts, STOP);
+        assert_eq!(
+            times(&occurrences),
+            vec![(datetime!(2025-06-02 22:00), datetime!(2025-06-02 23:59))]
+        );
+        assert_eq!(occurrences[0].cut_off_end, Some(time!(05:40)));
+        assert_eq!(
+            occurrences[0].to_shift().original_end_time,
+            Some(time!(05:40))
+        );
+    }
+
+    #[test]
+    fn stopping_wins_from_splitting() {
+        let shifts = vec![shift("N0001", 2, "22:00", "05:40")];
+        let options = SplitOptions {
+            split_broken: true,
+            stop_at_midnight: true,
+            split_at_midnight: true,
+        };
+        assert_eq!(shift_occurrences(&shifts, options).len(), 1);
+    }
+
+    #[test]
+    fn ending_at_midnight_is_not_crossing_it() {
+        let shifts = vec![shift("L1201", 2, "16:20", "00:00")];
+        let occurrences = shift_occurrences(&shifts, STOP);
+        assert_eq!(
+            times(&occurrences),
+            vec![(datetime!(2025-06-02 16:20), datetime!(2025-06-03 00:00))]
+        );
+        assert_eq!(occurrences[0].cut_off_end, None);
+    }
+
+    #[test]
+    fn two_night_shifts_in_a_week_do_not_duplicate() {
+        let shifts = vec![
+            shift("N0001", 2, "22:00", "05:40"),
+            shift("N0001", 5, "22:00", "05:40"),
+            // The same shift twice, as can happen when relevant and non relevant shifts overlap
+            shift("N0001", 5, "22:00", "05:40"),
+        ];
+        for options in [SPLIT, STOP, NOTHING] {
+            let occurrences = shift_occurrences(&shifts, options);
+            let mut deduped = times(&occurrences);
+            deduped.dedup();
+            assert_eq!(deduped.len(), occurrences.len());
+        }
+        assert_eq!(shift_occurrences(&shifts, SPLIT).len(), 4);
+    }
+
+    #[test]
+    fn broken_shift_with_multiple_breaks() {
+        let shifts = vec![broken_shift(vec![
+            (time!(09:00), time!(11:00)),
+            (time!(14:00), time!(15:30)),
+        ])];
+        assert_eq!(
+            times(&shift_occurrences(&shifts, SPLIT)),
+            vec![
+                (datetime!(2025-06-02 06:00), datetime!(2025-06-02 09:00)),
+                (datetime!(2025-06-02 11:00), datetime!(2025-06-02 14:00)),
+                (datetime!(2025-06-02 15:30), datetime!(2025-06-02 19:00)),
+            ]
+        );
+        assert_eq!(shift_occurrences(&shifts, NOTHING).len(), 1);
+    }
+
+    #[test]
+    fn broken_night_shift() {
+        let mut shift = shift("G1001", 2, "18:00", "02:00");
+        shift.is_broken = true;
+        shift.broken_period = Some(vec![(time!(20:00), time!(23:00))]);
+        let shifts = vec![shift];
+        assert_eq!(
+            times(&shift_occurrences(&shifts, SPLIT)),
+            vec![
+                (datetime!(2025-06-02 18:00), datetime!(2025-06-02 20:00)),
+                (datetime!(2025-06-02 23:00), datetime!(2025-06-03 00:00)),
+                (datetime!(2025-06-03 00:00), datetime!(2025-06-03 02:00)),
+            ]
+        );
+        let stopped = shift_occurrences(&shifts, STOP);
+        assert_eq!(stopped.len(), 2);
+        assert_eq!(stopped[1].end, datetime!(2025-06-02 23:59));
+        assert_eq!(stopped[1].cut_off_end, Some(time!(02:00)));
+    }
+
+    #[test]
+    fn break_after_midnight_is_placed_on_next_day() {
+        let mut shift = shift("G1001", 2, "22:00", "06:00");
+        shift.is_broken = true;
+        shift.broken_period = Some(vec![(time!(01:00), time!(03:00))]);
+        let shifts = vec![shift];
+        assert_eq!(
+            times(&shift_occurrences(&shifts, BROKEN_ONLY)),
+            vec![
+                (datetime!(2025-06-02 22:00), datetime!(2025-06-03 01:00)),
+                (datetime!(2025-06-03 03:00), datetime!(2025-06-03 06:00)),
+            ]
+        );
+    }
+
+    #[test]
+    fn break_outside_of_shift_is_ignored() {
+        let shifts = vec![broken_shift(vec![(time!(18:00), time!(21:00))])];
+        assert_eq!(
+            times(&shift_occurrences(&shifts, SPLIT)),
+            vec![(datetime!(2025-06-02 06:00), datetime!(2025-06-02 19:00))]
+        );
+    }
+
+    #[test]
+    fn dst_night_keeps_wall_clock_times() {
+        // Summer time ends in the night of 26 october 2025
+        let mut shift = shift("N0001", 2, "22:00", "05:40");
+        shift.date = date!(2025 - 10 - 25);
+        shift.end_date = date!(2025 - 10 - 26);
+        let shifts = vec![shift];
+        assert_eq!(
+            times(&shift_occurrences(&shifts, SPLIT)),
+            vec![
+                (datetime!(2025-10-25 22:00), datetime!(2025-10-26 00:00)),
+                (datetime!(2025-10-26 00:00), datetime!(2025-10-26 05:40)),
+            ]
+        );
+    }
+}
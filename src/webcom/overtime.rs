@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+
+use time::{Date, Duration};
+
+use crate::webcom::{shift::Shift, shift_diff::ShiftDiff};
+
+// The ISO year and week number a shift belongs to
+pub type WeekNumber = (i32, u8);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeekHours {
+    pub year: i32,
+    pub week: u8,
+    pub duration: Duration,
+}
+
+impl WeekHours {
+    // Warning line about this week, shown in mails
+    pub fn warning(&self, max_weekly_hours: i32) -> String {
+        format!(
+            "Week {} van {}: je bent {}:{:02} uur ingeroosterd, dat is meer dan {max_weekly_hours} uur",
+            self.week,
+            self.year,
+            self.duration.whole_hours(),
+            self.duration.whole_minutes() % 60
+        )
+    }
+}
+
+fn week_number(date: Date) -> WeekNumber {
+    let (year, week, _) = date.to_iso_week_date();
+    (year, week)
+}
+
+// Sums up the duration of the shifts per ISO week
+pub fn weekly_hours<'a>(shifts: impl Iterator<Item = &'a Shift>) -> BTreeMap<WeekNumber, Duration> {
+    let mut weeks = BTreeMap::new();
+    for shift in shifts {
+        *weeks
+            .entry(week_number(shift.date))
+            .or_insert(Duration::ZERO) += shift.duration;
+    }
+    weeks
+}
+
+// All weeks in which more than the maximum amount of hours are planned
+pub fn weeks_over_limit(
+    weeks: &BTreeMap<WeekNumber, Duration>,
+    max_weekly_hours: i32,
+) -> Vec<WeekHours> {
+    let limit = Duration::hours(max_weekly_hours.into());
+    weeks
+        .iter()
+        .filter(|(_, duration)| **duration > limit)
+        .map(|((year, week), duration)| WeekHours {
+            year: *year,
+            week: *week,
+            duration: *duration,
+        })
+        .collect()
+}
+
+/*
+Finds the weeks which went over the limit because of a new or changed shift.
+Weeks which were already over the limit before this diff are not returned, so the user is only notified once.
+Weeks in the past are ignored
+*/
+pub fn newly_exceeded_weeks(
+    previous_weeks: &BTreeMap<WeekNumber, Duration>,
+    diff: &ShiftDiff,
+    max_weekly_hours: i32,
+    current_date: Date,
+) -> Vec<WeekHours> {
+    let limit = Duration::hours(max_weekly_hours.into());
+    let current_week = week_number(current_date);
+    let touched_weeks: Vec<WeekNumber> = diff
+        .new_shifts()
+        .chain(diff.changed_shifts())
+        .map(|shift| week_number(shift.date))
+        .collect();
+    weeks_over_limit(&weekly_hours(diff.current_shifts()), max_weekly_hours)
+        .into_iter()
+        .filter(|week| (week.year, week.week) >= current_week)
+        .filter(|week| touched_weeks.contains(&(week.year, week.week)))
+        .filter(|week| {
+            previous_weeks
+                .get(&(week.year, week.week))
+                .is_none_or(|duration| *duration <= limit)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webcom::shift_diff::{diff_shifts, tests::shift};
+    use time::Month;
+
+    fn june(day: u8) -> Date {
+        Date::from_calendar_date(2025, Month::June, day).unwrap()
+    }
+
+    // 2 to 6 june 2025 is monday to friday of week 23
+    fn work_week(days: std::ops::RangeInclusive<u8>) -> Vec<Shift> {
+        days.map(|day| shift("V2309", day, "06:00", "13:40"))
+            .collect()
+    }
+
+    #[test]
+    fn sums_hours_per_iso_week() {
+        let mut shifts = work_week(2..=6);
+        shifts.push(shift("V2309", 9, "06:00", "13:40"));
+        let weeks = weekly_hours(shifts.iter());
+        assert_eq!(weeks[&(2025, 23)], Duration::minutes(5 * 460));
+        assert_eq!(weeks[&(2025, 24)], Duration::minutes(460));
+    }
+
+    #[test]
+    fn finds_weeks_over_limit() {
+        let shifts = work_week(2..=8);
+        let weeks = weekly_hours(shifts.iter());
+        assert_eq!(weeks_over_limit(&weeks, 48).len(), 1);
+        assert!(weeks_over_limit(&weeks, 60).is_empty());
+    }
+
+    #[test]
+    fn only_new_shifts_pushing_over_limit_are_reported() {
+        let previous = work_week(2..=7);
+        let previous_weeks = weekly_hours(previous.iter());
+        let mut current = previous.clone();
+        current.push(shift("L1201", 8, "14:00", "21:40"));
+
+        let diff = diff_shifts(previous.clone(), current.clone(), false);
+        let exceeded = newly_exceeded_weeks(&previous_weeks, &diff, 48, june(1));
+        assert_eq!(exceeded.len(), 1);
+        assert_eq!(exceeded[0].week, 23);
+
+        // Already over the limit before, so no new warning
+        let current_weeks = weekly_hours(current.iter());
+        let diff = diff_shifts(current.clone(), current.clone(), false);
+        assert!(newly_exceeded_weeks(&current_weeks, &diff, 48, june(1)).is_empty());
+
+        // Weeks in the past are not reported
+        let diff = diff_shifts(previous, current, false);
+        assert!(newly_exceeded_weeks(&previous_weeks, &diff, 48, june(20)).is_empty());
+    }
+}
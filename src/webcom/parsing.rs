@@ -1,147 +1,264 @@
 use crate::database::secret::Secret;
 use crate::errors::{OptionResult, check_if_webcom_unavailable, check_sign_in_error};
-use crate::health::ApplicationLogbook;
+use crate::execution::permissions::write_private_file;
+use crate::health::{ApplicationLogbook, Phase};
 use crate::webcom::email::DATE_DESCRIPTION;
 use crate::webcom::gebroken_shifts::{navigate_to_subdirectory, wait_for_response};
-use crate::webcom::webdriver::wait_until_loaded;
-use crate::{FailureType, GenResult, get_set_name, webcom::shift::Shift};
-use async_recursion::async_recursion;
+use crate::webcom::ical::CALENDAR_VERSION;
+use crate::{FailureType, GenResult, create_path, get_set_name, webcom::shift::Shift};
 use secrecy::ExposeSecret;
-use thirtyfour::prelude::ElementQueryable;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::time::Instant;
 use thirtyfour::{By, WebDriver};
 use time::{Date, Month};
 use tracing::*;
 
+// Reads every day of the roster in one go, instead of asking the browser for every day separately
+const ROSTER_DAYS_SCRIPT: &str = "return Array.from(document.getElementsByClassName('calDay')).map(day => {
+    const strong = day.querySelector('strong');
+    return { title: day.getAttribute('data-original-title'), day: strong ? strong.textContent : null };
+});";
+
+// Checks if the page with the given roster month is completely loaded
+const ROSTER_LOADED_SCRIPT: &str = "return document.readyState === 'complete' && window.location.href.indexOf(arguments[0]) !== -1;";
+
+// Hashes of the raw roster of every month, to find the months that did not change since the last run
+const MONTH_HASHES_PATH: &str = "month_hashes.json";
+
+// A single day of the roster exactly as it is on the page
+#[derive(Debug, Clone, Deserialize, Hash)]
+pub struct RawRosterDay {
+    pub title: Option<String>,
+    pub day: Option<String>,
+}
+
 /*
-Checks all supplied WebElements, it checks if the day contains the text "Dienstuur"  and if so, adds it to a Vec of valid shifts in the calendar
-Does not search itself for elements
+The hash of every loaded month, keyed by year and month
+The calendar version is stored as well, a new version can change how shifts are parsed so all hashes are thrown away
 */
-async fn get_elements(driver: &WebDriver, month: Month, year: i32) -> GenResult<(Vec<Shift>, u64)> {
-    let mut temp_emlements: Vec<Shift> = vec![];
-    let mut failed_shifts = 0;
-    let elements = driver
-        .query(By::ClassName("calDay"))
-        .all_from_selector()
-        .await?;
-    for element in elements {
-        let text = match element.attr("data-original-title").await? {
-            Some(x) => x,
-            None => {
-                return Err("no elements in rooster".into());
-            }
-        };
-        if !text.is_empty() && text.contains("Dienstduur") {
-            //debug!("Loading shift: {:?}", &text);
-            let dag_text = element.find(By::Tag("strong")).await?.text().await?;
-            let dag_text_split = dag_text.split_whitespace().next().result()?;
-
-            debug!("dag {}", &dag_text_split);
-            let dag: u8 = dag_text_split.parse()?;
-            let date = Date::from_calendar_date(year, month, dag)?;
-            let new_shift = Shift::new(text, date);
-            match new_shift {
-                Ok(shift) => {
-                    temp_emlements.push(shift.clone());
-                    debug!("Found Shift {}", &shift.number);
-                }
-                Err(error) => {
-                    error!(
-                        "FAILED TO CREATE SHIFT!\nDATE: {}\nERROR: {}",
-                        date.format(DATE_DESCRIPTION)?,
-                        error.to_string()
-                    );
-                    failed_shifts += 1;
-                    error!(
-                        "FAILED TO CREATE SHIFT!\nDATE: {}\nERROR: {}",
-                        date.format(DATE_DESCRIPTION)?,
-                        error.to_string()
-                    );
-                    failed_shifts += 1;
-                }
-            }
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct MonthHashes {
+    calendar_version: String,
+    months: HashMap<String, u64>,
+}
+
+impl MonthHashes {
+    pub fn load() -> Self {
+        std::fs::read_to_string(create_path(MONTH_HASHES_PATH))
+            .ok()
+            .and_then(|text| serde_json::from_str::<Self>(&text).ok())
+            .filter(|hashes| hashes.calendar_version == CALENDAR_VERSION)
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> GenResult<()> {
+        write_private_file(
+            create_path(MONTH_HASHES_PATH),
+            serde_json::to_string(self)?.as_bytes(),
+        )
+    }
+
+    fn is_unchanged(&self, month: Month, year: i32, hash: u64) -> bool {
+        self.months.get(&month_key(month, year)) == Some(&hash)
+    }
+
+    fn insert(&mut self, month: Month, year: i32, hash: u64) {
+        self.calendar_version = CALENDAR_VERSION.to_owned();
+        self.months.insert(month_key(month, year), hash);
+    }
+}
+
+fn month_key(month: Month, year: i32) -> String {
+    format!("{year}-{:02}", month as u8)
+}
+
+fn hash_roster_days(days: &[RawRosterDay]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    days.hash(&mut hasher);
+    hasher.finish()
+}
+
+// A month of which the roster is exactly the same as during the last run
+// The days are kept, so it can still be parsed if the shifts of the last run turn out to be unusable
+#[derive(Debug)]
+pub struct UnchangedMonth {
+    pub month: Month,
+    pub year: i32,
+    days: Vec<RawRosterDay>,
+}
+
+impl UnchangedMonth {
+    pub fn contains(&self, date: Date) -> bool {
+        date.month() == self.month && date.year() == self.year
+    }
+
+    pub fn key(&self) -> String {
+        month_key(self.month, self.year)
+    }
+
+    pub fn parse(self) -> GenResult<(Vec<Shift>, u64)> {
+        parse_roster_days(self.days, self.month, self.year)
+    }
+}
+
+// Everything found while loading the roster months
+#[derive(Debug, Default)]
+pub struct RosterMonths {
+    pub shifts: Vec<Shift>,
+    pub unchanged_months: Vec<UnchangedMonth>,
+    // Only save these once the calendar is written, otherwise a failed run would cause months to be skipped
+    pub hashes: MonthHashes,
+}
+
+impl RosterMonths {
+    // Parses the month, unless its hash is the same as last time
+    fn add_month(
+        &mut self,
+        days: Vec<RawRosterDay>,
+        month: Month,
+        year: i32,
+        known_hashes: &MonthHashes,
+        logbook: &mut ApplicationLogbook,
+    ) -> GenResult<()> {
+        let hash = hash_roster_days(&days);
+        self.hashes.insert(month, year, hash);
+        if known_hashes.is_unchanged(month, year, hash) {
+            debug!("Month {month} {year} has not changed");
+            self.unchanged_months
+                .push(UnchangedMonth { month, year, days });
+            return Ok(());
         }
+        let (mut month_shifts, failed_shifts) = parse_roster_days(days, month, year)?;
+        logbook.add_failed_shifts(failed_shifts, false);
+        self.shifts.append(&mut month_shifts);
+        Ok(())
     }
-    Ok((temp_emlements, failed_shifts))
+}
+
+async fn read_roster_days(driver: &WebDriver) -> GenResult<Vec<RawRosterDay>> {
+    Ok(driver
+        .execute(ROSTER_DAYS_SCRIPT, vec![])
+        .await?
+        .convert()?)
 }
 
 /*
-Just presses the previous button in webcom to load the previous month
+Checks all days of the roster, if the day contains the text "Dienstduur" it is turned into a shift
+Returns the shifts and the amount of shifts that could not be read
 */
-#[async_recursion]
-pub async fn load_previous_month_shifts(
-    driver: &WebDriver,
-    extra_months_back: usize,
-) -> GenResult<Vec<Shift>> {
-    debug!("Loading Previous Month..");
-    let now = time::OffsetDateTime::now_utc();
-    let today = now.date();
-    let mut new_month = today.month().previous();
-    let mut new_year = if new_month == Month::December {
-        today.year() - 1
-    } else {
-        today.year()
-    };
-    for _ in 0..extra_months_back {
-        info!("Going way back");
-        new_month = new_month.previous();
-
-        new_year = if new_month == Month::December {
-            new_year - 1
-        } else {
-            new_year
+pub fn parse_roster_days(
+    days: Vec<RawRosterDay>,
+    month: Month,
+    year: i32,
+) -> GenResult<(Vec<Shift>, u64)> {
+    let mut shifts: Vec<Shift> = vec![];
+    let mut failed_shifts = 0;
+    for day in days {
+        let Some(text) = day.title else {
+            return Err("no elements in rooster".into());
         };
+        if text.is_empty() || !text.contains("Dienstduur") {
+            continue;
+        }
+        let day_text = day.day.result_reason("Day without day number")?;
+        let day_number: u8 = day_text.split_whitespace().next().result()?.parse()?;
+        debug!("dag {day_number}");
+        let date = Date::from_calendar_date(year, month, day_number)?;
+        match Shift::new(text, date) {
+            Ok(shift) => {
+                debug!("Found Shift {}", &shift.number);
+                shifts.push(shift);
+            }
+            Err(error) => {
+                error!(
+                    "FAILED TO CREATE SHIFT!\nDATE: {}\nERROR: {}",
+                    date.format(DATE_DESCRIPTION)?,
+                    error.to_string()
+                );
+                failed_shifts += 1;
+            }
+        }
     }
-    let mut shifts = vec![];
-    if extra_months_back > 0 {
-        shifts.append(&mut load_previous_month_shifts(driver, extra_months_back - 1).await?)
-    }
-    navigate_to_subdirectory(
-        &driver,
-        &format!("roster.aspx?{}-{}-01", new_year, new_month as u8),
-    )
-    .await?;
-    wait_until_loaded(&driver).await?;
-    shifts.append(&mut get_elements(&driver, new_month, new_year).await?.0);
-    Ok(shifts)
+    Ok((shifts, failed_shifts))
 }
 
-/*
-Just presses the next button in webcom twice to load the next month.
-Only works correctly if the previous month function has been ran before
-*/
-pub async fn load_next_month_shifts(
-    driver: &WebDriver,
-    logbook: &mut ApplicationLogbook,
-) -> GenResult<Vec<Shift>> {
-    debug!("Loading Next Month..");
-    let now = time::OffsetDateTime::now_utc();
-    let today = now.date();
-    let new_month = today.month().next();
-    let new_year = if new_month == Month::January {
+fn roster_query(month: Month, year: i32) -> String {
+    format!("roster.aspx?{}-{}-01", year, month as u8)
+}
+
+// Waits until the roster of the given month is shown, a settle time is kept just like wait_until_loaded
+async fn wait_for_roster(driver: &WebDriver, query: &str) -> GenResult<()> {
+    tokio::time::timeout(std::time::Duration::from_secs(30), async {
+        loop {
+            let loaded = driver
+                .execute(ROSTER_LOADED_SCRIPT, vec![serde_json::json!(query)])
+                .await?
+                .convert::<bool>()?;
+            if loaded {
+                tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+                return GenResult::Ok(());
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+    })
+    .await?
+}
+
+// The months to load next to the current one, oldest first
+fn roster_months(today: Date, months_back: usize) -> Vec<(Month, i32)> {
+    let mut months = vec![];
+    let (mut month, mut year) = (today.month(), today.year());
+    for _ in 0..months_back {
+        month = month.previous();
+        if month == Month::December {
+            year -= 1;
+        }
+        months.insert(0, (month, year));
+    }
+    let next_month = today.month().next();
+    let next_year = if next_month == Month::January {
         today.year() + 1
     } else {
         today.year()
     };
-    navigate_to_subdirectory(
-        &driver,
-        &format!("roster.aspx?{}-{}-01", new_year, new_month as u8),
-    )
-    .await?;
-    wait_until_loaded(&driver).await?;
-    let shifts = get_elements(&driver, new_month, new_year).await?;
-    logbook.add_failed_shifts(shifts.1, false);
-    Ok(shifts.0)
-}
-
-pub async fn load_current_month_shifts(
+    months.push((next_month, next_year));
+    months
+}
+
+/*
+Loads the current month, which must already be open, and the given amount of months back plus the next month
+The months are pipelined, while the browser loads the next month the previous one is parsed
+Months with the same hash as in known_hashes are not parsed, but returned as unchanged
+*/
+pub async fn load_roster_months(
     driver: &WebDriver,
+    months_back: usize,
+    known_hashes: &MonthHashes,
     logbook: &mut ApplicationLogbook,
-) -> GenResult<Vec<Shift>> {
-    let now = time::OffsetDateTime::now_utc();
-    let today = now.date();
-    let shifts = get_elements(&driver, today.month(), today.year()).await?;
-    logbook.add_failed_shifts(shifts.1, false);
-    Ok(shifts.0)
+) -> GenResult<RosterMonths> {
+    let today = time::OffsetDateTime::now_utc().date();
+    let mut pending = (read_roster_days(driver).await?, today.month(), today.year());
+    let mut roster = RosterMonths::default();
+    for (month, year) in roster_months(today, months_back) {
+        debug!("Loading month {month} {year}..");
+        let started = Instant::now();
+        let query = roster_query(month, year);
+        navigate_to_subdirectory(driver, &query).await?;
+        let (days, previous_month, previous_year) = pending;
+        roster.add_month(days, previous_month, previous_year, known_hashes, logbook)?;
+        wait_for_roster(driver, &query).await?;
+        pending = (read_roster_days(driver).await?, month, year);
+        logbook.application_state.phase_timings.record(
+            Phase::MonthLoad,
+            Some(month_key(month, year)),
+            started,
+        );
+    }
+    let (days, month, year) = pending;
+    roster.add_month(days, month, year, known_hashes, logbook)?;
+    Ok(roster)
 }
 
 /*
@@ -202,3 +319,121 @@ async fn sign_in_webcom(driver: &WebDriver, user: Secret, pass: Secret) -> GenRe
     get_set_name(Some(name));
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::date;
+
+    fn roster_day(day: &str, title: &str) -> RawRosterDay {
+        RawRosterDay {
+            title: Some(title.to_owned()),
+            day: Some(day.to_owned()),
+        }
+    }
+
+    #[test]
+    fn months_around_today() {
+        assert_eq!(
+            roster_months(date!(2025 - 01 - 15), 2),
+            vec![
+                (Month::November, 2024),
+                (Month::December, 2024),
+                (Month::February, 2025)
+            ]
+        );
+        assert_eq!(
+            roster_months(date!(2025 - 12 - 15), 1),
+            vec![(Month::November, 2025), (Month::January, 2026)]
+        );
+    }
+
+    // The title of a day with a shift, a time which can't be parsed makes it a broken shift
+    fn shift_text(time: &str) -> String {
+        [
+            "Dienst: V2309",
+            "Geldig vanaf: 02.06.2025",
+            &format!("Tijd: {time}"),
+            "Dienstduur: 07:40 Uren",
+            "Loonuren: 07:40 Uren",
+            "Dagsoort: \u{a0}• Maandag",
+            "Dienstsoort: \u{a0}• Rijdienst",
+            "Startplaats: \u{a0}• ehvgas, Einhoven garage streek",
+            "Omschrijving: \u{a0}• V",
+        ]
+        .join("\u{a0}• \u{a0}• ")
+    }
+
+    #[test]
+    fn only_days_with_shifts_are_parsed() {
+        let days = vec![
+            roster_day("1 zo", ""),
+            roster_day("2 ma", &shift_text("06:14 - 13:54")),
+            roster_day("3 di", &shift_text("kapot")),
+        ];
+        let (shifts, failed) = parse_roster_days(days, Month::June, 2025).unwrap();
+        assert_eq!(shifts.len(), 1);
+        assert_eq!(shifts[0].date, date!(2025 - 06 - 02));
+        assert_eq!(failed, 1);
+    }
+
+    #[test]
+    fn day_without_title_is_an_error() {
+        let days = vec![RawRosterDay {
+            title: None,
+            day: Some("1 zo".to_owned()),
+        }];
+        assert!(parse_roster_days(days, Month::June, 2025).is_err());
+    }
+
+    #[test]
+    fn unchanged_months_are_not_parsed() {
+        let days = vec![
+            roster_day("1 zo", ""),
+            roster_day("3 di", &shift_text("kapot")),
+        ];
+        let mut logbook = ApplicationLogbook::default();
+        let mut first_run = RosterMonths::default();
+        first_run
+            .add_month(
+                days.clone(),
+                Month::June,
+                2025,
+                &MonthHashes::default(),
+                &mut logbook,
+            )
+            .unwrap();
+        assert!(first_run.unchanged_months.is_empty());
+        assert_eq!(logbook.application_state.failed_shifts, 1);
+
+        let mut second_run = RosterMonths::default();
+        second_run
+            .add_month(
+                days.clone(),
+                Month::June,
+                2025,
+                &first_run.hashes,
+                &mut logbook,
+            )
+            .unwrap();
+        assert_eq!(second_run.unchanged_months.len(), 1);
+        assert_eq!(second_run.unchanged_months[0].key(), "2025-06");
+        assert_eq!(second_run.hashes, first_run.hashes);
+        assert_eq!(logbook.application_state.failed_shifts, 1);
+
+        // The same roster in another month is not the same month
+        let mut other_month = RosterMonths::default();
+        other_month
+            .add_month(days, Month::July, 2025, &first_run.hashes, &mut logbook)
+            .unwrap();
+        assert!(other_month.unchanged_months.is_empty());
+    }
+
+    #[test]
+    fn changed_day_changes_hash() {
+        let days = vec![roster_day("1 zo", ""), roster_day("2 ma", "")];
+        let changed_days = vec![roster_day("1 zo", ""), roster_day("2 ma", "Dienst: V2309")];
+        assert_eq!(hash_roster_days(&days), hash_roster_days(&days.clone()));
+        assert_ne!(hash_roster_days(&days), hash_roster_days(&changed_days));
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use time::{Duration, PrimitiveDateTime};
+
+use crate::webcom::shift::Shift;
+
+// A shift which does not leave enough rest before or after it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RestViolation {
+    pub before: Option<Duration>,
+    pub after: Option<Duration>,
+}
+
+impl RestViolation {
+    // Readable explanation of the violation, used in both the mails and the calendar
+    pub fn description(&self) -> String {
+        let format_rest = |rest: Duration| {
+            format!(
+                "{} uur {} minuten",
+                rest.whole_hours(),
+                rest.whole_minutes() % 60
+            )
+        };
+        match (self.before, self.after) {
+            (Some(before), Some(after)) => format!(
+                "Te weinig rust: {} voor en {} na deze dienst",
+                format_rest(before),
+                format_rest(after)
+            ),
+            (Some(before), None) => {
+                format!("Te weinig rust: {} voor deze dienst", format_rest(before))
+            }
+            (None, Some(after)) => format!("Te weinig rust: {} na deze dienst", format_rest(after)),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+fn shift_start(shift: &Shift) -> PrimitiveDateTime {
+    PrimitiveDateTime::new(shift.date, shift.start)
+}
+
+fn shift_end(shift: &Shift) -> PrimitiveDateTime {
+    PrimitiveDateTime::new(shift.end_date, shift.end)
+}
+
+/*
+Finds all shifts which have less than the minimum rest between them and the shift before or after
+Returned by the magic number of the shift, so shifts that were split later on can still be found.
+Overlapping shifts are ignored, a minimum of 0 hours disables the check
+*/
+pub fn rest_violations<'a>(
+    shifts: impl Iterator<Item = &'a Shift>,
+    min_rest_hours: i32,
+) -> HashMap<i64, RestViolation> {
+    let mut violations: HashMap<i64, RestViolation> = HashMap::new();
+    if min_rest_hours <= 0 {
+        return violations;
+    }
+    let min_rest = Duration::hours(min_rest_hours.into());
+    let mut shifts: Vec<&Shift> = shifts.collect();
+    shifts.sort_by_key(|shift| shift_start(shift));
+    shifts.dedup_by_key(|shift| shift.magic_number);
+    for pair in shifts.windows(2) {
+        let rest = shift_start(pair[1]) - shift_end(pair[0]);
+        if rest.is_negative() || rest >= min_rest {
+            continue;
+        }
+        violations.entry(pair[0].magic_number).or_default().after = Some(rest);
+        violations.entry(pair[1].magic_number).or_default().before = Some(rest);
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webcom::shift_diff::tests::shift;
+
+    #[test]
+    fn finds_short_rest_between_days() {
+        let late = shift("L1201", 2, "15:00", "23:30");
+        let early = shift("V2309", 3, "06:00", "13:40");
+        let violations = rest_violations([&late, &early].into_iter(), 11);
+        assert_eq!(
+            violations[&late.magic_number].after,
+            Some(Duration::minutes(6 * 60 + 30))
+        );
+        assert_eq!(
+            violations[&early.magic_number].before,
+            Some(Duration::minutes(6 * 60 + 30))
+        );
+        assert!(violations[&early.magic_number].after.is_none());
+    }
+
+    #[test]
+    fn enough_rest_is_fine() {
+        let early = shift("V2309", 2, "06:00", "13:40");
+        let next_early = shift("V2310", 3, "06:00", "13:40");
+        assert!(rest_violations([&early, &next_early].into_iter(), 11).is_empty());
+        let late = shift("L1201", 2, "15:00", "23:30");
+        let early = shift("V2309", 3, "06:00", "13:40");
+        assert!(rest_violations([&early, &late].into_iter(), 0).is_empty());
+    }
+
+    #[test]
+    fn night_shift_ending_next_day() {
+        let night = shift("N0001", 2, "22:00", "02:00");
+        let early = shift("V2309", 3, "08:00", "15:40");
+        let violations = rest_violations([&night, &early].into_iter(), 11);
+        assert_eq!(
+            violations[&early.magic_number].before,
+            Some(Duration::hours(6))
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     hash::{DefaultHasher, Hash, Hasher},
     str::Split,
 };
@@ -9,7 +10,7 @@ use time::{Date, Duration, Time};
 
 use crate::{GenResult, errors::OptionResult};
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq, Hash)]
 pub enum ShiftState {
     New,
     Changed,
@@ -19,14 +20,27 @@ pub enum ShiftState {
     Unknown,
 }
 
+// What kind of duty a shift is, derived from the first letter of the shift number
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+pub enum ShiftCategory {
+    Regular,
+    Broken,
+    // Reserve duties can still turn into a real duty at short notice
+    Reserve,
+}
+
 #[serde_as]
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Hash)]
 pub struct Shift {
     pub date: Date,
     pub start: Time,
     pub end_date: Date,
     pub end: Time,
     pub duration: Duration,
+    // The hours that are paid for this shift, can differ from the duration
+    // Shifts saved before this was parsed don't have it, use working_hours() which falls back to the duration
+    #[serde(default)]
+    working_hours: Option<Duration>,
     pub number: String,
     pub kind: String,
     pub location: String,
@@ -37,8 +51,14 @@ pub struct Shift {
     #[serde(default)]
     #[serde_as(deserialize_as = "DefaultOnError")]
     pub broken_period: Option<Vec<(Time, Time)>>,
+    // Where the user is during each broken period, in the same order as broken_period
+    #[serde(default)]
+    pub pause_locations: Vec<String>,
     pub original_end_time: Option<Time>,
     pub magic_number: i64,
+    // Tells apart shifts with the same number on the same date, part of the uid
+    #[serde(default)]
+    pub sequence: u8,
     // This field is not always needed. Especially when serializing.
     #[serde(skip_deserializing, default)]
     pub state: ShiftState,
@@ -66,7 +86,7 @@ impl Shift {
         let _date: String = parts_list[1].nth(1).result()?.to_string();
         let time: String = parts_list[2].nth(1).unwrap_or("").to_string();
         let shift_duration: String = parts_list[3].nth(1).unwrap_or("").to_string();
-        let _working_hours: String = parts_list[4].nth(1).unwrap_or("").to_string();
+        let working_hours_str: String = parts_list[4].nth(1).unwrap_or("").to_string();
         let _day_of_week: String = parts_list[5].nth(1).unwrap_or("").to_string();
         let kind: String = parts_list[6].nth(1).unwrap_or("").to_string();
         let mut location = "Onbekend".to_string();
@@ -92,16 +112,9 @@ impl Shift {
             is_broken = true;
         }
 
-        let duration_split = shift_duration
-            .split_whitespace()
-            .nth(0)
-            .result()?
-            .split(":");
-        let duration_minutes =
-            Duration::minutes(duration_split.clone().nth(1).result()?.parse::<i64>()?);
-        let duration_hours =
-            Duration::hours(duration_split.clone().nth(0).result()?.parse::<i64>()?);
-        let duration = duration_hours + duration_minutes;
+        let duration = Shift::get_duration(&shift_duration)?;
+        // Not every shift lists its paid hours, then just assume it is the same as the duration
+        let working_hours = Shift::get_duration(&working_hours_str).unwrap_or(duration);
         let mut end_date = date;
         if end < start {
             end_date = date + Duration::days(1);
@@ -113,58 +126,58 @@ impl Shift {
             end_date,
             end,
             duration,
+            working_hours: Some(working_hours),
             kind,
             location,
             description,
             is_broken,
             broken_period: None,
+            pause_locations: vec![],
             original_end_time: None,
             magic_number,
+            sequence: 0,
             state: ShiftState::Unknown,
         })
     }
 
-    // Create new shifts from one broken shift.
-    // Assumes second shift cannot start after midnight
-    // None means no broken times have been found for the shift
-    pub fn split_broken(&self) -> Option<Vec<Self>> {
-        if let Some(broken_periods) = self.broken_period.as_deref() && !broken_periods.is_empty() {
-            let mut split_shifts = vec![];
-            for period in broken_periods {
-                let mut part_one = self.clone();
-                part_one.end = period.0;
-                let mut part_two = self.clone();
-                part_two.start = period.1;
-                split_shifts.push(part_one);
-                split_shifts.push(part_two);
-            }
-            Some(split_shifts)
-        } else {
-            None
+    pub fn working_hours(&self) -> Duration {
+        self.working_hours.unwrap_or(self.duration)
+    }
+
+    // Identity of the shift which, unlike the magic number, stays the same if the times change
+    pub fn uid(&self) -> String {
+        format!("{}-{}-{}", self.date, self.number, self.sequence)
+    }
+
+    // Numbers the shifts with the same number on the same date, in order of their start time
+    pub fn assign_sequences(shifts: &mut [Shift]) {
+        let mut order: Vec<usize> = (0..shifts.len()).collect();
+        order.sort_by_key(|index| (shifts[*index].date, shifts[*index].start));
+        let mut sequences: HashMap<(Date, String), u8> = HashMap::new();
+        for index in order {
+            let shift = &mut shifts[index];
+            let sequence = sequences.entry((shift.date, shift.number.clone())).or_insert(0);
+            shift.sequence = *sequence;
+            *sequence += 1;
+        }
+    }
+
+    pub fn category(&self) -> ShiftCategory {
+        match self.number.chars().nth(0) {
+            Some('r') | Some('R') => ShiftCategory::Reserve,
+            _ if self.is_broken => ShiftCategory::Broken,
+            _ => ShiftCategory::Regular,
         }
     }
 
-    // Create two new shifts from one broken shift.
-    // Assumes second shift cannot start after midnight
-    pub fn new_from_existing(
-        new_between_times: (Time, Time),
-        existing_shift: &Self,
-        start_next_day: bool,
-    ) -> Vec<Self> {
-        let mut part_one = existing_shift.clone();
-        part_one.end = new_between_times.0;
-        part_one.end_date = match start_next_day {
-            true => existing_shift.end_date,
-            false => existing_shift.date,
-        };
-        let mut part_two = existing_shift.clone();
-        part_two.start = new_between_times.1;
-        part_two.date = match start_next_day {
-            true => existing_shift.end_date,
-            false => existing_shift.date,
-        };
-        let shifts: Vec<Self> = vec![part_one, part_two];
-        shifts
+    // Creates a duration from a string like 07:40 Uren
+    fn get_duration(str_duration: &str) -> GenResult<Duration> {
+        let duration_split = str_duration.split_whitespace().nth(0).result()?.split(":");
+        let duration_minutes =
+            Duration::minutes(duration_split.clone().nth(1).result()?.parse::<i64>()?);
+        let duration_hours =
+            Duration::hours(duration_split.clone().nth(0).result()?.parse::<i64>()?);
+        Ok(duration_hours + duration_minutes)
     }
 
     // Creates and returns a Time::time from a given string of time eg: 12:34
@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+
+use time::{Date, PrimitiveDateTime};
+
+use crate::webcom::shift::{Shift, ShiftCategory, ShiftState};
+
+/*
+Result of comparing the previously known shifts to the shifts just loaded from webcom
+Every shift that was known or has been found is in here exactly once, with its state attached
+*/
+#[derive(Debug, Clone, Default)]
+pub struct ShiftDiff {
+    shifts: Vec<Shift>,
+    // The previous version of every changed shift, by the magic number of the new version
+    replaced: HashMap<i64, Shift>,
+}
+
+impl ShiftDiff {
+    pub fn new_shifts(&self) -> impl Iterator<Item = &Shift> {
+        self.with_state(ShiftState::New)
+    }
+
+    pub fn changed_shifts(&self) -> impl Iterator<Item = &Shift> {
+        self.with_state(ShiftState::Changed)
+    }
+
+    pub fn removed_shifts(&self) -> impl Iterator<Item = &Shift> {
+        self.with_state(ShiftState::Deleted)
+    }
+
+    pub fn unchanged_shifts(&self) -> impl Iterator<Item = &Shift> {
+        self.with_state(ShiftState::Unchanged)
+    }
+
+    // The shift that was replaced by the given changed shift
+    pub fn replaced_shift(&self, shift: &Shift) -> Option<&Shift> {
+        self.replaced.get(&shift.magic_number)
+    }
+
+    /*
+    Reserve duties which turned into a real duty, starting before the deadline
+    Returned as (reserve duty, new duty)
+    */
+    pub fn reserve_call_outs(&self, deadline: PrimitiveDateTime) -> Vec<(&Shift, &Shift)> {
+        self.changed_shifts()
+            .filter(|shift| PrimitiveDateTime::new(shift.date, shift.start) <= deadline)
+            .filter(|shift| shift.category() != ShiftCategory::Reserve)
+            .filter_map(|shift| Some((self.replaced_shift(shift)?, shift)))
+            .filter(|(previous, _)| previous.category() == ShiftCategory::Reserve)
+            .collect()
+    }
+
+    // All shifts which are still valid, without consuming the diff
+    pub fn current_shifts(&self) -> impl Iterator<Item = &Shift> {
+        self.shifts
+            .iter()
+            .filter(|shift| shift.state != ShiftState::Deleted)
+    }
+
+    fn with_state(&self, state: ShiftState) -> impl Iterator<Item = &Shift> {
+        self.shifts.iter().filter(move |shift| shift.state == state)
+    }
+
+    // All shifts which are still valid, so everything except the removed shifts
+    pub fn into_current(self) -> Vec<Shift> {
+        self.shifts
+            .into_iter()
+            .filter(|shift| shift.state != ShiftState::Deleted)
+            .collect()
+    }
+}
+
+/*
+Compares the previously known shifts to the currently loaded shifts
+A shift whose hash is still present is unchanged, if replace_old is true the new version of that shift is kept.
+A new shift which shares its date with a previous shift that has disappeared is seen as a change of that shift,
+preferably the one with the same uid, otherwise it is new. Every previous shift which is not matched by either is removed.
+More than one shift can exist on the same date, every previous shift can only be matched once.
+*/
+pub fn diff_shifts(
+    mut previous_shifts: Vec<Shift>,
+    mut current_shifts: Vec<Shift>,
+    replace_old: bool,
+) -> ShiftDiff {
+    Shift::assign_sequences(&mut previous_shifts);
+    Shift::assign_sequences(&mut current_shifts);
+    // We start with a list of previously valid shifts. All marked as deleted
+    let mut previous_shifts: Vec<Shift> = previous_shifts
+        .into_iter()
+        .map(|mut shift| {
+            shift.state = ShiftState::Deleted;
+            shift
+        })
+        .collect();
+    let previous_index: HashMap<i64, usize> = previous_shifts
+        .iter()
+        .enumerate()
+        .map(|(index, shift)| (shift.magic_number, index))
+        .collect();
+
+    // First find all shifts which have not changed, so they can never be mistaken for a changed shift later on
+    let mut unmatched_shifts = vec![];
+    for mut current_shift in current_shifts {
+        match previous_index.get(&current_shift.magic_number) {
+            Some(index) if previous_shifts[*index].state == ShiftState::Deleted => {
+                let previous_shift = &mut previous_shifts[*index];
+                if replace_old {
+                    current_shift.state = ShiftState::Unchanged;
+                    *previous_shift = current_shift;
+                } else {
+                    previous_shift.state = ShiftState::Unchanged;
+                }
+            }
+            // The same shift is listed twice, just keep the first one
+            Some(_) => (),
+            None => unmatched_shifts.push(current_shift),
+        }
+    }
+
+    // Then pair the shifts that were not found with a disappeared shift on the same date
+    let mut added_shifts = vec![];
+    let mut replaced = HashMap::new();
+    for mut current_shift in unmatched_shifts {
+        match find_replaced_shift(&previous_shifts, &current_shift) {
+            Some(index) => {
+                current_shift.state = ShiftState::Changed;
+                let mut previous_shift =
+                    std::mem::replace(&mut previous_shifts[index], current_shift);
+                previous_shift.state = ShiftState::Unknown;
+                replaced.insert(previous_shifts[index].magic_number, previous_shift);
+            }
+            None => {
+                current_shift.state = ShiftState::New;
+                added_shifts.push(current_shift);
+            }
+        }
+    }
+
+    previous_shifts.extend(added_shifts);
+    ShiftDiff {
+        shifts: previous_shifts,
+        replaced,
+    }
+}
+
+/*
+Find the previous shift which was most likely replaced by the given shift
+Only shifts on the same date which have not been matched yet are considered.
+A shift with the same uid is preferred, then the same number, otherwise the one with the closest starting time
+*/
+fn find_replaced_shift(previous_shifts: &[Shift], current_shift: &Shift) -> Option<usize> {
+    previous_shifts
+        .iter()
+        .enumerate()
+        .filter(|(_, shift)| shift.state == ShiftState::Deleted && shift.date == current_shift.date)
+        .min_by_key(|(_, shift)| {
+            (
+                shift.uid() != current_shift.uid(),
+                shift.number != current_shift.number,
+                (shift.start - current_shift.start).abs(),
+            )
+        })
+        .map(|(index, _)| index)
+}
+
+// Only keep the shifts which happen today or later, there is no need to notify about shifts in the past
+pub fn upcoming<'a>(shifts: impl Iterator<Item = &'a Shift>, current_date: Date) -> Vec<&'a Shift> {
+    shifts.filter(|shift| shift.date >= current_date).collect()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use time::Month;
+
+    // Creates a shift in june 2025 lasting 7:40 hours
+    pub(crate) fn shift(number: &str, day: u8, start: &str, end: &str) -> Shift {
+        let separator = "\u{a0}• \u{a0}• ";
+        let text = [
+            format!("Dienst: {number}"),
+            format!("Geldig vanaf: {day:02}.06.2025"),
+            format!("Tijd: {start} - {end}"),
+            "Dienstduur: 07:40 Uren".to_owned(),
+            "Loonuren: 07:40 Uren".to_owned(),
+            "Dagsoort: \u{a0}• Maandag".to_owned(),
+            "Dienstsoort: \u{a0}• Rijdienst".to_owned(),
+            "Startplaats: \u{a0}• ehvgas, Einhoven garage streek".to_owned(),
+            "Omschrijving: \u{a0}• V".to_owned(),
+        ]
+        .join(separator);
+        Shift::new(
+            text,
+            Date::from_calendar_date(2025, Month::June, day).unwrap(),
+        )
+        .unwrap()
+    }
+
+    fn numbers<'a>(shifts: impl Iterator<Item = &'a Shift>) -> Vec<String> {
+        let mut numbers: Vec<String> = shifts.map(|shift| shift.number.clone()).collect();
+        numbers.sort();
+        numbers
+    }
+
+    #[test]
+    fn unchanged_shifts_are_kept() {
+        let previous = vec![shift("V2309", 2, "06:14", "13:54")];
+        let diff = diff_shifts(previous.clone(), previous, false);
+        assert_eq!(numbers(diff.unchanged_shifts()), vec!["V2309"]);
+        assert_eq!(diff.into_current().len(), 1);
+    }
+
+    #[test]
+    fn detects_new_changed_and_removed() {
+        let previous = vec![
+            shift("V2309", 2, "06:14", "13:54"),
+            shift("V2310", 3, "06:14", "13:54"),
+        ];
+        let current = vec![
+            shift("V2309", 2, "07:14", "14:54"),
+            shift("L1201", 4, "14:00", "22:00"),
+        ];
+        let diff = diff_shifts(previous, current, false);
+        assert_eq!(numbers(diff.changed_shifts()), vec!["V2309"]);
+        assert_eq!(numbers(diff.new_shifts()), vec!["L1201"]);
+        assert_eq!(numbers(diff.removed_shifts()), vec!["V2310"]);
+        assert_eq!(numbers(diff.into_current().iter()), vec!["L1201", "V2309"]);
+    }
+
+    #[test]
+    fn unchanged_shift_on_same_date_is_not_replaced() {
+        // Two shifts on one day, only the second one changes
+        let previous = vec![
+            shift("V2309", 2, "06:14", "10:00"),
+            shift("L1201", 2, "15:00", "19:00"),
+        ];
+        let current = vec![
+            shift("L1201", 2, "16:00", "20:00"),
+            shift("V2309", 2, "06:14", "10:00"),
+        ];
+        let diff = diff_shifts(previous, current, false);
+        assert_eq!(numbers(diff.unchanged_shifts()), vec!["V2309"]);
+        assert_eq!(numbers(diff.changed_shifts()), vec!["L1201"]);
+        assert_eq!(diff.removed_shifts().count(), 0);
+    }
+
+    #[test]
+    fn extra_shift_on_same_date_is_new() {
+        let previous = vec![shift("V2309", 2, "06:14", "10:00")];
+        let current = vec![
+            shift("V2309", 2, "06:14", "10:00"),
+            shift("L1201", 2, "15:00", "19:00"),
+        ];
+        let diff = diff_shifts(previous, current, false);
+        assert_eq!(numbers(diff.new_shifts()), vec!["L1201"]);
+        assert_eq!(diff.changed_shifts().count(), 0);
+    }
+
+    #[test]
+    fn reserve_turning_into_duty_is_a_call_out() {
+        let previous = vec![
+            shift("R0001", 2, "06:00", "13:40"),
+            shift("R0002", 9, "06:00", "13:40"),
+        ];
+        let current = vec![
+            shift("V2309", 2, "06:14", "13:54"),
+            shift("V2310", 9, "06:14", "13:54"),
+        ];
+        let diff = diff_shifts(previous, current, false);
+        let deadline = PrimitiveDateTime::new(
+            Date::from_calendar_date(2025, Month::June, 3).unwrap(),
+            time::Time::MIDNIGHT,
+        );
+        let call_outs = diff.reserve_call_outs(deadline);
+        assert_eq!(call_outs.len(), 1);
+        assert_eq!(call_outs[0].0.number, "R0001");
+        assert_eq!(call_outs[0].1.number, "V2309");
+    }
+
+    #[test]
+    fn changed_shift_prefers_same_number() {
+        let previous = vec![
+            shift("V2309", 2, "06:14", "10:00"),
+            shift("L1201", 2, "15:00", "19:00"),
+        ];
+        let current = vec![
+            shift("L1201", 2, "06:30", "10:30"),
+            shift("V2309", 2, "15:30", "19:30"),
+        ];
+        let diff = diff_shifts(previous, current, false);
+        let changed: Vec<&Shift> = diff.changed_shifts().collect();
+        assert_eq!(changed.len(), 2);
+        assert_eq!(diff.removed_shifts().count(), 0);
+    }
+
+    #[test]
+    fn same_duty_twice_a_day_is_matched_by_uid() {
+        let previous = vec![
+            shift("V2309", 2, "06:00", "09:00"),
+            shift("V2309", 2, "15:00", "18:00"),
+        ];
+        // The first one now starts closer to the old start of the second one
+        let current = vec![
+            shift("V2309", 2, "11:00", "13:00"),
+            shift("V2309", 2, "16:00", "18:00"),
+        ];
+        let diff = diff_shifts(previous, current, false);
+        let changed: Vec<&Shift> = diff.changed_shifts().collect();
+        assert_eq!(changed.len(), 2);
+        for shift in changed {
+            let replaced = diff.replaced_shift(shift).unwrap();
+            assert_eq!(replaced.uid(), shift.uid());
+        }
+    }
+
+    #[test]
+    fn sequences_follow_start_time() {
+        let mut shifts = vec![
+            shift("V2309", 2, "15:00", "18:00"),
+            shift("L1201", 2, "10:00", "12:00"),
+            shift("V2309", 2, "06:00", "09:00"),
+        ];
+        Shift::assign_sequences(&mut shifts);
+        let sequences: Vec<u8> = shifts.iter().map(|shift| shift.sequence).collect();
+        assert_eq!(sequences, vec![1, 0, 0]);
+    }
+
+    #[test]
+    fn replace_old_keeps_newest_version() {
+        let mut previous = shift("V2309", 2, "06:14", "13:54");
+        previous.description = "oud".to_owned();
+        let current = shift("V2309", 2, "06:14", "13:54");
+        let diff = diff_shifts(vec![previous.clone()], vec![current.clone()], true);
+        assert_eq!(diff.into_current()[0].description, current.description);
+        let diff = diff_shifts(vec![previous.clone()], vec![current], false);
+        assert_eq!(diff.into_current()[0].description, previous.description);
+    }
+
+    #[test]
+    fn upcoming_filters_past_shifts() {
+        let previous = vec![];
+        let current = vec![
+            shift("V2309", 2, "06:14", "13:54"),
+            shift("V2310", 5, "06:14", "13:54"),
+        ];
+        let diff = diff_shifts(previous, current, false);
+        let today = Date::from_calendar_date(2025, Month::June, 4).unwrap();
+        let upcoming = upcoming(diff.new_shifts(), today);
+        assert_eq!(numbers(upcoming.into_iter()), vec!["V2310"]);
+    }
+
+    // Randomly generated rosters, checking the invariants that should always hold for a diff
+    #[test]
+    fn diff_invariants_hold_for_random_rosters() {
+        let numbers = ["V2309", "L1201", "G3001", "R0001"];
+        let times = [("06:00", "14:00"), ("10:00", "18:00"), ("15:00", "23:00")];
+        let random_roster = || -> Vec<Shift> {
+            let mut roster: Vec<Shift> = vec![];
+            for _ in 0..rand::random_range(0..12) {
+                let (start, end) = times[rand::random_range(0..times.len())];
+                let new = shift(
+                    numbers[rand::random_range(0..numbers.len())],
+                    rand::random_range(1..8),
+                    start,
+                    end,
+                );
+                if !roster
+                    .iter()
+                    .any(|shift| shift.magic_number == new.magic_number)
+                {
+                    roster.push(new);
+                }
+            }
+            roster
+        };
+        for _ in 0..500 {
+            let previous = random_roster();
+            let current = random_roster();
+            let diff = diff_shifts(previous.clone(), current.clone(), false);
+
+            // Every current shift is still known afterwards
+            let mut current_hashes: Vec<i64> =
+                current.iter().map(|shift| shift.magic_number).collect();
+            let mut result_hashes: Vec<i64> = diff
+                .clone()
+                .into_current()
+                .iter()
+                .map(|shift| shift.magic_number)
+                .collect();
+            current_hashes.sort();
+            result_hashes.sort();
+            assert_eq!(current_hashes, result_hashes);
+
+            // Every previous shift is either unchanged, replaced by a change, or removed
+            assert_eq!(
+                diff.unchanged_shifts().count()
+                    + diff.changed_shifts().count()
+                    + diff.removed_shifts().count(),
+                previous.len()
+            );
+
+            // A changed shift always replaced a shift on the same date
+            for changed in diff.changed_shifts() {
+                assert!(previous.iter().any(|shift| shift.date == changed.date));
+                assert!(
+                    !previous
+                        .iter()
+                        .any(|shift| shift.magic_number == changed.magic_number)
+                );
+            }
+
+            // Removed shifts are not in the current roster
+            for removed in diff.removed_shifts() {
+                assert!(
+                    !current
+                        .iter()
+                        .any(|shift| shift.magic_number == removed.magic_number)
+                );
+            }
+
+            // Diffing a roster against itself never finds anything
+            let same = diff_shifts(current.clone(), current.clone(), false);
+            assert_eq!(same.unchanged_shifts().count(), current.len());
+            assert!(
+                same.shifts
+                    .iter()
+                    .all(|shift| shift.state == ShiftState::Unchanged)
+            );
+        }
+    }
+}
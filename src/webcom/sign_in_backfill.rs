@@ -0,0 +1,165 @@
+use chrono::NaiveDateTime;
+use entity::user_data;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, sea_query::Expr};
+use serde::Serialize;
+use tracing::*;
+
+use crate::{
+    GenResult,
+    database::variables::{GeneralProperties, UserData},
+    errors::{FailureType, SignInFailure},
+    execution::watchdog::InstanceMap,
+    get_database_connection,
+    health::ApplicationLogbook,
+    webcom::deletion::FRESH_DELETE_DURATION,
+};
+
+// Where the sign in date of a user was found
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackfillSource {
+    LogbookHistory,
+    LogbookFile,
+    CalendarWrite,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct BackfilledSignIn {
+    pub user_name: String,
+    pub sign_in_date: NaiveDateTime,
+    pub source: BackfillSource,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct SignInBackfillReport {
+    pub dry_run: bool,
+    pub backfilled: Vec<BackfilledSignIn>,
+    // Users without a sign in date for which nothing was found, they are still judged by their creation date
+    pub ambiguous: Vec<String>,
+}
+
+// The same rule as update_instance_timestamps, every run that was not refused by Webcomm had a working sign in
+fn signed_in(state: &FailureType) -> bool {
+    state != &FailureType::SignInFailed(SignInFailure::IncorrectCredentials)
+}
+
+fn newest_sign_in(
+    candidates: Vec<(BackfillSource, NaiveDateTime)>,
+) -> Option<(BackfillSource, NaiveDateTime)> {
+    candidates.into_iter().max_by_key(|(_, date)| *date)
+}
+
+async fn sign_in_candidates(
+    user: &UserData,
+    properties: &GeneralProperties,
+) -> GenResult<Vec<(BackfillSource, NaiveDateTime)>> {
+    let mut candidates = vec![];
+    let history = ApplicationLogbook::load_history(&user.user_name, None, None).await?;
+    if let Some(logbook) = history
+        .iter()
+        .rev()
+        .find(|logbook| signed_in(&logbook.state))
+    {
+        candidates.push((BackfillSource::LogbookHistory, logbook.execution_timestamp));
+    }
+    // Runs from before the logbook was saved to the database are only in the file
+    let logbook = ApplicationLogbook::load_local(user, properties)?;
+    if logbook.execution_timestamp != NaiveDateTime::default() && signed_in(&logbook.state) {
+        candidates.push((BackfillSource::LogbookFile, logbook.execution_timestamp));
+    }
+    // The calendar is only written after signing in
+    if let Some(write_date) = user.last_calendar_write_date {
+        candidates.push((BackfillSource::CalendarWrite, write_date));
+    }
+    Ok(candidates)
+}
+
+/*
+Users created before the account deletion migration have no sign in date, so they are treated like fresh accounts
+This finds the last run they signed in from what is left of earlier runs, users that are still fresh are left alone
+*/
+pub async fn backfill_sign_in_dates(
+    instances: &InstanceMap,
+    dry_run: bool,
+) -> GenResult<SignInBackfillReport> {
+    let db = get_database_connection().await;
+    let now = ApplicationLogbook::get_naive_datetime();
+    let mut report = SignInBackfillReport {
+        dry_run,
+        ..Default::default()
+    };
+    for instance in instances.values() {
+        let (user, properties) = instance.user_instance_data.get_data_local().await;
+        if user.last_succesfull_sign_in_date.is_some()
+            || now.signed_duration_since(user.creation_date) < FRESH_DELETE_DURATION
+        {
+            continue;
+        }
+        let Some((source, sign_in_date)) =
+            newest_sign_in(sign_in_candidates(&user, &properties).await?)
+        else {
+            report.ambiguous.push(user.user_name.clone());
+            continue;
+        };
+        info!(
+            "Sign in date of {} is {sign_in_date}, found in {source:?}",
+            user.user_name
+        );
+        if !dry_run {
+            // Only filled in if it is still empty, a run could have finished in the meantime
+            user_data::Entity::update_many()
+                .col_expr(
+                    user_data::Column::LastSuccesfullSignInDate,
+                    Expr::value(sign_in_date),
+                )
+                .filter(user_data::Column::UserDataId.eq(user.id))
+                .filter(user_data::Column::LastSuccesfullSignInDate.is_null())
+                .exec(&db)
+                .await?;
+            instance
+                .user_instance_data
+                .user_data
+                .write()
+                .await
+                .last_succesfull_sign_in_date
+                .get_or_insert(sign_in_date);
+        }
+        report.backfilled.push(BackfilledSignIn {
+            user_name: user.user_name.clone(),
+            sign_in_date,
+            source,
+        });
+    }
+    if !report.ambiguous.is_empty() {
+        warn!(
+            "No sign in date found for {} users: {:?}",
+            report.ambiguous.len(),
+            report.ambiguous
+        );
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn newest_sign_in_is_used() {
+        let date = NaiveDateTime::default() + Duration::days(365);
+        assert_eq!(newest_sign_in(vec![]), None);
+        assert_eq!(
+            newest_sign_in(vec![
+                (BackfillSource::LogbookHistory, date - Duration::days(3)),
+                (BackfillSource::CalendarWrite, date),
+                (BackfillSource::LogbookFile, date - Duration::hours(1)),
+            ]),
+            Some((BackfillSource::CalendarWrite, date))
+        );
+        assert!(signed_in(&FailureType::TriesExceeded));
+        assert!(!signed_in(&FailureType::SignInFailed(
+            SignInFailure::IncorrectCredentials
+        )));
+    }
+}
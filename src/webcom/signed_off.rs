@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use thirtyfour::{By, WebDriver, prelude::ElementQueryable};
+use time::{Date, Month, Time};
+use tracing::*;
+
+use crate::{
+    GenResult, database::feature_flags::FeatureFlag,
+    webcom::gebroken_shifts::navigate_to_subdirectory,
+};
+
+// Page in webcom listing the duties that have been signed off, with the times that were actually worked
+const SIGNED_OFF_PAGE: &str = "/WebComm/signedoffduties.aspx";
+// The page is only extra information, so the run does not wait for it as long as for the roster
+const SIGNED_OFF_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The actual times of a duty in the past, as signed off in webcom
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignedOffDuty {
+    pub date: Date,
+    pub number: String,
+    pub start: Time,
+    pub end: Time,
+}
+
+/*
+Loads the signed off duties page and reads every row
+Rows that can not be read are skipped, the page also contains rows for other kinds of registrations
+The layout of the page is not known for every depot, so it is only loaded if the SignedOffDuties flag is set for the group
+*/
+pub async fn load_signed_off_duties(driver: &WebDriver) -> GenResult<Option<Vec<SignedOffDuty>>> {
+    if !FeatureFlag::SignedOffDuties.is_enabled().await {
+        debug!("Signed off duties are not enabled for this group");
+        return Ok(None);
+    }
+    info!("Loading signed off duties");
+    navigate_to_subdirectory(driver, SIGNED_OFF_PAGE).await?;
+    driver
+        .query(By::Tag("tbody"))
+        .wait(SIGNED_OFF_TIMEOUT, Duration::from_secs(1))
+        .first()
+        .await?;
+    let rows = driver.find_all(By::Css("tbody tr")).await?;
+    let mut duties = vec![];
+    for row in rows {
+        let mut cells = vec![];
+        for cell in row.find_all(By::Tag("td")).await? {
+            cells.push(cell.text().await?);
+        }
+        match parse_signed_off_row(&cells) {
+            Some(duty) => duties.push(duty),
+            None => debug!("Skipping signed off row {cells:?}"),
+        }
+    }
+    info!("Found {} signed off duties", duties.len());
+    Ok(Some(duties))
+}
+
+/*
+A row contains the date, the duty number and the signed off start and end time, in that order
+eg: 29-06-2025 | V2309 | 06:14 | 14:02
+*/
+pub fn parse_signed_off_row(cells: &[String]) -> Option<SignedOffDuty> {
+    let [date, number, start, end, ..] = cells else {
+        return None;
+    };
+    let mut date_split = date.trim().split(['-', '.']);
+    let day: u8 = date_split.next()?.parse().ok()?;
+    let month: u8 = date_split.next()?.parse().ok()?;
+    let year: i32 = date_split.next()?.parse().ok()?;
+    let date = Date::from_calendar_date(year, Month::try_from(month).ok()?, day).ok()?;
+    let number = number.trim();
+    if number.is_empty() {
+        return None;
+    }
+    Some(SignedOffDuty {
+        date,
+        number: number.to_owned(),
+        start: parse_time(start)?,
+        end: parse_time(end)?,
+    })
+}
+
+fn parse_time(text: &str) -> Option<Time> {
+    let (hour, minute) = text.trim().split_once(':')?;
+    let hour: u8 = hour.parse().ok()?;
+    // Webcom shows times after midnight as 24:00 and later
+    Time::from_hms(hour % 24, minute.parse().ok()?, 0).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::{date, time};
+
+    fn row(cells: &[&str]) -> Vec<String> {
+        cells.iter().map(|cell| cell.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_signed_off_row() {
+        let duty = parse_signed_off_row(&row(&["29-06-2025", " V2309 ", "06:14", "14:02"]));
+        assert_eq!(
+            duty,
+            Some(SignedOffDuty {
+                date: date!(2025 - 06 - 29),
+                number: "V2309".to_owned(),
+                start: time!(06:14),
+                end: time!(14:02),
+            })
+        );
+    }
+
+    #[test]
+    fn parses_times_after_midnight() {
+        let duty = parse_signed_off_row(&row(&["29.06.2025", "N0001", "22:00", "25:30"])).unwrap();
+        assert_eq!(duty.end, time!(01:30));
+    }
+
+    #[test]
+    fn skips_other_rows() {
+        assert!(parse_signed_off_row(&row(&["Totaal", "", "", ""])).is_none());
+        assert!(parse_signed_off_row(&row(&["29-06-2025", "V2309"])).is_none());
+    }
+}
@@ -0,0 +1,352 @@
+use std::fmt::Write;
+
+use chrono::{Datelike, Months, NaiveDate, NaiveTime, Timelike};
+use time::{Date, Month, PrimitiveDateTime, Time};
+
+use crate::{
+    database::shift_history::ShiftHistoryEntry,
+    webcom::{holidays::holiday_minutes_between, shift::ShiftState},
+};
+
+// A4 in points
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 50.0;
+const LINE_HEIGHT: f32 = 16.0;
+const FONT_SIZE: f32 = 10.0;
+const MAX_KIND_LENGTH: usize = 20;
+const MONTHS: [&str; 12] = [
+    "januari",
+    "februari",
+    "maart",
+    "april",
+    "mei",
+    "juni",
+    "juli",
+    "augustus",
+    "september",
+    "oktober",
+    "november",
+    "december",
+];
+const COLUMNS: [(&str, f32); 7] = [
+    ("Datum", MARGIN),
+    ("Dienst", 120.0),
+    ("Soort", 180.0),
+    ("Begin", 310.0),
+    ("Eind", 360.0),
+    ("Duur", 420.0),
+    ("Betaald", 480.0),
+];
+
+// First and last day of the month, None if the month does not exist
+pub fn month_range(year: i32, month: u32) -> Option<(NaiveDate, NaiveDate)> {
+    let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+    let last = first.checked_add_months(Months::new(1))?.pred_opt()?;
+    Some((first, last))
+}
+
+fn hours(minutes: i64) -> String {
+    format!("{}:{:02}", minutes / 60, minutes % 60)
+}
+
+fn to_primitive(date: NaiveDate, time: NaiveTime) -> Option<PrimitiveDateTime> {
+    let date = Date::from_calendar_date(
+        date.year(),
+        Month::try_from(date.month() as u8).ok()?,
+        date.day() as u8,
+    )
+    .ok()?;
+    let time = Time::from_hms(time.hour() as u8, time.minute() as u8, time.second() as u8).ok()?;
+    Some(PrimitiveDateTime::new(date, time))
+}
+
+// Minutes of the shifts on public holidays, these get the holiday surcharge
+fn holiday_minutes(shifts: &[&ShiftHistoryEntry]) -> i64 {
+    shifts
+        .iter()
+        .filter_map(|shift| {
+            Some(holiday_minutes_between(
+                to_primitive(shift.date, shift.start)?,
+                to_primitive(shift.end_date, shift.end)?,
+            ))
+        })
+        .sum()
+}
+
+/*
+Printable time sheet of one month, like a punch card. Lists every shift of the history with the totals,
+and has room for the signatures of the driver and the planner
+The PDF is written by hand, it only needs text and lines so a PDF crate would be overkill
+*/
+pub fn create_time_sheet(
+    name: &str,
+    year: i32,
+    month: u32,
+    shifts: &[ShiftHistoryEntry],
+) -> Vec<u8> {
+    let shifts: Vec<&ShiftHistoryEntry> = shifts
+        .iter()
+        .filter(|shift| shift.state != ShiftState::Deleted)
+        .collect();
+    let month_name = MONTHS
+        .get(month.wrapping_sub(1) as usize)
+        .copied()
+        .unwrap_or_default();
+    let mut pdf = PdfPages::new();
+    let y = pdf.next_line();
+    pdf.text(
+        MARGIN,
+        y,
+        16.0,
+        true,
+        &format!("Urenoverzicht {month_name} {year}"),
+    );
+    pdf.next_line();
+    let y = pdf.next_line();
+    pdf.text(MARGIN, y, FONT_SIZE, false, &format!("Naam: {name}"));
+    pdf.next_line();
+    table_header(&mut pdf);
+    for shift in &shifts {
+        if pdf.page_full() {
+            pdf.new_page();
+            table_header(&mut pdf);
+        }
+        let y = pdf.next_line();
+        let kind: String = shift.kind.chars().take(MAX_KIND_LENGTH).collect();
+        let values = [
+            shift.date.format("%d-%m-%Y").to_string(),
+            shift.number.clone(),
+            kind,
+            shift.start.format("%H:%M").to_string(),
+            shift.end.format("%H:%M").to_string(),
+            hours(shift.duration_minutes),
+            hours(shift.working_minutes),
+        ];
+        for ((_, x), value) in COLUMNS.iter().zip(values) {
+            pdf.text(*x, y, FONT_SIZE, false, &value);
+        }
+    }
+    let y = pdf.next_line();
+    pdf.line(MARGIN, y + LINE_HEIGHT - 4.0, PAGE_WIDTH - MARGIN);
+    pdf.text(
+        MARGIN,
+        y,
+        FONT_SIZE,
+        true,
+        &format!("Totaal {} diensten", shifts.len()),
+    );
+    let duration = shifts.iter().map(|shift| shift.duration_minutes).sum();
+    let working = shifts.iter().map(|shift| shift.working_minutes).sum();
+    pdf.text(COLUMNS[5].1, y, FONT_SIZE, true, &hours(duration));
+    pdf.text(COLUMNS[6].1, y, FONT_SIZE, true, &hours(working));
+    let y = pdf.next_line();
+    pdf.text(MARGIN, y, FONT_SIZE, false, "Waarvan op feestdagen");
+    pdf.text(
+        COLUMNS[5].1,
+        y,
+        FONT_SIZE,
+        false,
+        &hours(holiday_minutes(&shifts)),
+    );
+
+    // Both signatures stay together, on a new page if they don't fit anymore
+    if pdf.y < MARGIN + LINE_HEIGHT * 6.0 {
+        pdf.new_page();
+    }
+    pdf.next_line();
+    pdf.next_line();
+    pdf.next_line();
+    let y = pdf.next_line();
+    let half = PAGE_WIDTH / 2.0;
+    pdf.line(MARGIN, y, half - 20.0);
+    pdf.line(half + 20.0, y, PAGE_WIDTH - MARGIN);
+    let y = pdf.next_line();
+    pdf.text(MARGIN, y, FONT_SIZE, false, "Handtekening medewerker");
+    pdf.text(
+        half + 20.0,
+        y,
+        FONT_SIZE,
+        false,
+        "Handtekening leidinggevende",
+    );
+    pdf.finish()
+}
+
+fn table_header(pdf: &mut PdfPages) {
+    let y = pdf.next_line();
+    for (title, x) in COLUMNS {
+        pdf.text(x, y, FONT_SIZE, true, title);
+    }
+    pdf.line(MARGIN, y - 4.0, PAGE_WIDTH - MARGIN);
+}
+
+// Text in a PDF string is WinAnsi, everything else than ASCII is written as an octal escape
+fn escape_pdf_text(text: &str) -> String {
+    let mut escaped = String::new();
+    for char in text.chars() {
+        match char {
+            '(' | ')' | '\\' => {
+                escaped.push('\\');
+                escaped.push(char);
+            }
+            ' '..='~' => escaped.push(char),
+            '•' => escaped.push_str("\\225"),
+            '\u{a0}'..='\u{ff}' => _ = write!(escaped, "\\{:03o}", char as u32),
+            _ => escaped.push('?'),
+        }
+    }
+    escaped
+}
+
+// The content streams of the pages, with the position of the next line on the last page
+struct PdfPages {
+    pages: Vec<String>,
+    y: f32,
+}
+
+impl PdfPages {
+    fn new() -> Self {
+        let mut pages = Self {
+            pages: vec![],
+            y: 0.0,
+        };
+        pages.new_page();
+        pages
+    }
+
+    fn new_page(&mut self) {
+        self.pages.push(String::new());
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    fn page_full(&self) -> bool {
+        self.y < MARGIN + LINE_HEIGHT
+    }
+
+    fn next_line(&mut self) -> f32 {
+        if self.page_full() {
+            self.new_page();
+        }
+        self.y -= LINE_HEIGHT;
+        self.y
+    }
+
+    fn content(&mut self) -> &mut String {
+        self.pages.last_mut().expect("There is always a page")
+    }
+
+    fn text(&mut self, x: f32, y: f32, size: f32, bold: bool, text: &str) {
+        let font = if bold { "F2" } else { "F1" };
+        let text = escape_pdf_text(text);
+        _ = writeln!(
+            self.content(),
+            "BT /{font} {size} Tf {x} {y} Td ({text}) Tj ET"
+        );
+    }
+
+    fn line(&mut self, from_x: f32, y: f32, to_x: f32) {
+        _ = writeln!(self.content(), "0.5 w {from_x} {y} m {to_x} {y} l S");
+    }
+
+    // Object 1 is the catalog, 2 the page tree, 3 and 4 the fonts, then a page and its content for every page
+    fn finish(self) -> Vec<u8> {
+        let page_ids: Vec<usize> = (0..self.pages.len()).map(|page| 5 + page * 2).collect();
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_owned(),
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                page_ids
+                    .iter()
+                    .map(|id| format!("{id} 0 R"))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                page_ids.len()
+            ),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+                .to_owned(),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>"
+                .to_owned(),
+        ];
+        for (content, page_id) in self.pages.iter().zip(&page_ids) {
+            objects.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] \
+                /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                page_id + 1
+            ));
+            objects.push(format!(
+                "<< /Length {} >>\nstream\n{content}endstream",
+                content.len()
+            ));
+        }
+        let mut pdf = String::from("%PDF-1.4\n");
+        let mut offsets = vec![];
+        for (index, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            _ = write!(pdf, "{} 0 obj\n{object}\nendobj\n", index + 1);
+        }
+        let xref_offset = pdf.len();
+        _ = write!(pdf, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+        for offset in offsets {
+            _ = writeln!(pdf, "{offset:010} 00000 n ");
+        }
+        _ = write!(
+            pdf,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref_offset}\n%%EOF\n",
+            objects.len() + 1
+        );
+        pdf.into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveTime;
+
+    #[test]
+    fn time_sheet_lists_shifts_and_totals() {
+        let shift = |number: &str, state| ShiftHistoryEntry {
+            magic_number: 1,
+            uid: number.to_owned(),
+            number: number.to_owned(),
+            kind: "Rijdienst (standby)".to_owned(),
+            date: NaiveDate::from_ymd_opt(2026, 2, 3).unwrap(),
+            start: NaiveTime::from_hms_opt(6, 0, 0).unwrap(),
+            end_date: NaiveDate::from_ymd_opt(2026, 2, 3).unwrap(),
+            end: NaiveTime::from_hms_opt(14, 30, 0).unwrap(),
+            duration_minutes: 510,
+            working_minutes: 480,
+            state,
+        };
+        let shifts = [
+            shift("1234", ShiftState::Unchanged),
+            shift("5678", ShiftState::Deleted),
+        ];
+        let pdf = String::from_utf8(create_time_sheet("Piet", 2026, 2, &shifts)).unwrap();
+        assert!(pdf.starts_with("%PDF-1.4"));
+        assert!(pdf.contains("(Urenoverzicht februari 2026)"));
+        assert!(pdf.contains("(Rijdienst \\(standby\\))"));
+        assert!(pdf.contains("(1234)"));
+        assert!(!pdf.contains("(5678)"));
+        assert!(pdf.contains("(Totaal 1 diensten)"));
+        assert!(pdf.contains("(8:00)"));
+        assert!(pdf.contains("(Waarvan op feestdagen)"));
+        let christmas = ShiftHistoryEntry {
+            date: NaiveDate::from_ymd_opt(2025, 12, 24).unwrap(),
+            start: NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+            end_date: NaiveDate::from_ymd_opt(2025, 12, 25).unwrap(),
+            end: NaiveTime::from_hms_opt(2, 30, 0).unwrap(),
+            ..shift("9999", ShiftState::Unchanged)
+        };
+        assert_eq!(holiday_minutes(&[&christmas]), 150);
+        assert_eq!(
+            month_range(2024, 2),
+            Some((
+                NaiveDate::from_ymd_opt(2024, 2, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2024, 2, 29).unwrap()
+            ))
+        );
+        assert_eq!(month_range(2024, 13), None);
+    }
+}
@@ -1,52 +1,44 @@
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Instant;
 
-use crate::StartRequest;
+use crate::config::var;
+use crate::database::shift_history::{save_actual_times, save_shift_history};
+use crate::database::shift_notes::load_shift_notes;
 use crate::errors::ResultLog;
+use crate::execution::permissions::write_private_file;
+use crate::execution::request_queue::RequestQueue;
+use crate::webcom::calendar_file::write_calendar;
+use crate::webcom::event_store::EventStore;
 use crate::webcom::gebroken_shifts;
 use crate::webcom::ical::{CalendarVersionError, PreviousShifts};
+use crate::webcom::occurrence::{ShiftOccurrence, SplitOptions, shift_occurrences};
 use crate::webcom::shift::Shift;
+use crate::webcom::signed_off;
 use crate::{
-    FALLBACK_URL, GenError, GenResult, MAIN_URL, create_path,
-    errors::{FailureType, IncorrectCredentialsCount},
+    FALLBACK_URL, MAIN_URL, create_path,
+    database::onboarding_variants::load_welcome_template,
+    errors::{AppError, AppResult, ErrorCategory, FailureType, IncorrectCredentialsCount},
     get_data, get_set_name,
-    health::{ApplicationLogbook, send_heartbeat, update_calendar_exit_code},
+    health::{ApplicationLogbook, Phase, send_heartbeat, update_calendar_exit_code},
+    is_dry_run,
     webcom::{
-        email::{self, send_errors, send_welcome_mail},
+        email::{self, send_errors, send_welcome_mail, welcome_mail_enabled},
         ical::{
             self, NON_RELEVANT_EVENTS_PATH, RELEVANT_EVENTS_PATH, create_calendar_file,
             get_ical_path, get_previous_shifts, split_relevant_shifts,
         },
-        parsing::{
-            load_current_month_shifts, load_next_month_shifts, load_previous_month_shifts,
-            sign_in_and_open_calendar_view,
-        },
+        parsing::{MonthHashes, load_roster_months, sign_in_and_open_calendar_view},
         webdriver::{get_driver, wait_until_loaded, wait_untill_redirect},
     },
 };
-use dotenvy::var;
+use crate::{InstanceRequest, StartRequest};
 use thirtyfour::WebDriver;
-use tokio::fs::{self, write};
-use tokio::sync::mpsc::Sender;
+use tokio::fs;
 use tracing::*;
 
-async fn init_shifts(driver: &WebDriver) -> GenResult<(Vec<Shift>, Vec<Shift>)> {
-    info!(
-        "Existing calendar file not found, adding two extra months of shifts and removing partial calendars"
-    );
-    _ = fs::remove_file(PathBuf::from(NON_RELEVANT_EVENTS_PATH)).await;
-    _ = fs::remove_file(PathBuf::from(RELEVANT_EVENTS_PATH)).await;
-    let found_shifts = load_previous_month_shifts(&driver, 2).await?;
-    debug!("Found a total of {} shifts", found_shifts.len());
-    Ok(split_relevant_shifts(found_shifts))
-}
-
-// Main program logic that has to run, if it fails it will all be reran.
-async fn main_program(
-    driver: &WebDriver,
-    retry_count: usize,
-    logbook: &mut ApplicationLogbook,
-) -> GenResult<()> {
+// Signs in and opens the roster, without loading anything yet
+async fn open_webcom(driver: &WebDriver, retry_count: usize) -> AppResult<()> {
     let (user, _properties) = get_data();
     let personeelsnummer = user.personeelsnummer.clone();
     let password = user.password.clone();
@@ -62,97 +54,209 @@ async fn main_program(
             driver
                 .goto(FALLBACK_URL[retry_count % FALLBACK_URL.len()])
                 .await
-                .map_err(|_| Box::new(FailureType::ConnectError))?
+                .map_err(|_| FailureType::ConnectError)?
         }
     };
     sign_in_and_open_calendar_view(&driver, personeelsnummer, password).await?;
+    Ok(())
+}
+
+// Main program logic that has to run, if it fails it will all be reran.
+async fn main_program(
+    driver: &WebDriver,
+    retry_count: usize,
+    logbook: &mut ApplicationLogbook,
+) -> AppResult<()> {
+    let (user, _properties) = get_data();
+    // Reset, because a retry does everything again
+    logbook.application_state.skipped_months.clear();
+    logbook.application_state.phase_timings.clear();
+    logbook.application_state.changed_shifts = 0;
+    let started = Instant::now();
+    open_webcom(driver, retry_count).await?;
     wait_until_loaded(&driver).await?;
+    logbook
+        .application_state
+        .phase_timings
+        .record(Phase::Login, None, started);
     let mut send_welcome = false;
-    let mut new_shifts = load_current_month_shifts(&driver, logbook).await?;
-    let mut non_relevant_shifts = vec![];
     let ical_path = get_ical_path();
-    if !ical_path.exists() {
+    let (roster, mut non_relevant_shifts) = if !ical_path.exists() {
+        info!(
+            "Existing calendar file not found, adding two extra months of shifts and removing partial calendars"
+        );
         send_welcome = true;
-        let mut initial_shifts = init_shifts(driver).await?;
-        new_shifts.append(&mut initial_shifts.0);
-        non_relevant_shifts.append(&mut initial_shifts.1);
+        _ = fs::remove_file(PathBuf::from(NON_RELEVANT_EVENTS_PATH)).await;
+        _ = fs::remove_file(PathBuf::from(RELEVANT_EVENTS_PATH)).await;
+        // Without a calendar there are no previous shifts to reuse, so every month is parsed
+        let mut roster = load_roster_months(driver, 3, &MonthHashes::default(), logbook).await?;
+        let (relevant_shifts, non_relevant_shifts) =
+            split_relevant_shifts(std::mem::take(&mut roster.shifts));
         debug!(
             "Got {} relevant and {} non-relevant events",
-            new_shifts.len(),
+            relevant_shifts.len(),
             non_relevant_shifts.len()
         );
+        roster.shifts = relevant_shifts;
+        (roster, non_relevant_shifts)
     } else {
         debug!("Existing calendar file found");
-        new_shifts.append(&mut load_previous_month_shifts(&driver, 0).await?);
-    }
-    new_shifts.append(&mut load_next_month_shifts(&driver, logbook).await?);
-    info!("Found {} shifts", new_shifts.len());
+        (
+            load_roster_months(driver, 1, &MonthHashes::load(), logbook).await?,
+            vec![],
+        )
+    };
+    info!(
+        "Found {} shifts, {} months unchanged",
+        roster.shifts.len(),
+        roster.unchanged_months.len()
+    );
 
     let mut force_replace = false;
+    let mut previous_shifts_usable = create_path(RELEVANT_EVENTS_PATH).exists();
     // If getting previous shift information failed, just create an empty one. Because it will cause a new calendar to be created
     let mut previous_shifts =
         match get_previous_shifts().warn_owned("Getting previous shift information") {
             Ok(Err(CalendarVersionError::ForceReplace)) => {
                 warn!("Force replacing shifts");
                 force_replace = true;
+                previous_shifts_usable = false;
                 PreviousShifts::default()
             }
             Ok(Ok(previous_shifs)) => previous_shifs,
-            _ => PreviousShifts::default(),
+            _ => {
+                previous_shifts_usable = false;
+                PreviousShifts::default()
+            }
         };
     non_relevant_shifts.append(&mut previous_shifts.non_relevant_shifts);
     let previous_relevant_shifts = previous_shifts.relevant_shifts;
 
+    // Months which have not changed get the shifts of the last run, those only need parsing if the last run can not be used
+    let mut new_shifts = roster.shifts;
+    for unchanged_month in roster.unchanged_months {
+        if previous_shifts_usable {
+            info!("Skipping unchanged month {}", unchanged_month.key());
+            new_shifts.extend(
+                previous_relevant_shifts
+                    .iter()
+                    .filter(|shift| unchanged_month.contains(shift.date))
+                    .cloned(),
+            );
+            logbook.add_skipped_month(unchanged_month.key());
+        } else {
+            let (mut month_shifts, failed_shifts) = unchanged_month.parse()?;
+            logbook.add_failed_shifts(failed_shifts, false);
+            new_shifts.append(&mut month_shifts);
+        }
+    }
+
     // The main send email function will return the broken shifts that are new or have changed.
     // This is because the send email functions uses the previous shifts and scans for new shifts
-    let relevant_shifts =
-        match email::send_emails(new_shifts, previous_relevant_shifts, force_replace) {
-            Ok(shifts) => shifts,
-            Err(err) => return Err(err),
-        };
+    let relevant_shifts = email::send_emails(
+        new_shifts,
+        previous_relevant_shifts,
+        force_replace,
+        &mut logbook.application_state,
+    )?;
+
+    if !is_dry_run() {
+        save_shift_history(&relevant_shifts)
+            .await
+            .warn("Saving shift history");
+        // The actual times are only extra information, so failing to load them is not a problem
+        if let Ok(Some(duties)) = signed_off::load_signed_off_duties(driver)
+            .await
+            .warn_owned("Loading signed off duties")
+        {
+            save_actual_times(&duties)
+                .await
+                .warn("Saving signed off duties");
+        }
+    }
 
     let non_relevant_shift_len = non_relevant_shifts.len();
     let mut all_shifts = relevant_shifts;
     all_shifts.append(&mut non_relevant_shifts);
+    Shift::assign_sequences(&mut all_shifts);
 
-    let mut all_shifts_modified;
-    if var("SKIP_BROKEN").unwrap_or_default() != "true" {
+    let split_broken = var("SKIP_BROKEN").unwrap_or_default() != "true";
+    if split_broken {
+        let started = Instant::now();
         all_shifts = gebroken_shifts::add_broken_shift_information(&driver, &all_shifts).await?; // Replace the shifts with the newly created list of broken shifts
-        ical::save_partial_shift_files(&all_shifts).error("Saving partial shift files");
-        all_shifts_modified = gebroken_shifts::split_broken_shifts(&all_shifts);
-    } else {
-        all_shifts_modified = all_shifts.clone();
-    }
-
-    if user.user_properties.stop_midnight_shift {
-        all_shifts_modified = gebroken_shifts::stop_shift_at_midnight(&all_shifts_modified);
-    }
-
-    if user.user_properties.split_night_shift {
-        all_shifts_modified = gebroken_shifts::split_night_shift(&all_shifts_modified)?;
+        logbook
+            .application_state
+            .phase_timings
+            .record(Phase::BrokenShifts, None, started);
+        if !is_dry_run() {
+            ical::save_partial_shift_files(&all_shifts).error("Saving partial shift files");
+        }
     }
 
-    all_shifts_modified.sort_by_key(|shift| shift.magic_number); // I do just just for peace of mind, it is probably not needed though
-    all_shifts_modified.dedup();
+    // Broken shifts and shifts crossing midnight are cut up in one go, so they can not be split twice
+    let split_options = SplitOptions {
+        split_broken,
+        stop_at_midnight: user.user_properties.stop_midnight_shift,
+        split_at_midnight: user.user_properties.split_night_shift,
+    };
+    let all_shifts_modified: Vec<Shift> = shift_occurrences(&all_shifts, split_options)
+        .iter()
+        .map(ShiftOccurrence::to_shift)
+        .collect();
 
     debug!("Saving {} shifts", all_shifts.len());
-    let calendar = create_calendar_file(&all_shifts_modified, &all_shifts, &logbook.state)?;
-
-    info!("Writing to: {:?}", &ical_path);
-    write(ical_path, calendar.as_bytes()).await?;
+    // Notes are optional, a calendar without them is better than no calendar
+    let notes = load_shift_notes(user.id)
+        .await
+        .warn_owned("Loading shift notes")
+        .unwrap_or_default();
+    let started = Instant::now();
+    let mut event_store = EventStore::load();
+    let calendar = create_calendar_file(
+        &all_shifts_modified,
+        &all_shifts,
+        &logbook.state,
+        &notes,
+        &mut event_store,
+    )?;
+
+    if is_dry_run() {
+        info!(
+            "Dry run, not writing calendar to {:?}. It would contain {} shifts",
+            &ical_path,
+            all_shifts_modified.len()
+        );
+    } else {
+        info!("Writing to: {:?}", &ical_path);
+        write_calendar(&ical_path, &create_path(""), calendar.as_bytes())?;
+        // Only now the months are really processed, so only now they can be skipped next time
+        roster.hashes.save().warn("Saving month hashes");
+        event_store.save().warn("Saving event store");
+    }
+    logbook
+        .application_state
+        .phase_timings
+        .record(Phase::IcalWrite, None, started);
 
-    if send_welcome {
-        send_welcome_mail(false)?;
+    if send_welcome && welcome_mail_enabled(false) {
+        send_welcome_mail(&load_welcome_template().await)?;
     }
 
     logbook.generate_shift_statistics(&all_shifts, non_relevant_shift_len);
+    if is_dry_run() {
+        let statistics = &logbook.application_state;
+        info!(
+            "Dry run finished. Found {} shifts ({} broken, {} not relevant), mails are saved in the dry_run_mails folder",
+            statistics.shifts, statistics.broken_shifts, statistics.non_relevant_shifts
+        );
+    }
     Ok(())
 }
 
 // Create file on disk to show webcom ical is currently active
 // Always delete the file at the beginning of this function
 // Only create a new file if start reason is Some
-async fn create_delete_lock(start_reason: Option<&StartRequest>) -> GenResult<()> {
+async fn create_delete_lock(start_reason: Option<&StartRequest>) -> AppResult<()> {
     let path = create_path("active");
     if path.exists() {
         debug!("Removing existing lock file");
@@ -161,7 +265,7 @@ async fn create_delete_lock(start_reason: Option<&StartRequest>) -> GenResult<()
     if let Some(start_reason) = start_reason {
         debug!("Creating new lock file");
         let text = serde_json::to_string(start_reason).unwrap_or_default();
-        write(&path, text.as_bytes()).await?;
+        write_private_file(&path, text.as_bytes())?;
     }
     Ok(())
 }
@@ -176,11 +280,8 @@ pub enum ResumeReason {
     SigninFailureReduce,
 }
 
-pub async fn webcom_instance(
-    start_reason: StartRequest,
-    sender: Arc<Sender<StartRequest>>,
-) -> FailureType {
-    let (_user, properties) = get_data();
+pub async fn webcom_instance(start_reason: StartRequest, sender: Arc<RequestQueue>) -> FailureType {
+    let (user, properties) = get_data();
 
     create_delete_lock(Some(&start_reason))
         .await
@@ -192,11 +293,19 @@ pub async fn webcom_instance(
 
     let mut current_exit_code = FailureType::default();
     let previous_exit_code = logbook.clone().state;
-    let mut running_errors: Vec<GenError> = vec![];
+    let mut running_errors: Vec<AppError> = vec![];
+    let mut permanent_error = None;
+    logbook.application_state.ended_by = None;
 
     let mut allow_execution = true;
     let mut retry_count: usize = 0;
-    let max_retry_count: usize = properties.execution_retry_count as usize;
+    let max_retry_count: usize = user.execution_retry_count(&properties) as usize;
+
+    if start_reason == StartRequest::VerifyLogin {
+        verify_login(&mut failure_counter).await;
+        create_delete_lock(None).await.warn("Removing lock");
+        return previous_exit_code;
+    }
 
     // Check if the program is allowed to run, or not due to failed sign-in
     let resume_reason: ResumeReason = failure_counter.sign_in_failed_check();
@@ -217,7 +326,7 @@ pub async fn webcom_instance(
     }
 
     // Load the driver, do an early return if it fails
-    let driver = match get_driver(&mut logbook).await {
+    let driver = match get_driver().await {
         Ok(driver) => driver,
         Err(err) => {
             error!("Failed to get driver! error: {}", err.to_string());
@@ -238,30 +347,24 @@ pub async fn webcom_instance(
                     .warn("Updating signin failure");
                 allow_execution = false;
             }
-            Err(err) if err.downcast_ref::<FailureType>().is_some() => {
-                let webcom_error = err
-                    .downcast_ref::<FailureType>()
-                    .cloned()
-                    .unwrap_or_default();
-                match webcom_error.clone() {
-                    FailureType::SignInFailed(signin_failure) => {
-                        allow_execution = false;
-                        failure_counter
-                            .update_signin_failure(
-                                true,
-                                &resume_reason,
-                                Some(signin_failure.clone()),
-                            )
-                            .warn("Updating signin failure 2");
-                        current_exit_code = webcom_error;
-                    }
-                    FailureType::ConnectError => {
-                        allow_execution = false;
-                        current_exit_code = FailureType::ConnectError;
-                    }
-                    _ => {
-                        running_errors.push(err);
+            // Trying again does not help for these, the run stops right away
+            Err(err) if !err.retry() => {
+                allow_execution = false;
+                logbook.application_state.ended_by = Some(err.category());
+                match err {
+                    AppError::Failure(failure) => {
+                        if let FailureType::SignInFailed(signin_failure) = &failure {
+                            failure_counter
+                                .update_signin_failure(
+                                    true,
+                                    &resume_reason,
+                                    Some(signin_failure.clone()),
+                                )
+                                .warn("Updating signin failure 2");
+                        }
+                        current_exit_code = failure;
                     }
+                    err => permanent_error = Some(err),
                 }
             }
             Err(err) => {
@@ -271,13 +374,26 @@ pub async fn webcom_instance(
         retry_count += 1;
     }
 
-    if running_errors.is_empty() {
+    if let Some(err) = permanent_error {
+        error!("Not retrying, this error does not go away by trying again: {err}");
+        let category = err.category();
+        // Only the category, so the exit code stays the same as long as the same kind of error happens
+        current_exit_code = FailureType::Other(<&'static str>::from(category).to_owned());
+        running_errors.push(err);
+        send_errors(&running_errors, &name, category).warn("Sending permanent error");
+    } else if running_errors.is_empty() {
         info!("Alles is in een keer goed gegaan, jippie!");
     } else if running_errors.len() < max_retry_count {
         warn!("Errors have occured, but succeded in the end");
     } else {
         current_exit_code = FailureType::TriesExceeded;
-        send_errors(&running_errors, &name).warn("Sending errors in loop");
+        // Grouped by what went wrong in the last try, TriesExceeded itself says nothing about the cause
+        let category = running_errors
+            .last()
+            .map(AppError::category)
+            .unwrap_or(ErrorCategory::Other);
+        logbook.application_state.ended_by = Some(category);
+        send_errors(&running_errors, &name, category).warn("Sending errors in loop");
     }
 
     _ = driver.quit().await.is_err_and(|_| {
@@ -286,7 +402,7 @@ pub async fn webcom_instance(
     });
 
     // Update the exit code in the calendar if it is not equal to the previous value
-    if previous_exit_code != current_exit_code {
+    if previous_exit_code != current_exit_code && !is_dry_run() {
         warn!("Previous exit code was different than current, need to update");
         update_calendar_exit_code(&previous_exit_code, &current_exit_code)
             .warn("Updating calendar exit code");
@@ -297,17 +413,66 @@ pub async fn webcom_instance(
     current_exit_code
 }
 
+/*
+Only signs in, so a user who changed their password hears within minutes if the new one works
+The run itself is left to the timer, so the exit code and logbook are not touched
+*/
+async fn verify_login(failure_counter: &mut IncorrectCredentialsCount) {
+    if !failure_counter.has_new_password() {
+        debug!("No new password to verify");
+        return;
+    }
+    info!("Verifying new password");
+    let driver = match get_driver().await {
+        Ok(driver) => driver,
+        Err(err) => {
+            warn!("Failed to get driver for login check: {err}");
+            return;
+        }
+    };
+    let signin_failure = match open_webcom(&driver, 0).await {
+        Ok(()) => None,
+        Err(err) => match err {
+            AppError::Failure(FailureType::SignInFailed(signin_failure)) => Some(signin_failure),
+            // Not a verdict on the password, the next run tries again
+            err => {
+                warn!("Login check failed: {err}");
+                _ = driver.quit().await;
+                return;
+            }
+        },
+    };
+    failure_counter
+        .update_signin_failure(
+            signin_failure.is_some(),
+            &ResumeReason::NewPassword,
+            signin_failure,
+        )
+        .warn("Updating signin failure after login check");
+    _ = driver.quit().await;
+}
+
 async fn clean_execution(
     logbook: &mut ApplicationLogbook,
     exit_code: &FailureType,
-    sender: Arc<Sender<StartRequest>>,
+    sender: Arc<RequestQueue>,
 ) {
-    logbook.save(exit_code).warn("Saving logbook in loop");
+    // A dry run leaves the logbook and the exit code of the instance as they were
+    let finished = if is_dry_run() {
+        StartRequest::DryRunFinished(exit_code.clone())
+    } else {
+        logbook.save(exit_code).warn("Saving logbook in loop");
+        logbook
+            .save_to_database()
+            .await
+            .warn("Saving logbook to database");
+        StartRequest::ExecutionFinished(exit_code.clone())
+    };
     create_delete_lock(None).await.warn("Removing lock");
     sender
-        .try_send(StartRequest::ExecutionFinished(exit_code.clone()))
+        .push(InstanceRequest::new(finished))
         .warn("Sending exit code back to instance manager");
-    send_heartbeat(&exit_code)
+    send_heartbeat(&exit_code, logbook)
         .await
         .warn("Sending Heartbeat in loop");
 }
@@ -1,40 +1,86 @@
+use crate::config::var;
 use crate::errors::ResultLog;
 use crate::{
-    GenResult,
-    errors::FailureType,
+    errors::{AppResult, ErrorCategory},
+    execution::circuit_breaker::SELENIUM_BREAKER,
     get_set_name,
-    health::{ApplicationLogbook, send_heartbeat},
-    webcom::email::send_errors,
+    webcom::{
+        admin_webhook::send_admin_webhook,
+        email::{send_admin_alert_mail, send_errors},
+    },
 };
-use dotenvy::var;
+use std::time::Instant;
 use thirtyfour::{DesiredCapabilities, WebDriver, error::WebDriverError};
 use tracing::*;
 
-pub async fn initiate_webdriver() -> GenResult<WebDriver> {
-    let gecko_ip = var("SELENIUM_URL")?;
+pub async fn initiate_webdriver() -> AppResult<WebDriver> {
+    let gecko_ip = var("SELENIUM_URL").map_err(|_| "SELENIUM_URL is not set")?;
     let caps = DesiredCapabilities::firefox();
     let driver = WebDriver::new(format!("http://{}", gecko_ip), caps).await?;
     Ok(driver)
 }
 
-pub async fn get_driver(logbook: &mut ApplicationLogbook) -> GenResult<WebDriver> {
+// A failure is recorded in the logbook, the history and Kuma by the run which asked for the driver
+pub async fn get_driver() -> AppResult<WebDriver> {
+    let remaining_cooldown = SELENIUM_BREAKER
+        .lock()
+        .ok()
+        .and_then(|mut breaker| breaker.try_attempt(Instant::now()));
+    if let Some(remaining_cooldown) = remaining_cooldown {
+        info!(
+            "Selenium failed too often, not starting a browser for another {} seconds",
+            remaining_cooldown.as_secs()
+        );
+        return Err("Selenium circuit breaker open".into());
+    }
     match initiate_webdriver().await {
-        Ok(driver) => Ok(driver),
+        Ok(driver) => {
+            if SELENIUM_BREAKER
+                .lock()
+                .is_ok_and(|mut breaker| breaker.record_success())
+            {
+                info!("Selenium works again, runs are resumed");
+                alert_admin(
+                    "Selenium werkt weer",
+                    "Selenium werkt weer, diensten worden weer geladen",
+                    true,
+                )
+                .await;
+            }
+            Ok(driver)
+        }
         Err(error) => {
             error!("Kon driver niet opstarten: {:?}", &error);
-            send_errors(&vec![error], &get_set_name(None)).info("Send errors");
-            logbook
-                .save(&FailureType::GeckoEngine)
-                .warn("Saving Logbook");
-            send_heartbeat(&FailureType::GeckoEngine)
-                .await
-                .warn("Sending heartbeat");
+            if SELENIUM_BREAKER
+                .lock()
+                .is_ok_and(|mut breaker| breaker.record_failure(Instant::now()))
+            {
+                error!("Selenium keeps failing, pausing all runs");
+                alert_admin(
+                    "Selenium faalt steeds",
+                    &format!(
+                        "Selenium faalt steeds, alle gebruikers worden gepauzeerd. Laatste fout: {error}"
+                    ),
+                    false,
+                )
+                .await;
+            }
+            send_errors(&vec![error], &get_set_name(None), ErrorCategory::Browser)
+                .info("Send errors");
             return Err("driver fout".into());
         }
     }
 }
 
-pub async fn wait_until_loaded(driver: &WebDriver) -> GenResult<()> {
+// The support address always gets the alert, ADMIN_WEBHOOK_URL only if it is set
+async fn alert_admin(title: &str, message: &str, healthy: bool) {
+    send_admin_alert_mail(title, message, healthy).warn("Sending Selenium alert mail");
+    send_admin_webhook(message)
+        .await
+        .warn("Sending Selenium alert webhook");
+}
+
+pub async fn wait_until_loaded(driver: &WebDriver) -> AppResult<()> {
     let mut started_loading = false;
     let timeout_duration = std::time::Duration::from_secs(30);
     let _ = tokio::time::timeout(timeout_duration, async {
@@ -55,7 +101,7 @@ pub async fn wait_until_loaded(driver: &WebDriver) -> GenResult<()> {
     Ok(())
 }
 
-pub async fn wait_untill_redirect(driver: &WebDriver) -> GenResult<()> {
+pub async fn wait_untill_redirect(driver: &WebDriver) -> AppResult<()> {
     let initial_url = driver.current_url().await?;
     let mut current_url = driver.current_url().await?;
     let timeout = std::time::Duration::from_secs(30); // Maximum wait time.
@@ -74,9 +120,7 @@ pub async fn wait_untill_redirect(driver: &WebDriver) -> GenResult<()> {
 
     if current_url == initial_url {
         warn!("Timeout waiting for redirect.");
-        return Err(Box::new(WebDriverError::Timeout(
-            "Redirect did not occur".into(),
-        )));
+        return Err(WebDriverError::Timeout("Redirect did not occur".into()).into());
     }
 
     debug!("Redirected to: {}", current_url);
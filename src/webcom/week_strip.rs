@@ -0,0 +1,147 @@
+use time::{Date, Duration, Time};
+
+use crate::webcom::{email::COLOR_BASE, shift::Shift};
+
+const DAYS_BEFORE: i64 = 3;
+const DAYS_SHOWN: i64 = 7;
+const DAY_WIDTH: i64 = 48;
+// Every row of the table is an hour of 5 pixels high, so a day is 120 pixels high
+const SLOT_MINUTES: i64 = 60;
+const SLOT_HEIGHT: i64 = 5;
+const MINUTES_PER_DAY: i64 = 24 * 60;
+const WEEKDAYS: [&str; 7] = ["ma", "di", "wo", "do", "vr", "za", "zo"];
+const COLOR_OTHER_SHIFT: &str = "#c0bfbc";
+const COLOR_GRID: &str = "#deddda";
+
+/*
+A small overview of the days around the first highlighted shift, every day is a column going from midnight to midnight
+Highlighted shifts are drawn in the base color and the other known shifts in grey, so it is easy to see how a new shift fits in
+Shifts past midnight continue at the top of the next day
+It is a plain table with colored cells, as most mail clients block SVG and an attached image would need a renderer
+*/
+pub fn render_week_strip(shifts: &[&Shift], highlighted: &[&Shift]) -> Option<String> {
+    let first_date = highlighted.iter().map(|shift| shift.date).min()?;
+    let start_date = first_date - Duration::days(DAYS_BEFORE);
+    let dates: Vec<Date> = (0..DAYS_SHOWN)
+        .map(|day_index| start_date + Duration::days(day_index))
+        .collect();
+
+    let mut html = format!(
+        "<table role=\"presentation\" cellpadding=\"0\" cellspacing=\"0\" width=\"{}\" style=\"border-collapse:collapse;font-family:sans-serif;font-size:11px;\"><tr>",
+        DAYS_SHOWN * DAY_WIDTH
+    );
+    for date in &dates {
+        let weekday = WEEKDAYS[date.weekday().number_days_from_monday() as usize];
+        let weight = if *date == first_date {
+            "bold"
+        } else {
+            "normal"
+        };
+        html.push_str(&format!(
+            "<td width=\"{DAY_WIDTH}\" align=\"center\" style=\"padding-bottom:4px;font-weight:{weight};\">{weekday} {}</td>",
+            date.day()
+        ));
+    }
+    html.push_str("</tr>");
+    for slot in 0..MINUTES_PER_DAY / SLOT_MINUTES {
+        let slot_start = slot * SLOT_MINUTES;
+        // A line every six hours, so the time of a shift can be estimated
+        let grid = match slot_start % (6 * 60) == 0 && slot_start != 0 {
+            true => COLOR_GRID,
+            false => "#ffffff",
+        };
+        html.push_str(&format!("<tr height=\"{SLOT_HEIGHT}\">"));
+        for date in &dates {
+            let color = slot_color(shifts, highlighted, *date, slot_start).unwrap_or("#ffffff");
+            html.push_str(&format!(
+                "<td style=\"height:{SLOT_HEIGHT}px;line-height:{SLOT_HEIGHT}px;font-size:0;border-top:1px solid {grid};border-left:6px solid #ffffff;border-right:6px solid #ffffff;background-color:{color};\">&nbsp;</td>"
+            ));
+        }
+        html.push_str("</tr>");
+    }
+    html.push_str("</table>");
+    Some(html)
+}
+
+// Highlighted shifts go over other shifts, in case they are part of the known shifts as well
+fn slot_color(
+    shifts: &[&Shift],
+    highlighted: &[&Shift],
+    date: Date,
+    slot_start: i64,
+) -> Option<&'static str> {
+    let in_slot = |shift: &&Shift| {
+        minutes_on_date(shift, date)
+            .is_some_and(|(start, end)| start < slot_start + SLOT_MINUTES && end > slot_start)
+    };
+    if highlighted.iter().any(in_slot) {
+        Some(COLOR_BASE)
+    } else if shifts.iter().any(in_slot) {
+        Some(COLOR_OTHER_SHIFT)
+    } else {
+        None
+    }
+}
+
+// The minutes since midnight the shift starts and ends on this date, if the shift is on this date at all
+fn minutes_on_date(shift: &Shift, date: Date) -> Option<(i64, i64)> {
+    if date < shift.date || date > shift.end_date {
+        return None;
+    }
+    let minutes = |time: Time| time.hour() as i64 * 60 + time.minute() as i64;
+    let start = if date == shift.date {
+        minutes(shift.start)
+    } else {
+        0
+    };
+    let end = if date == shift.end_date {
+        minutes(shift.end)
+    } else {
+        MINUTES_PER_DAY
+    };
+    (end > start).then_some((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webcom::shift_diff::tests::shift;
+    use time::macros::date;
+
+    #[test]
+    fn night_shifts_continue_on_the_next_day() {
+        let night = shift("N1234", 2, "22:00", "02:00");
+        assert_eq!(
+            minutes_on_date(&night, date!(2025 - 06 - 02)),
+            Some((22 * 60, MINUTES_PER_DAY))
+        );
+        assert_eq!(
+            minutes_on_date(&night, date!(2025 - 06 - 03)),
+            Some((0, 120))
+        );
+        assert_eq!(minutes_on_date(&night, date!(2025 - 06 - 04)), None);
+    }
+
+    #[test]
+    fn highlighted_shift_is_drawn_in_context() {
+        let new_shift = shift("V2309", 5, "06:00", "14:00");
+        let other_shift = shift("V1234", 3, "12:00", "20:00");
+        let html = render_week_strip(&[&new_shift, &other_shift], &[&new_shift]).unwrap();
+        // Monday the 2nd is the first column, so thursday the 5th is the fourth
+        assert!(html.contains(">ma 2</td>"));
+        assert!(html.contains("font-weight:bold;\">do 5</td>"));
+        // Eight hours each, the new shift is not drawn twice
+        assert_eq!(
+            html.matches(&format!("background-color:{COLOR_BASE}"))
+                .count(),
+            8
+        );
+        assert_eq!(
+            html.matches(&format!("background-color:{COLOR_OTHER_SHIFT}"))
+                .count(),
+            8
+        );
+        assert!(!html.contains("<svg"));
+        assert!(render_week_strip(&[&other_shift], &[]).is_none());
+    }
+}
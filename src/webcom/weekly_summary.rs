@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use chrono::{Duration, NaiveDateTime};
+use entity::user_data;
+use sea_orm::{ActiveValue::Set, EntityTrait, IntoActiveModel};
+use tokio::sync::RwLock;
+use tracing::*;
+
+use crate::{
+    GenResult,
+    database::variables::UserData,
+    errors::{FailureType, ResultLog},
+    get_database_connection,
+    health::ApplicationLogbook,
+    webcom::{email::send_weekly_summary_mail, ical::load_known_shifts},
+};
+
+const SUMMARY_INTERVAL: Duration = Duration::days(7);
+
+// Users which never got a summary count from the moment they signed up
+fn weekly_summary_due(
+    creation_date: NaiveDateTime,
+    last_summary: Option<NaiveDateTime>,
+    now: NaiveDateTime,
+) -> bool {
+    now >= last_summary.unwrap_or(creation_date) + SUMMARY_INTERVAL
+}
+
+// The number of successful runs and whether any of them found a change in the roster
+fn summarize_week(history: &[ApplicationLogbook]) -> (usize, bool) {
+    let checks = history
+        .iter()
+        .filter(|entry| entry.state == FailureType::OK)
+        .count();
+    let changed = history
+        .iter()
+        .any(|entry| entry.application_state.changed_shifts > 0);
+    (checks, changed)
+}
+
+/*
+Once a week, tells the users which opted in that their roster was checked and nothing changed
+If something did change they already got a mail about it, so the summary is skipped for that week
+Only done after a successful run, otherwise the mail would claim everything is fine while it is not
+*/
+pub async fn check_weekly_summary(
+    exit_code: &FailureType,
+    instance_data: Arc<RwLock<UserData>>,
+) -> GenResult<()> {
+    let user = instance_data.read().await.clone();
+    if exit_code != &FailureType::OK || !user.user_properties.send_weekly_summary {
+        return Ok(());
+    }
+    let now = chrono::offset::Utc::now().naive_utc();
+    if !weekly_summary_due(user.creation_date, user.last_weekly_summary_date, now) {
+        return Ok(());
+    }
+    let history =
+        ApplicationLogbook::load_history(&user.user_name, Some(now - SUMMARY_INTERVAL), None)
+            .await?;
+    let (checks, changed) = summarize_week(&history);
+    if changed {
+        debug!("Roster changed this week, skipping weekly summary");
+    } else {
+        let today = time::OffsetDateTime::now_utc().date();
+        let mut shifts = load_known_shifts()?;
+        shifts.retain(|shift| shift.date >= today);
+        shifts.sort_by_key(|shift| (shift.date, shift.start));
+        info!("Sending weekly summary");
+        send_weekly_summary_mail(checks, shifts.first())?;
+    }
+
+    // Stored even if the database update fails, so a broken database does not cause a summary every run
+    instance_data.write().await.last_weekly_summary_date = Some(now);
+    let db = get_database_connection().await;
+    if let Some(user) = user_data::Entity::find_by_id(user.id).one(&db).await? {
+        let mut active_user = user.into_active_model();
+        active_user.last_weekly_summary_date = Set(Some(now));
+        user_data::Entity::update(active_user)
+            .validate()?
+            .exec(&db)
+            .await
+            .warn("Storing weekly summary date");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(year, month, day)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+    }
+
+    fn run(state: FailureType, changed_shifts: u64) -> ApplicationLogbook {
+        let mut logbook = ApplicationLogbook {
+            state,
+            ..Default::default()
+        };
+        logbook.application_state.changed_shifts = changed_shifts;
+        logbook
+    }
+
+    #[test]
+    fn summary_is_sent_weekly() {
+        let created = date(2026, 10, 1);
+        assert!(!weekly_summary_due(created, None, date(2026, 10, 7)));
+        assert!(weekly_summary_due(created, None, date(2026, 10, 8)));
+        let last = Some(date(2026, 10, 8));
+        assert!(!weekly_summary_due(created, last, date(2026, 10, 14)));
+        assert!(weekly_summary_due(created, last, date(2026, 10, 15)));
+    }
+
+    #[test]
+    fn only_successful_runs_are_counted() {
+        let history = [
+            run(FailureType::OK, 0),
+            run(FailureType::TriesExceeded, 0),
+            run(FailureType::OK, 0),
+        ];
+        assert_eq!(summarize_week(&history), (2, false));
+        let history = [run(FailureType::OK, 0), run(FailureType::OK, 3)];
+        assert_eq!(summarize_week(&history), (2, true));
+    }
+}